@@ -0,0 +1,570 @@
+//! LRC parsing, validation and conversion.
+//!
+//! This is the part of lyricsync with no I/O, kept in its own crate so it also builds for
+//! `wasm32-unknown-unknown`. With the `wasm` feature, [`wasm`] exports it to JavaScript, letting a
+//! web front-end check lyrics with the same rules the command line applies.
+
+pub mod subtitles;
+pub mod ttml;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use std::collections::BTreeMap;
+
+/// A single lyric line with its start time in milliseconds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "wasm", wasm_bindgen::prelude::wasm_bindgen(getter_with_clone))]
+pub struct TimedLine {
+  #[cfg_attr(feature = "wasm", wasm_bindgen(js_name = timeMs))]
+  pub time_ms: u32,
+  pub text: String,
+}
+
+/// Parses the timestamped lines of an LRC document.
+///
+/// Header tags such as `[ar:Artist]` and lines without a timestamp are ignored. A line carrying
+/// several leading timestamps yields one entry per timestamp, in the order they appear.
+pub fn parse_timed_lines(lyrics: &str) -> Vec<TimedLine> {
+  let mut lines = Vec::new();
+
+  for line in lyrics.lines() {
+    let (times, text) = split_timestamps(line);
+    lines.extend(times.into_iter().map(|time_ms| TimedLine { time_ms, text: text.to_string() }));
+  }
+
+  lines
+}
+
+// Splits the leading timestamps off a line, returning them with the text that follows
+fn split_timestamps(line: &str) -> (Vec<u32>, &str) {
+  let mut rest = line.trim_end_matches('\r');
+  let mut times = Vec::new();
+
+  while let Some(tag_end) = rest.strip_prefix('[').and_then(|tag| tag.find(']')) {
+    let Some(time_ms) = parse_timestamp(&rest[1..=tag_end]) else {
+      break;
+    };
+    times.push(time_ms);
+    rest = &rest[tag_end + 2..];
+  }
+
+  (times, rest)
+}
+
+// Header tags are `[key:value]` with an alphabetic key, like `[ar:Artist]` or `[offset:+200]`
+fn is_header_tag(line: &str) -> bool {
+  line
+    .strip_prefix('[')
+    .and_then(|tag| tag.strip_suffix(']'))
+    .and_then(|tag| tag.split_once(':'))
+    .is_some_and(|(key, _)| !key.is_empty() && key.bytes().all(|byte| byte.is_ascii_alphabetic()))
+}
+
+/// Parses the inside of a timestamp tag into milliseconds, accepting `mm:ss`, `mm:ss.xx`,
+/// `mm:ss.xxx` and the `mm:ss:xx` variant some tools emit.
+pub fn parse_timestamp(tag: &str) -> Option<u32> {
+  let (minutes, rest) = tag.split_once(':')?;
+  let (seconds, fraction) = match rest.split_once(['.', ':']) {
+    Some((seconds, fraction)) => (seconds, Some(fraction)),
+    None => (rest, None),
+  };
+
+  if !is_digits(minutes) || seconds.len() != 2 || !is_digits(seconds) {
+    return None;
+  }
+
+  let minutes: u32 = minutes.parse().ok()?;
+  let seconds: u32 = seconds.parse().ok()?;
+  if seconds >= 60 {
+    return None;
+  }
+
+  let millis = match fraction {
+    None => 0,
+    Some(fraction) if fraction.len() > 3 || !is_digits(fraction) => return None,
+    Some(fraction) => {
+      let value: u32 = fraction.parse().ok()?;
+      value * 10u32.pow(3 - fraction.len() as u32)
+    },
+  };
+
+  // Past u32::MAX milliseconds, some seven weeks, it can't be a time in a song
+  minutes.checked_mul(60_000)?.checked_add(seconds * 1000 + millis)
+}
+
+fn is_digits(value: &str) -> bool {
+  !value.is_empty() && value.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+/// Returns the latest timestamp of an LRC document, roughly where its lyrics end, or `None` when no
+/// line is timed.
+pub fn last_timestamp(lyrics: &str) -> Option<u32> {
+  lyrics.lines().flat_map(|line| split_timestamps(line).0).max()
+}
+
+/// Formats a millisecond offset as an LRC `[mm:ss.xx]` timestamp.
+pub fn format_timestamp(time_ms: u32) -> String {
+  let minutes = time_ms / 60_000;
+  let seconds = (time_ms / 1000) % 60;
+  let hundredths = (time_ms % 1000) / 10;
+  format!("[{minutes:02}:{seconds:02}.{hundredths:02}]")
+}
+
+/// Formats a millisecond offset as an enhanced LRC `<mm:ss.xx>` word timestamp.
+pub fn format_word_timestamp(time_ms: u32) -> String {
+  let tag = format_timestamp(time_ms);
+  format!("<{}>", &tag[1..tag.len() - 1])
+}
+
+/// Renders timed lines back into an LRC document.
+pub fn format_timed_lines(lines: &[TimedLine]) -> String {
+  lines.iter().map(|line| format!("{}{}\n", format_timestamp(line.time_ms), line.text)).collect()
+}
+
+/// Whether any line of an LRC document times its words, as enhanced LRC does with a `<mm:ss.xx>`
+/// tag in front of each word, like `[00:12.00]<00:12.00>Hello <00:12.40>world<00:13.10>`.
+pub fn has_word_timing(lyrics: &str) -> bool {
+  lyrics.lines().any(|line| !word_tags(split_timestamps(line).1).is_empty())
+}
+
+/// Takes the word timestamps out of an LRC document, leaving each line timed as a whole.
+pub fn strip_word_timing(lyrics: &str) -> String {
+  if !has_word_timing(lyrics) {
+    return lyrics.to_string();
+  }
+  let mut stripped = String::new();
+  for line in lyrics.lines() {
+    let line = line.trim_end_matches('\r');
+    let (_, text) = split_timestamps(line);
+    let line = format!("{}{}", &line[..line.len() - text.len()], without_word_tags(text));
+    stripped.push_str(line.trim_end());
+    stripped.push('\n');
+  }
+  stripped
+}
+
+/// Parses the timestamped lines of an LRC document into one entry per timed word, which is how SYLT
+/// frames time syllables. Each line starts its first entry with a newline, the way SYLT marks new
+/// lines, so [`join_timed_words`] can tell them apart again; a line's text before its first word
+/// timestamp is an entry at the line's time. Timestamps with no text after them, like the one
+/// ending the last word, are left out, as a SYLT entry can't be empty.
+pub fn parse_timed_words(lyrics: &str) -> Vec<TimedLine> {
+  let mut entries = Vec::new();
+  for line in parse_timed_lines(lyrics) {
+    let (lead, words) = split_words(&line.text);
+    let mut pieces = Vec::new();
+    if !lead.is_empty() || words.first().is_none_or(|&(time_ms, _)| time_ms != line.time_ms) {
+      pieces.push((line.time_ms, lead));
+    }
+    pieces.extend(words.into_iter().filter(|(_, text)| !text.is_empty()));
+    for (index, (time_ms, text)) in pieces.into_iter().enumerate() {
+      let newline = if index == 0 { "\n" } else { "" };
+      entries.push(TimedLine { time_ms, text: format!("{newline}{text}") });
+    }
+  }
+  entries
+}
+
+/// Puts entries timed word by word, as [`parse_timed_words`] gives them, back together into lines
+/// with word timestamps. When no entry starts a line with a newline, each is a line already, and
+/// they come back as they are.
+pub fn join_timed_words(entries: Vec<TimedLine>) -> Vec<TimedLine> {
+  if !entries.iter().any(|entry| entry.text.starts_with('\n')) {
+    return entries;
+  }
+  let mut lines: Vec<(TimedLine, Vec<TimedLine>)> = Vec::new();
+  for entry in entries {
+    match (entry.text.strip_prefix('\n'), lines.last_mut()) {
+      (None, Some((_, words))) => words.push(entry),
+      (text, _) => {
+        let text = text.unwrap_or(&entry.text).to_string();
+        lines.push((TimedLine { time_ms: entry.time_ms, text }, Vec::new()));
+      },
+    }
+  }
+  let join = |(first, words): (TimedLine, Vec<TimedLine>)| {
+    // A line of one entry is timed as a whole
+    if words.is_empty() {
+      return first;
+    }
+    let mut text =
+      if first.text.is_empty() { String::new() } else { format_word_timestamp(first.time_ms) + &first.text };
+    for word in words {
+      text.push_str(&format_word_timestamp(word.time_ms));
+      text.push_str(&word.text);
+    }
+    TimedLine { time_ms: first.time_ms, text }
+  };
+  lines.into_iter().map(join).collect()
+}
+
+// Where each word timestamp in `text` starts and ends, and its time
+fn word_tags(text: &str) -> Vec<(usize, usize, u32)> {
+  let mut tags = Vec::new();
+  let mut from = 0;
+  while let Some(start) = text[from..].find('<').map(|start| from + start) {
+    let tag =
+      text[start..].find('>').and_then(|end| Some((start + end + 1, parse_timestamp(&text[start + 1..start + end])?)));
+    match tag {
+      Some((end, time_ms)) => {
+        tags.push((start, end, time_ms));
+        from = end;
+      },
+      None => from = start + 1,
+    }
+  }
+  tags
+}
+
+// Splits the text of a line at its word timestamps, into what comes before the first and each
+// word with its time
+fn split_words(text: &str) -> (&str, Vec<(u32, &str)>) {
+  let tags = word_tags(text);
+  let lead = tags.first().map_or(text, |&(start, _, _)| &text[..start]);
+  let words = tags
+    .iter()
+    .enumerate()
+    .map(|(index, &(_, end, time_ms))| (time_ms, &text[end..tags.get(index + 1).map_or(text.len(), |tag| tag.0)]))
+    .collect();
+  (lead, words)
+}
+
+// The text of a line without its word timestamps
+fn without_word_tags(text: &str) -> String {
+  let (lead, words) = split_words(text);
+  words.iter().fold(lead.to_string(), |text, (_, word)| text + word)
+}
+
+/// Returns the lyrics of an LRC document without timestamps, word timestamps or header tags, one
+/// line per line of the document. Untimed text passes through unchanged.
+pub fn to_plain_text(lyrics: &str) -> String {
+  let mut text = String::new();
+
+  for line in lyrics.lines() {
+    let (times, rest) = split_timestamps(line);
+    if times.is_empty() && is_header_tag(rest.trim()) {
+      continue;
+    }
+    if times.is_empty() {
+      text.push_str(rest);
+    } else {
+      text.push_str(&without_word_tags(rest));
+    }
+    text.push('\n');
+  }
+
+  text
+}
+
+/// Decodes the bytes of an LRC file. UTF-8 is taken as it is. Anything else is decoded from the
+/// legacy encoding it looks most like, such as GBK, Big5, Shift-JIS or Windows-1252, or from UTF-16
+/// when it starts with a byte order mark; the name of that encoding comes back with the text.
+pub fn decode(bytes: &[u8]) -> (String, Option<&'static str>) {
+  if let Ok(text) = std::str::from_utf8(bytes) {
+    return (text.to_string(), None);
+  }
+  let mut detector = EncodingDetector::new(Iso2022JpDetection::Deny);
+  detector.feed(bytes, true);
+  // A byte order mark outranks the guess
+  let (text, encoding, _) = detector.guess(None, Utf8Detection::Deny).decode(bytes);
+  (text.into_owned(), Some(encoding.name()))
+}
+
+/// Drops a leading byte order mark and turns CRLF and CR line endings into LF, which is all players
+/// expect: the others show up as a stray `\u{feff}` or carriage return in the lyrics.
+pub fn normalize(lyrics: &str) -> String {
+  let lyrics = lyrics.strip_prefix('\u{feff}').unwrap_or(lyrics);
+  lyrics.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// Bakes an `[offset:±ms]` header tag into the timestamps and drops it, for players that ignore the
+/// tag. A positive offset shows the lines sooner, as players that honor it do, and times it would
+/// take below zero become zero. Lyrics without a valid offset tag come back unchanged.
+pub fn apply_offset(lyrics: &str) -> String {
+  let offset = lyrics.lines().find_map(|line| {
+    let (key, value) = line.trim().strip_prefix('[')?.strip_suffix(']')?.split_once(':')?;
+    key.trim().eq_ignore_ascii_case("offset").then(|| value.trim().parse::<i64>().ok())?
+  });
+  let Some(offset) = offset else {
+    return lyrics.to_string();
+  };
+
+  let mut untagged = String::new();
+  for line in lyrics.lines() {
+    let rest = line.trim();
+    if !(is_header_tag(rest) && rest[1..].to_ascii_lowercase().starts_with("offset:")) {
+      untagged.push_str(line);
+      untagged.push('\n');
+    }
+  }
+  shift(&untagged, -offset)
+}
+
+/// Drops the header tags with any of `keys`, like `ar` for `[ar:Artist]`, ignoring case, for
+/// players that show them as lines of the lyrics.
+pub fn strip_headers(lyrics: &str, keys: &[impl AsRef<str>]) -> String {
+  if keys.is_empty() {
+    return lyrics.to_string();
+  }
+  let mut stripped = String::new();
+  for line in lyrics.lines() {
+    let tag = line.trim();
+    let key = tag.get(1..).and_then(|tag| tag.split_once(':')).map(|(key, _)| key);
+    if is_header_tag(tag) && key.is_some_and(|key| keys.iter().any(|strip| strip.as_ref().eq_ignore_ascii_case(key))) {
+      continue;
+    }
+    stripped.push_str(line);
+    stripped.push('\n');
+  }
+  stripped
+}
+
+/// Expands condensed lines like `[00:12.00][01:45.00]Chorus`, a line for each of its timestamps,
+/// and sorts the timed lines by time, for players that take the lines in the order they come.
+/// Header tags and other lines without timestamps go first in their order, blank ones aside.
+/// Lyrics without a condensed line come back unchanged.
+pub fn expand(lyrics: &str) -> String {
+  if !lyrics.lines().any(|line| split_timestamps(line).0.len() > 1) {
+    return lyrics.to_string();
+  }
+  let mut lines = parse_timed_lines(lyrics);
+  lines.sort_by_key(|line| line.time_ms);
+  with_untimed_lines(lyrics, &lines)
+}
+
+/// What [`sort_lines`] changed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SortReport {
+  /// Timed lines that came before a line with an earlier time
+  pub out_of_order: usize,
+  /// Timed lines merged into an earlier one with the same time and text
+  pub duplicates: usize,
+}
+
+/// Sorts the timed lines by time and merges exact duplicates, ones with the same time and text,
+/// laid out as [`expand`] lays them out. Lyrics with nothing to sort or merge come back unchanged.
+pub fn sort_lines(lyrics: &str) -> (String, SortReport) {
+  let mut lines = parse_timed_lines(lyrics);
+  let out_of_order = lines.windows(2).filter(|pair| pair[1].time_ms < pair[0].time_ms).count();
+  let count = lines.len();
+  lines.sort_by_key(|line| line.time_ms);
+  // Sorting keeps lines with the same time in their order, but duplicates needn't be next to each other
+  let mut seen = std::collections::HashSet::new();
+  lines.retain(|line| seen.insert((line.time_ms, line.text.clone())));
+  let report = SortReport { out_of_order, duplicates: count - lines.len() };
+  if report == SortReport::default() {
+    return (lyrics.to_string(), report);
+  }
+  (with_untimed_lines(lyrics, &lines), report)
+}
+
+// Header tags and other untimed lines of `lyrics`, blank ones aside, followed by `lines`
+fn with_untimed_lines(lyrics: &str, lines: &[TimedLine]) -> String {
+  let mut laid_out = String::new();
+  for line in lyrics.lines() {
+    if split_timestamps(line).0.is_empty() && !line.trim().is_empty() {
+      laid_out.push_str(line);
+      laid_out.push('\n');
+    }
+  }
+  laid_out.push_str(&format_timed_lines(lines));
+  laid_out
+}
+
+/// Interleaves a translation with the lyrics it translates, each translated line right after the
+/// line with its timestamp, as the bilingual LRC files most CJK players expect have them:
+/// `[00:12.00]Hello` then `[00:12.00]你好`. Translated lines no line of `lyrics` has the time of go
+/// where their time puts them, and the translation's header tags and blank lines are left out.
+pub fn merge_translation(lyrics: &str, translation: &str) -> String {
+  let mut translated: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+  for line in parse_timed_lines(translation) {
+    let text = without_word_tags(&line.text);
+    if !text.trim().is_empty() {
+      translated.entry(line.time_ms).or_default().push(text.trim().to_string());
+    }
+  }
+  let push = |merged: &mut String, time_ms: u32, texts: Vec<String>| {
+    for text in texts {
+      merged.push_str(&format_timestamp(time_ms));
+      merged.push_str(&text);
+      merged.push('\n');
+    }
+  };
+
+  let mut merged = String::new();
+  for line in lyrics.lines() {
+    let (times, _) = split_timestamps(line);
+    if let Some(&first) = times.first() {
+      while let Some(entry) = translated.first_entry().filter(|entry| *entry.key() < first) {
+        let (time_ms, texts) = entry.remove_entry();
+        push(&mut merged, time_ms, texts);
+      }
+    }
+    merged.push_str(line);
+    merged.push('\n');
+    for time_ms in times {
+      if let Some(texts) = translated.remove(&time_ms) {
+        push(&mut merged, time_ms, texts);
+      }
+    }
+  }
+  for (time_ms, texts) in translated {
+    push(&mut merged, time_ms, texts);
+  }
+  merged
+}
+
+/// Keeps the lines whose text, timestamps and word timestamps aside, `keep` holds on to. Header
+/// tags and blank lines are always kept.
+pub fn retain_lines(lyrics: &str, mut keep: impl FnMut(&str) -> bool) -> String {
+  let mut retained = String::new();
+  for line in lyrics.lines() {
+    let (_, text) = split_timestamps(line);
+    if text.trim().is_empty() || is_header_tag(text.trim()) || keep(&without_word_tags(text)) {
+      retained.push_str(line);
+      retained.push('\n');
+    }
+  }
+  retained
+}
+
+/// Rewrites the text of each line as `map` gives it, word timestamps and all, keeping the line's
+/// timestamps in front of it. Header tags and blank lines are kept as they are.
+pub fn map_text(lyrics: &str, mut map: impl FnMut(&str) -> String) -> String {
+  let mut mapped = String::new();
+  for line in lyrics.lines() {
+    let (times, text) = split_timestamps(line);
+    if text.trim().is_empty() || (times.is_empty() && is_header_tag(text.trim())) {
+      mapped.push_str(line);
+    } else {
+      times.into_iter().for_each(|time_ms| mapped.push_str(&format_timestamp(time_ms)));
+      mapped.push_str(&map(text));
+    }
+    mapped.push('\n');
+  }
+  mapped
+}
+
+/// Moves every timestamp `delta_ms` later, or earlier when it's negative, with times it would take
+/// below zero becoming zero. Header tags and untimed lines are kept as they are.
+pub fn shift(lyrics: &str, delta_ms: i64) -> String {
+  if delta_ms == 0 {
+    return lyrics.to_string();
+  }
+  map_timestamps(lyrics, |time_ms| i64::from(time_ms) + delta_ms)
+}
+
+/// Stretches or compresses the timestamps to fit two anchors, each an LRC time and the time it
+/// should be at in milliseconds, for lyrics synced to a master with a slightly different tempo or
+/// length. Times between the anchors and beyond them are scaled along the same line, and times it
+/// would take below zero become zero. `None` when the anchors share their LRC time.
+pub fn rescale(lyrics: &str, anchors: [(u32, u32); 2]) -> Option<String> {
+  let [(from_a, to_a), (from_b, to_b)] = anchors.map(|(from, to)| (f64::from(from), f64::from(to)));
+  if from_a == from_b {
+    return None;
+  }
+  let scale = (to_b - to_a) / (from_b - from_a);
+  Some(map_timestamps(lyrics, |time_ms| (to_a + (f64::from(time_ms) - from_a) * scale).round() as i64))
+}
+
+// Rewrites every timestamp as `map` gives it, word timestamps included, clamped to what a
+// timestamp can hold
+fn map_timestamps(lyrics: &str, map: impl Fn(u32) -> i64) -> String {
+  let map = |time_ms| map(time_ms).clamp(0, i64::from(u32::MAX)) as u32;
+  let mut mapped = String::new();
+  for line in lyrics.lines() {
+    let (times, rest) = split_timestamps(line);
+    if times.is_empty() {
+      mapped.push_str(line);
+      mapped.push('\n');
+      continue;
+    }
+    for time_ms in times {
+      mapped.push_str(&format_timestamp(map(time_ms)));
+    }
+    let (lead, words) = split_words(rest);
+    mapped.push_str(lead);
+    for (time_ms, word) in words {
+      mapped.push_str(&format_word_timestamp(map(time_ms)));
+      mapped.push_str(word);
+    }
+    mapped.push('\n');
+  }
+  mapped
+}
+
+/// A problem found by [`validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "wasm", wasm_bindgen::prelude::wasm_bindgen(getter_with_clone))]
+pub struct Issue {
+  /// 1-based line number, or 0 when the problem concerns the document as a whole
+  pub line: usize,
+  pub message: String,
+}
+
+/// Checks an LRC document for problems that would keep it from syncing as intended: timestamps
+/// that can't be parsed, lines that go back in time, header tags given twice, and documents
+/// without a single timed line.
+///
+/// An empty result means the document is valid. Blank lines are always accepted.
+pub fn validate(lyrics: &str) -> Vec<Issue> {
+  let mut issues = Vec::new();
+  let mut previous: Option<u32> = None;
+  // Header tags by name, with the line each was first given on
+  let mut headers: Vec<(String, usize)> = Vec::new();
+
+  for (index, line) in lyrics.lines().enumerate() {
+    let line_number = index + 1;
+    let (times, rest) = split_timestamps(line);
+
+    if times.is_empty() && is_header_tag(rest.trim()) {
+      let (name, _) = rest.trim()[1..].split_once(':').unwrap_or_default();
+      let name = name.to_ascii_lowercase();
+      match headers.iter().find(|(header, _)| *header == name) {
+        Some((_, first)) => {
+          let message = format!("duplicate [{name}:] header tag, first given on line {first}");
+          issues.push(Issue { line: line_number, message });
+        },
+        None => headers.push((name, line_number)),
+      }
+      continue;
+    }
+
+    // Something that starts like a timestamp but didn't parse as one
+    if rest.starts_with('[') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+      let tag = rest.find(']').map_or(rest, |tag_end| &rest[..=tag_end]);
+      let message =
+        if rest.contains(']') { format!("invalid timestamp {tag}") } else { format!("unterminated timestamp {tag}") };
+      issues.push(Issue { line: line_number, message });
+    }
+
+    // Several timestamps on a line repeat it, so only the first one has to keep the order
+    if let Some(&time_ms) = times.first() {
+      if previous.is_some_and(|previous| time_ms < previous) {
+        let message = format!("timestamp {} is earlier than the line before it", format_timestamp(time_ms));
+        issues.push(Issue { line: line_number, message });
+      }
+      previous = Some(time_ms);
+
+      // Each word of an enhanced LRC line starts after the one before it
+      let mut word_start = time_ms;
+      for (word_time_ms, _) in split_words(rest).1 {
+        if word_time_ms < word_start {
+          let message =
+            format!("word timestamp {} is earlier than the one before it", format_word_timestamp(word_time_ms));
+          issues.push(Issue { line: line_number, message });
+        }
+        word_start = word_time_ms;
+      }
+    }
+  }
+
+  if lyrics.trim().is_empty() {
+    issues.push(Issue { line: 0, message: "the lyrics are empty".to_string() });
+  } else if previous.is_none() {
+    issues.push(Issue { line: 0, message: "no line has a timestamp".to_string() });
+  }
+
+  issues
+}
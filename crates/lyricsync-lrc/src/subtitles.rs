@@ -0,0 +1,224 @@
+//! Subtitle files read as lyrics: each cue becomes an LRC line timed at its start, for timings that
+//! circulate as subtitles rather than LRC files. SubRip and WebVTT are close enough to be read the
+//! same way, WebVTT with a few more blocks and escapes to look past. ASS and SSA, which karaoke
+//! timers use, time syllables too, and those come through as enhanced LRC word timestamps.
+
+use crate::{TimedLine, format_timed_lines, format_word_timestamp};
+
+/// Converts SubRip (`.srt`) subtitles to LRC. A cue's lines are joined into one, without the
+/// `<i>`-style tags and `{\an8}` positioning some files carry, and cues without text are dropped.
+pub fn from_srt(srt: &str) -> String {
+  format_timed_lines(&cues(srt, str::to_string))
+}
+
+/// Converts WebVTT (`.vtt`) subtitles to LRC, as [`from_srt`] does. The `WEBVTT` header and the
+/// `NOTE`, `STYLE` and `REGION` blocks are skipped, and so are cue settings like `align:start`,
+/// with `<v Singer>` voice tags and inline `<00:01.500>` timestamps dropped and entities like
+/// `&amp;` decoded.
+pub fn from_vtt(vtt: &str) -> String {
+  format_timed_lines(&cues(vtt, decode_entities))
+}
+
+/// Converts ASS or SSA (`.ass`, `.ssa`) subtitles to LRC, a line for each `Dialogue` event in time
+/// order. Karaoke timing, the `\k` tags that give each syllable's length in hundredths of a second,
+/// becomes a [word timestamp](crate::has_word_timing) in front of each syllable and one where the
+/// last ends. Other override tags are dropped, and `\N` line breaks become spaces.
+///
+/// Files a karaoke template has been applied to have `fx` events drawing the effects and the lines
+/// they were made from commented out as `karaoke`, and those are read instead.
+pub fn from_ass(ass: &str) -> String {
+  let mut events = Vec::new();
+  let mut in_events = false;
+  // Where the start time, the effect and the text are among an event's fields; this is the order
+  // files that leave the format out have
+  let mut fields = (1, 8, 9);
+  for row in ass.lines().map(str::trim) {
+    if row.starts_with('[') {
+      in_events = row.eq_ignore_ascii_case("[events]");
+      continue;
+    }
+    let Some((kind, values)) = row.split_once(':').filter(|_| in_events) else {
+      continue;
+    };
+    let kind = kind.trim();
+    if kind == "Format" {
+      let names: Vec<&str> = values.split(',').map(str::trim).collect();
+      let index = |name: &str| names.iter().position(|field| field.eq_ignore_ascii_case(name));
+      if let (Some(start), Some(effect), Some(text)) = (index("Start"), index("Effect"), index("Text")) {
+        fields = (start, effect, text);
+      }
+      continue;
+    }
+    // The text is the last field, and the only one that can have commas in it
+    let values: Vec<&str> = values.trim_start().splitn(fields.2 + 1, ',').collect();
+    let field = |index: usize| values.get(index).map_or("", |value| value.trim());
+    if kind == "Dialogue" || kind == "Comment" {
+      events.push((kind == "Comment", field(fields.1).to_ascii_lowercase(), field(fields.0), field(fields.2)));
+    }
+  }
+
+  let templated = events.iter().any(|(comment, effect, _, _)| !comment && effect == "fx");
+  let mut lines: Vec<TimedLine> = events
+    .iter()
+    .filter(|(comment, effect, _, _)| if templated { *comment && effect == "karaoke" } else { !comment })
+    .filter_map(|(_, _, start, text)| {
+      let time_ms = parse_cue_time(start)?;
+      let text = karaoke_text(text, time_ms);
+      (!text.is_empty()).then_some(TimedLine { time_ms, text })
+    })
+    .collect();
+  lines.sort_by_key(|line| line.time_ms);
+  format_timed_lines(&lines)
+}
+
+// The text of an ASS event starting at `start_ms`, its `\k` syllables timed
+fn karaoke_text(text: &str, start_ms: u32) -> String {
+  let mut timed = String::new();
+  // Where the next syllable starts, and the start of one whose text hasn't come yet
+  let mut next_ms = start_ms;
+  let mut pending: Option<u32> = None;
+  let mut karaoke = false;
+  for (index, piece) in text.split(['{', '}']).enumerate() {
+    // Pieces alternate between text and override blocks, as blocks don't nest
+    if index % 2 == 1 {
+      if let Some(centiseconds) = karaoke_length(piece) {
+        // A syllable without text, like a pause, is only time passing before the next
+        pending = Some(next_ms);
+        // Lengths come from the file, so a corrupt one stops at the last time there is
+        next_ms = next_ms.saturating_add(centiseconds.saturating_mul(10));
+        karaoke = true;
+      }
+      continue;
+    }
+    let piece = piece.replace("\\N", " ").replace("\\n", " ").replace("\\h", " ");
+    if !piece.trim().is_empty()
+      && let Some(starts) = pending.take()
+    {
+      timed.push_str(&format_word_timestamp(starts));
+    }
+    timed.push_str(&piece);
+  }
+  let mut text = timed.split_whitespace().collect::<Vec<_>>().join(" ");
+  if karaoke && !text.is_empty() {
+    text.push_str(&format_word_timestamp(next_ms));
+  }
+  text
+}
+
+// The length a `\k`, `\K`, `\kf` or `\ko` tag in an override block gives, in hundredths
+fn karaoke_length(overrides: &str) -> Option<u32> {
+  overrides.split('\\').find_map(|tag| {
+    let length = ["kf", "ko", "k", "K"].iter().find_map(|name| tag.strip_prefix(name))?;
+    length.trim().parse().ok()
+  })
+}
+
+// The cues of SubRip or WebVTT subtitles with some text, each at its start, its text passed through
+// `decode` once markup is dropped
+fn cues(subtitles: &str, decode: impl Fn(&str) -> String) -> Vec<TimedLine> {
+  let mut lines = Vec::new();
+  for block in subtitles.split("\n\n") {
+    let mut rows = block.lines().map(str::trim).skip_while(|row| row.is_empty());
+    let Some(mut row) = rows.next() else {
+      continue;
+    };
+    if ["WEBVTT", "NOTE", "STYLE", "REGION"].iter().any(|keyword| row.split_whitespace().next() == Some(keyword)) {
+      continue;
+    }
+    // The cue's number or identifier, if it has one
+    if !row.contains("-->") {
+      let Some(next) = rows.next() else {
+        continue;
+      };
+      row = next;
+    }
+    let Some(time_ms) = row.split_once("-->").and_then(|(start, _)| parse_cue_time(start.trim())) else {
+      continue;
+    };
+    let text = decode(&cue_text(rows));
+    if !text.trim().is_empty() {
+      lines.push(TimedLine { time_ms, text: text.trim().to_string() });
+    }
+  }
+  lines
+}
+
+// Accepts `hh:mm:ss,mmm`, with a period in place of the comma as some tools write it, and without
+// the hours
+fn parse_cue_time(time: &str) -> Option<u32> {
+  let (clock, millis) = time.split_once([',', '.']).unwrap_or((time, "0"));
+  let mut fields = clock.split(':').rev();
+  let seconds: u32 = fields.next()?.parse().ok()?;
+  let minutes: u32 = fields.next()?.parse().ok()?;
+  let hours: u32 = fields.next().map_or(Some(0), |hours| hours.parse().ok())?;
+  if fields.next().is_some() || seconds >= 60 || minutes >= 60 || millis.is_empty() || millis.len() > 3 {
+    return None;
+  }
+  let millis = millis.parse::<u32>().ok()? * 10u32.pow(3 - millis.len() as u32);
+  // A cue more hours in than a u32 of milliseconds holds is as malformed as any other
+  let seconds = hours.checked_mul(3600)?.checked_add(minutes * 60 + seconds)?;
+  seconds.checked_mul(1000)?.checked_add(millis)
+}
+
+// The text of a cue's lines, joined by spaces, with markup dropped
+fn cue_text<'a>(rows: impl Iterator<Item = &'a str>) -> String {
+  let rows: Vec<String> = rows.map(strip_markup).filter(|row| !row.is_empty()).collect();
+  rows.join(" ")
+}
+
+// Decodes the character references WebVTT has names for, and numeric ones
+pub(crate) fn decode_entities(text: &str) -> String {
+  let mut decoded = String::new();
+  let mut rest = text;
+  while let Some(start) = rest.find('&') {
+    decoded.push_str(&rest[..start]);
+    rest = &rest[start..];
+    let Some((entity, c)) = rest.find(';').and_then(|end| Some((&rest[1..end], entity_char(&rest[1..end])?))) else {
+      decoded.push('&');
+      rest = &rest[1..];
+      continue;
+    };
+    decoded.extend(c);
+    rest = &rest[entity.len() + 2..];
+  }
+  decoded.push_str(rest);
+  decoded
+}
+
+// What an entity stands for, `Some(None)` for direction marks, which have no place in a lyric line
+fn entity_char(entity: &str) -> Option<Option<char>> {
+  let c = match entity {
+    "amp" => '&',
+    "lt" => '<',
+    "gt" => '>',
+    "nbsp" => ' ',
+    "quot" => '"',
+    "apos" => '\'',
+    "lrm" | "rlm" => return Some(None),
+    _ => {
+      let number = entity.strip_prefix('#')?;
+      let code = match number.strip_prefix(['x', 'X']) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => number.parse().ok()?,
+      };
+      char::from_u32(code)?
+    },
+  };
+  Some(Some(c))
+}
+
+// Drops `<...>` tags and `{...}` overrides, leaving the text between them
+fn strip_markup(row: &str) -> String {
+  let mut text = String::new();
+  let mut closing = None;
+  for c in row.chars() {
+    match (closing, c) {
+      (None, '<') => closing = Some('>'),
+      (None, '{') => closing = Some('}'),
+      (None, _) => text.push(c),
+      (Some(close), _) if c == close => closing = None,
+      (Some(_), _) => {},
+    }
+  }
+  text.trim().to_string()
+}
@@ -0,0 +1,200 @@
+//! TTML lyrics, as Apple Music and some providers deliver them: each `<p>` is a line timed by its
+//! `begin`, and word-timed files give each word a `<span>` with a `begin` of its own, which is kept
+//! as an [enhanced LRC](crate::has_word_timing) word timestamp.
+//!
+//! Times are taken as they're written, from the start of the track, as the files lyrics come in
+//! write them, rather than from the start of the element they're in.
+
+use crate::subtitles::decode_entities;
+use crate::{format_timestamp, format_word_timestamp};
+
+// Roles of the spans that aren't sung, like Apple's inline translations
+const UNSUNG_ROLES: [&str; 3] = ["x-translation", "x-roman", "x-pronunciation"];
+
+/// Converts TTML (`.ttml`) lyrics to LRC. Word timings come through as `<mm:ss.xx>` before each word,
+/// with the end of the last word after it; [`crate::strip_word_timing`] takes them out again.
+/// Paragraphs without a time are kept as untimed lines, and the `<head>` is skipped.
+pub fn from_ttml(ttml: &str) -> String {
+  let mut lyrics = String::new();
+  let mut head = false;
+  let mut paragraph: Option<Paragraph> = None;
+
+  for token in tokens(ttml) {
+    match token {
+      Token::Open { name: "head", empty: false, .. } => head = true,
+      Token::Close("head") => head = false,
+      _ if head => {},
+      Token::Open { name: "p", attributes, empty } => {
+        let opened = Paragraph { begin: attribute(attributes, "begin").and_then(parse_time), ..Paragraph::default() };
+        // An empty paragraph has no text, and shouldn't take the text after it
+        paragraph = (!empty).then_some(opened);
+      },
+      Token::Close("p") => {
+        if let Some(paragraph) = paragraph.take() {
+          paragraph.finish(&mut lyrics);
+        }
+      },
+      Token::Open { name, attributes, empty } => {
+        if let Some(paragraph) = &mut paragraph {
+          paragraph.open(name, attributes, empty);
+        }
+      },
+      Token::Close(name) => {
+        if let Some(paragraph) = &mut paragraph
+          && name == "span"
+        {
+          paragraph.skipped.pop();
+        }
+      },
+      Token::Text(text) => {
+        if let Some(paragraph) = &mut paragraph
+          && !paragraph.skipping()
+        {
+          paragraph.text.push_str(&decode_entities(text));
+        }
+      },
+    }
+  }
+
+  lyrics
+}
+
+// A `<p>` being read
+#[derive(Default)]
+struct Paragraph {
+  begin: Option<u32>,
+  // The text so far, with a word timestamp in front of each timed word
+  text: String,
+  // When the last timed word ends, if it says
+  end: Option<u32>,
+  // Whether each span open in it is left out
+  skipped: Vec<bool>,
+  first_word: Option<u32>,
+}
+
+impl Paragraph {
+  fn skipping(&self) -> bool {
+    self.skipped.last().copied().unwrap_or(false)
+  }
+
+  fn open(&mut self, name: &str, attributes: &str, empty: bool) {
+    match name {
+      "br" if !self.skipping() => self.text.push(' '),
+      "span" => {
+        let role = attribute(attributes, "role").unwrap_or_default();
+        let skipped = self.skipping() || UNSUNG_ROLES.contains(&role);
+        if !skipped && let Some(begin) = attribute(attributes, "begin").and_then(parse_time) {
+          self.text.push_str(&format_word_timestamp(begin));
+          self.first_word.get_or_insert(begin);
+          self.end = attribute(attributes, "end").and_then(parse_time);
+        }
+        if !empty {
+          self.skipped.push(skipped);
+        }
+      },
+      _ => {},
+    }
+  }
+
+  // Adds the line to `lyrics`, unless it has no text
+  fn finish(self, lyrics: &mut String) {
+    let mut text = self.text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+      return;
+    }
+    if let (Some(_), Some(end)) = (self.first_word, self.end) {
+      text.push_str(&format_word_timestamp(end));
+    }
+    if let Some(begin) = self.begin.or(self.first_word) {
+      lyrics.push_str(&format_timestamp(begin));
+    }
+    lyrics.push_str(&text);
+    lyrics.push('\n');
+  }
+}
+
+// As much of XML as lyrics need: elements by their name without a namespace prefix, and the text
+// between them. Comments, declarations and processing instructions are dropped.
+enum Token<'a> {
+  Open { name: &'a str, attributes: &'a str, empty: bool },
+  Close(&'a str),
+  Text(&'a str),
+}
+
+fn tokens(xml: &str) -> Vec<Token<'_>> {
+  let mut tokens = Vec::new();
+  let mut rest = xml;
+  while let Some(start) = rest.find('<') {
+    if start > 0 {
+      tokens.push(Token::Text(&rest[..start]));
+    }
+    rest = &rest[start..];
+    let closing = if rest.starts_with("<!--") { "-->" } else { ">" };
+    let Some(end) = rest.find(closing) else {
+      return tokens;
+    };
+    let tag = &rest[1..end];
+    rest = &rest[end + closing.len()..];
+    if tag.starts_with(['!', '?']) {
+      continue;
+    }
+    if let Some(name) = tag.strip_prefix('/') {
+      tokens.push(Token::Close(local_name(name.trim())));
+      continue;
+    }
+    let (tag, empty) = tag.strip_suffix('/').map_or((tag, false), |tag| (tag, true));
+    let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+    tokens.push(Token::Open { name: local_name(name), attributes, empty });
+  }
+  if !rest.is_empty() {
+    tokens.push(Token::Text(rest));
+  }
+  tokens
+}
+
+// `span` for `tt:span`
+fn local_name(name: &str) -> &str {
+  name.rsplit(':').next().unwrap_or(name)
+}
+
+// The value of the attribute named `wanted`, whatever its namespace prefix
+fn attribute<'a>(attributes: &'a str, wanted: &str) -> Option<&'a str> {
+  let mut rest = attributes;
+  while let Some((name, value)) = rest.split_once('=') {
+    let value = value.trim_start();
+    let quote = value.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+    let (value, after) = value[1..].split_once(quote)?;
+    if local_name(name.trim()) == wanted {
+      return Some(value);
+    }
+    rest = after;
+  }
+  None
+}
+
+// Accepts clock times, `01:02:03.250` or `02:03.250` as Apple writes them, and offset times like
+// `12.5s`, `1500ms` or a bare number of seconds. Frame counts aren't, as no lyrics file uses them.
+fn parse_time(time: &str) -> Option<u32> {
+  let time = time.trim();
+  let units = [("ms", 1.0), ("h", 3_600_000.0), ("m", 60_000.0), ("s", 1000.0)];
+  if let Some((number, scale)) = units.iter().find_map(|&(unit, scale)| Some((time.strip_suffix(unit)?, scale))) {
+    return millis(decimal(number)? * scale);
+  }
+  let mut fields = time.rsplit(':');
+  let seconds = decimal(fields.next()?)?;
+  let minutes = fields.next().map_or(Some(0.0), decimal)?;
+  let hours = fields.next().map_or(Some(0.0), decimal)?;
+  if fields.next().is_some() || (time.contains(':') && seconds >= 60.0) {
+    return None;
+  }
+  millis(((hours * 60.0 + minutes) * 60.0 + seconds) * 1000.0)
+}
+
+fn decimal(number: &str) -> Option<f64> {
+  let digits = !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit() || byte == b'.');
+  digits.then(|| number.parse().ok()).flatten()
+}
+
+fn millis(value: f64) -> Option<u32> {
+  (value <= f64::from(u32::MAX)).then_some(value.round() as u32)
+}
@@ -0,0 +1,41 @@
+//! JavaScript bindings, for building with wasm-pack or wasm-bindgen:
+//!
+//! ```sh
+//! wasm-pack build crates/lyricsync-lrc --target web --features wasm
+//! ```
+//!
+//! [`TimedLine`] and [`Issue`] become classes with `timeMs`/`text` and `line`/`message` properties.
+
+use crate::{Issue, TimedLine};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Returns the problems in an LRC document, an empty array when it's valid.
+#[wasm_bindgen]
+pub fn validate(lyrics: &str) -> Vec<Issue> {
+  crate::validate(lyrics)
+}
+
+/// Returns the timed lines of an LRC document, for previewing the sync.
+#[wasm_bindgen(js_name = parseTimedLines)]
+pub fn parse_timed_lines(lyrics: &str) -> Vec<TimedLine> {
+  crate::parse_timed_lines(lyrics)
+}
+
+/// Rewrites an LRC document with one `[mm:ss.xx]` timestamp per line, as lyricsync writes synced
+/// lyrics into a file.
+#[wasm_bindgen(js_name = normalizeLrc)]
+pub fn normalize_lrc(lyrics: &str) -> String {
+  crate::format_timed_lines(&crate::parse_timed_lines(lyrics))
+}
+
+/// Returns the lyrics without timestamps or header tags.
+#[wasm_bindgen(js_name = toPlainText)]
+pub fn to_plain_text(lyrics: &str) -> String {
+  crate::to_plain_text(lyrics)
+}
+
+/// Formats milliseconds as an LRC `[mm:ss.xx]` timestamp.
+#[wasm_bindgen(js_name = formatTimestamp)]
+pub fn format_timestamp(time_ms: u32) -> String {
+  crate::format_timestamp(time_ms)
+}
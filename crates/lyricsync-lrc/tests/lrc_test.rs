@@ -0,0 +1,278 @@
+use lyricsync_lrc::subtitles::{from_ass, from_srt, from_vtt};
+use lyricsync_lrc::ttml::from_ttml;
+use lyricsync_lrc::{
+  Issue, SortReport, TimedLine, apply_offset, decode, expand, format_timed_lines, has_word_timing, join_timed_words,
+  last_timestamp, map_text, merge_translation, normalize, parse_timed_lines, parse_timed_words, parse_timestamp,
+  rescale, retain_lines, shift, sort_lines, strip_headers, strip_word_timing, to_plain_text, validate,
+};
+
+#[test]
+fn test_parse_and_format() {
+  let lines = parse_timed_lines("[ar:Artist]\n[00:01.5]First\r\n[00:02:25][01:00]Chorus\nUntimed\n");
+  let expected = [(1500, "First"), (2250, "Chorus"), (60_000, "Chorus")]
+    .map(|(time_ms, text)| TimedLine { time_ms, text: text.to_string() });
+  assert_eq!(lines, expected);
+  assert_eq!(format_timed_lines(&lines), "[00:01.50]First\n[00:02.25]Chorus\n[01:00.00]Chorus\n");
+
+  // Times past what a u32 of milliseconds holds are invalid rather than wrapped
+  assert_eq!(parse_timestamp("71582:47.29"), Some(u32::MAX - 5));
+  assert_eq!(parse_timestamp("71582:47.30"), None);
+  assert_eq!(parse_timestamp("99999:00.00"), None);
+  let issues = validate("[00:01.00]First\n[99999:00.00]Hello\n");
+  assert_eq!(issues, [Issue { line: 2, message: "invalid timestamp [99999:00.00]".to_string() }]);
+}
+
+#[test]
+fn test_last_timestamp() {
+  // Out-of-order lines and repeated choruses still end at the latest time
+  assert_eq!(last_timestamp("[ar:Artist]\n[00:10.00][03:05.50]Chorus\n[01:00.00]Verse\n"), Some(185_500));
+  assert_eq!(last_timestamp("[ti:Title]\nUntimed\n"), None);
+}
+
+#[test]
+fn test_to_plain_text() {
+  let lyrics = "[ti:Title]\n[length: 03:00]\n[00:01.00]First\n\n[00:02.00][00:03.00]Second\nUntimed [note]\n";
+  assert_eq!(to_plain_text(lyrics), "First\n\nSecond\nUntimed [note]\n");
+  assert_eq!(to_plain_text("[00:01.00]<00:01.00>Word <00:01.50>by word<00:02.00>\n"), "Word by word\n");
+}
+
+#[test]
+fn test_normalize() {
+  assert_eq!(
+    normalize("\u{feff}[00:01.00]First\r\n[00:02.00]Second\r[00:03.00]Third\n"),
+    "[00:01.00]First\n[00:02.00]Second\n[00:03.00]Third\n"
+  );
+  assert_eq!(normalize("[ti:Title]\n[00:01.00]Already fine\n"), "[ti:Title]\n[00:01.00]Already fine\n");
+}
+
+#[test]
+fn test_apply_offset() {
+  // Shown 500ms sooner, and never before the start
+  let lyrics = "[ar:Artist]\n[offset:+500]\n[00:00.20]Intro\n[00:02.00][01:00.50]Chorus\nUntimed\n";
+  assert_eq!(apply_offset(lyrics), "[ar:Artist]\n[00:00.00]Intro\n[00:01.50][01:00.00]Chorus\nUntimed\n");
+  assert_eq!(apply_offset("[Offset: -250]\n[00:01.00]Later\n"), "[00:01.25]Later\n");
+  for unchanged in ["[00:01.00]No offset\n", "[offset:soon]\n[00:01.00]Unparsable\n"] {
+    assert_eq!(apply_offset(unchanged), unchanged);
+  }
+}
+
+#[test]
+fn test_shift() {
+  let lyrics = "[ar:Artist]\n[00:00.20]Intro\n[00:02.00][01:00.50]Chorus\nUntimed\n";
+  assert_eq!(shift(lyrics, 1500), "[ar:Artist]\n[00:01.70]Intro\n[00:03.50][01:02.00]Chorus\nUntimed\n");
+  assert_eq!(shift(lyrics, -500), "[ar:Artist]\n[00:00.00]Intro\n[00:01.50][01:00.00]Chorus\nUntimed\n");
+  assert_eq!(shift(lyrics, 0), lyrics);
+}
+
+#[test]
+fn test_strip_headers() {
+  let lyrics = "[ar:Artist]\n[TI:Title]\n[al:Album]\n[by:Someone]\n[00:01.00]First\n[ti:not a header] line\n";
+  assert_eq!(strip_headers(lyrics, &["ar", "ti", "by"]), "[al:Album]\n[00:01.00]First\n[ti:not a header] line\n");
+  assert_eq!(strip_headers(lyrics, &[] as &[&str]), lyrics);
+}
+
+#[test]
+fn test_expand() {
+  let lyrics = "[ar:Artist]\n[00:12.00][01:45.00]Chorus\n\n[00:30.00]Verse\n[01:45.00]Also at 1:45\n";
+  assert_eq!(
+    expand(lyrics),
+    "[ar:Artist]\n[00:12.00]Chorus\n[00:30.00]Verse\n[01:45.00]Chorus\n[01:45.00]Also at 1:45\n"
+  );
+  let uncondensed = "[00:02.00]Out of order\n\n[00:01.00]Left alone\n";
+  assert_eq!(expand(uncondensed), uncondensed);
+}
+
+#[test]
+fn test_sort_lines() {
+  let lyrics = "[ar:Artist]\n[00:03.00]Third\n[00:01.00]First\n[00:02.00]Second\n[00:03.00]Third\n[00:02.00]Again\n";
+  let (sorted, report) = sort_lines(lyrics);
+  assert_eq!(sorted, "[ar:Artist]\n[00:01.00]First\n[00:02.00]Second\n[00:02.00]Again\n[00:03.00]Third\n");
+  assert_eq!(report, SortReport { out_of_order: 2, duplicates: 1 });
+
+  let tidy = "[00:01.00]First\n\n[00:02.00]Second\n";
+  assert_eq!(sort_lines(tidy), (tidy.to_string(), SortReport::default()));
+}
+
+#[test]
+fn test_retain_lines() {
+  let lyrics = "[ar:Artist]\n[00:01.00]Drop the first\n[00:02.00][00:04.00]Second\n\nDrop plain text too\n";
+  let retained = retain_lines(lyrics, |text| !text.starts_with("Drop"));
+  assert_eq!(retained, "[ar:Artist]\n[00:02.00][00:04.00]Second\n\n");
+}
+
+#[test]
+fn test_rescale() {
+  // Synced to a master 2% slower, with a second more of lead-in
+  let lyrics = "[ar:Artist]\n[00:00.00]Intro\n[00:10.00]Verse\n[01:40.00]Chorus\n[02:00.00]Outro\n";
+  assert_eq!(
+    rescale(lyrics, [(10_000, 10_200), (100_000, 102_000)]).unwrap(),
+    "[ar:Artist]\n[00:00.00]Intro\n[00:10.20]Verse\n[01:42.00]Chorus\n[02:02.40]Outro\n"
+  );
+  assert_eq!(rescale(lyrics, [(10_000, 11_000), (100_000, 101_000)]), Some(shift(lyrics, 1000)));
+  assert_eq!(rescale(lyrics, [(10_000, 11_000), (10_000, 12_000)]), None);
+
+  assert_eq!(parse_timestamp("01:02.5"), Some(62_500));
+  assert_eq!(parse_timestamp("1:2"), None);
+}
+
+#[test]
+fn test_from_srt() {
+  let srt = "1\n00:00:01,500 --> 00:00:03,000\n<i>First</i> line\nwrapped\n\n\
+             2\n00:00:04,250 --> 00:00:05,000\n{\\an8}Second\n\n\
+             3\n01:02:03.4 --> 01:02:05.000\nAn hour in\n\n\
+             4\n00:00:09,000 --> 00:00:10,000\n\n\
+             5\nnot a time\nDropped\n";
+  assert_eq!(from_srt(srt), "[00:01.50]First line wrapped\n[00:04.25]Second\n[62:03.40]An hour in\n");
+}
+
+#[test]
+fn test_from_vtt() {
+  let vtt = "WEBVTT - Song\nKind: captions\n\nNOTE timed by hand\n\nSTYLE\n::cue { color: yellow }\n\n\
+             intro\n00:01.000 --> 00:03.000 align:start position:10%\n<v Singer>Tom &amp; <c.loud>Jerry</c></v>\n\n\
+             00:00:04.500 --> 00:00:06.000\n<00:04.500>Word <00:05.000>by word &#x2665;&lrm;\n";
+  assert_eq!(from_vtt(vtt), "[00:01.00]Tom & Jerry\n[00:04.50]Word by word \u{2665}\n");
+
+  // Hours past what the times can hold make the cue malformed
+  let vtt = "WEBVTT\n\n9999999:00:00.000 --> 9999999:00:01.000\nToo late\n\n00:01.000 --> 00:02.000\nKept\n";
+  assert_eq!(from_vtt(vtt), "[00:01.00]Kept\n");
+}
+
+#[test]
+fn test_from_ass() {
+  let ass = "[Script Info]\nTitle: Song\n\n[Events]\n\
+             Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+             Dialogue: 0,0:00:05.00,0:00:07.00,Default,,0,0,0,,{\\i1}Plain, with a comma{\\i0}\\Nand a break\n\
+             Comment: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,Left out\n\
+             Dialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,karaoke,{\\k50}Hel{\\kf30}lo {\\k20}{\\k100}world\n";
+  assert_eq!(
+    from_ass(ass),
+    "[00:01.00]<00:01.00>Hel<00:01.50>lo <00:02.00>world<00:03.00>\n[00:05.00]Plain, with a comma and a break\n"
+  );
+
+  // Once a template is applied, the lines it was applied to are the commented-out ones
+  let templated = "[Events]\nComment: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,karaoke,{\\k100}Sung\n\
+                   Dialogue: 1,0:00:01.00,0:00:02.00,Default,,0,0,0,fx,{\\pos(10,10)}S\n";
+  assert_eq!(from_ass(templated), "[00:01.00]<00:01.00>Sung<00:02.00>\n");
+
+  let overlong = "[Events]\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{\\k999999999}Hi\n";
+  assert_eq!(from_ass(overlong), "[00:01.00]<00:01.00>Hi<71582:47.29>\n", "The end should stop at the latest time");
+}
+
+#[test]
+fn test_from_ttml() {
+  let ttml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata" itunes:timing="Word">
+  <head><metadata><ttm:title>Song</ttm:title></metadata></head>
+  <body><div begin="1.000" end="9.000">
+    <!-- a comment -->
+    <p begin="00:01.000" end="00:02.500"><span begin="00:01.000" end="00:01.400">Hello</span>
+      <span begin="00:01.400" end="00:02.500">world</span><span ttm:role="x-translation">Hola mundo</span></p>
+    <p begin="3s" end="4.5s">Rock &amp; roll<br/>all night</p>
+    <p>Untimed</p>
+  </div></body>
+</tt>"#;
+  assert_eq!(
+    from_ttml(ttml),
+    "[00:01.00]<00:01.00>Hello <00:01.40>world<00:02.50>\n[00:03.00]Rock & roll all night\nUntimed\n"
+  );
+}
+
+#[test]
+fn test_word_timing() {
+  let lyrics = "[ar:Artist]\n[00:01.00]<00:01.00>Hello <00:01.40>world<00:02.50>\n[00:03.00]Whole line\n";
+  assert!(has_word_timing(lyrics));
+  assert!(!has_word_timing("[00:01.00]A <b>bold</b> line\n"));
+  assert_eq!(strip_word_timing(lyrics), "[ar:Artist]\n[00:01.00]Hello world\n[00:03.00]Whole line\n");
+  assert_eq!(
+    strip_word_timing("[00:01.00]Verse\n\n[00:05.00]<00:05.00>Second <00:05.50>verse\n"),
+    "[00:01.00]Verse\n\n[00:05.00]Second verse\n",
+    "Blank lines between verses should be kept"
+  );
+  assert_eq!(
+    shift(lyrics, 500),
+    "[ar:Artist]\n[00:01.50]<00:01.50>Hello <00:01.90>world<00:03.00>\n[00:03.50]Whole line\n"
+  );
+
+  let words = parse_timed_words(lyrics);
+  let expected = [(1000, "\nHello "), (1400, "world"), (3000, "\nWhole line")]
+    .map(|(time_ms, text)| TimedLine { time_ms, text: text.to_string() });
+  assert_eq!(words, expected);
+  let joined = parse_timed_lines("[00:01.00]<00:01.00>Hello <00:01.40>world\n[00:03.00]Whole line\n");
+  assert_eq!(join_timed_words(words), joined, "The words should be put back into their lines");
+  let lines = parse_timed_lines("[00:01.00]First\n[00:02.00]Second\n");
+  assert_eq!(join_timed_words(lines.clone()), lines);
+}
+
+#[test]
+fn test_merge_translation() {
+  let lyrics = "[ti:Song]\n[00:01.00]Hello\n[00:03.00]\n[00:05.00]World\n";
+  let translation = "[ti:歌]\n[00:01.00]你好\n[00:02.00]插曲\n[00:05.00]<00:05.00>世界<00:06.00>\n[00:07.00]\n";
+  assert_eq!(
+    merge_translation(lyrics, translation),
+    "[ti:Song]\n[00:01.00]Hello\n[00:01.00]你好\n[00:02.00]插曲\n[00:03.00]\n[00:05.00]World\n[00:05.00]世界\n"
+  );
+  assert_eq!(merge_translation(lyrics, ""), lyrics);
+}
+
+#[test]
+fn test_map_text() {
+  let lyrics = "[ti:Song]\n[00:01.00][00:05.00]<00:01.00>Hello <00:01.50>world\n\nUntimed\n[00:07.00]\n";
+  assert_eq!(
+    map_text(lyrics, str::to_uppercase),
+    "[ti:Song]\n[00:01.00][00:05.00]<00:01.00>HELLO <00:01.50>WORLD\n\nUNTIMED\n[00:07.00]\n"
+  );
+}
+
+#[test]
+fn test_validate() {
+  assert!(validate("[ar:Artist]\n\n[00:01.00]First\n[00:02.00][00:00.50]Second\n").is_empty());
+
+  let issues = validate("[00:05.00]First\n[00:04.00]Second\n[00:61.00]Third\n[01:00.00 Fourth\n");
+  let issue = |line, message: &str| Issue { line, message: message.to_string() };
+  assert_eq!(
+    issues,
+    [
+      issue(2, "timestamp [00:04.00] is earlier than the line before it"),
+      issue(3, "invalid timestamp [00:61.00]"),
+      issue(4, "unterminated timestamp [01:00.00 Fourth"),
+    ]
+  );
+
+  let issues = validate("[ar:Artist]\n[ti:Title]\n[00:01.00]First\n[AR:Another artist]\n");
+  assert_eq!(issues, [issue(4, "duplicate [ar:] header tag, first given on line 1")]);
+
+  let issues =
+    validate("[00:01.00]<00:01.00>In <00:01.50>order\n[00:02.00]<00:01.90>Too <00:02.80>late <00:02.50>twice\n");
+  assert_eq!(
+    issues,
+    [
+      issue(2, "word timestamp <00:01.90> is earlier than the one before it"),
+      issue(2, "word timestamp <00:02.50> is earlier than the one before it"),
+    ]
+  );
+
+  assert_eq!(validate(" \n"), [issue(0, "the lyrics are empty")]);
+  assert_eq!(validate("Just text\n"), [issue(0, "no line has a timestamp")]);
+}
+
+#[test]
+fn test_decode() {
+  assert_eq!(decode("[00:01.00]Déjà vu\n".as_bytes()), ("[00:01.00]Déjà vu\n".to_string(), None));
+
+  for (lyrics, encoding) in [
+    ("[ti:月亮代表我的心]\n[00:12.00]你问我爱你有多深\n[00:18.00]我爱你有几分\n", encoding_rs::GBK),
+    ("[ti:月亮代表我的心]\n[00:12.00]你問我愛你有多深\n[00:18.00]我愛你有幾分\n", encoding_rs::BIG5),
+    ("[ti:さくら]\n[00:10.00]さくら さくら 弥生の空は\n[00:15.00]見渡す限り\n", encoding_rs::SHIFT_JIS),
+    (
+      "[ti:La Bohème]\n[00:05.00]Je vous parle d'un temps que les moins de vingt ans ne peuvent pas connaître\n",
+      encoding_rs::WINDOWS_1252,
+    ),
+  ] {
+    let (bytes, _, _) = encoding.encode(lyrics);
+    assert_eq!(decode(&bytes), (lyrics.to_string(), Some(encoding.name())), "{}", encoding.name());
+  }
+
+  let utf16: Vec<u8> =
+    [0xFF, 0xFE].into_iter().chain("[00:01.00]Hi\n".encode_utf16().flat_map(u16::to_le_bytes)).collect();
+  assert_eq!(decode(&utf16), ("[00:01.00]Hi\n".to_string(), Some("UTF-16LE")));
+}
@@ -0,0 +1,40 @@
+/*
+ * C interface to lyricsync, built into liblyricsync with `cargo build --release --features ffi`.
+ *
+ * Strings are NUL-terminated UTF-8. When a call fails, lyricsync_last_error() describes why until
+ * the next call on the same thread.
+ */
+
+#ifndef LYRICSYNC_H
+#define LYRICSYNC_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define LYRICSYNC_OK 0
+#define LYRICSYNC_ERROR -1
+
+/* Embeds LRC or plain-text lyrics into an audio file. Returns LYRICSYNC_OK or LYRICSYNC_ERROR. */
+int lyricsync_embed_file(const char *audio_path, const char *lyrics);
+
+/* Returns 1 if the file has embedded lyrics, 0 if not, or LYRICSYNC_ERROR. */
+int lyricsync_has_lyrics(const char *audio_path);
+
+/*
+ * Returns the embedded lyrics, to be released with lyricsync_free_string(). Returns NULL when the
+ * file has none (lyricsync_last_error() is then NULL too) or on failure.
+ */
+char *lyricsync_extract(const char *audio_path);
+
+/* Releases a string returned by lyricsync_extract(). NULL is ignored. */
+void lyricsync_free_string(char *value);
+
+/* The last failure on this thread, or NULL if the last call succeeded. Owned by the library. */
+const char *lyricsync_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* LYRICSYNC_H */
@@ -0,0 +1,305 @@
+//! Minimal ASF (WMA) header handling.
+//!
+//! lofty has no ASF support, so this reads and rewrites just enough of the header object to
+//! manage attributes stored in the Extended Content Description object. Everything after the
+//! header (data packets, indexes) is copied over untouched.
+
+use crate::lrc::TimedLine;
+use crate::{LrcError, Result};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+const HEADER_OBJECT: [u8; 16] =
+  [0x30, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11, 0xA6, 0xD9, 0x00, 0xAA, 0x00, 0x62, 0xCE, 0x6C];
+const CONTENT_DESCRIPTION_OBJECT: [u8; 16] =
+  [0x33, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11, 0xA6, 0xD9, 0x00, 0xAA, 0x00, 0x62, 0xCE, 0x6C];
+const EXTENDED_CONTENT_DESCRIPTION_OBJECT: [u8; 16] =
+  [0x40, 0xA4, 0xD0, 0xD2, 0x07, 0xE3, 0xD2, 0x11, 0x97, 0xF0, 0x00, 0xA0, 0xC9, 0x5E, 0xA8, 0x50];
+
+// GUID + object size
+const OBJECT_HEADER_LEN: u64 = 24;
+// Object header + child count + two reserved bytes
+const HEADER_OBJECT_LEN: u64 = OBJECT_HEADER_LEN + 6;
+
+const VALUE_TYPE_UNICODE: u16 = 0;
+const VALUE_TYPE_BYTES: u16 = 1;
+
+/// A single attribute from the Extended Content Description object.
+pub struct ContentDescriptor {
+  pub name: String,
+  pub value_type: u16,
+  pub value: Vec<u8>,
+}
+
+impl ContentDescriptor {
+  /// Creates a Unicode string attribute.
+  pub fn text(name: &str, value: &str) -> Self {
+    Self { name: name.to_string(), value_type: VALUE_TYPE_UNICODE, value: encode_utf16(value) }
+  }
+
+  /// Creates a byte array attribute.
+  pub fn bytes(name: &str, value: Vec<u8>) -> Self {
+    Self { name: name.to_string(), value_type: VALUE_TYPE_BYTES, value }
+  }
+
+  /// Returns the value of a Unicode string attribute.
+  pub fn as_text(&self) -> Option<String> {
+    (self.value_type == VALUE_TYPE_UNICODE).then(|| decode_utf16(&self.value))
+  }
+}
+
+struct AsfObject {
+  guid: [u8; 16],
+  data: Vec<u8>,
+}
+
+/// The parsed header of an ASF file.
+pub struct AsfFile {
+  objects: Vec<AsfObject>,
+  reserved: [u8; 2],
+  descriptors: Vec<ContentDescriptor>,
+  header_len: u64,
+}
+
+impl AsfFile {
+  pub fn read_from<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+    let start = reader.stream_position()?;
+    let (guid, header_len) = read_object_header(reader)?;
+    if guid != HEADER_OBJECT {
+      return Err(LrcError::InvalidAsf("missing header object".to_string()));
+    }
+    // Sizes come from the file, so a corrupt one mustn't have us allocate more than it holds
+    let stream_len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(start + OBJECT_HEADER_LEN))?;
+    if header_len > stream_len - start {
+      return Err(LrcError::InvalidAsf("header object is longer than the file".to_string()));
+    }
+
+    let mut counts = [0; 6];
+    reader.read_exact(&mut counts)?;
+    let object_count = u32::from_le_bytes([counts[0], counts[1], counts[2], counts[3]]);
+    let reserved = [counts[4], counts[5]];
+
+    let mut objects = Vec::new();
+    let mut descriptors = Vec::new();
+    let mut position = HEADER_OBJECT_LEN;
+
+    for _ in 0..object_count {
+      let (guid, size) = read_object_header(reader)?;
+      let end = position.checked_add(size).filter(|&end| size >= OBJECT_HEADER_LEN && end <= header_len);
+      let Some(end) = end else {
+        return Err(LrcError::InvalidAsf("header object size mismatch".to_string()));
+      };
+
+      let len = size - OBJECT_HEADER_LEN;
+      let mut data = Vec::new();
+      reader.by_ref().take(len).read_to_end(&mut data)?;
+      if data.len() as u64 != len {
+        return Err(LrcError::InvalidAsf("header object is cut short".to_string()));
+      }
+      position = end;
+
+      if guid == EXTENDED_CONTENT_DESCRIPTION_OBJECT {
+        descriptors = parse_descriptors(&data)?;
+      }
+      objects.push(AsfObject { guid, data });
+    }
+
+    Ok(Self { objects, reserved, descriptors, header_len })
+  }
+
+  /// Returns the attribute with the given name, compared case-insensitively.
+  pub fn attribute(&self, name: &str) -> Option<&ContentDescriptor> {
+    self.descriptors.iter().find(|descriptor| descriptor.name.eq_ignore_ascii_case(name))
+  }
+
+  /// Returns the title and author from the Content Description object, if present.
+  pub fn content_description(&self) -> Option<(String, String)> {
+    let object = self.objects.iter().find(|object| object.guid == CONTENT_DESCRIPTION_OBJECT)?;
+    // Five 16-bit lengths (title, author, copyright, description, rating) followed by the strings
+    let mut reader = object.data.as_slice();
+    let title_len = take_u16(&mut reader).ok()? as usize;
+    let author_len = take_u16(&mut reader).ok()? as usize;
+    take(&mut reader, 6).ok()?;
+    let title = decode_utf16(take(&mut reader, title_len).ok()?);
+    let author = decode_utf16(take(&mut reader, author_len).ok()?);
+    Some((title, author))
+  }
+
+  /// Inserts an attribute, replacing any existing attribute with the same name.
+  pub fn set_attribute(&mut self, attribute: ContentDescriptor) {
+    self.descriptors.retain(|descriptor| !descriptor.name.eq_ignore_ascii_case(&attribute.name));
+    self.descriptors.push(attribute);
+  }
+
+  /// Removes the attribute with the given name, returning whether it was present.
+  pub fn remove_attribute(&mut self, name: &str) -> bool {
+    let len = self.descriptors.len();
+    self.descriptors.retain(|descriptor| !descriptor.name.eq_ignore_ascii_case(name));
+    self.descriptors.len() != len
+  }
+
+  /// Rewrites the header of the file at `path`, keeping the rest of the file intact.
+  pub fn save_to_path(&self, path: &Path) -> Result<()> {
+    let mut file = fs::File::open(path)?;
+    file.seek(SeekFrom::Start(self.header_len))?;
+    let mut body = Vec::new();
+    file.read_to_end(&mut body)?;
+    drop(file);
+
+    let descriptor_data = encode_descriptors(&self.descriptors)?;
+    let mut children = Vec::new();
+    let mut object_count = 0u32;
+    let mut wrote_descriptors = false;
+
+    for object in &self.objects {
+      if object.guid == EXTENDED_CONTENT_DESCRIPTION_OBJECT {
+        if wrote_descriptors {
+          continue;
+        }
+        wrote_descriptors = true;
+        write_object(&mut children, &EXTENDED_CONTENT_DESCRIPTION_OBJECT, &descriptor_data);
+      } else {
+        write_object(&mut children, &object.guid, &object.data);
+      }
+      object_count += 1;
+    }
+
+    if !wrote_descriptors {
+      write_object(&mut children, &EXTENDED_CONTENT_DESCRIPTION_OBJECT, &descriptor_data);
+      object_count += 1;
+    }
+
+    let mut output = Vec::with_capacity(HEADER_OBJECT_LEN as usize + children.len() + body.len());
+    output.extend_from_slice(&HEADER_OBJECT);
+    output.extend_from_slice(&(HEADER_OBJECT_LEN + children.len() as u64).to_le_bytes());
+    output.extend_from_slice(&object_count.to_le_bytes());
+    output.extend_from_slice(&self.reserved);
+    output.extend_from_slice(&children);
+    output.extend_from_slice(&body);
+
+    fs::write(path, output)?;
+    Ok(())
+  }
+}
+
+/// Encodes timed lines as a `WM/Lyrics_Synchronised` value.
+///
+/// The layout mirrors ID3v2 SYLT: timestamp format (2 = milliseconds), content type (1 = lyrics),
+/// a NUL-terminated description, then the length-prefixed list of NUL-terminated lines, each
+/// followed by its timestamp.
+pub fn encode_synchronised_lyrics(lines: &[TimedLine]) -> Vec<u8> {
+  let mut lyrics = Vec::new();
+  for line in lines {
+    lyrics.extend(encode_utf16(&line.text));
+    lyrics.extend_from_slice(&line.time_ms.to_le_bytes());
+  }
+
+  let mut value = vec![2, 1];
+  value.extend(encode_utf16(""));
+  value.extend_from_slice(&(lyrics.len() as u32).to_le_bytes());
+  value.extend(lyrics);
+  value
+}
+
+/// Decodes a `WM/Lyrics_Synchronised` value laid out as in [`encode_synchronised_lyrics`].
+pub fn decode_synchronised_lyrics(value: &[u8]) -> Option<Vec<TimedLine>> {
+  let mut reader = value.get(2..)?;
+  take_utf16_string(&mut reader)?;
+  let lyrics_len = u32::from_le_bytes(reader.get(..4)?.try_into().ok()?) as usize;
+  let mut lyrics = reader.get(4..4 + lyrics_len)?;
+
+  let mut lines = Vec::new();
+  while !lyrics.is_empty() {
+    let text = take_utf16_string(&mut lyrics)?;
+    let time_ms = u32::from_le_bytes(lyrics.get(..4)?.try_into().ok()?);
+    lyrics = &lyrics[4..];
+    lines.push(TimedLine { time_ms, text });
+  }
+
+  Some(lines)
+}
+
+fn read_object_header<R: Read>(reader: &mut R) -> Result<([u8; 16], u64)> {
+  let mut guid = [0; 16];
+  let mut size = [0; 8];
+  reader.read_exact(&mut guid)?;
+  reader.read_exact(&mut size)?;
+  Ok((guid, u64::from_le_bytes(size)))
+}
+
+fn write_object(output: &mut Vec<u8>, guid: &[u8; 16], data: &[u8]) {
+  output.extend_from_slice(guid);
+  output.extend_from_slice(&(OBJECT_HEADER_LEN + data.len() as u64).to_le_bytes());
+  output.extend_from_slice(data);
+}
+
+fn parse_descriptors(data: &[u8]) -> Result<Vec<ContentDescriptor>> {
+  let mut reader = data;
+  let count = take_u16(&mut reader)?;
+  let mut descriptors = Vec::with_capacity(count as usize);
+
+  for _ in 0..count {
+    let name_len = take_u16(&mut reader)? as usize;
+    let name = decode_utf16(take(&mut reader, name_len)?);
+    let value_type = take_u16(&mut reader)?;
+    let value_len = take_u16(&mut reader)? as usize;
+    let value = take(&mut reader, value_len)?.to_vec();
+    descriptors.push(ContentDescriptor { name, value_type, value });
+  }
+
+  Ok(descriptors)
+}
+
+fn encode_descriptors(descriptors: &[ContentDescriptor]) -> Result<Vec<u8>> {
+  let mut data = Vec::new();
+  data.extend_from_slice(&(descriptors.len() as u16).to_le_bytes());
+
+  for descriptor in descriptors {
+    let name = encode_utf16(&descriptor.name);
+    // Attribute lengths are 16-bit in this object
+    let value_len = u16::try_from(descriptor.value.len())
+      .map_err(|_| LrcError::InvalidAsf(format!("attribute {} is too large", descriptor.name)))?;
+
+    data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    data.extend(name);
+    data.extend_from_slice(&descriptor.value_type.to_le_bytes());
+    data.extend_from_slice(&value_len.to_le_bytes());
+    data.extend_from_slice(&descriptor.value);
+  }
+
+  Ok(data)
+}
+
+fn take<'a>(reader: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+  if reader.len() < len {
+    return Err(LrcError::InvalidAsf("truncated content descriptor".to_string()));
+  }
+  let (value, rest) = reader.split_at(len);
+  *reader = rest;
+  Ok(value)
+}
+
+fn take_u16(reader: &mut &[u8]) -> Result<u16> {
+  let bytes = take(reader, 2)?;
+  Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+// ASF strings are NUL-terminated UTF-16LE
+fn encode_utf16(value: &str) -> Vec<u8> {
+  value.encode_utf16().chain(std::iter::once(0)).flat_map(u16::to_le_bytes).collect()
+}
+
+// Reads a NUL-terminated UTF-16LE string and advances past the terminator
+fn take_utf16_string(reader: &mut &[u8]) -> Option<String> {
+  let end = reader.chunks_exact(2).position(|unit| unit == [0, 0])? * 2;
+  let text = decode_utf16(&reader[..end]);
+  *reader = &reader[end + 2..];
+  Some(text)
+}
+
+fn decode_utf16(bytes: &[u8]) -> String {
+  let units: Vec<u16> = bytes.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect();
+  String::from_utf16_lossy(&units).trim_end_matches('\0').to_string()
+}
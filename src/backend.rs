@@ -0,0 +1,759 @@
+//! Per-format reading and writing of lyrics fields.
+//!
+//! Each [`AudioFormat`] has a [`LyricsBackend`] in one table, so supporting a new format means
+//! writing one backend and adding it there.
+
+use crate::asf::{self, AsfFile, ContentDescriptor};
+use crate::dsd::{DsdFile, DsdKind};
+use crate::{
+  AudioFormat, EmbedOptions, FrameFilter, ID3_LANGUAGE, Id3Frames, Lyrics, Mp4Lyrics, ROMANIZATION_DESCRIPTION,
+  ReplacePolicy, Romanization, TRANSLATION_DESCRIPTION,
+};
+use crate::{LrcError, Result, VorbisKeys, lrc};
+use lofty::{
+  aac::AacFile,
+  ape::{ApeFile, ApeItem, ApeTag},
+  config::{ParseOptions, WriteOptions},
+  file::AudioFile,
+  flac::FlacFile,
+  id3::v2::{
+    BinaryFrame, Frame, FrameId, Id3v2Tag, Id3v2Version, SyncTextContentType, SynchronizedTextFrame, TimestampFormat,
+    UnsynchronizedTextFrame,
+  },
+  iff::{aiff::AiffFile, wav::WavFile},
+  mp4::{Atom, AtomData, AtomIdent, Mp4File},
+  mpeg::MpegFile,
+  ogg::{OpusFile, VorbisComments},
+  tag::ItemValue,
+  wavpack::WavPackFile,
+};
+use std::borrow::Cow;
+use std::fs::{File, OpenOptions};
+use std::marker::PhantomData;
+use std::path::Path;
+
+// Vorbis comment keys players read lyrics from, in the order they're checked
+const VORBIS_LYRICS_KEYS: [&str; 3] = ["LYRICS", "UNSYNCEDLYRICS", "SYNCEDLYRICS"];
+
+// The standard `©lyr` atom, then the freeform atom Apple players read timed lyrics from
+const MP4_LYRICS_ATOMS: [AtomIdent<'static>; 2] = [
+  AtomIdent::Fourcc(*b"\xa9lyr"),
+  AtomIdent::Freeform { mean: Cow::Borrowed("com.apple.iTunes"), name: Cow::Borrowed("LYRICS") },
+];
+
+// A FLAC metadata block's length is 24 bits, and the Vorbis comment block has to fit in one
+const FLAC_BLOCK_LIMIT: usize = (1 << 24) - 1;
+
+// WMA attribute values have a 16-bit length
+const ASF_ATTRIBUTE_LIMIT: usize = u16::MAX as usize;
+
+/// Reads and writes the lyrics fields of one audio format.
+pub trait LyricsBackend: Sync {
+  /// Whether the file has lyrics in any of the fields lyricsync reads. Only ID3v2 frames are
+  /// labeled, so other backends ignore `filter`.
+  fn detect(&self, audio_path: &Path, filter: &FrameFilter<'_>) -> Result<bool>;
+
+  /// The embedded lyrics, rendering synchronized-only fields back into LRC text.
+  fn read(&self, audio_path: &Path) -> Result<Option<String>>;
+
+  /// Every lyrics field's value, for checking what was written. Formats that can hold more than
+  /// one version override the default, which is [`LyricsBackend::read`] alone.
+  fn read_all(&self, audio_path: &Path) -> Result<Vec<String>> {
+    Ok(self.read(audio_path)?.into_iter().collect())
+  }
+
+  /// The versions of `lyrics` that [`LyricsBackend::write`] stores, with any it makes of them like a
+  /// romanization; by default only the first.
+  fn written<'a>(&self, lyrics: &'a [Lyrics], _options: &EmbedOptions) -> Vec<Cow<'a, str>> {
+    vec![Cow::Borrowed(&lyrics[0].text)]
+  }
+
+  /// Writes `lyrics`, which is never empty. Formats with a single lyrics field take the first.
+  fn write(&self, audio_path: &Path, lyrics: &[Lyrics], options: &EmbedOptions) -> Result<()>;
+
+  /// Removes every lyrics field, returning whether there were any.
+  fn remove(&self, audio_path: &Path) -> Result<bool>;
+}
+
+static BACKENDS: [(AudioFormat, &dyn LyricsBackend); 12] = [
+  (AudioFormat::Flac, &Flac),
+  (AudioFormat::Mp3, &Id3v2Backend::<MpegFile>::NEW),
+  (AudioFormat::M4a, &M4a),
+  (AudioFormat::Opus, &Opus),
+  (AudioFormat::Wav, &Id3v2Backend::<WavFile>::NEW),
+  (AudioFormat::Aiff, &Id3v2Backend::<AiffFile>::NEW),
+  (AudioFormat::Ape, &ApeBackend::<ApeFile>::NEW),
+  (AudioFormat::WavPack, &ApeBackend::<WavPackFile>::NEW),
+  (AudioFormat::Wma, &Wma),
+  (AudioFormat::Dsf, &Dsd(DsdKind::Dsf)),
+  (AudioFormat::Dff, &Dsd(DsdKind::Dff)),
+  (AudioFormat::Aac, &Id3v2Backend::<AacFile>::NEW),
+];
+
+/// Returns the backend that handles `format`.
+pub fn backend(format: AudioFormat) -> &'static dyn LyricsBackend {
+  BACKENDS
+    .iter()
+    .find_map(|&(handled, backend)| (handled == format).then_some(backend))
+    .expect("every audio format has a backend")
+}
+
+fn open_read(audio_path: &Path) -> Result<File> {
+  Ok(OpenOptions::new().read(true).open(audio_path)?)
+}
+
+fn open_write(audio_path: &Path) -> Result<File> {
+  Ok(OpenOptions::new().read(true).write(true).open(audio_path)?)
+}
+
+struct Flac;
+
+impl LyricsBackend for Flac {
+  fn detect(&self, audio_path: &Path, _filter: &FrameFilter<'_>) -> Result<bool> {
+    let flac_file = FlacFile::read_from(&mut open_read(audio_path)?, ParseOptions::new())?;
+    Ok(flac_file.vorbis_comments().is_some_and(vorbis_has_lyrics))
+  }
+
+  fn read(&self, audio_path: &Path) -> Result<Option<String>> {
+    let flac_file = FlacFile::read_from(&mut open_read(audio_path)?, ParseOptions::new())?;
+    Ok(flac_file.vorbis_comments().and_then(vorbis_lyrics))
+  }
+
+  fn read_all(&self, audio_path: &Path) -> Result<Vec<String>> {
+    let flac_file = FlacFile::read_from(&mut open_read(audio_path)?, ParseOptions::new())?;
+    Ok(flac_file.vorbis_comments().map(all_vorbis_lyrics).unwrap_or_default())
+  }
+
+  fn write(&self, audio_path: &Path, lyrics: &[Lyrics], options: &EmbedOptions) -> Result<()> {
+    check_size(audio_path, lyrics[0].text.len(), FLAC_BLOCK_LIMIT)?;
+    let mut flac_file = FlacFile::read_from(&mut open_write(audio_path)?, ParseOptions::new())?;
+
+    let Some(vorbis_comments) = flac_file.vorbis_comments_mut() else {
+      return Err(LrcError::TagMissing { path: audio_path.to_path_buf() });
+    };
+    insert_vorbis_lyrics(vorbis_comments, &lyrics[0].text, options);
+    flac_file.save_to_path(audio_path, WriteOptions::default())?;
+
+    Ok(())
+  }
+
+  fn remove(&self, audio_path: &Path) -> Result<bool> {
+    let mut flac_file = FlacFile::read_from(&mut open_write(audio_path)?, ParseOptions::new())?;
+    let stripped = flac_file.vorbis_comments_mut().is_some_and(strip_vorbis_lyrics);
+    if stripped {
+      flac_file.save_to_path(audio_path, WriteOptions::default())?;
+    }
+    Ok(stripped)
+  }
+}
+
+struct Opus;
+
+impl LyricsBackend for Opus {
+  fn detect(&self, audio_path: &Path, _filter: &FrameFilter<'_>) -> Result<bool> {
+    let opus_file = OpusFile::read_from(&mut open_read(audio_path)?, ParseOptions::new())?;
+    Ok(vorbis_has_lyrics(opus_file.vorbis_comments()))
+  }
+
+  fn read(&self, audio_path: &Path) -> Result<Option<String>> {
+    let opus_file = OpusFile::read_from(&mut open_read(audio_path)?, ParseOptions::new())?;
+    Ok(vorbis_lyrics(opus_file.vorbis_comments()))
+  }
+
+  fn read_all(&self, audio_path: &Path) -> Result<Vec<String>> {
+    let opus_file = OpusFile::read_from(&mut open_read(audio_path)?, ParseOptions::new())?;
+    Ok(all_vorbis_lyrics(opus_file.vorbis_comments()))
+  }
+
+  fn write(&self, audio_path: &Path, lyrics: &[Lyrics], options: &EmbedOptions) -> Result<()> {
+    let mut opus_file = OpusFile::read_from(&mut open_write(audio_path)?, ParseOptions::new())?;
+
+    // OpusTags is mandatory in Opus streams, so the comment block is always present
+    insert_vorbis_lyrics(opus_file.vorbis_comments_mut(), &lyrics[0].text, options);
+    opus_file.save_to_path(audio_path, WriteOptions::default())?;
+
+    Ok(())
+  }
+
+  fn remove(&self, audio_path: &Path) -> Result<bool> {
+    let mut opus_file = OpusFile::read_from(&mut open_write(audio_path)?, ParseOptions::new())?;
+    let stripped = strip_vorbis_lyrics(opus_file.vorbis_comments_mut());
+    if stripped {
+      opus_file.save_to_path(audio_path, WriteOptions::default())?;
+    }
+    Ok(stripped)
+  }
+}
+
+fn vorbis_has_lyrics(vorbis_comments: &VorbisComments) -> bool {
+  VORBIS_LYRICS_KEYS.iter().any(|key| vorbis_comments.get(key).is_some())
+}
+
+fn vorbis_lyrics(vorbis_comments: &VorbisComments) -> Option<String> {
+  VORBIS_LYRICS_KEYS.iter().find_map(|key| vorbis_comments.get(key)).map(str::to_string)
+}
+
+fn all_vorbis_lyrics(vorbis_comments: &VorbisComments) -> Vec<String> {
+  VORBIS_LYRICS_KEYS.iter().flat_map(|key| vorbis_comments.get_all(key)).map(str::to_string).collect()
+}
+
+fn insert_vorbis_lyrics(vorbis_comments: &mut VorbisComments, lyrics: &str, options: &EmbedOptions) {
+  let synced = options.vorbis_synced_key && !lrc::parse_timed_lines(lyrics).is_empty();
+  let selected = [
+    ("LYRICS", options.vorbis_keys != VorbisKeys::UnsyncedLyrics),
+    ("UNSYNCEDLYRICS", options.vorbis_keys != VorbisKeys::Lyrics),
+    ("SYNCEDLYRICS", synced),
+  ];
+
+  for (key, write) in selected {
+    if write {
+      vorbis_comments.insert(key.to_string(), lyrics.to_string());
+    } else if options.replace != ReplacePolicy::Matching {
+      // Clear keys left over from a run with other settings, so readers don't see stale lyrics
+      vorbis_comments.remove(key).for_each(drop);
+    }
+  }
+}
+
+fn strip_vorbis_lyrics(vorbis_comments: &mut VorbisComments) -> bool {
+  let removed = VORBIS_LYRICS_KEYS.iter().map(|key| vorbis_comments.remove(key).count()).sum::<usize>();
+  removed > 0
+}
+
+// lofty's file types share no trait for their ID3v2 tag, so this gives them one
+trait Id3v2File: AudioFile {
+  fn id3v2(&self) -> Option<&Id3v2Tag>;
+  fn id3v2_mut(&mut self) -> Option<&mut Id3v2Tag>;
+  fn set_id3v2(&mut self, tag: Id3v2Tag);
+}
+
+macro_rules! impl_id3v2_file {
+  ($($file:ty),*) => {
+    $(impl Id3v2File for $file {
+      fn id3v2(&self) -> Option<&Id3v2Tag> {
+        <$file>::id3v2(self)
+      }
+
+      fn id3v2_mut(&mut self) -> Option<&mut Id3v2Tag> {
+        <$file>::id3v2_mut(self)
+      }
+
+      fn set_id3v2(&mut self, tag: Id3v2Tag) {
+        <$file>::set_id3v2(self, tag);
+      }
+    })*
+  };
+}
+
+impl_id3v2_file!(MpegFile, WavFile, AiffFile, AacFile);
+
+// MP3, WAV, AIFF and AAC files, which all keep their lyrics in an ID3v2 tag
+struct Id3v2Backend<F>(PhantomData<fn() -> F>);
+
+impl<F> Id3v2Backend<F> {
+  const NEW: Self = Id3v2Backend(PhantomData);
+}
+
+impl<F: Id3v2File> LyricsBackend for Id3v2Backend<F> {
+  fn detect(&self, audio_path: &Path, filter: &FrameFilter<'_>) -> Result<bool> {
+    let file = F::read_from(&mut open_read(audio_path)?, ParseOptions::new())?;
+    Ok(file.id3v2().is_some_and(|id3v2| id3v2_has_lyrics(id3v2, filter)))
+  }
+
+  fn read(&self, audio_path: &Path) -> Result<Option<String>> {
+    let file = F::read_from(&mut open_read(audio_path)?, ParseOptions::new())?;
+    Ok(file.id3v2().and_then(id3v2_lyrics))
+  }
+
+  fn read_all(&self, audio_path: &Path) -> Result<Vec<String>> {
+    let file = F::read_from(&mut open_read(audio_path)?, ParseOptions::new())?;
+    Ok(file.id3v2().map(all_id3v2_lyrics).unwrap_or_default())
+  }
+
+  fn written<'a>(&self, lyrics: &'a [Lyrics], options: &EmbedOptions) -> Vec<Cow<'a, str>> {
+    id3v2_written(lyrics, options)
+  }
+
+  fn write(&self, audio_path: &Path, lyrics: &[Lyrics], options: &EmbedOptions) -> Result<()> {
+    let mut file = F::read_from(&mut open_write(audio_path)?, ParseOptions::new())?;
+
+    // WAV files rarely ship with an ID3 chunk, and neither RIFF INFO nor AIFF text chunks can
+    // hold lyrics, so create the tag when there's none
+    if file.id3v2().is_none() {
+      file.set_id3v2(Id3v2Tag::default());
+    }
+
+    if let Some(id3v2) = file.id3v2_mut() {
+      insert_id3v2_lyrics(id3v2, lyrics, options)?;
+      file.save_to_path(audio_path, options.id3v2_write_options())?;
+    }
+
+    Ok(())
+  }
+
+  fn remove(&self, audio_path: &Path) -> Result<bool> {
+    let mut file = F::read_from(&mut open_write(audio_path)?, ParseOptions::new())?;
+    let write_options = file.id3v2().map(id3v2_resave_options);
+    let stripped = file.id3v2_mut().is_some_and(strip_id3v2_lyrics);
+    if let (true, Some(write_options)) = (stripped, write_options) {
+      file.save_to_path(audio_path, write_options)?;
+    }
+    Ok(stripped)
+  }
+}
+
+struct Dsd(DsdKind);
+
+impl LyricsBackend for Dsd {
+  fn detect(&self, audio_path: &Path, filter: &FrameFilter<'_>) -> Result<bool> {
+    let dsd_file = DsdFile::read_from(&mut open_read(audio_path)?, self.0)?;
+    Ok(dsd_file.id3v2().is_some_and(|id3v2| id3v2_has_lyrics(id3v2, filter)))
+  }
+
+  fn read(&self, audio_path: &Path) -> Result<Option<String>> {
+    let dsd_file = DsdFile::read_from(&mut open_read(audio_path)?, self.0)?;
+    Ok(dsd_file.id3v2().and_then(id3v2_lyrics))
+  }
+
+  fn read_all(&self, audio_path: &Path) -> Result<Vec<String>> {
+    let dsd_file = DsdFile::read_from(&mut open_read(audio_path)?, self.0)?;
+    Ok(dsd_file.id3v2().map(all_id3v2_lyrics).unwrap_or_default())
+  }
+
+  fn written<'a>(&self, lyrics: &'a [Lyrics], options: &EmbedOptions) -> Vec<Cow<'a, str>> {
+    id3v2_written(lyrics, options)
+  }
+
+  fn write(&self, audio_path: &Path, lyrics: &[Lyrics], options: &EmbedOptions) -> Result<()> {
+    let mut dsd_file = DsdFile::read_from(&mut open_read(audio_path)?, self.0)?;
+
+    if dsd_file.id3v2().is_none() {
+      dsd_file.set_id3v2(Id3v2Tag::default());
+    }
+
+    if let Some(id3v2) = dsd_file.id3v2_mut() {
+      insert_id3v2_lyrics(id3v2, lyrics, options)?;
+      dsd_file.save_to_path(audio_path, options.id3v2_write_options())?;
+    }
+
+    Ok(())
+  }
+
+  fn remove(&self, audio_path: &Path) -> Result<bool> {
+    let mut dsd_file = DsdFile::read_from(&mut open_read(audio_path)?, self.0)?;
+    let write_options = dsd_file.id3v2().map(id3v2_resave_options);
+    let stripped = dsd_file.id3v2_mut().is_some_and(strip_id3v2_lyrics);
+    if let (true, Some(write_options)) = (stripped, write_options) {
+      dsd_file.save_to_path(audio_path, write_options)?;
+    }
+    Ok(stripped)
+  }
+}
+
+fn check_size(audio_path: &Path, size: usize, limit: usize) -> Result<()> {
+  if size > limit {
+    return Err(LrcError::LyricsTooLarge { path: audio_path.to_path_buf(), size, limit });
+  }
+  Ok(())
+}
+
+fn id3v2_has_lyrics(id3v2: &Id3v2Tag, filter: &FrameFilter<'_>) -> bool {
+  // Check for USLT (unsynchronized lyrics) or SYLT (synchronized lyrics) frames
+  let languages: Vec<[u8; 3]> = id3v2
+    .into_iter()
+    .filter_map(lyrics_frame_label)
+    // Translations and romanizations are written whatever the description, so they count whatever
+    // the one wanted
+    .filter(|(_, description)| {
+      let extra = EXTRA_DESCRIPTIONS.contains(&description.as_str());
+      filter.description.is_none_or(|wanted| extra || description == wanted)
+    })
+    .map(|(language, _)| language)
+    .collect();
+
+  !languages.is_empty() && filter.languages.iter().all(|wanted| languages.contains(wanted))
+}
+
+// Returns the language and description of USLT and SYLT frames, and `None` for any other frame
+fn lyrics_frame_label(frame: &Frame<'_>) -> Option<([u8; 3], String)> {
+  match frame {
+    Frame::UnsynchronizedText(uslt) => Some((uslt.language, uslt.description.clone())),
+    Frame::Binary(binary_frame) if binary_frame.id().as_str() == "SYLT" => {
+      // SYLT frames aren't parsed by lofty; one we can't decode is treated as unlabeled
+      let sylt_frame = SynchronizedTextFrame::parse(&binary_frame.data, binary_frame.flags()).ok();
+      let language = sylt_frame.as_ref().map_or(ID3_LANGUAGE, |sylt| sylt.language);
+      Some((language, sylt_frame.and_then(|sylt| sylt.description).unwrap_or_default()))
+    },
+    _ => None,
+  }
+}
+
+fn id3v2_lyrics(id3v2: &Id3v2Tag) -> Option<String> {
+  // The original, rather than a translation or romanization of it
+  let mut uslt_frames = id3v2.unsync_text();
+  let uslt_frame =
+    uslt_frames.clone().find(|uslt| !EXTRA_DESCRIPTIONS.contains(&uslt.description.as_str())).or(uslt_frames.next());
+  if let Some(uslt_frame) = uslt_frame {
+    return Some(uslt_frame.content.clone());
+  }
+
+  // SYLT frames aren't parsed by lofty, so decode the raw frame ourselves
+  let sylt_frame_id = FrameId::new("SYLT").unwrap();
+  let Some(Frame::Binary(binary_frame)) = id3v2.get(&sylt_frame_id) else {
+    return None;
+  };
+  let sylt_frame = SynchronizedTextFrame::parse(&binary_frame.data, binary_frame.flags()).ok()?;
+  let lines: Vec<_> = sylt_frame.content.into_iter().map(|(time_ms, text)| lrc::TimedLine { time_ms, text }).collect();
+  Some(lrc::format_timed_lines(&lrc::join_timed_words(lines)))
+}
+
+// Every USLT frame, then every SYLT frame rendered back into LRC
+fn all_id3v2_lyrics(id3v2: &Id3v2Tag) -> Vec<String> {
+  let sylt_frame_id = FrameId::new("SYLT").unwrap();
+  let sylt = id3v2.into_iter().filter_map(|frame| match frame {
+    Frame::Binary(binary_frame) if binary_frame.id() == &sylt_frame_id => {
+      let sylt_frame = SynchronizedTextFrame::parse(&binary_frame.data, binary_frame.flags()).ok()?;
+      let lines: Vec<_> =
+        sylt_frame.content.into_iter().map(|(time_ms, text)| lrc::TimedLine { time_ms, text }).collect();
+      Some(lrc::format_timed_lines(&lrc::join_timed_words(lines)))
+    },
+    _ => None,
+  });
+  id3v2.unsync_text().map(|uslt_frame| uslt_frame.content.clone()).chain(sylt).collect()
+}
+
+fn insert_id3v2_lyrics(id3v2: &mut Id3v2Tag, lyrics: &[Lyrics], options: &EmbedOptions) -> Result<()> {
+  // Replace the frames a previous run may have written, whatever language they were labeled with, so
+  // readers don't see stale lyrics. lofty only matches USLT frames of the same language on insert, and
+  // SYLT frames not at all.
+  let description = options.id3v2_description();
+  let (writes_uslt, writes_sylt) = lyrics
+    .iter()
+    .map(|variant| id3v2_frame_types(&variant.text, options))
+    .fold((false, false), |(any_uslt, any_sylt), (uslt, sylt)| (any_uslt || uslt, any_sylt || sylt));
+  id3v2.retain(|frame| {
+    let Some((_, found)) = lyrics_frame_label(frame) else {
+      return true;
+    };
+    let own = found == description || EXTRA_DESCRIPTIONS.contains(&found.as_str());
+    match options.replace {
+      ReplacePolicy::All => false,
+      ReplacePolicy::Own => !own,
+      ReplacePolicy::Matching => {
+        let written = if matches!(frame, Frame::UnsynchronizedText(_)) { writes_uslt } else { writes_sylt };
+        !own || !written
+      },
+    }
+  });
+
+  let variants = id3v2_variants(lyrics, options);
+  for variant in &variants {
+    let description = if variant.translation { TRANSLATION_DESCRIPTION } else { description };
+    insert_id3v2_frames(id3v2, variant.text, variant.language, description, options)?;
+  }
+  if let Some((romanized, language)) = id3v2_romanization(&variants, options) {
+    insert_id3v2_frames(id3v2, &romanized, language, ROMANIZATION_DESCRIPTION, options)?;
+  }
+
+  Ok(())
+}
+
+// The descriptions of the frames written next to the original's, whatever its description is
+const EXTRA_DESCRIPTIONS: [&str; 2] = [TRANSLATION_DESCRIPTION, ROMANIZATION_DESCRIPTION];
+
+// The romanized original, with its language, when the options ask for frames of it
+fn id3v2_romanization(variants: &[Id3v2Variant<'_>], options: &EmbedOptions) -> Option<(String, [u8; 3])> {
+  if options.romanization != Some(Romanization::Frames) {
+    return None;
+  }
+  let original = variants.iter().find(|variant| !variant.translation)?;
+  Some((crate::romanize::romanize(original.text)?, original.language))
+}
+
+// The text of every version that gets frames, and of the romanization that does
+fn id3v2_written<'a>(lyrics: &'a [Lyrics], options: &EmbedOptions) -> Vec<Cow<'a, str>> {
+  let variants = id3v2_variants(lyrics, options);
+  let romanized = id3v2_romanization(&variants, options).map(|(romanized, _)| Cow::Owned(romanized));
+  variants.into_iter().map(|variant| Cow::Borrowed(variant.text)).chain(romanized).collect()
+}
+
+// A version that gets frames of its own
+struct Id3v2Variant<'a> {
+  text: &'a str,
+  language: [u8; 3],
+  translation: bool,
+}
+
+// The versions that get frames, with the language they're labeled with. Next to a version without
+// a language, the ones labeled with another are its translations.
+fn id3v2_variants<'a>(lyrics: &'a [Lyrics], options: &EmbedOptions) -> Vec<Id3v2Variant<'a>> {
+  let labeled: Vec<[u8; 3]> = lyrics.iter().filter_map(|variant| variant.language).collect();
+  let original =
+    lyrics.iter().find(|variant| variant.language.is_none()).map(|variant| options.id3v2_language(&variant.text));
+  lyrics
+    .iter()
+    .map(|variant| (variant, variant.language.unwrap_or_else(|| options.id3v2_language(&variant.text))))
+    // A sidecar named for the language takes precedence over the plain one
+    .filter(|(variant, language)| variant.language.is_some() || !labeled.contains(language))
+    .map(|(variant, language)| Id3v2Variant {
+      text: &variant.text,
+      language,
+      translation: variant.language.is_some() && original.is_some_and(|original| original != language),
+    })
+    .collect()
+}
+
+// Returns whether USLT and SYLT frames are written for these lyrics
+fn id3v2_frame_types(lyrics: &str, options: &EmbedOptions) -> (bool, bool) {
+  let timed = !lrc::parse_timed_lines(lyrics).is_empty();
+  // Without timestamps there's nothing to put in a SYLT frame, so USLT is always written
+  (options.id3_frames != Id3Frames::Sylt || !timed, options.id3_frames != Id3Frames::Uslt && timed)
+}
+
+fn insert_id3v2_frames(
+  id3v2: &mut Id3v2Tag,
+  lyrics: &str,
+  language: [u8; 3],
+  description: &str,
+  options: &EmbedOptions,
+) -> Result<()> {
+  let (write_uslt, write_sylt) = id3v2_frame_types(lyrics, options);
+  let encoding = options.id3v2_text_encoding(lyrics);
+
+  if write_uslt {
+    let uslt_frame = UnsynchronizedTextFrame::new(encoding, language, description.to_string(), lyrics.to_string());
+    id3v2.insert(Frame::UnsynchronizedText(uslt_frame));
+  }
+
+  if write_sylt {
+    // Word timestamps make an entry of each word, which players that understand them highlight
+    let timed_lines =
+      if lrc::has_word_timing(lyrics) { lrc::parse_timed_words(lyrics) } else { lrc::parse_timed_lines(lyrics) };
+    let sylt_frame = SynchronizedTextFrame::new(
+      encoding,
+      language,
+      TimestampFormat::MS,
+      SyncTextContentType::Lyrics,
+      // Always Some, as lofty terminates a missing description with a single byte even in UTF-16
+      Some(description.to_string()),
+      timed_lines.into_iter().map(|line| (line.time_ms, line.text)).collect(),
+    );
+    let sylt_frame_id = FrameId::new("SYLT").unwrap();
+    id3v2.insert(Frame::Binary(BinaryFrame::new(sylt_frame_id, sylt_frame.as_bytes()?)));
+  }
+
+  Ok(())
+}
+
+// Keeps an ID3v2.3 tag at 2.3 when it's saved again, for the players that can't read 2.4
+fn id3v2_resave_options(id3v2: &Id3v2Tag) -> WriteOptions {
+  WriteOptions::default().use_id3v23(id3v2.original_version() == Id3v2Version::V3)
+}
+
+fn strip_id3v2_lyrics(id3v2: &mut Id3v2Tag) -> bool {
+  let removed = ["USLT", "SYLT"].into_iter().map(|id| id3v2.remove(&FrameId::new(id).unwrap()).count()).sum::<usize>();
+  removed > 0
+}
+
+struct M4a;
+
+impl LyricsBackend for M4a {
+  fn detect(&self, audio_path: &Path, _filter: &FrameFilter<'_>) -> Result<bool> {
+    let mp4_file = Mp4File::read_from(&mut open_read(audio_path)?, ParseOptions::new())?;
+    Ok(mp4_file.ilst().is_some_and(|ilst| MP4_LYRICS_ATOMS.iter().any(|ident| ilst.get(ident).is_some())))
+  }
+
+  fn read(&self, audio_path: &Path) -> Result<Option<String>> {
+    let mp4_file = Mp4File::read_from(&mut open_read(audio_path)?, ParseOptions::new())?;
+    let lyrics = mp4_file.ilst().and_then(|ilst| {
+      MP4_LYRICS_ATOMS.iter().filter_map(|ident| ilst.get(ident)).find_map(|atom| match atom.data().next() {
+        Some(AtomData::UTF8(text) | AtomData::UTF16(text)) => Some(text.clone()),
+        _ => None,
+      })
+    });
+    Ok(lyrics)
+  }
+
+  fn read_all(&self, audio_path: &Path) -> Result<Vec<String>> {
+    let mp4_file = Mp4File::read_from(&mut open_read(audio_path)?, ParseOptions::new())?;
+    let lyrics = mp4_file.ilst().map(|ilst| {
+      MP4_LYRICS_ATOMS
+        .iter()
+        .filter_map(|ident| ilst.get(ident))
+        .flat_map(|atom| atom.data())
+        .filter_map(|data| match data {
+          AtomData::UTF8(text) | AtomData::UTF16(text) => Some(text.clone()),
+          _ => None,
+        })
+        .collect()
+    });
+    Ok(lyrics.unwrap_or_default())
+  }
+
+  fn write(&self, audio_path: &Path, lyrics: &[Lyrics], options: &EmbedOptions) -> Result<()> {
+    let lyrics = &lyrics[0].text;
+    let mut mp4_file = Mp4File::read_from(&mut open_write(audio_path)?, ParseOptions::new())?;
+
+    let Some(ilst) = mp4_file.ilst_mut() else {
+      return Err(LrcError::TagMissing { path: audio_path.to_path_buf() });
+    };
+
+    // Without timestamps there's nothing timed to write, so `©lyr` is always used
+    let timed = !lrc::parse_timed_lines(lyrics).is_empty();
+    let write_plain = options.mp4_lyrics != Mp4Lyrics::Timed || !timed;
+    let write_timed = options.mp4_lyrics != Mp4Lyrics::Plain && timed;
+
+    for (ident, write) in MP4_LYRICS_ATOMS.into_iter().zip([write_plain, write_timed]) {
+      if write {
+        ilst.insert(Atom::new(ident, AtomData::UTF8(lyrics.to_string())));
+      } else if options.replace != ReplacePolicy::Matching {
+        ilst.remove(&ident).for_each(drop);
+      }
+    }
+
+    mp4_file.save_to_path(audio_path, WriteOptions::default())?;
+
+    Ok(())
+  }
+
+  fn remove(&self, audio_path: &Path) -> Result<bool> {
+    let mut mp4_file = Mp4File::read_from(&mut open_write(audio_path)?, ParseOptions::new())?;
+    let stripped = mp4_file
+      .ilst_mut()
+      .is_some_and(|ilst| MP4_LYRICS_ATOMS.iter().map(|ident| ilst.remove(ident).count()).sum::<usize>() > 0);
+    if stripped {
+      mp4_file.save_to_path(audio_path, WriteOptions::default())?;
+    }
+    Ok(stripped)
+  }
+}
+
+// Same as `Id3v2File`, for the formats with an APEv2 tag
+trait ApeTagFile: AudioFile {
+  fn ape(&self) -> Option<&ApeTag>;
+  fn ape_mut(&mut self) -> Option<&mut ApeTag>;
+  fn set_ape(&mut self, tag: ApeTag);
+}
+
+macro_rules! impl_ape_tag_file {
+  ($($file:ty),*) => {
+    $(impl ApeTagFile for $file {
+      fn ape(&self) -> Option<&ApeTag> {
+        <$file>::ape(self)
+      }
+
+      fn ape_mut(&mut self) -> Option<&mut ApeTag> {
+        <$file>::ape_mut(self)
+      }
+
+      fn set_ape(&mut self, tag: ApeTag) {
+        <$file>::set_ape(self, tag);
+      }
+    })*
+  };
+}
+
+impl_ape_tag_file!(ApeFile, WavPackFile);
+
+// Monkey's Audio and WavPack files, which keep their lyrics in an APEv2 `Lyrics` item
+struct ApeBackend<F>(PhantomData<fn() -> F>);
+
+impl<F> ApeBackend<F> {
+  const NEW: Self = ApeBackend(PhantomData);
+}
+
+impl<F: ApeTagFile> LyricsBackend for ApeBackend<F> {
+  fn detect(&self, audio_path: &Path, _filter: &FrameFilter<'_>) -> Result<bool> {
+    let file = F::read_from(&mut open_read(audio_path)?, ParseOptions::new())?;
+    Ok(file.ape().is_some_and(|ape| ape.get("Lyrics").is_some()))
+  }
+
+  fn read(&self, audio_path: &Path) -> Result<Option<String>> {
+    let file = F::read_from(&mut open_read(audio_path)?, ParseOptions::new())?;
+    let lyrics = file.ape().and_then(|ape| match ape.get("Lyrics")?.value() {
+      ItemValue::Text(text) => Some(text.clone()),
+      _ => None,
+    });
+    Ok(lyrics)
+  }
+
+  fn write(&self, audio_path: &Path, lyrics: &[Lyrics], _options: &EmbedOptions) -> Result<()> {
+    let mut file = F::read_from(&mut open_write(audio_path)?, ParseOptions::new())?;
+
+    if file.ape().is_none() {
+      file.set_ape(ApeTag::default());
+    }
+
+    if let Some(ape) = file.ape_mut() {
+      ape.insert(ApeItem::new("Lyrics".to_string(), ItemValue::Text(lyrics[0].text.clone()))?);
+      file.save_to_path(audio_path, WriteOptions::default())?;
+    }
+
+    Ok(())
+  }
+
+  fn remove(&self, audio_path: &Path) -> Result<bool> {
+    let mut file = F::read_from(&mut open_write(audio_path)?, ParseOptions::new())?;
+    let stripped = file.ape_mut().is_some_and(|ape| {
+      let present = ape.get("Lyrics").is_some();
+      ape.remove("Lyrics");
+      present
+    });
+    if stripped {
+      file.save_to_path(audio_path, WriteOptions::default())?;
+    }
+    Ok(stripped)
+  }
+}
+
+struct Wma;
+
+impl LyricsBackend for Wma {
+  fn detect(&self, audio_path: &Path, _filter: &FrameFilter<'_>) -> Result<bool> {
+    let asf_file = AsfFile::read_from(&mut open_read(audio_path)?)?;
+    Ok(asf_file.attribute("WM/Lyrics").is_some() || asf_file.attribute("WM/Lyrics_Synchronised").is_some())
+  }
+
+  fn read(&self, audio_path: &Path) -> Result<Option<String>> {
+    let asf_file = AsfFile::read_from(&mut open_read(audio_path)?)?;
+    let lyrics = asf_file.attribute("WM/Lyrics").and_then(ContentDescriptor::as_text).or_else(|| {
+      let synchronised = asf_file.attribute("WM/Lyrics_Synchronised")?;
+      asf::decode_synchronised_lyrics(&synchronised.value).map(|lines| lrc::format_timed_lines(&lines))
+    });
+    Ok(lyrics)
+  }
+
+  fn write(&self, audio_path: &Path, lyrics: &[Lyrics], _options: &EmbedOptions) -> Result<()> {
+    let lyrics = &lyrics[0].text;
+    let mut asf_file = AsfFile::read_from(&mut open_read(audio_path)?)?;
+
+    let plain = ContentDescriptor::text("WM/Lyrics", lyrics);
+    check_size(audio_path, plain.value.len(), ASF_ATTRIBUTE_LIMIT)?;
+    asf_file.set_attribute(plain);
+
+    // Windows Media Player only scrolls lyrics from the synchronised attribute, a line at a time
+    let timed_lines = lrc::parse_timed_lines(&lrc::strip_word_timing(lyrics));
+    if !timed_lines.is_empty() {
+      let synchronised = asf::encode_synchronised_lyrics(&timed_lines);
+      check_size(audio_path, synchronised.len(), ASF_ATTRIBUTE_LIMIT)?;
+      asf_file.set_attribute(ContentDescriptor::bytes("WM/Lyrics_Synchronised", synchronised));
+    } else {
+      // Synced lyrics from an earlier run would be shown in place of the new ones
+      asf_file.remove_attribute("WM/Lyrics_Synchronised");
+    }
+
+    asf_file.save_to_path(audio_path)?;
+
+    Ok(())
+  }
+
+  fn remove(&self, audio_path: &Path) -> Result<bool> {
+    let mut asf_file = AsfFile::read_from(&mut open_read(audio_path)?)?;
+    // Non-short-circuiting so both attributes are removed
+    let stripped = asf_file.remove_attribute("WM/Lyrics") | asf_file.remove_attribute("WM/Lyrics_Synchronised");
+    if stripped {
+      asf_file.save_to_path(audio_path)?;
+    }
+    Ok(stripped)
+  }
+}
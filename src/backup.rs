@@ -0,0 +1,122 @@
+//! Copies of audio files taken before lyricsync modifies them.
+//!
+//! Each file is copied to the same path relative to the library root under the backup directory,
+//! once: a file that already has a backup keeps it, so the backup is always the file as it was
+//! before lyricsync first touched it. An optional size budget and maximum age keep the directory
+//! from growing forever, pruning the oldest backups first.
+
+use crate::{LrcError, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+pub struct Backups {
+  dir: PathBuf,
+  root: PathBuf,
+  max_size: Option<u64>,
+  max_age: Option<Duration>,
+  // Backups taken by this run, which pruning never removes. The lock also keeps concurrent workers
+  // from counting the same free space twice.
+  taken: Mutex<HashSet<PathBuf>>,
+}
+
+impl Backups {
+  /// Backs up files under `root` into `dir`.
+  pub fn new(dir: impl Into<PathBuf>, root: impl Into<PathBuf>) -> Self {
+    Backups { dir: dir.into(), root: root.into(), max_size: None, max_age: None, taken: Mutex::default() }
+  }
+
+  /// Keep the backup directory under `bytes`, removing the oldest backups to make room.
+  pub fn max_size(mut self, bytes: u64) -> Self {
+    self.max_size = Some(bytes);
+    self
+  }
+
+  /// Remove backups older than `age` when pruning.
+  pub fn max_age(mut self, age: Duration) -> Self {
+    self.max_age = Some(age);
+    self
+  }
+
+  /// Where the backup of `path` goes. Files outside the root are kept by name alone.
+  pub fn backup_path(&self, path: &Path) -> PathBuf {
+    match path.strip_prefix(&self.root) {
+      Ok(relative) => self.dir.join(relative),
+      Err(_) => self.dir.join(path.file_name().unwrap_or_default()),
+    }
+  }
+
+  /// Copies `path` into the backup directory, unless it already has a backup there.
+  pub fn save(&self, path: &Path) -> Result<()> {
+    let mut taken = self.taken.lock().unwrap_or_else(PoisonError::into_inner);
+    let backup_path = self.backup_path(path);
+    if backup_path.exists() {
+      return Ok(());
+    }
+
+    let failed = |source| LrcError::BackupFailed { path: path.to_path_buf(), source };
+    let size = fs::metadata(path).map_err(failed)?.len();
+    if let Some(limit) = self.max_size {
+      let room = limit.saturating_sub(size);
+      if size > limit || self.prune_to(room, &taken)? > room {
+        return Err(LrcError::BackupFull { path: path.to_path_buf(), size, limit });
+      }
+    }
+
+    if let Some(parent) = backup_path.parent() {
+      fs::create_dir_all(parent).map_err(failed)?;
+    }
+    fs::copy(path, &backup_path).map_err(failed)?;
+    tracing::debug!(backup = %backup_path.display(), "backed up");
+    taken.insert(backup_path);
+    Ok(())
+  }
+
+  /// Removes backups past the maximum age, then the oldest ones until the directory fits the size
+  /// budget. Returns how many were removed.
+  pub fn prune(&self) -> Result<usize> {
+    let taken = self.taken.lock().unwrap_or_else(PoisonError::into_inner);
+    let before = self.backups().len();
+    self.prune_to(self.max_size.unwrap_or(u64::MAX), &taken)?;
+    Ok(before - self.backups().len())
+  }
+
+  // Prunes down to `budget` bytes, returning the size left, which stays above the budget when this
+  // run's own backups don't fit in it
+  fn prune_to(&self, budget: u64, taken: &HashSet<PathBuf>) -> Result<u64> {
+    let mut backups = self.backups();
+    // Oldest first; copies are dated by when they were taken
+    backups.sort_by_key(|backup| backup.1);
+    let mut total: u64 = backups.iter().map(|backup| backup.2).sum();
+    let now = SystemTime::now();
+
+    for (path, modified, size) in backups {
+      if taken.contains(&path) {
+        continue;
+      }
+      let expired = self.max_age.is_some_and(|age| now.duration_since(modified).is_ok_and(|elapsed| elapsed > age));
+      if !expired && total <= budget {
+        continue;
+      }
+      fs::remove_file(&path).map_err(|error| LrcError::from(error).in_file(&path))?;
+      tracing::debug!(backup = %path.display(), "pruned backup");
+      total -= size;
+    }
+    Ok(total)
+  }
+
+  fn backups(&self) -> Vec<(PathBuf, SystemTime, u64)> {
+    WalkDir::new(&self.dir)
+      .into_iter()
+      .filter_map(|entry| entry.ok())
+      .filter(|entry| entry.file_type().is_file())
+      .filter_map(|entry| {
+        let metadata = entry.metadata().ok()?;
+        Some((entry.into_path(), metadata.modified().ok()?, metadata.len()))
+      })
+      .collect()
+  }
+}
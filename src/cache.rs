@@ -0,0 +1,81 @@
+//! On-disk cache of fetch results.
+//!
+//! Each lookup is stored as a small JSON file named after a hash of the track and the provider
+//! chain that answered it. Misses are cached too (with their own, usually shorter, TTL) so known
+//! gaps in a library aren't queried again on every run.
+
+use crate::Result;
+use crate::provider::FetchedLyrics;
+use crate::track::TrackInfo;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+  key: String,
+  fetched_at: u64,
+  lyrics: Option<FetchedLyrics>,
+}
+
+pub struct FetchCache {
+  dir: PathBuf,
+  ttl: Duration,
+  negative_ttl: Duration,
+}
+
+impl FetchCache {
+  pub fn new(dir: PathBuf, ttl: Duration, negative_ttl: Duration) -> Self {
+    Self { dir, ttl, negative_ttl }
+  }
+
+  /// The per-user cache directory, if the platform has one.
+  pub fn default_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("lyricsync").join("fetch"))
+  }
+
+  /// Returns the cached result for a lookup: `Some(None)` is a cached miss, and `None` means the
+  /// lookup isn't cached or has expired.
+  pub fn get(&self, track: &TrackInfo, chain: &str) -> Option<Option<FetchedLyrics>> {
+    let key = cache_key(track, chain);
+    let data = fs::read(self.entry_path(&key)).ok()?;
+    let entry: CacheEntry = serde_json::from_slice(&data).ok()?;
+    // Guard against hash collisions
+    if entry.key != key {
+      return None;
+    }
+
+    let ttl = if entry.lyrics.is_some() { self.ttl } else { self.negative_ttl };
+    let age = now_secs().saturating_sub(entry.fetched_at);
+    (age < ttl.as_secs()).then_some(entry.lyrics)
+  }
+
+  pub fn put(&self, track: &TrackInfo, chain: &str, lyrics: Option<&FetchedLyrics>) -> Result<()> {
+    let key = cache_key(track, chain);
+    let path = self.entry_path(&key);
+    let entry = CacheEntry { key, fetched_at: now_secs(), lyrics: lyrics.cloned() };
+
+    fs::create_dir_all(&self.dir)?;
+    fs::write(path, serde_json::to_vec(&entry).expect("cache entries always serialize"))?;
+    Ok(())
+  }
+
+  fn entry_path(&self, key: &str) -> PathBuf {
+    self.dir.join(format!("{:016x}.json", fnv1a(key.as_bytes())))
+  }
+}
+
+fn cache_key(track: &TrackInfo, chain: &str) -> String {
+  let duration = track.duration_secs.map(|secs| secs.to_string()).unwrap_or_default();
+  format!("{}\n{}\n{}\n{}", chain, track.artist.to_lowercase(), track.title.to_lowercase(), duration)
+}
+
+// A stable hash, so cache file names survive toolchain upgrades (unlike `DefaultHasher`)
+fn fnv1a(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+fn now_secs() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default()
+}
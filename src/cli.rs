@@ -0,0 +1,892 @@
+use crate::{
+  mpd, parse_anchor, parse_check_report, parse_confidence, parse_cron, parse_embed_report, parse_extension_alias,
+  parse_glob, parse_language, parse_name_rule, parse_pattern, parse_size,
+};
+use clap::builder::BoolishValueParser;
+use clap::{Arg, ArgAction, Command, ValueHint};
+use lyricsync::{genius, lrclib, musixmatch, netease, transcribe};
+use std::path::PathBuf;
+
+const PROVIDERS: [&str; 4] = ["lrclib", "musixmatch", "netease", "genius"];
+
+pub fn build_cli() -> Command {
+  Command::new("lyricsync")
+    .version(env!("CARGO_PKG_VERSION"))
+    .author("Daniel")
+    .about("Embed LRC lyrics into audio files (FLAC, MP3, M4A, Opus, WAV, AIFF, APE, WavPack, WMA, DSD)")
+    // `lyricsync -d DIR` keeps working as a shorthand for `lyricsync embed -d DIR`
+    .args(embed_args())
+    .args_conflicts_with_subcommands(true)
+    .subcommand_negates_reqs(true)
+    .arg(
+      Arg::new("generate-completion")
+        .long("generate-completion")
+        .value_name("SHELL")
+        .value_parser(["bash", "zsh", "fish", "powershell", "elvish"])
+        .exclusive(true)
+        .help("Generate shell completion script"),
+    )
+    .arg(
+      Arg::new("config")
+        .long("config")
+        .value_name("PATH")
+        .value_parser(clap::value_parser!(PathBuf))
+        .value_hint(ValueHint::FilePath)
+        .global(true)
+        .help("Read option defaults from PATH instead of config.toml in the user's config directory"),
+    )
+    .arg(
+      Arg::new("profile")
+        .long("profile")
+        .value_name("NAME")
+        .global(true)
+        .help("Also apply the options of the [profile.NAME] table of the config file"),
+    )
+    .args(logging_args())
+    .arg(
+      Arg::new("fail-on")
+        .long("fail-on")
+        .value_name("POLICY")
+        .value_parser(["none", "errors", "skipped"])
+        .default_value("errors")
+        .global(true)
+        .help("Exit non-zero when files fail (errors), also when files are skipped (skipped), or never (none)"),
+    )
+    .subcommand(Command::new("embed").about("Embed LRC sidecar files into audio files (default)").args(embed_args()))
+    .subcommand(
+      Command::new("extract")
+        .about("Write embedded lyrics back to .lrc (or .txt for unsynced lyrics) sidecar files")
+        .args(extract_args()),
+    )
+    .subcommand(Command::new("strip").about("Remove embedded lyrics from audio files").args(strip_args()))
+    .subcommand(
+      Command::new("check")
+        .about("Report embedded and sidecar lyrics coverage without modifying anything")
+        .args(check_args()),
+    )
+    .subcommand(
+      Command::new("validate")
+        .about("Check LRC files for malformed timestamps, lines out of order and other problems")
+        .args(validate_args()),
+    )
+    .subcommand(
+      Command::new("shift")
+        .about("Stretch or compress an LRC file's timestamps to fit two points of the track they're for")
+        .args(shift_args()),
+    )
+    .subcommand(
+      Command::new("fetch")
+        .about("Download synced lyrics for files that have none")
+        .args(fetch_args()),
+    )
+    .subcommand(
+      Command::new("undo")
+        .about("Revert the changes recorded in a run's journal (the latest one by default)")
+        .args(undo_args()),
+    )
+    .subcommand(
+      Command::new("watch")
+        .about("Embed LRC files as they're added to a directory, until stopped")
+        .args(watch_args()),
+    )
+    .subcommand(
+      Command::new("daemon")
+        .about("Watch a directory and go through all of it on a schedule, until stopped")
+        .args(daemon_args()),
+    )
+    .subcommand(
+      Command::new("now-playing")
+        .about("Print the lyrics of the track MPD is playing, each line as it's sung")
+        .args(now_playing_args()),
+    )
+    .subcommand(
+      Command::new("play")
+        .about("Play an audio file and show its synced lyrics, the line being sung highlighted")
+        .args(play_args()),
+    )
+    .subcommand(
+      Command::new("tap")
+        .about("Time plain lyrics by pressing a key as each line starts, and save them as an LRC file")
+        .args(tap_args()),
+    )
+    .subcommand(
+      Command::new("serve")
+        .about("Answer HTTP requests for the lyrics of a directory's files, until stopped")
+        .args(serve_args()),
+    )
+    .mut_args(with_env)
+    .mut_subcommands(|subcommand| subcommand.mut_args(with_env))
+}
+
+// Every option can also be set as LYRICSYNC_ and its name in the environment, e.g. LYRICSYNC_JOBS,
+// for containers and cron jobs
+fn with_env(arg: Arg) -> Arg {
+  if arg.get_id() == "generate-completion" {
+    return arg;
+  }
+  let name = format!("LYRICSYNC_{}", arg.get_id().as_str().to_uppercase().replace('-', "_"));
+  match arg.get_action() {
+    // Flags take 1, yes or on as well, the way shell scripts tend to set them
+    ArgAction::SetTrue => arg.env(name).value_parser(BoolishValueParser::new()),
+    _ => arg.env(name),
+  }
+}
+
+fn logging_args() -> Vec<Arg> {
+  vec![
+    Arg::new("log-level")
+      .long("log-level")
+      .value_name("LEVEL")
+      .value_parser(["off", "error", "warn", "info", "debug", "trace"])
+      .default_value("warn")
+      .global(true)
+      .help("Most detailed log messages to print to stderr; info and above replace the progress bar"),
+    Arg::new("log-format")
+      .long("log-format")
+      .value_name("FORMAT")
+      .value_parser(["text", "json"])
+      .default_value("text")
+      .global(true)
+      .help("Print log messages as text or as JSON objects, one per line"),
+    Arg::new("log-file")
+      .long("log-file")
+      .value_name("PATH")
+      .value_parser(clap::value_parser!(PathBuf))
+      .value_hint(ValueHint::FilePath)
+      .global(true)
+      .help("Also append timestamped log messages, at info level or above, to PATH"),
+    Arg::new("quiet")
+      .short('q')
+      .long("quiet")
+      .action(ArgAction::SetTrue)
+      .conflicts_with("verbose")
+      .global(true)
+      .help("Print only the summary: no banner, progress bar, per-file lines or warnings"),
+    Arg::new("verbose")
+      .short('v')
+      .long("verbose")
+      .action(ArgAction::Count)
+      .conflicts_with("log-level")
+      .global(true)
+      .help("Log what's decided for each file; -vv also logs how sidecars were matched"),
+    Arg::new("color")
+      .long("color")
+      .value_name("WHEN")
+      .value_parser(["auto", "always", "never"])
+      .default_value("auto")
+      .global(true)
+      .help("Color the progress bar and log messages; auto colors a terminal unless NO_COLOR is set"),
+  ]
+}
+
+fn embed_args() -> Vec<Arg> {
+  let mut args = vec![directory_arg()];
+  args.extend(policy_args());
+  args.extend([
+    recursive_arg(),
+    resume_arg(),
+    retry_arg(),
+    failed_list_arg(),
+    dry_run_arg(),
+    Arg::new("jobs")
+      .short('j')
+      .long("jobs")
+      .value_name("N")
+      .value_parser(clap::value_parser!(usize))
+      .default_value("1")
+      .help("Number of files to embed at once; 0 uses one per CPU core"),
+    Arg::new("progress")
+      .long("progress")
+      .value_name("MODE")
+      .value_parser(["bar", "jsonl"])
+      .default_value("bar")
+      .help("Show progress as bars, or as one JSON event per line on stdout for wrappers"),
+    Arg::new("report")
+      .long("report")
+      .value_name("FORMAT[=PATH]")
+      .value_parser(parse_embed_report)
+      .help("Write a json or csv report of every file to PATH, or to stdout in place of the summary"),
+    Arg::new("review")
+      .long("review")
+      .help("Approve, reject or change each match on a full-screen list before anything is written")
+      .conflicts_with("progress")
+      .action(ArgAction::SetTrue),
+    Arg::new("interactive")
+      .short('i')
+      .long("interactive")
+      .help("Ask before embedding an LRC file that's only a loose match, as well as before overwriting")
+      .conflicts_with_all(["progress", "review"])
+      .action(ArgAction::SetTrue),
+  ]);
+  args.extend(tag_args());
+  args.extend(backup_args());
+  args.extend(filter_args());
+  args.extend(format_args());
+  args.extend(matching_args());
+  args
+}
+
+// What's done about files that already have lyrics, and with LRC files once they're embedded
+fn policy_args() -> [Arg; 7] {
+  [
+    Arg::new("skip")
+      .short('s')
+      .long("skip")
+      .help("Skip files that already have embedded lyrics")
+      .action(ArgAction::SetTrue),
+    Arg::new("force")
+      .short('f')
+      .long("force")
+      .help("Overwrite existing embedded lyrics without asking")
+      .conflicts_with("skip")
+      .action(ArgAction::SetTrue),
+    Arg::new("if-newer")
+      .long("if-newer")
+      .help("Overwrite existing embedded lyrics only when the LRC file is newer than the audio file")
+      .conflicts_with_all(["skip", "force"])
+      .action(ArgAction::SetTrue),
+    Arg::new("rewrite")
+      .long("rewrite")
+      .help("Rewrite files even when their embedded lyrics already match the LRC file")
+      .action(ArgAction::SetTrue),
+    Arg::new("reduce")
+      .short('r')
+      .long("reduce")
+      .help("Delete LRC files after successful embedding")
+      .action(ArgAction::SetTrue),
+    Arg::new("normalize-sidecars")
+      .long("normalize-sidecars")
+      .help("Write embedded LRC files back as UTF-8 without a byte order mark or CRLF line endings")
+      .conflicts_with("reduce")
+      .action(ArgAction::SetTrue),
+    Arg::new("reject-invalid")
+      .long("reject-invalid")
+      .help("Fail files whose LRC file doesn't pass `lyricsync validate`, instead of embedding it")
+      .action(ArgAction::SetTrue),
+  ]
+}
+
+fn watch_args() -> Vec<Arg> {
+  let mut args = vec![directory_arg()];
+  args.extend(policy_args());
+  args.extend([
+    recursive_arg(),
+    dry_run_arg(),
+    Arg::new("debounce")
+      .long("debounce")
+      .value_name("MS")
+      .value_parser(clap::value_parser!(u64))
+      .default_value("2000")
+      .help("Wait until a new file has gone MS milliseconds without changing before embedding it"),
+  ]);
+  args.extend(tag_args());
+  args.extend(backup_args());
+  args.extend(filter_args());
+  args.extend(format_args());
+  args.extend(matching_args());
+  args
+}
+
+fn daemon_args() -> Vec<Arg> {
+  let mut args = watch_args();
+  args.extend([
+    Arg::new("interval")
+      .long("interval")
+      .value_name("MINUTES")
+      .value_parser(clap::value_parser!(u64).range(1..))
+      .default_value("1440")
+      .help("Scan the whole directory every MINUTES minutes"),
+    Arg::new("cron")
+      .long("cron")
+      .value_name("EXPRESSION")
+      .value_parser(parse_cron)
+      .conflicts_with("interval")
+      .help("Scan the whole directory at the times a cron expression matches, like \"0 3 * * *\""),
+    Arg::new("socket")
+      .long("socket")
+      .value_name("PATH")
+      .value_parser(clap::value_parser!(PathBuf))
+      .value_hint(ValueHint::FilePath)
+      .help("Take status, rescan, pause and resume commands on a Unix socket (a named pipe on Windows)"),
+  ]);
+  args
+}
+
+fn serve_args() -> Vec<Arg> {
+  let mut args = vec![
+    directory_arg(),
+    recursive_arg(),
+    Arg::new("listen")
+      .long("listen")
+      .value_name("ADDRESS")
+      .default_value("127.0.0.1:8080")
+      .help("Listen for requests on ADDRESS, a host and port"),
+    Arg::new("lrclib-api")
+      .long("lrclib-api")
+      .help("Also answer LRCLIB's /api/get requests, for players that speak to LRCLIB")
+      .action(ArgAction::SetTrue),
+  ];
+  args.extend(filter_args());
+  args.extend(format_args());
+  args.extend(matching_args());
+  args
+}
+
+fn now_playing_args() -> Vec<Arg> {
+  let mut args = vec![
+    directory_arg().help("MPD's music directory, which the paths MPD gives are relative to"),
+    Arg::new("mpd-host")
+      .long("mpd-host")
+      .value_name("HOST")
+      .default_value("localhost")
+      .help("Host MPD listens on, with PASSWORD@ in front if it needs one [default: MPD_HOST]"),
+    Arg::new("mpd-port")
+      .long("mpd-port")
+      .value_name("PORT")
+      .value_parser(clap::value_parser!(u16))
+      .default_value(mpd::DEFAULT_PORT.to_string())
+      .help("Port MPD listens on [default: MPD_PORT]"),
+    Arg::new("once")
+      .long("once")
+      .help("Print the line being sung, or plain lyrics whole, and exit")
+      .action(ArgAction::SetTrue),
+  ];
+  args.extend(format_args());
+  args.extend(matching_args());
+  args
+}
+
+fn play_args() -> Vec<Arg> {
+  let mut args = vec![
+    Arg::new("file")
+      .value_name("FILE")
+      .value_parser(clap::value_parser!(PathBuf))
+      .value_hint(ValueHint::FilePath)
+      .required(true)
+      .help("Audio file to play"),
+    Arg::new("silent")
+      .long("silent")
+      .help("Don't play the sound, only show the lyrics on the clock as if it were playing")
+      .action(ArgAction::SetTrue),
+  ];
+  args.extend(format_args());
+  args.extend(matching_args());
+  args
+}
+
+fn tap_args() -> Vec<Arg> {
+  let mut args = vec![
+    Arg::new("file")
+      .value_name("FILE")
+      .value_parser(clap::value_parser!(PathBuf))
+      .value_hint(ValueHint::FilePath)
+      .required(true)
+      .help("Audio file to play while timing its lyrics"),
+    Arg::new("lyrics")
+      .long("lyrics")
+      .value_name("PATH")
+      .value_parser(clap::value_parser!(PathBuf))
+      .value_hint(ValueHint::FilePath)
+      .help("Text file with the lyrics to time [default: the file's own, or its .txt]"),
+    Arg::new("output")
+      .short('o')
+      .long("output")
+      .value_name("PATH")
+      .value_parser(clap::value_parser!(PathBuf))
+      .value_hint(ValueHint::FilePath)
+      .help("Where to save the LRC file [default: next to the audio file, named like it]"),
+    Arg::new("overwrite").long("overwrite").help("Replace the LRC file if there's one").action(ArgAction::SetTrue),
+    Arg::new("silent")
+      .long("silent")
+      .help("Don't play the sound, only run the clock as if it were playing")
+      .action(ArgAction::SetTrue),
+  ];
+  args.extend(format_args());
+  args.extend(matching_args());
+  args
+}
+
+fn extract_args() -> Vec<Arg> {
+  let mut args = vec![
+    directory_arg(),
+    Arg::new("overwrite").long("overwrite").help("Replace existing sidecar files").action(ArgAction::SetTrue),
+    recursive_arg(),
+    dry_run_arg(),
+  ];
+  args.extend(filter_args());
+  args.extend(format_args());
+  args
+}
+
+fn strip_args() -> Vec<Arg> {
+  let mut args = vec![directory_arg(), recursive_arg(), resume_arg(), retry_arg(), failed_list_arg(), dry_run_arg()];
+  args.extend(backup_args());
+  args.extend(filter_args());
+  args.extend(format_args());
+  args
+}
+
+fn check_args() -> Vec<Arg> {
+  let mut args = vec![
+    directory_arg(),
+    recursive_arg(),
+    Arg::new("report")
+      .long("report")
+      .value_name("html[=PATH]")
+      .value_parser(parse_check_report)
+      .help("Write an HTML coverage page to PATH, or to stdout in place of the listing"),
+  ];
+  args.extend(filter_args());
+  args.extend(format_args());
+  args
+}
+
+fn validate_args() -> Vec<Arg> {
+  let mut args = vec![directory_arg(), recursive_arg()];
+  args.extend(filter_args());
+  args
+}
+
+fn shift_args() -> [Arg; 4] {
+  [
+    Arg::new("file")
+      .value_name("FILE")
+      .value_parser(clap::value_parser!(PathBuf))
+      .value_hint(ValueHint::FilePath)
+      .required(true)
+      .help("LRC file to retime"),
+    Arg::new("anchor")
+      .long("anchor")
+      .value_name("LRC=ACTUAL")
+      .value_parser(parse_anchor)
+      .action(ArgAction::Append)
+      .required(true)
+      .help("A time in the LRC file and the time it should be, like 00:10.50=00:11.20 (given twice)"),
+    Arg::new("output")
+      .short('o')
+      .long("output")
+      .value_name("PATH")
+      .value_parser(clap::value_parser!(PathBuf))
+      .value_hint(ValueHint::FilePath)
+      .help("Where to save the retimed LRC file [default: over FILE]"),
+    Arg::new("dry-run")
+      .long("dry-run")
+      .help("Print the retimed LRC file instead of saving it")
+      .action(ArgAction::SetTrue),
+  ]
+}
+
+fn fetch_args() -> Vec<Arg> {
+  let mut args = vec![
+    directory_arg(),
+    Arg::new("embed")
+      .long("embed")
+      .help("Embed fetched lyrics directly instead of writing .lrc files")
+      .action(ArgAction::SetTrue),
+    Arg::new("provider")
+      .long("provider")
+      .value_name("PROVIDERS")
+      .value_parser(PROVIDERS)
+      .value_delimiter(',')
+      .action(ArgAction::Append)
+      .default_value("lrclib")
+      .help("Comma-separated lyrics sources to query in order (e.g. lrclib,netease,genius)"),
+    Arg::new("disable-provider")
+      .long("disable-provider")
+      .value_name("PROVIDER")
+      .value_parser(PROVIDERS)
+      .action(ArgAction::Append)
+      .help("Remove a provider from the chain; may be repeated"),
+    Arg::new("synced-only").long("synced-only").help("Only accept synced lyrics").action(ArgAction::SetTrue),
+    Arg::new("min-lines")
+      .long("min-lines")
+      .value_name("N")
+      .value_parser(clap::value_parser!(usize))
+      .help("Reject lyrics with fewer than N non-empty lines"),
+    Arg::new("cache-dir")
+      .long("cache-dir")
+      .value_name("DIRECTORY")
+      .value_parser(clap::value_parser!(PathBuf))
+      .value_hint(ValueHint::DirPath)
+      .help("Directory for cached lookups (defaults to the user cache directory)"),
+    Arg::new("no-cache")
+      .long("no-cache")
+      .help("Always query providers, without reading or writing the cache")
+      .action(ArgAction::SetTrue),
+    Arg::new("cache-ttl")
+      .long("cache-ttl")
+      .value_name("DAYS")
+      .value_parser(clap::value_parser!(u64))
+      .default_value("30")
+      .help("How long found lyrics stay cached"),
+    Arg::new("negative-cache-ttl")
+      .long("negative-cache-ttl")
+      .value_name("DAYS")
+      .value_parser(clap::value_parser!(u64))
+      .default_value("7")
+      .help("How long \"not found\" results stay cached"),
+    Arg::new("lrclib-url")
+      .long("lrclib-url")
+      .value_name("URL")
+      .default_value(lrclib::DEFAULT_BASE_URL)
+      .value_hint(ValueHint::Url)
+      .help("Base URL of the LRCLIB instance to query"),
+    Arg::new("musixmatch-token")
+      .long("musixmatch-token")
+      .value_name("TOKEN")
+      .help("Musixmatch API key (required to use the musixmatch provider)"),
+    Arg::new("musixmatch-url")
+      .long("musixmatch-url")
+      .value_name("URL")
+      .default_value(musixmatch::DEFAULT_BASE_URL)
+      .value_hint(ValueHint::Url)
+      .hide(true)
+      .help("Base URL of the Musixmatch API"),
+    Arg::new("netease-url")
+      .long("netease-url")
+      .value_name("URL")
+      .default_value(netease::DEFAULT_BASE_URL)
+      .value_hint(ValueHint::Url)
+      .hide(true)
+      .help("Base URL of the NetEase Cloud Music API"),
+    Arg::new("save-translation")
+      .long("save-translation")
+      .help("Also save translated lyrics, when the provider has them, as <name>.<language>.lrc, like <name>.zh.lrc")
+      .action(ArgAction::SetTrue),
+    Arg::new("allow-unsynced-fetch")
+      .long("allow-unsynced-fetch")
+      .requires("genius-token")
+      .help("Fall back to plain lyrics from Genius when no synced lyrics are found (adds genius to the chain)")
+      .action(ArgAction::SetTrue),
+    Arg::new("genius-token")
+      .long("genius-token")
+      .value_name("TOKEN")
+      .help("Genius API access token (required to use the genius provider)"),
+    Arg::new("genius-api-url")
+      .long("genius-api-url")
+      .value_name("URL")
+      .default_value(genius::DEFAULT_API_URL)
+      .value_hint(ValueHint::Url)
+      .hide(true)
+      .help("Base URL of the Genius API"),
+    Arg::new("genius-web-url")
+      .long("genius-web-url")
+      .value_name("URL")
+      .default_value(genius::DEFAULT_WEB_URL)
+      .value_hint(ValueHint::Url)
+      .hide(true)
+      .help("Base URL of the Genius website"),
+    Arg::new("transcribe")
+      .long("transcribe")
+      .value_name("MODEL")
+      .value_parser(clap::value_parser!(PathBuf))
+      .value_hint(ValueHint::FilePath)
+      .help("Transcribe files no provider has lyrics for with this whisper.cpp model (needs the transcribe feature)"),
+    Arg::new("whisper-cli")
+      .long("whisper-cli")
+      .value_name("PROGRAM")
+      .default_value(transcribe::DEFAULT_PROGRAM)
+      .value_hint(ValueHint::CommandName)
+      .help("The whisper.cpp program --transcribe runs"),
+    recursive_arg(),
+    resume_arg(),
+    retry_arg(),
+    failed_list_arg(),
+    dry_run_arg(),
+  ];
+  args.extend(tag_args());
+  args.extend(backup_args());
+  args.extend(filter_args());
+  args.extend(format_args());
+  args
+}
+
+fn directory_arg() -> Arg {
+  Arg::new("directory")
+    .short('d')
+    .long("directory")
+    .value_name("DIRECTORY")
+    .help("Directory containing audio and LRC files")
+    .required(true)
+    .value_hint(ValueHint::DirPath)
+}
+
+fn recursive_arg() -> Arg {
+  Arg::new("recursive")
+    .short('R')
+    .long("recursive")
+    .help("Process subdirectories recursively")
+    .action(ArgAction::SetTrue)
+}
+
+fn filter_args() -> [Arg; 2] {
+  [
+    Arg::new("include")
+      .long("include")
+      .value_name("GLOB")
+      .value_parser(parse_glob)
+      .action(ArgAction::Append)
+      .help("Only process files whose path from the directory matches GLOB (can be repeated)"),
+    Arg::new("exclude")
+      .long("exclude")
+      .value_name("GLOB")
+      .value_parser(parse_glob)
+      .action(ArgAction::Append)
+      .help("Skip files and folders whose path from the directory matches GLOB (can be repeated)"),
+  ]
+}
+
+fn resume_arg() -> Arg {
+  Arg::new("resume")
+    .long("resume")
+    .help("Only process the files an interrupted run of the same command didn't get to")
+    .action(ArgAction::SetTrue)
+}
+
+fn retry_arg() -> Arg {
+  Arg::new("retry")
+    .long("retry")
+    .value_name("MANIFEST")
+    .value_parser(clap::value_parser!(PathBuf))
+    .value_hint(ValueHint::FilePath)
+    .conflicts_with("resume")
+    .help("Only process the files listed in a --failed-list manifest")
+}
+
+fn failed_list_arg() -> Arg {
+  Arg::new("failed-list")
+    .long("failed-list")
+    .value_name("PATH")
+    .value_parser(clap::value_parser!(PathBuf))
+    .num_args(0..=1)
+    .default_missing_value("lyricsync-failed.txt")
+    .value_hint(ValueHint::FilePath)
+    .help("Write the files that failed and why to PATH (lyricsync-failed.txt; JSON if it ends in .json)")
+}
+
+fn dry_run_arg() -> Arg {
+  Arg::new("dry-run")
+    .long("dry-run")
+    .help("Show what would be done without making any changes")
+    .action(ArgAction::SetTrue)
+}
+
+// Options for how lyrics are written, shared by every command that embeds
+fn tag_args() -> [Arg; 19] {
+  [
+    Arg::new("replace")
+      .long("replace")
+      .value_name("MODE")
+      .value_parser(["all", "own", "matching"])
+      .default_value("own")
+      .help("Existing lyrics to replace: all lyrics fields, the ones lyricsync writes, or only the ones written now"),
+    Arg::new("id3-frames")
+      .long("id3-frames")
+      .value_name("FRAMES")
+      .value_parser(["uslt", "sylt", "both"])
+      .default_value("both")
+      .help("ID3v2 frames for timestamped lyrics: unsynchronized (USLT), synchronized (SYLT), or both"),
+    Arg::new("id3-version")
+      .long("id3-version")
+      .value_name("VERSION")
+      .value_parser(["2.3", "2.4"])
+      .default_value("2.4")
+      .help("ID3v2 version to save MP3, WAV, AIFF, DSF and DFF tags as; use 2.3 for older devices"),
+    Arg::new("encoding")
+      .long("encoding")
+      .value_name("ENCODING")
+      .value_parser(["utf8", "utf16", "latin1"])
+      .help("Text encoding of ID3v2 lyrics frames [default: utf8 for ID3v2.4, utf16 for ID3v2.3]"),
+    Arg::new("language")
+      .long("language")
+      .value_name("CODE")
+      .value_parser(parse_language)
+      .default_value("auto")
+      .help("ISO 639-2 language code for ID3v2 lyrics frames, or auto to detect it (falling back to eng)"),
+    Arg::new("description")
+      .long("description")
+      .value_name("TEXT")
+      .help("Description of the ID3v2 lyrics frames to write; only frames with it are replaced or counted by --skip"),
+    Arg::new("vorbis-key")
+      .long("vorbis-key")
+      .value_name("KEY")
+      .value_parser(["lyrics", "unsyncedlyrics", "both"])
+      .default_value("lyrics")
+      .help("Vorbis comment key for lyrics in FLAC and Opus files: LYRICS, UNSYNCEDLYRICS, or both"),
+    Arg::new("synced-lyrics-key")
+      .long("synced-lyrics-key")
+      .help("Also write timestamped lyrics to a SYNCEDLYRICS Vorbis comment")
+      .action(ArgAction::SetTrue),
+    Arg::new("mp4-lyrics")
+      .long("mp4-lyrics")
+      .value_name("MODE")
+      .value_parser(["plain", "timed", "both"])
+      .default_value("plain")
+      .help("M4A atoms for timestamped lyrics: ©lyr (plain), ----:com.apple.iTunes:LYRICS (timed), or both"),
+    Arg::new("preserve-mtime")
+      .long("preserve-mtime")
+      .help("Keep the modification and access times of audio files that get lyrics")
+      .action(ArgAction::SetTrue),
+    Arg::new("no-verify")
+      .long("no-verify")
+      .help("Don't read saved files back to check the lyrics and audio before replacing the originals")
+      .action(ArgAction::SetTrue),
+    Arg::new("offset")
+      .long("offset")
+      .value_name("MS")
+      .value_parser(clap::value_parser!(i64))
+      .allow_negative_numbers(true)
+      .default_value("0")
+      .help("Shift every timestamp MS milliseconds later before embedding, or earlier when negative"),
+    Arg::new("strip-headers")
+      .long("strip-headers")
+      .value_name("TAGS")
+      .value_delimiter(',')
+      .num_args(0..=1)
+      .default_missing_value("ar,ti,by,re,ve")
+      .help("Leave these LRC header tags out of the embedded lyrics (ar,ti,by,re,ve when none are given)"),
+    Arg::new("strip-credits")
+      .long("strip-credits")
+      .help("Leave out lines that look like credits or watermarks, like \"Lyrics by example.com\"")
+      .action(ArgAction::SetTrue),
+    Arg::new("strip-line")
+      .long("strip-line")
+      .value_name("REGEX")
+      .value_parser(parse_pattern)
+      .action(ArgAction::Append)
+      .help("Leave out lines whose text matches REGEX (can be repeated)"),
+    Arg::new("sort-lines")
+      .long("sort-lines")
+      .help("Sort LRC lines by timestamp and merge exact duplicates before embedding, logging what changed")
+      .action(ArgAction::SetTrue),
+    Arg::new("word-timing")
+      .long("word-timing")
+      .value_name("MODE")
+      .value_parser(["keep", "strip"])
+      .default_value("strip")
+      .help("Embed word timestamps from enhanced LRC, TTML or ASS karaoke, timing SYLT frames word by word (keep)"),
+    Arg::new("translations")
+      .long("translations")
+      .value_name("MODE")
+      .value_parser(["frames", "merge"])
+      .default_value("frames")
+      .help("Embed translations like Song.zh.lrc in ID3v2 frames of their own, or merge them into Song.lrc"),
+    Arg::new("romanize")
+      .long("romanize")
+      .value_name("MODE")
+      .value_parser(["frames", "merge"])
+      .help("Also embed CJK lyrics romanized, in ID3v2 frames of their own or merged in (needs the romanize feature)"),
+  ]
+}
+
+fn undo_args() -> Vec<Arg> {
+  let mut args = vec![
+    Arg::new("journal")
+      .value_name("JOURNAL")
+      .value_parser(clap::value_parser!(PathBuf))
+      .value_hint(ValueHint::FilePath)
+      .help("Journal to undo, as printed at the end of the run [default: the latest in --journal-dir]"),
+    Arg::new("list").long("list").help("List the journals that can be undone").action(ArgAction::SetTrue),
+    journal_dir_arg(),
+    dry_run_arg(),
+  ];
+  args.extend(format_args());
+  args
+}
+
+fn journal_dir_arg() -> Arg {
+  Arg::new("journal-dir")
+    .long("journal-dir")
+    .value_name("DIRECTORY")
+    .value_parser(clap::value_parser!(PathBuf))
+    .value_hint(ValueHint::DirPath)
+    .help("Directory for run journals (defaults to the user state directory)")
+}
+
+// Copies of audio files taken before they're modified, and the journal of what changed
+fn backup_args() -> [Arg; 5] {
+  [
+    Arg::new("backup-dir")
+      .long("backup-dir")
+      .value_name("DIRECTORY")
+      .value_parser(clap::value_parser!(PathBuf))
+      .value_hint(ValueHint::DirPath)
+      .help("Copy each audio file here, under its path relative to --directory, before modifying it"),
+    Arg::new("backup-max-size")
+      .long("backup-max-size")
+      .value_name("SIZE")
+      .value_parser(parse_size)
+      .requires("backup-dir")
+      .help("Keep the backup directory under SIZE (e.g. 500M or 2G), pruning the oldest backups"),
+    Arg::new("backup-max-age")
+      .long("backup-max-age")
+      .value_name("DAYS")
+      .value_parser(clap::value_parser!(u64))
+      .requires("backup-dir")
+      .help("Prune backups older than DAYS"),
+    journal_dir_arg(),
+    Arg::new("no-journal")
+      .long("no-journal")
+      .help("Don't record the changes, which leaves nothing for `lyricsync undo`")
+      .conflicts_with("journal-dir")
+      .action(ArgAction::SetTrue),
+  ]
+}
+
+// How audio files are paired with LRC files not named exactly like them
+fn matching_args() -> [Arg; 7] {
+  [
+    Arg::new("name-rule")
+      .long("name-rule")
+      .value_name("PATTERN=>REPLACEMENT")
+      .value_parser(parse_name_rule)
+      .action(ArgAction::Append)
+      .help("Also look for the LRC file named as a regex replacement of the audio file's name (can be repeated)"),
+    Arg::new("fuzzy")
+      .long("fuzzy")
+      .help("When no LRC file is named like an audio file, take the one with the most similar name")
+      .action(ArgAction::SetTrue),
+    Arg::new("min-confidence")
+      .long("min-confidence")
+      .value_name("SCORE")
+      .value_parser(parse_confidence)
+      .default_value("0.8")
+      .help("How alike names must be for --fuzzy, from 0 to 1, ignoring track numbers, case and punctuation"),
+    Arg::new("by-tags")
+      .long("by-tags")
+      .help("When no LRC file is named like an audio file, look for one named after its artist and title tags")
+      .action(ArgAction::SetTrue),
+    Arg::new("lyrics-dir")
+      .long("lyrics-dir")
+      .value_name("DIR")
+      .value_parser(clap::value_parser!(PathBuf))
+      .action(ArgAction::Append)
+      .requires("by-tags")
+      .help("Where --by-tags looks for LRC files, instead of the music directory (can be repeated)"),
+    Arg::new("map")
+      .long("map")
+      .value_name("FILE")
+      .value_parser(clap::value_parser!(PathBuf))
+      .value_hint(ValueHint::FilePath)
+      .help("Pair audio files with LRC files as listed in FILE (CSV, TSV or JSON of audio_path, lrc_path, offset_ms)"),
+    Arg::new("txt-fallback")
+      .long("txt-fallback")
+      .help("When an audio file has no LRC file, embed the plain lyrics of a .txt file named like it, unsynced")
+      .action(ArgAction::SetTrue),
+  ]
+}
+
+fn format_args() -> [Arg; 2] {
+  [
+    Arg::new("probe")
+      .long("probe")
+      .help("Detect audio formats from file contents instead of extensions")
+      .action(ArgAction::SetTrue),
+    Arg::new("ext-alias")
+      .long("ext-alias")
+      .value_name("EXT=FORMAT")
+      .value_parser(parse_extension_alias)
+      .action(ArgAction::Append)
+      .help("Treat files with extension EXT as FORMAT (e.g. m4r=m4a); may be repeated"),
+  ]
+}
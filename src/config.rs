@@ -0,0 +1,294 @@
+//! Defaults for the command-line options, read from `config.toml` in the user's config directory
+//! or the file given with `--config`.
+//!
+//! Keys are the long names of the options, without the dashes. Top-level keys apply to every
+//! command that has the option, and a table named after a command (`[embed]`, `[fetch]`, ...)
+//! applies to that command alone, over the top-level ones:
+//!
+//! ```toml
+//! directory = "~/Music"
+//! recursive = true
+//! language = "jpn"
+//!
+//! [embed]
+//! skip = true
+//! id3-version = "2.3"
+//! ```
+//!
+//! What's in the file becomes the options' defaults, so anything given on the command line wins.
+//!
+//! Named profiles, chosen with `--profile NAME`, bundle options for one purpose. They're laid out
+//! like the file itself, with command tables of their own, and apply over the rest of it:
+//!
+//! ```toml
+//! [profile.car]
+//! id3-version = "2.3"
+//! encoding = "utf16"
+//!
+//! [profile.car.embed]
+//! reduce = false
+//! ```
+//!
+//! A `.lyricsync.toml` in a music folder sets the tag options for the files in it and below, over
+//! the user's config file. Those options are the only keys it takes, at its top level.
+
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use lyricsync::{LrcError, Result};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+const FILE_NAME: &str = "config.toml";
+
+/// The config file of a directory, for the files in it and its subdirectories.
+pub const DIRECTORY_FILE: &str = ".lyricsync.toml";
+
+/// The options a directory's config file can set: how lyrics are written to its files.
+const DIRECTORY_OPTIONS: [&str; 19] = [
+  "replace",
+  "id3-frames",
+  "id3-version",
+  "encoding",
+  "language",
+  "description",
+  "vorbis-key",
+  "synced-lyrics-key",
+  "mp4-lyrics",
+  "preserve-mtime",
+  "no-verify",
+  "offset",
+  "strip-headers",
+  "strip-credits",
+  "strip-line",
+  "sort-lines",
+  "word-timing",
+  "translations",
+  "romanize",
+];
+
+/// The options that aren't defaults for anything, since they pick what the rest apply to.
+const NOT_CONFIGURABLE: [&str; 3] = ["config", "profile", "generate-completion"];
+
+const PROFILES: &str = "profile";
+
+/// The config file read when `--config` isn't given, if the platform has a config directory.
+pub fn default_path() -> Option<PathBuf> {
+  dirs::config_dir().map(|dir| dir.join("lyricsync").join(FILE_NAME))
+}
+
+fn invalid(path: &Path, message: String) -> LrcError {
+  LrcError::FileIo { path: path.to_path_buf(), source: io::Error::new(io::ErrorKind::InvalidData, message) }
+}
+
+/// Reads the config file for the command line in `args`, if there's one, and returns `cmd` with
+/// the options it sets turned into defaults.
+pub fn apply(cmd: Command, args: &[OsString]) -> Result<Command> {
+  let Some(matches) = first_pass(&cmd, args) else {
+    return Ok(cmd);
+  };
+  let profile = matches.get_one::<String>("profile");
+  let path = match matches.get_one::<PathBuf>("config") {
+    Some(path) => path.clone(),
+    // A profile has to come from somewhere, so a missing file is an error then
+    None if profile.is_some() => default_path().unwrap_or_else(|| PathBuf::from(FILE_NAME)),
+    None => match default_path().filter(|path| path.is_file()) {
+      Some(path) => path,
+      None => return Ok(cmd),
+    },
+  };
+  let mut table = read(&path)?;
+  let profiles = match table.remove(PROFILES) {
+    Some(Value::Table(profiles)) => profiles,
+    Some(_) => return Err(invalid(&path, format!("`{PROFILES}` should be a table of profiles"))),
+    None => Table::new(),
+  };
+  let cmd = apply_table(cmd, &matches, &path, &table)?;
+  let Some(name) = profile else {
+    return Ok(cmd);
+  };
+  match profiles.get(name) {
+    Some(Value::Table(profile)) => apply_table(cmd, &matches, &path, profile),
+    Some(_) => Err(invalid(&path, format!("profile `{name}` should be a table"))),
+    None => Err(invalid(&path, format!("no profile `{name}`"))),
+  }
+}
+
+/// A command line, kept to be parsed again with the config files of directories on top.
+pub struct CommandLine {
+  cmd: Command,
+  args: Vec<OsString>,
+}
+
+impl CommandLine {
+  pub fn new(cmd: Command, args: Vec<OsString>) -> Self {
+    CommandLine { cmd, args }
+  }
+
+  /// Parses the command line again with the options of each of `files`, directory config files
+  /// from the outermost in, as defaults.
+  pub fn with_directory_files(&self, files: &[PathBuf]) -> Result<ArgMatches> {
+    let matches = first_pass(&self.cmd, &self.args).unwrap_or_default();
+    let mut cmd = self.cmd.clone();
+    for path in files {
+      let table = read(path)?;
+      if let Some(key) = table.keys().find(|key| !DIRECTORY_OPTIONS.contains(&key.as_str())) {
+        return Err(invalid(path, format!("`{key}` can't be set for a directory")));
+      }
+      cmd = apply_table(cmd, &matches, path, &table)?;
+      // Checked one file at a time, so a bad value is blamed on the file it's in
+      cmd.clone().try_get_matches_from(&self.args).map_err(|error| {
+        let message = error.to_string();
+        let message = message.lines().next().unwrap_or_default();
+        invalid(path, message.trim_start_matches("error: ").to_string())
+      })?;
+    }
+    Ok(cmd.get_matches_from(&self.args))
+  }
+}
+
+// A first pass, errors and all, tells which file to read, the command and what's given already.
+// It only fails to asking for --help or --version, which need no config.
+fn first_pass(cmd: &Command, args: &[OsString]) -> Option<ArgMatches> {
+  cmd.clone().ignore_errors(true).try_get_matches_from(args).ok()
+}
+
+fn read(path: &Path) -> Result<Table> {
+  let contents = fs::read_to_string(path).map_err(|source| LrcError::FileIo { path: path.to_path_buf(), source })?;
+  contents.parse().map_err(|error: toml::de::Error| {
+    let line = error.span().map_or(1, |span| contents[..span.start].matches('\n').count() + 1);
+    invalid(path, format!("line {line}: {}", error.message()))
+  })
+}
+
+fn apply_table(cmd: Command, matches: &ArgMatches, path: &Path, table: &Table) -> Result<Command> {
+  let (command, sub_matches) = match matches.subcommand() {
+    Some((command, sub_matches)) => (command, Some(sub_matches)),
+    None => ("embed", None),
+  };
+  let explicit = sub_matches.unwrap_or(matches);
+
+  let commands: Vec<&str> = cmd.get_subcommands().map(Command::get_name).collect();
+  let mut options = Vec::new();
+  for (key, value) in table {
+    match value {
+      Value::Table(_) if !commands.contains(&key.as_str()) => {
+        return Err(invalid(path, format!("unknown command `[{key}]`")));
+      },
+      Value::Table(_) => {},
+      _ if !cmd
+        .get_arguments()
+        .chain(cmd.get_subcommands().flat_map(Command::get_arguments))
+        .any(|arg| arg.get_id() == key) =>
+      {
+        return Err(invalid(path, format!("unknown option `{key}`")));
+      },
+      // Options of other commands are defaults for those
+      _ => options.push((key, value)),
+    }
+  }
+  if let Some(Value::Table(section)) = table.get(command) {
+    let target = target_command(&cmd, sub_matches.map(|_| command));
+    for (key, value) in section {
+      if !target
+        .get_arguments()
+        .chain(cmd.get_arguments().filter(|arg| arg.is_global_set()))
+        .any(|arg| arg.get_id() == key)
+      {
+        return Err(invalid(path, format!("unknown option `{key}` for `{command}`")));
+      }
+      options.push((key, value));
+    }
+  }
+
+  let mut cmd = cmd;
+  for (key, value) in options {
+    if NOT_CONFIGURABLE.contains(&key.as_str()) {
+      return Err(invalid(path, format!("`{key}` can't be set in a config file")));
+    }
+    let values = values(value).map_err(|message| invalid(path, format!("`{key}`: {message}")))?;
+    let target = target_command(&cmd, sub_matches.map(|_| command));
+    let global = !target.get_arguments().any(|arg| arg.get_id() == key);
+    let search = if global { &cmd } else { target };
+    let Some(arg) = search.get_arguments().find(|arg| arg.get_id() == key) else {
+      // The option belongs to another command
+      continue;
+    };
+    if global && !arg.is_global_set() {
+      continue;
+    }
+    // An option given on the command line or in the environment, or one it conflicts with, leaves
+    // the file's value unused
+    let given = |id: &str| {
+      explicit.try_get_raw(id).is_ok()
+        && matches!(explicit.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable))
+    };
+    // Each option only lists the conflicts it was declared with, so both sides are looked at
+    let names = |of: &Arg, id: &clap::Id| search.get_arg_conflicts_with(of).iter().any(|other| other.get_id() == id);
+    let conflicts = |other: &Arg| names(arg, other.get_id()) || names(other, arg.get_id());
+    if given(key) || search.get_arguments().any(|other| given(other.get_id().as_str()) && conflicts(other)) {
+      continue;
+    }
+    match arg.get_action() {
+      ArgAction::SetTrue | ArgAction::SetFalse if !value.is_bool() => {
+        return Err(invalid(path, format!("`{key}`: expected true or false")));
+      },
+      ArgAction::Count if !value.is_integer() => return Err(invalid(path, format!("`{key}`: expected a number"))),
+      _ => {},
+    }
+    // A flag set here takes over from the ones it conflicts with that were set before it, e.g. by
+    // the file's top level under a profile
+    let unset: Vec<String> = match value.as_bool() {
+      Some(false) => Vec::new(),
+      _ => search
+        .get_arguments()
+        .filter(|other| matches!(other.get_action(), ArgAction::SetTrue) && conflicts(other))
+        .map(|other| other.get_id().to_string())
+        .collect(),
+    };
+
+    let in_subcommand = !global && sub_matches.is_some();
+    cmd = set_default(cmd, in_subcommand.then_some(command), key, values);
+    for flag in unset {
+      cmd = set_default(cmd, in_subcommand.then_some(command), &flag, vec!["false".to_string()]);
+    }
+  }
+  Ok(cmd)
+}
+
+fn set_default(cmd: Command, subcommand: Option<&str>, id: &str, values: Vec<String>) -> Command {
+  let set = move |arg: Arg| arg.default_values(values).required(false);
+  match subcommand {
+    Some(subcommand) => cmd.mut_subcommand(subcommand, |sub| sub.mut_arg(id, set)),
+    None => cmd.mut_arg(id, set),
+  }
+}
+
+// The top-level command stands in for `embed` when it's run without one
+fn target_command<'c>(cmd: &'c Command, command: Option<&str>) -> &'c Command {
+  command.and_then(|command| cmd.find_subcommand(command)).unwrap_or(cmd)
+}
+
+fn values(value: &Value) -> std::result::Result<Vec<String>, String> {
+  match value {
+    Value::String(text) => Ok(vec![expand_home(text)]),
+    Value::Integer(number) => Ok(vec![number.to_string()]),
+    Value::Float(number) => Ok(vec![number.to_string()]),
+    Value::Boolean(flag) => Ok(vec![flag.to_string()]),
+    Value::Array(items) => {
+      items.iter().map(values).collect::<std::result::Result<Vec<_>, _>>().map(|items| items.concat())
+    },
+    Value::Datetime(_) | Value::Table(_) => Err("expected a string, number, boolean or array".to_string()),
+  }
+}
+
+// Paths in a config file are usually written from the home directory, which no shell expands there
+fn expand_home(text: &str) -> String {
+  match (text.strip_prefix("~/"), dirs::home_dir()) {
+    (Some(rest), Some(home)) => home.join(rest).to_string_lossy().into_owned(),
+    _ if text == "~" => dirs::home_dir().map_or(text.to_string(), |home| home.to_string_lossy().into_owned()),
+    _ => text.to_string(),
+  }
+}
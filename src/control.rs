@@ -0,0 +1,154 @@
+//! The daemon's control socket, given with `--socket`: a Unix socket, or a named pipe such as
+//! `\\.\pipe\lyricsync` on Windows, for other programs to ask how it's doing and drive it.
+//!
+//! A client writes one command per line and gets the daemon's status back for each as a line of
+//! JSON, after the command has taken effect:
+//!
+//! - `status` changes nothing
+//! - `rescan` goes through the whole directory as soon as the daemon is free
+//! - `pause` stops embedding, including scheduled scans, while files that turn up are still noted
+//! - `resume` picks up what was put off while paused
+//!
+//! Anything else is answered with an `error` object.
+
+use interprocess::local_socket::prelude::*;
+use interprocess::local_socket::{GenericFilePath, ListenerOptions, Stream};
+use serde::Serialize;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What the daemon is busy with.
+#[derive(Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Activity {
+  #[default]
+  Idle,
+  Embedding,
+  Scanning,
+}
+
+/// How the daemon is doing, as a `status` command is answered.
+#[derive(Default, Serialize)]
+pub struct Status {
+  pub directory: PathBuf,
+  pub paused: bool,
+  pub activity: Activity,
+  /// Files that turned up and haven't settled or been embedded yet
+  pub pending: usize,
+  pub checked: usize,
+  pub embedded: usize,
+  pub failed: usize,
+  /// When the last scan finished, in seconds since the Unix epoch
+  pub last_scan: Option<u64>,
+  /// When the next scan is due, in seconds since the Unix epoch
+  pub next_scan: Option<u64>,
+  #[serde(skip)]
+  rescan: bool,
+}
+
+/// A listening control socket, taken down when it's dropped.
+pub struct ControlSocket {
+  path: PathBuf,
+  status: Arc<Mutex<Status>>,
+}
+
+impl ControlSocket {
+  /// Listens on `path` for the daemon watching `directory`. A socket file left by a daemon that
+  /// didn't get to remove it is replaced, but one still listening isn't.
+  pub fn bind(path: &Path, directory: &Path) -> io::Result<Self> {
+    let name = || path.to_fs_name::<GenericFilePath>();
+    if Stream::connect(name()?).is_ok() {
+      return Err(io::Error::new(io::ErrorKind::AddrInUse, "another daemon is listening on it"));
+    }
+    let listener = ListenerOptions::new().name(name()?).try_overwrite(true).create_sync()?;
+    // Whoever can connect can pause the daemon, so the socket is kept to its user
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    let status = Arc::new(Mutex::new(Status { directory: directory.to_path_buf(), ..Status::default() }));
+    let shared = Arc::clone(&status);
+    thread::spawn(move || {
+      for connection in listener.incoming() {
+        match connection {
+          Ok(connection) => {
+            let status = Arc::clone(&shared);
+            thread::spawn(move || serve(connection, &status));
+          },
+          Err(error) => tracing::warn!(%error, "control socket connection failed"),
+        }
+      }
+    });
+    tracing::info!(socket = %path.display(), "listening for control commands");
+    Ok(ControlSocket { path: path.to_path_buf(), status })
+  }
+
+  pub fn update(&self, change: impl FnOnce(&mut Status)) {
+    change(&mut self.status.lock().unwrap_or_else(PoisonError::into_inner));
+  }
+
+  pub fn paused(&self) -> bool {
+    self.status.lock().unwrap_or_else(PoisonError::into_inner).paused
+  }
+
+  /// Whether a client asked for a scan since the last call.
+  pub fn take_rescan(&self) -> bool {
+    std::mem::take(&mut self.status.lock().unwrap_or_else(PoisonError::into_inner).rescan)
+  }
+}
+
+impl Drop for ControlSocket {
+  fn drop(&mut self) {
+    // A named pipe goes away with the process; a socket file stays behind
+    #[cfg(unix)]
+    let _ = std::fs::remove_file(&self.path);
+  }
+}
+
+/// Seconds since the Unix epoch, as the status reports times.
+pub fn unix_seconds(time: SystemTime) -> u64 {
+  time.duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default()
+}
+
+fn serve(connection: Stream, status: &Mutex<Status>) {
+  let mut reader = BufReader::new(connection);
+  let mut line = String::new();
+  while reader.read_line(&mut line).is_ok_and(|read| read > 0) {
+    let command = line.trim();
+    if !command.is_empty() {
+      let reply = {
+        let mut status = status.lock().unwrap_or_else(PoisonError::into_inner);
+        let error = match command {
+          "status" => None,
+          "rescan" => {
+            status.rescan = true;
+            None
+          },
+          "pause" => {
+            status.paused = true;
+            None
+          },
+          "resume" => {
+            status.paused = false;
+            None
+          },
+          _ => Some(format!("unknown command `{command}`; expected status, rescan, pause or resume")),
+        };
+        match error {
+          Some(error) => serde_json::json!({ "error": error }),
+          None => serde_json::json!(*status),
+        }
+      };
+      let mut reply = reply.to_string();
+      reply.push('\n');
+      if reader.get_mut().write_all(reply.as_bytes()).is_err() {
+        break;
+      }
+    }
+    line.clear();
+  }
+}
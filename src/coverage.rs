@@ -0,0 +1,252 @@
+//! The standalone HTML page written by `check --report html`: coverage by folder, artist and album,
+//! the files missing lyrics and the ones that couldn't be read, in tables that sort by any column.
+
+use crate::report::ReportTarget;
+use lyricsync::{FormatResolver, LyricsStatus, track};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const UNKNOWN_ARTIST: &str = "Unknown artist";
+const UNKNOWN_ALBUM: &str = "Unknown album";
+
+/// One file `check` went through.
+pub struct CheckedFile {
+  pub path: PathBuf,
+  /// Why the file couldn't be checked, when it couldn't
+  pub status: Result<LyricsStatus, String>,
+}
+
+struct Entry<'a> {
+  file: &'a CheckedFile,
+  folder: String,
+  artist: String,
+  album: String,
+}
+
+#[derive(Default)]
+struct Coverage {
+  files: usize,
+  embedded: usize,
+  sidecar_only: usize,
+  missing: usize,
+  failed: usize,
+}
+
+impl Coverage {
+  fn add(&mut self, status: &Result<LyricsStatus, String>) {
+    self.files += 1;
+    match status {
+      Ok(LyricsStatus::Match | LyricsStatus::Differs | LyricsStatus::EmbeddedOnly) => self.embedded += 1,
+      Ok(LyricsStatus::SidecarOnly) => self.sidecar_only += 1,
+      Ok(LyricsStatus::Missing) => self.missing += 1,
+      Err(_) => self.failed += 1,
+    }
+  }
+
+  fn with_lyrics(&self) -> usize {
+    self.embedded + self.sidecar_only
+  }
+
+  fn fraction(&self) -> f64 {
+    if self.files > 0 { self.with_lyrics() as f64 / self.files as f64 } else { 0.0 }
+  }
+}
+
+pub struct CoveragePage<'a> {
+  directory: &'a Path,
+  entries: Vec<Entry<'a>>,
+}
+
+impl<'a> CoveragePage<'a> {
+  /// Reads the artist and album of each of `files`, found under `directory`.
+  pub fn new(directory: &'a Path, files: &'a [CheckedFile], resolver: &FormatResolver) -> Self {
+    let entries = files
+      .iter()
+      .map(|file| {
+        let folder = file.path.parent().and_then(|parent| parent.strip_prefix(directory).ok());
+        let folder = folder.filter(|folder| !folder.as_os_str().is_empty()).map_or(".".into(), Path::to_string_lossy);
+        // Files without an artist and title are grouped as unknown, like the players that list them do
+        let track =
+          resolver.resolve(&file.path).ok().and_then(|format| track::read_track_info(&file.path, format).ok());
+        let track = track.flatten();
+        Entry {
+          file,
+          folder: folder.into_owned(),
+          artist: track.as_ref().map_or(UNKNOWN_ARTIST.to_string(), |track| track.artist.clone()),
+          album: track.and_then(|track| track.album).unwrap_or_else(|| UNKNOWN_ALBUM.to_string()),
+        }
+      })
+      .collect();
+    CoveragePage { directory, entries }
+  }
+
+  pub fn write(&self, target: &ReportTarget) -> io::Result<()> {
+    match &target.path {
+      Some(path) => self.write_to(BufWriter::new(File::create(path)?)),
+      None => self.write_to(io::stdout().lock()),
+    }
+  }
+
+  fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+    writer.write_all(self.render().as_bytes())?;
+    writer.flush()
+  }
+
+  fn render(&self) -> String {
+    let title = format!("Lyrics coverage of {}", self.directory.display());
+    let mut total = Coverage::default();
+    for entry in &self.entries {
+      total.add(&entry.file.status);
+    }
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>{}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n", escape(&title)));
+    html.push_str(&format!("<h1>{}</h1>\n", escape(&title)));
+    html.push_str(&format!(
+      "<p class=\"summary\">{} of {} files have lyrics ({}): {} embedded, {} in sidecars only, {} missing, {} \
+       unreadable.</p>\n",
+      total.with_lyrics(),
+      total.files,
+      percentage(total.fraction()),
+      total.embedded,
+      total.sidecar_only,
+      total.missing,
+      total.failed,
+    ));
+
+    self.coverage_table(&mut html, "By folder", &["Folder"], |entry| vec![entry.folder.as_str()]);
+    self.coverage_table(&mut html, "By artist", &["Artist"], |entry| vec![entry.artist.as_str()]);
+    self.coverage_table(&mut html, "By album", &["Album", "Artist"], |entry| {
+      vec![entry.album.as_str(), entry.artist.as_str()]
+    });
+
+    let missing: Vec<&Entry> =
+      self.entries.iter().filter(|entry| matches!(entry.file.status, Ok(LyricsStatus::Missing))).collect();
+    html.push_str(&format!("<h2>Missing lyrics ({})</h2>\n", missing.len()));
+    table(
+      &mut html,
+      &["File", "Artist", "Album"],
+      missing
+        .iter()
+        .map(|entry| vec![cell(&entry.file.path.to_string_lossy()), cell(&entry.artist), cell(&entry.album)]),
+    );
+
+    let failed: Vec<(&Entry, &str)> = self
+      .entries
+      .iter()
+      .filter_map(|entry| entry.file.status.as_ref().err().map(|error| (entry, error.as_str())))
+      .collect();
+    html.push_str(&format!("<h2>Failures ({})</h2>\n", failed.len()));
+    table(
+      &mut html,
+      &["File", "Error"],
+      failed.iter().map(|(entry, error)| vec![cell(&entry.file.path.to_string_lossy()), cell(error)]),
+    );
+
+    html.push_str(&format!("<script>{SCRIPT}</script>\n</body>\n</html>\n"));
+    html
+  }
+
+  fn coverage_table<'e>(
+    &'e self,
+    html: &mut String,
+    heading: &str,
+    columns: &[&str],
+    key: impl Fn(&'e Entry<'a>) -> Vec<&'e str>,
+  ) {
+    let mut groups: BTreeMap<Vec<&str>, Coverage> = BTreeMap::new();
+    for entry in &self.entries {
+      groups.entry(key(entry)).or_default().add(&entry.file.status);
+    }
+
+    html.push_str(&format!("<h2>{heading}</h2>\n"));
+    let mut headings = columns.to_vec();
+    headings.extend(["Files", "With lyrics", "Coverage", "Embedded", "Sidecar only", "Missing", "Unreadable"]);
+    table(
+      html,
+      &headings,
+      groups.iter().map(|(names, coverage)| {
+        let mut row: Vec<String> = names.iter().map(|name| cell(name)).collect();
+        row.extend([coverage.files, coverage.with_lyrics()].map(number));
+        row.push(format!("<td data-sort=\"{}\">{}</td>", coverage.fraction(), percentage(coverage.fraction())));
+        row.extend([coverage.embedded, coverage.sidecar_only, coverage.missing, coverage.failed].map(number));
+        row
+      }),
+    );
+  }
+}
+
+fn table(html: &mut String, headings: &[&str], rows: impl Iterator<Item = Vec<String>>) {
+  html.push_str("<table>\n<thead><tr>");
+  for heading in headings {
+    html.push_str(&format!("<th>{}</th>", escape(heading)));
+  }
+  html.push_str("</tr></thead>\n<tbody>\n");
+  for row in rows {
+    html.push_str(&format!("<tr>{}</tr>\n", row.concat()));
+  }
+  html.push_str("</tbody>\n</table>\n");
+}
+
+fn cell(text: &str) -> String {
+  format!("<td>{}</td>", escape(text))
+}
+
+fn number(value: usize) -> String {
+  format!("<td data-sort=\"{value}\">{value}</td>")
+}
+
+fn percentage(fraction: f64) -> String {
+  format!("{:.2}%", fraction * 100.0)
+}
+
+fn escape(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      _ => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }
+th { background: #f0f0f0; cursor: pointer; user-select: none; }
+th[data-order=asc]::after { content: ' \\25B2'; }
+th[data-order=desc]::after { content: ' \\25BC'; }
+td[data-sort] { text-align: right; }
+tbody tr:nth-child(even) { background: #fafafa; }
+";
+
+// Clicking a heading sorts its table by that column, numbers by value and text alphabetically
+const SCRIPT: &str = "
+for (const heading of document.querySelectorAll('th')) {
+  heading.addEventListener('click', () => {
+    const table = heading.closest('table');
+    const ascending = heading.dataset.order !== 'asc';
+    for (const other of table.querySelectorAll('th')) delete other.dataset.order;
+    heading.dataset.order = ascending ? 'asc' : 'desc';
+    const value = (row) => {
+      const cell = row.cells[heading.cellIndex];
+      return cell.dataset.sort !== undefined ? Number(cell.dataset.sort) : cell.textContent;
+    };
+    const rows = [...table.tBodies[0].rows].sort((a, b) => {
+      const [x, y] = [value(a), value(b)];
+      const order = typeof x === 'number' ? x - y : x.localeCompare(y);
+      return ascending ? order : -order;
+    });
+    table.tBodies[0].append(...rows);
+  });
+}
+";
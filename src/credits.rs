@@ -0,0 +1,37 @@
+//! Credit lines that lyrics providers and uploaders leave in the lyrics, like `Lyrics by
+//! example.com` or a `Synced by someone` signature, which players show as lines of the song.
+
+use crate::lrc;
+use regex::Regex;
+
+/// What [`defaults`] match, each a regular expression tried against a line's text without its
+/// timestamps.
+pub const PATTERNS: [&str; 5] = [
+  // Lyrics by example.com, LRC from ..., Subtitles provided by ...
+  r"(?i)\b(?:lyrics|lrc|subtitles?|translation)\s+(?:by|from|provided by|powered by|courtesy of)\b",
+  // Uploaders' signatures
+  r"(?i)\b(?:uploaded|synced|synchronized|timed|edited|transcribed)\s+by\b",
+  r"(?i)(?:https?://|www\.)\S+",
+  r"(?i)\b[a-z0-9-]+\.(?:com|net|org|cn|ru|io|info)\b",
+  // The songwriter credits Chinese providers open with, like 作词 : ...
+  r"^\s*(?:作词|作詞|作曲|编曲|編曲|制作人|製作人|监制|監製)\s*[:：]",
+];
+
+/// The built-in [`PATTERNS`], compiled.
+pub fn defaults() -> Vec<Regex> {
+  PATTERNS.iter().map(|pattern| Regex::new(pattern).expect("the built-in patterns are valid")).collect()
+}
+
+/// Leaves out the lines of `lyrics` that match any of `patterns`, keeping header tags.
+pub fn strip(lyrics: &str, patterns: &[Regex]) -> String {
+  if patterns.is_empty() {
+    return lyrics.to_string();
+  }
+  lrc::retain_lines(lyrics, |text| {
+    let credit = patterns.iter().any(|pattern| pattern.is_match(text));
+    if credit {
+      tracing::info!(line = text.trim(), "left out a credit line");
+    }
+    !credit
+  })
+}
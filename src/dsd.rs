@@ -0,0 +1,186 @@
+//! ID3v2 tag handling for DSD containers (DSF and DSDIFF).
+//!
+//! lofty can't read these containers, but both carry a plain ID3v2 tag: DSF points at it from the
+//! `DSD ` header chunk, and DSDIFF stores it in a top-level `ID3 ` chunk. The tag itself is parsed
+//! and serialized with lofty.
+
+use crate::{LrcError, Result};
+use lofty::{
+  config::{ParseOptions, WriteOptions},
+  file::AudioFile,
+  id3::v2::Id3v2Tag,
+  iff::wav::WavFile,
+  tag::TagExt,
+};
+use std::fs;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// "DSD " id + chunk size + total file size + metadata pointer
+const DSF_HEADER_LEN: usize = 28;
+// "FRM8" id + size + "DSD " form type
+const DFF_HEADER_LEN: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DsdKind {
+  Dsf,
+  Dff,
+}
+
+pub struct DsdFile {
+  kind: DsdKind,
+  id3v2: Option<Id3v2Tag>,
+}
+
+impl DsdFile {
+  pub fn read_from<R: Read + Seek>(reader: &mut R, kind: DsdKind) -> Result<Self> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    let tag_bytes = match kind {
+      DsdKind::Dsf => {
+        let pointer = dsf_metadata_pointer(&data)?;
+        (pointer != 0).then(|| &data[pointer..])
+      },
+      DsdKind::Dff => dff_chunks(&data)?.into_iter().find(|chunk| &chunk.id == b"ID3 ").map(|chunk| chunk.data),
+    };
+
+    let id3v2 = match tag_bytes {
+      Some(bytes) if bytes.starts_with(b"ID3") => parse_id3v2(bytes)?,
+      _ => None,
+    };
+
+    Ok(Self { kind, id3v2 })
+  }
+
+  pub fn id3v2(&self) -> Option<&Id3v2Tag> {
+    self.id3v2.as_ref()
+  }
+
+  pub fn id3v2_mut(&mut self) -> Option<&mut Id3v2Tag> {
+    self.id3v2.as_mut()
+  }
+
+  pub fn set_id3v2(&mut self, tag: Id3v2Tag) {
+    self.id3v2 = Some(tag);
+  }
+
+  /// Rewrites the tag of the file at `path`, keeping the audio data intact.
+  pub fn save_to_path(&self, path: &Path, write_options: WriteOptions) -> Result<()> {
+    let data = fs::read(path)?;
+
+    let mut tag_bytes = Vec::new();
+    if let Some(id3v2) = &self.id3v2 {
+      id3v2.dump_to(&mut tag_bytes, write_options)?;
+    }
+
+    let output = match self.kind {
+      DsdKind::Dsf => rewrite_dsf(&data, &tag_bytes)?,
+      DsdKind::Dff => rewrite_dff(&data, &tag_bytes)?,
+    };
+
+    fs::write(path, output)?;
+    Ok(())
+  }
+}
+
+fn dsf_metadata_pointer(data: &[u8]) -> Result<usize> {
+  if data.len() < DSF_HEADER_LEN || &data[..4] != b"DSD " {
+    return Err(LrcError::InvalidDsd("missing DSF header chunk".to_string()));
+  }
+
+  let pointer = u64::from_le_bytes(data[20..28].try_into().unwrap()) as usize;
+  if pointer != 0 && (pointer < DSF_HEADER_LEN || pointer > data.len()) {
+    return Err(LrcError::InvalidDsd("metadata pointer out of range".to_string()));
+  }
+
+  Ok(pointer)
+}
+
+fn rewrite_dsf(data: &[u8], tag_bytes: &[u8]) -> Result<Vec<u8>> {
+  // The metadata chunk always sits at the end of the file, so drop it and append the new tag
+  let audio_end = match dsf_metadata_pointer(data)? {
+    0 => data.len(),
+    pointer => pointer,
+  };
+
+  let mut output = data[..audio_end].to_vec();
+  let pointer = if tag_bytes.is_empty() { 0 } else { audio_end as u64 };
+  output.extend_from_slice(tag_bytes);
+
+  let total_len = output.len() as u64;
+  output[12..20].copy_from_slice(&total_len.to_le_bytes());
+  output[20..28].copy_from_slice(&pointer.to_le_bytes());
+  Ok(output)
+}
+
+struct DffChunk<'a> {
+  id: [u8; 4],
+  // The whole chunk including its header and padding
+  raw: &'a [u8],
+  data: &'a [u8],
+}
+
+fn dff_chunks(data: &[u8]) -> Result<Vec<DffChunk<'_>>> {
+  if data.len() < DFF_HEADER_LEN || &data[..4] != b"FRM8" || &data[12..16] != b"DSD " {
+    return Err(LrcError::InvalidDsd("missing DSDIFF FRM8 chunk".to_string()));
+  }
+
+  let mut chunks = Vec::new();
+  let mut offset = DFF_HEADER_LEN;
+
+  while offset + 12 <= data.len() {
+    let id: [u8; 4] = data[offset..offset + 4].try_into().unwrap();
+    let size = u64::from_be_bytes(data[offset + 4..offset + 12].try_into().unwrap()) as usize;
+    let data_start = offset + 12;
+    let data_end = data_start.checked_add(size).filter(|end| *end <= data.len());
+    let Some(data_end) = data_end else {
+      return Err(LrcError::InvalidDsd("chunk size exceeds file length".to_string()));
+    };
+
+    // Chunks are padded to an even length
+    let chunk_end = (data_end + (size & 1)).min(data.len());
+    chunks.push(DffChunk { id, raw: &data[offset..chunk_end], data: &data[data_start..data_end] });
+    offset = chunk_end;
+  }
+
+  Ok(chunks)
+}
+
+fn rewrite_dff(data: &[u8], tag_bytes: &[u8]) -> Result<Vec<u8>> {
+  let mut output = data[..DFF_HEADER_LEN].to_vec();
+  for chunk in dff_chunks(data)?.iter().filter(|chunk| &chunk.id != b"ID3 ") {
+    output.extend_from_slice(chunk.raw);
+  }
+
+  if !tag_bytes.is_empty() {
+    output.extend_from_slice(b"ID3 ");
+    output.extend_from_slice(&(tag_bytes.len() as u64).to_be_bytes());
+    output.extend_from_slice(tag_bytes);
+    if tag_bytes.len() % 2 == 1 {
+      output.push(0);
+    }
+  }
+
+  let form_len = (output.len() - 12) as u64;
+  output[4..12].copy_from_slice(&form_len.to_be_bytes());
+  Ok(output)
+}
+
+// lofty has no public standalone ID3v2 parser, so wrap the tag in a minimal RIFF/WAVE
+// container and let the WAV reader pick up its `ID3 ` chunk
+fn parse_id3v2(tag_bytes: &[u8]) -> Result<Option<Id3v2Tag>> {
+  let padding = tag_bytes.len() % 2;
+  let mut riff = Vec::with_capacity(tag_bytes.len() + 20);
+  riff.extend_from_slice(b"RIFF");
+  riff.extend_from_slice(&((tag_bytes.len() + padding + 12) as u32).to_le_bytes());
+  riff.extend_from_slice(b"WAVE");
+  riff.extend_from_slice(b"ID3 ");
+  riff.extend_from_slice(&(tag_bytes.len() as u32).to_le_bytes());
+  riff.extend_from_slice(tag_bytes);
+  riff.resize(riff.len() + padding, 0);
+
+  let mut wav_file = WavFile::read_from(&mut Cursor::new(riff), ParseOptions::new().read_properties(false))?;
+  Ok(wav_file.remove_id3v2())
+}
@@ -0,0 +1,117 @@
+//! C ABI over the library, declared in `include/lyricsync.h`.
+//!
+//! `cargo build --release --features ffi` builds it into `liblyricsync.so` (or `.dylib`, `.dll`).
+//! Paths and lyrics are NUL-terminated UTF-8. Functions that fail return a negative status or a
+//! null pointer, and [`lyricsync_last_error`] then describes what went wrong.
+
+use crate::{EmbedOptions, FormatResolver, Lyrics};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::path::Path;
+use std::ptr;
+
+/// Returned by functions that succeed without a boolean answer.
+pub const LYRICSYNC_OK: c_int = 0;
+/// Returned by functions that fail; see [`lyricsync_last_error`].
+pub const LYRICSYNC_ERROR: c_int = -1;
+
+thread_local! {
+  static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: Option<String>) {
+  // Messages never contain NUL bytes, but don't panic across the ABI if one ever does
+  let message = message.map(|message| CString::new(message.replace('\0', " ")).unwrap_or_default());
+  LAST_ERROR.with(|last_error| *last_error.borrow_mut() = message);
+}
+
+// Records the outcome of a call, so a stale error isn't reported after a later success
+fn record<T, E: ToString>(result: Result<T, E>) -> Option<T> {
+  match result {
+    Ok(value) => {
+      set_last_error(None);
+      Some(value)
+    },
+    Err(error) => {
+      set_last_error(Some(error.to_string()));
+      None
+    },
+  }
+}
+
+unsafe fn str_arg<'a>(value: *const c_char, name: &str) -> Result<&'a str, String> {
+  if value.is_null() {
+    return Err(format!("{name} is null"));
+  }
+  // SAFETY: the caller passes a NUL-terminated string that outlives this call
+  unsafe { CStr::from_ptr(value) }.to_str().map_err(|_| format!("{name} is not valid UTF-8"))
+}
+
+/// Embeds `lyrics` (LRC or plain text) into the file at `audio_path` with the default options.
+///
+/// Returns `LYRICSYNC_OK` or `LYRICSYNC_ERROR`.
+///
+/// # Safety
+///
+/// Both arguments must be null or point to NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lyricsync_embed_file(audio_path: *const c_char, lyrics: *const c_char) -> c_int {
+  let result = (|| {
+    let audio_path = unsafe { str_arg(audio_path, "audio_path") }?;
+    let lyrics = unsafe { str_arg(lyrics, "lyrics") }?;
+    crate::embed_lyrics(Path::new(audio_path), &Lyrics::new(lyrics), &EmbedOptions::default())
+      .map_err(|e| e.to_string())
+  })();
+  record(result).map_or(LYRICSYNC_ERROR, |()| LYRICSYNC_OK)
+}
+
+/// Returns 1 if the file at `audio_path` has embedded lyrics, 0 if not, or `LYRICSYNC_ERROR`.
+///
+/// # Safety
+///
+/// `audio_path` must be null or point to a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lyricsync_has_lyrics(audio_path: *const c_char) -> c_int {
+  let result = unsafe { str_arg(audio_path, "audio_path") }.and_then(|audio_path| {
+    crate::has_embedded_lyrics(Path::new(audio_path), &FormatResolver::default()).map_err(|e| e.to_string())
+  });
+  record(result).map_or(LYRICSYNC_ERROR, c_int::from)
+}
+
+/// Returns the embedded lyrics of the file at `audio_path`, to be released with
+/// [`lyricsync_free_string`]. Returns null when the file has no lyrics, in which case
+/// [`lyricsync_last_error`] is null too, or on failure.
+///
+/// # Safety
+///
+/// `audio_path` must be null or point to a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lyricsync_extract(audio_path: *const c_char) -> *mut c_char {
+  let result = unsafe { str_arg(audio_path, "audio_path") }.and_then(|audio_path| {
+    crate::extract_lyrics(Path::new(audio_path), &FormatResolver::default()).map_err(|e| e.to_string())
+  });
+  match record(result).flatten() {
+    Some(lyrics) => CString::new(lyrics.replace('\0', "")).unwrap_or_default().into_raw(),
+    None => ptr::null_mut(),
+  }
+}
+
+/// Releases a string returned by [`lyricsync_extract`]. Passing null does nothing.
+///
+/// # Safety
+///
+/// `value` must be null or a pointer returned by this library that hasn't been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lyricsync_free_string(value: *mut c_char) {
+  if !value.is_null() {
+    // SAFETY: the pointer came from `CString::into_raw` in `lyricsync_extract`
+    drop(unsafe { CString::from_raw(value) });
+  }
+}
+
+/// Describes the last failure on the calling thread, or returns null if the last call succeeded.
+/// The string stays valid until the next call into the library on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn lyricsync_last_error() -> *const c_char {
+  LAST_ERROR.with(|last_error| last_error.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
@@ -0,0 +1,193 @@
+//! Client for [Genius](https://genius.com), a source of plain (unsynced) lyrics.
+//!
+//! The API only covers song search; the lyrics themselves are scraped from the song page.
+
+use crate::Result;
+use crate::track::TrackInfo;
+use serde::Deserialize;
+use std::time::Duration;
+use ureq::Agent;
+
+pub const DEFAULT_API_URL: &str = "https://api.genius.com";
+pub const DEFAULT_WEB_URL: &str = "https://genius.com";
+
+const LYRICS_CONTAINER: &str = "data-lyrics-container=\"true\"";
+// Song page header blocks ("123 Contributors", "Translations") nested inside the lyrics container
+const EXCLUDED_BLOCK: &str = "data-exclude-from-selection=\"true\"";
+
+#[derive(Deserialize)]
+struct SearchEnvelope {
+  response: SearchResponse,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+  hits: Vec<SearchHit>,
+}
+
+#[derive(Deserialize)]
+struct SearchHit {
+  result: Song,
+}
+
+#[derive(Deserialize)]
+struct Song {
+  path: String,
+  primary_artist: Artist,
+}
+
+#[derive(Deserialize)]
+struct Artist {
+  name: String,
+}
+
+pub struct GeniusClient {
+  api_url: String,
+  web_url: String,
+  token: String,
+  agent: Agent,
+}
+
+impl GeniusClient {
+  pub fn new(api_url: &str, web_url: &str, token: &str) -> Self {
+    let agent = Agent::config_builder().timeout_global(Some(Duration::from_secs(15))).build().new_agent();
+    Self {
+      api_url: api_url.trim_end_matches('/').to_string(),
+      web_url: web_url.trim_end_matches('/').to_string(),
+      token: token.to_string(),
+      agent,
+    }
+  }
+
+  /// Looks up plain lyrics for a track, returning `None` if no song by the same artist is found.
+  pub fn plain_lyrics(&self, track: &TrackInfo) -> Result<Option<String>> {
+    let search: SearchEnvelope = self
+      .agent
+      .get(format!("{}/search", self.api_url))
+      .header("Authorization", format!("Bearer {}", self.token))
+      .query("q", format!("{} {}", track.artist, track.title))
+      .call()?
+      .body_mut()
+      .read_json()?;
+
+    let artist = track.artist.to_lowercase();
+    let Some(hit) = search.response.hits.into_iter().find(|hit| {
+      let name = hit.result.primary_artist.name.to_lowercase();
+      name.contains(&artist) || artist.contains(&name)
+    }) else {
+      return Ok(None);
+    };
+
+    let html = self.agent.get(format!("{}{}", self.web_url, hit.result.path)).call()?.body_mut().read_to_string()?;
+    Ok(extract_lyrics(&html))
+  }
+}
+
+fn extract_lyrics(html: &str) -> Option<String> {
+  let mut sections = Vec::new();
+  let mut rest = html;
+
+  while let Some(start) = rest.find(LYRICS_CONTAINER) {
+    let container = &rest[start..];
+    let Some(open_end) = container.find('>') else {
+      break;
+    };
+    let (text, consumed) = container_text(&container[open_end + 1..]);
+    sections.push(text);
+    rest = &container[open_end + 1 + consumed..];
+  }
+
+  let lyrics = sections.join("\n").trim().to_string();
+  (!lyrics.is_empty()).then_some(lyrics)
+}
+
+// Collects the text of a lyrics container up to its closing `</div>`, turning `<br>` into line
+// breaks. Returns the text and the number of bytes consumed.
+fn container_text(html: &str) -> (String, usize) {
+  let mut text = String::new();
+  let mut depth = 0usize;
+  let mut excluded_at: Option<usize> = None;
+  let mut position = 0;
+
+  while position < html.len() {
+    let rest = &html[position..];
+    if !rest.starts_with('<') {
+      let end = rest.find('<').unwrap_or(rest.len());
+      if excluded_at.is_none() {
+        text.push_str(&decode_entities(&rest[..end]));
+      }
+      position += end;
+      continue;
+    }
+
+    let Some(tag_end) = rest.find('>') else {
+      break;
+    };
+    let tag = &rest[1..tag_end];
+    position += tag_end + 1;
+
+    let name = tag.trim_start_matches('/').split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or_default();
+    if name.eq_ignore_ascii_case("br") {
+      if excluded_at.is_none() {
+        text.push('\n');
+      }
+    } else if name.eq_ignore_ascii_case("div") {
+      if tag.starts_with('/') {
+        if depth == 0 {
+          break;
+        }
+        depth -= 1;
+        if excluded_at == Some(depth) {
+          excluded_at = None;
+        }
+      } else {
+        if excluded_at.is_none() && tag.contains(EXCLUDED_BLOCK) {
+          excluded_at = Some(depth);
+        }
+        depth += 1;
+      }
+    }
+  }
+
+  (text, position)
+}
+
+fn decode_entities(text: &str) -> String {
+  let mut decoded = String::with_capacity(text.len());
+  let mut rest = text;
+
+  while let Some(start) = rest.find('&') {
+    decoded.push_str(&rest[..start]);
+    rest = &rest[start..];
+
+    let entity = rest.find(';').map(|end| (&rest[1..end], end));
+    let character = entity.and_then(|(name, _)| match name {
+      "amp" => Some('&'),
+      "lt" => Some('<'),
+      "gt" => Some('>'),
+      "quot" => Some('"'),
+      "apos" => Some('\''),
+      _ => {
+        let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+          Some(hex) => u32::from_str_radix(hex, 16).ok(),
+          None => name.strip_prefix('#').and_then(|decimal| decimal.parse().ok()),
+        };
+        code.and_then(char::from_u32)
+      },
+    });
+
+    match (character, entity) {
+      (Some(character), Some((_, end))) => {
+        decoded.push(character);
+        rest = &rest[end + 1..];
+      },
+      _ => {
+        decoded.push('&');
+        rest = &rest[1..];
+      },
+    }
+  }
+
+  decoded.push_str(rest);
+  decoded
+}
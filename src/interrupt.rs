@@ -0,0 +1,68 @@
+//! Ctrl-C and SIGTERM handling, and the marker an interrupted run leaves for `--resume`.
+//!
+//! The first signal lets the files in progress finish and stops the run there, so no file is left
+//! half-written; a second one exits straight away.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set once the user asks the run to stop.
+pub static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+const MARKER_NAME: &str = ".lyricsync-resume.json";
+
+pub fn install() {
+  let handler = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+  // SAFETY: the handler only touches an atomic and calls the async-signal-safe `_exit`
+  unsafe {
+    libc::signal(libc::SIGINT, handler);
+    libc::signal(libc::SIGTERM, handler);
+  }
+}
+
+extern "C" fn handle(_signal: libc::c_int) {
+  if INTERRUPTED.swap(true, Ordering::Relaxed) {
+    // SAFETY: `_exit` is async-signal-safe
+    unsafe { libc::_exit(130) };
+  }
+}
+
+pub fn interrupted() -> bool {
+  INTERRUPTED.load(Ordering::Relaxed)
+}
+
+#[derive(Serialize, Deserialize)]
+struct Marker {
+  command: String,
+  /// Relative to the directory, so the run can be resumed from anywhere
+  remaining: Vec<PathBuf>,
+}
+
+/// Records the files an interrupted `command` didn't get to.
+pub fn write_marker(directory: &Path, command: &str, remaining: &[PathBuf]) -> std::io::Result<PathBuf> {
+  let path = directory.join(MARKER_NAME);
+  let remaining = remaining.iter().map(|file| file.strip_prefix(directory).unwrap_or(file).to_path_buf()).collect();
+  let marker = Marker { command: command.to_string(), remaining };
+  fs::write(&path, serde_json::to_vec_pretty(&marker)?)?;
+  Ok(path)
+}
+
+/// The files left by an interrupted run of `command` over `directory`, if there was one.
+pub fn read_marker(directory: &Path, command: &str) -> Option<Vec<PathBuf>> {
+  let contents = fs::read(directory.join(MARKER_NAME)).ok()?;
+  let marker: Marker = serde_json::from_slice(&contents).ok()?;
+  let remaining = marker.remaining.iter().map(|file| directory.join(file)).collect();
+  (marker.command == command).then_some(remaining)
+}
+
+/// Removes the marker once a run gets through every file.
+pub fn clear_marker(directory: &Path) {
+  match fs::remove_file(directory.join(MARKER_NAME)) {
+    Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+      tracing::warn!(%error, "couldn't remove the resume marker");
+    },
+    _ => {},
+  }
+}
@@ -0,0 +1,157 @@
+//! A record of what each run changed, so `lyricsync undo` can put it back.
+//!
+//! A run that modifies files writes a JSON Lines journal: a header describing the run, then one
+//! entry per change, each flushed to disk before the change is made. Undoing replays the entries
+//! from last to first. Lyrics are restored as text, so a file that had several lyrics frames (one
+//! per language or description) gets a single one back.
+
+use crate::{EmbedOptions, FormatResolver, LrcError, Lyrics, ReplacePolicy, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One line of a journal.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JournalEntry {
+  /// The first line, describing the run
+  Run { command: String, directory: PathBuf, started_at: u64 },
+  /// Lyrics written to or removed from an audio file, with the lyrics it had before
+  Lyrics { path: PathBuf, tag_type: String, previous: Option<String> },
+  /// A sidecar deleted once it was embedded, with the bytes it held
+  SidecarDeleted { path: PathBuf, content: Vec<u8> },
+  /// A sidecar written back in normalized form, with the bytes it held before
+  SidecarRewritten { path: PathBuf, content: Vec<u8> },
+}
+
+pub struct Journal {
+  path: PathBuf,
+  header: JournalEntry,
+  // Created on the first change, so runs that change nothing leave no journal behind. Workers
+  // share it, so entries are appended one at a time.
+  file: Mutex<Option<File>>,
+}
+
+impl Journal {
+  /// Starts a journal in `dir` for a run of `command` over `directory`.
+  pub fn new(dir: &Path, command: &str, directory: &Path) -> Self {
+    let started_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let path = dir.join(format!("{}-{}.jsonl", started_at.as_millis(), std::process::id()));
+    let header = JournalEntry::Run {
+      command: command.to_string(),
+      directory: directory.to_path_buf(),
+      started_at: started_at.as_secs(),
+    };
+    Journal { path, header, file: Mutex::new(None) }
+  }
+
+  /// The per-user state directory journals go to, if the platform has one.
+  pub fn default_dir() -> Option<PathBuf> {
+    dirs::state_dir().or_else(dirs::data_local_dir).map(|dir| dir.join("lyricsync").join("journal"))
+  }
+
+  /// Where the journal is written once something changes.
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Whether anything has been recorded yet.
+  pub fn is_written(&self) -> bool {
+    self.file.lock().unwrap_or_else(PoisonError::into_inner).is_some()
+  }
+
+  /// Appends `entry`, making sure it's on disk before the change it describes is made.
+  pub fn record(&self, entry: &JournalEntry) -> Result<()> {
+    let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+    let write = |file: &mut Option<File>| -> std::io::Result<()> {
+      if file.is_none() {
+        if let Some(parent) = self.path.parent() {
+          fs::create_dir_all(parent)?;
+        }
+        let mut created = File::create_new(&self.path)?;
+        write_entry(&mut created, &self.header)?;
+        *file = Some(created);
+      }
+      let file = file.as_mut().expect("the journal was just created");
+      write_entry(file, entry)?;
+      file.sync_data()
+    };
+    write(&mut file).map_err(|error| LrcError::from(error).in_file(&self.path))
+  }
+
+  /// Records the lyrics `audio_path` has now, before they're replaced or removed.
+  pub fn record_lyrics(&self, audio_path: &Path, resolver: &FormatResolver) -> Result<()> {
+    let tag_type = resolver.resolve(audio_path)?.tag_type().to_string();
+    let previous = crate::extract_lyrics(audio_path, resolver)?;
+    self.record(&JournalEntry::Lyrics { path: audio_path.to_path_buf(), tag_type, previous })
+  }
+}
+
+fn write_entry(file: &mut File, entry: &JournalEntry) -> std::io::Result<()> {
+  let mut line = serde_json::to_vec(entry).expect("journal entries always serialize");
+  line.push(b'\n');
+  file.write_all(&line)
+}
+
+/// The journals in `dir` that haven't been undone, oldest first.
+pub fn journals(dir: &Path) -> Vec<PathBuf> {
+  let mut journals: Vec<PathBuf> = fs::read_dir(dir)
+    .into_iter()
+    .flatten()
+    .filter_map(|entry| entry.ok())
+    .map(|entry| entry.path())
+    .filter(|path| path.extension().is_some_and(|extension| extension == "jsonl"))
+    .collect();
+  // Names start with the run's start time in milliseconds, which keeps the same number of digits
+  // until the 2280s
+  journals.sort();
+  journals
+}
+
+/// Reads a journal, ignoring a last line cut short by a crash.
+pub fn read_journal(path: &Path) -> Result<Vec<JournalEntry>> {
+  let contents = fs::read_to_string(path).map_err(|error| LrcError::from(error).in_file(path))?;
+  let lines: Vec<&str> = contents.lines().filter(|line| !line.trim().is_empty()).collect();
+  let mut entries = Vec::with_capacity(lines.len());
+  for (index, line) in lines.iter().enumerate() {
+    match serde_json::from_str(line) {
+      Ok(entry) => entries.push(entry),
+      Err(_) if index + 1 == lines.len() && !contents.ends_with('\n') => break,
+      Err(error) => {
+        let source = std::io::Error::new(std::io::ErrorKind::InvalidData, error);
+        return Err(LrcError::FileIo { path: path.to_path_buf(), source });
+      },
+    }
+  }
+  Ok(entries)
+}
+
+/// Reverts one change. Sidecars that have been re-created since are left alone, and fail.
+pub fn undo_entry(entry: &JournalEntry, resolver: &FormatResolver) -> Result<()> {
+  match entry {
+    JournalEntry::Run { .. } => Ok(()),
+    JournalEntry::Lyrics { path, previous: Some(previous), .. } => {
+      // Replacing every lyrics field clears whatever the run added beside the old lyrics
+      let options = EmbedOptions { formats: resolver.clone(), replace: ReplacePolicy::All, ..EmbedOptions::default() };
+      crate::embed_lyrics(path, &Lyrics::new(previous.clone()), &options)
+    },
+    JournalEntry::Lyrics { path, previous: None, .. } => crate::strip_lyrics(path, resolver).map(|_| ()),
+    JournalEntry::SidecarDeleted { path, content } => {
+      let create = || File::create_new(path)?.write_all(content);
+      create().map_err(|error| LrcError::from(error).in_file(path))
+    },
+    JournalEntry::SidecarRewritten { path, content } => {
+      fs::write(path, content).map_err(|error| LrcError::from(error).in_file(path))
+    },
+  }
+}
+
+/// Renames an undone journal so it isn't undone again.
+pub fn mark_undone(path: &Path) -> Result<PathBuf> {
+  let undone = path.with_extension("jsonl.undone");
+  fs::rename(path, &undone).map_err(|error| LrcError::from(error).in_file(path))?;
+  Ok(undone)
+}
@@ -0,0 +1,132 @@
+//! Finding the lyrics of a file to show, and following synced ones as the track plays: the part of
+//! `serve`, `now-playing`, `play` and `tap` that isn't about where the file comes from.
+
+use console::{Term, style};
+use lyricsync::lrc::{self, TimedLine};
+use lyricsync::matching::SidecarMatcher;
+use lyricsync::{FormatResolver, extract_lyrics, read_sidecar};
+use std::io;
+use std::path::Path;
+
+// Lines drawn before and after the one being sung, when it's highlighted among them
+const LINES_BEFORE: usize = 2;
+const LINES_AFTER: usize = 4;
+
+/// Where lyrics were read from.
+#[derive(Clone, Copy)]
+pub enum Source {
+  Embedded,
+  Sidecar,
+}
+
+impl Source {
+  pub fn name(self) -> &'static str {
+    match self {
+      Source::Embedded => "embedded",
+      Source::Sidecar => "sidecar",
+    }
+  }
+}
+
+pub struct Lyrics {
+  pub text: String,
+  pub source: Source,
+  pub synced: bool,
+}
+
+/// The lyrics of `audio_path`: the embedded ones, or else its sidecar LRC, or else a `.txt` next to
+/// it.
+pub fn find(audio_path: &Path, formats: &FormatResolver, matcher: &SidecarMatcher) -> Option<Lyrics> {
+  let embedded = extract_lyrics(audio_path, formats).ok().flatten();
+  let (text, source) = match embedded.filter(|text| !text.trim().is_empty()) {
+    Some(text) => (text, Source::Embedded),
+    None => {
+      let sidecars = matcher.find(audio_path, formats).into_iter().map(|sidecar| sidecar.path);
+      let text = sidecars
+        .chain([audio_path.with_extension("txt")])
+        .find_map(|path| read_sidecar(&path).ok().filter(|text| !text.trim().is_empty()))?;
+      (text, Source::Sidecar)
+    },
+  };
+  let synced = !lrc::parse_timed_lines(&text).is_empty();
+  Some(Lyrics { text, source, synced })
+}
+
+/// Synced lyrics in the order they're sung.
+pub struct Timeline {
+  lines: Vec<TimedLine>,
+}
+
+impl Timeline {
+  pub fn new(lyrics: &str) -> Self {
+    // Lines are shown whole, so word timestamps would only get in the way
+    let mut lines = lrc::parse_timed_lines(&lrc::strip_word_timing(lyrics));
+    // A line sung more than once has its timestamps together, so they're spread out here
+    lines.sort_by_key(|line| line.time_ms);
+    Timeline { lines }
+  }
+
+  pub fn lines(&self) -> &[TimedLine] {
+    &self.lines
+  }
+
+  /// The index of the line being sung `elapsed_ms` into the track, if the first has started.
+  pub fn line_at(&self, elapsed_ms: u32) -> Option<usize> {
+    self.lines.partition_point(|line| line.time_ms <= elapsed_ms).checked_sub(1)
+  }
+
+  /// When the line after the one being sung at `elapsed_ms` starts, if there's one.
+  pub fn next_start(&self, elapsed_ms: u32) -> Option<u32> {
+    self.lines.get(self.lines.partition_point(|line| line.time_ms <= elapsed_ms)).map(|line| line.time_ms)
+  }
+}
+
+/// Shows synced lyrics as they're sung. On a terminal the lines around the one being sung are
+/// redrawn in place, with that one highlighted; anywhere else each line is printed as it comes.
+pub struct Display {
+  term: Term,
+  live: bool,
+  color: bool,
+  // What was shown last, and how many lines it took up on the terminal
+  shown: Option<Option<usize>>,
+  drawn: usize,
+}
+
+impl Display {
+  pub fn new(color: bool) -> Self {
+    let term = Term::stdout();
+    Display { live: term.is_term(), term, color, shown: None, drawn: 0 }
+  }
+
+  /// Shows `line` of `timeline` as the one being sung, or the first lines to come before any is.
+  pub fn show(&mut self, timeline: &Timeline, line: Option<usize>) -> io::Result<()> {
+    if self.shown == Some(line) {
+      return Ok(());
+    }
+    self.shown = Some(line);
+    if !self.live {
+      if let Some(index) = line {
+        self.term.write_line(&timeline.lines()[index].text)?;
+      }
+      return Ok(());
+    }
+    let current = line.unwrap_or(0);
+    let first = current.saturating_sub(LINES_BEFORE);
+    let last = (current + LINES_AFTER + 1).min(timeline.lines().len());
+    // Lines that wrapped would throw off how many there are to clear, so they're cut to fit
+    let width = usize::from(self.term.size().1);
+    self.term.clear_last_lines(self.drawn)?;
+    for index in first..last {
+      let text = console::truncate_str(&timeline.lines()[index].text, width.saturating_sub(2), "…");
+      let sung = line == Some(index);
+      match (sung, self.color) {
+        (true, true) => self.term.write_line(&format!("  {}", style(text).bold().cyan().force_styling(true)))?,
+        (true, false) => self.term.write_line(&format!("> {text}"))?,
+        (false, true) => self.term.write_line(&format!("  {}", style(text).dim().force_styling(true)))?,
+        (false, false) => self.term.write_line(&format!("  {text}"))?,
+      }
+    }
+    self.drawn = last - first;
+    Ok(())
+  }
+}
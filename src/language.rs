@@ -0,0 +1,130 @@
+//! Guessing the language of lyrics from the scripts they're written in.
+
+use crate::lrc;
+
+/// Returns the ISO 639-2 code of the language the lyrics are most likely written in.
+///
+/// Only the writing system is considered, so lyrics in Latin script (or with no letters at all)
+/// yield `None`: there's no telling English from French by script alone. Japanese is recognized
+/// by its kana, since Chinese and Japanese share Han characters. Cyrillic is taken as Russian.
+pub fn detect(lyrics: &str) -> Option<[u8; 3]> {
+  let timed_lines = lrc::parse_timed_lines(lyrics);
+  let mut counts = ScriptCounts::default();
+  if timed_lines.is_empty() {
+    counts.add(lyrics);
+  } else {
+    // Keeps header tags like `[ar:Artist]` out of the count
+    timed_lines.iter().for_each(|line| counts.add(&line.text));
+  }
+
+  let (japanese, chinese) = if counts.kana > 0 { (counts.kana + counts.han, 0) } else { (0, counts.han) };
+  let candidates = [
+    (japanese, *b"jpn"),
+    (chinese, *b"chi"),
+    (counts.hangul, *b"kor"),
+    (counts.cyrillic, *b"rus"),
+    (counts.greek, *b"gre"),
+    (counts.arabic, *b"ara"),
+    (counts.hebrew, *b"heb"),
+    (counts.thai, *b"tha"),
+    (counts.devanagari, *b"hin"),
+  ];
+  let (count, language) = candidates.into_iter().max_by_key(|(count, _)| *count)?;
+
+  // Songs in other languages often mix in English lines, and a CJK character stands for a whole
+  // syllable, so a fifth of the letters is enough to tell
+  let total = counts.latin + candidates.iter().map(|(count, _)| count).sum::<usize>();
+  (count > 0 && count * 5 >= total).then_some(language)
+}
+
+#[derive(Default)]
+struct ScriptCounts {
+  latin: usize,
+  kana: usize,
+  han: usize,
+  hangul: usize,
+  cyrillic: usize,
+  greek: usize,
+  arabic: usize,
+  hebrew: usize,
+  thai: usize,
+  devanagari: usize,
+}
+
+impl ScriptCounts {
+  fn add(&mut self, text: &str) {
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+      let count = match u32::from(c) {
+        0x0000..=0x024F | 0x1E00..=0x1EFF => &mut self.latin,
+        0x3040..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9F => &mut self.kana,
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF => &mut self.han,
+        0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => &mut self.hangul,
+        0x0400..=0x052F => &mut self.cyrillic,
+        0x0370..=0x03FF | 0x1F00..=0x1FFF => &mut self.greek,
+        0x0600..=0x06FF | 0x0750..=0x077F => &mut self.arabic,
+        0x0590..=0x05FF => &mut self.hebrew,
+        0x0E00..=0x0E7F => &mut self.thai,
+        0x0900..=0x097F => &mut self.devanagari,
+        _ => continue,
+      };
+      *count += 1;
+    }
+  }
+}
+
+// ISO 639-1 codes with their ISO 639-2 bibliographic and terminology codes, as used in sidecar names
+const SIDECAR_CODES: [(&str, &str, &str); 30] = [
+  ("ar", "ara", "ara"),
+  ("cs", "cze", "ces"),
+  ("da", "dan", "dan"),
+  ("de", "ger", "deu"),
+  ("el", "gre", "ell"),
+  ("en", "eng", "eng"),
+  ("es", "spa", "spa"),
+  ("fi", "fin", "fin"),
+  ("fr", "fre", "fra"),
+  ("he", "heb", "heb"),
+  ("hi", "hin", "hin"),
+  ("hu", "hun", "hun"),
+  ("id", "ind", "ind"),
+  ("it", "ita", "ita"),
+  ("ja", "jpn", "jpn"),
+  ("ko", "kor", "kor"),
+  ("ms", "may", "msa"),
+  ("nl", "dut", "nld"),
+  ("no", "nor", "nor"),
+  ("pl", "pol", "pol"),
+  ("pt", "por", "por"),
+  ("ro", "rum", "ron"),
+  ("ru", "rus", "rus"),
+  ("sv", "swe", "swe"),
+  ("th", "tha", "tha"),
+  ("tl", "tgl", "tgl"),
+  ("tr", "tur", "tur"),
+  ("uk", "ukr", "ukr"),
+  ("vi", "vie", "vie"),
+  ("zh", "chi", "zho"),
+];
+
+/// The code a sidecar in `language` is named with, two letters where there are, as in `Song.ja.lrc`.
+pub fn sidecar_code(language: [u8; 3]) -> String {
+  let language = String::from_utf8_lossy(&language).into_owned();
+  let two_letter = SIDECAR_CODES
+    .iter()
+    .find(|(_, bibliographic, terminology)| [bibliographic, terminology].contains(&&language.as_str()));
+  two_letter.map_or(language, |(two_letter, _, _)| two_letter.to_string())
+}
+
+/// Maps the language part of a sidecar name like `Song.ja.lrc` to an ISO 639-2 code.
+///
+/// Only well-known codes are accepted, so that names such as `Song.alt.lrc` aren't taken for a
+/// language. Three-letter codes are kept as written; two-letter ones map to the bibliographic code,
+/// which is also what [`detect`] returns.
+pub fn from_sidecar_code(code: &str) -> Option<[u8; 3]> {
+  let code = code.to_ascii_lowercase();
+  SIDECAR_CODES.iter().find_map(|&(two_letter, bibliographic, terminology)| {
+    let iso639_2 = if code == terminology { terminology } else { bibliographic };
+    (code == two_letter || code == bibliographic || code == terminology)
+      .then(|| iso639_2.as_bytes().try_into().unwrap())
+  })
+}
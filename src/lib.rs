@@ -0,0 +1,1109 @@
+//! Embedding, reading and removing lyrics in audio file tags.
+//!
+//! The `lyricsync` binary is a thin command-line layer over this crate, which can also be used from
+//! other programs: [`embed_lyrics`] writes lyrics into a file, [`has_embedded_lyrics`] checks for
+//! them and [`extract_lyrics`] reads them back. For whole libraries, an [`EmbedSession`] embeds
+//! each file's LRC sidecars and reports what happened to it.
+
+mod asf;
+pub mod backend;
+pub mod backup;
+pub mod cache;
+pub mod credits;
+mod dsd;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod genius;
+pub mod journal;
+mod language;
+pub mod lock;
+pub mod matching;
+pub use lyricsync_lrc as lrc;
+pub mod lrclib;
+pub mod musixmatch;
+pub mod netease;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod provider;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod romanize;
+mod session;
+pub mod track;
+pub mod transcribe;
+
+use dsd::DsdKind;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use lofty::{
+  TextEncoding,
+  config::WriteOptions,
+  file::{AudioFile, FileType},
+  probe::Probe,
+};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs::{self, FileTimes, OpenOptions};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use walkdir::WalkDir;
+
+pub use session::{EmbedEvent, EmbedOutcome, EmbedSession, FileResult, SkipReason};
+
+/// Errors raised while reading, writing or fetching lyrics.
+#[derive(Error, Debug)]
+pub enum LrcError {
+  #[error("IO error: {0}")]
+  Io(#[from] std::io::Error),
+  #[error("Audio file error: {0}")]
+  Audio(#[from] lofty::error::LoftyError),
+  #[error("Unsupported file format: {0}")]
+  UnsupportedFormat(String),
+  #[error("Invalid glob pattern: {0}")]
+  InvalidPattern(String),
+  #[error("Invalid ASF file: {0}")]
+  InvalidAsf(String),
+  #[error("Invalid DSD file: {0}")]
+  InvalidDsd(String),
+  #[error("HTTP error: {0}")]
+  Http(#[from] ureq::Error),
+  #[error("Lyrics provider error: {0}")]
+  Provider(String),
+  #[error("{}: the file has no tag to hold lyrics", path.display())]
+  TagMissing { path: PathBuf },
+  #[error("{}: the file is read-only ({source})", path.display())]
+  ReadOnlyFile { path: PathBuf, source: std::io::Error },
+  #[error("{}: corrupt audio file ({source})", path.display())]
+  CorruptAudio { path: PathBuf, source: Box<dyn std::error::Error + Send + Sync> },
+  #[error("{}: the lyrics are {size} bytes, more than the {limit} its tag can hold", path.display())]
+  LyricsTooLarge { path: PathBuf, size: usize, limit: usize },
+  #[error("{}: no matching LRC file", path.display())]
+  NoMatchingLrc { path: PathBuf },
+  #[error("{}: invalid LRC file ({reason})", path.display())]
+  InvalidLrc { path: PathBuf, reason: String },
+  #[error("{}: {source}", path.display())]
+  FileIo { path: PathBuf, source: std::io::Error },
+  #[error("{}: couldn't back up the file ({source})", path.display())]
+  BackupFailed { path: PathBuf, source: std::io::Error },
+  #[error("{}: the {size}-byte file doesn't fit in the {limit}-byte backup budget", path.display())]
+  BackupFull { path: PathBuf, size: u64, limit: u64 },
+  #[error("{}: the saved file failed verification ({reason}); the original was left in place", path.display())]
+  VerifyFailed { path: PathBuf, reason: String },
+  #[error(
+    "{}: another lyricsync run is already working on this directory{}",
+    path.display(),
+    pid.map(|pid| format!(" (process {pid})")).unwrap_or_default()
+  )]
+  Locked { path: PathBuf, pid: Option<u32> },
+  #[error("{}: couldn't transcribe the file ({reason})", path.display())]
+  Transcription { path: PathBuf, reason: String },
+  #[error("no journal to undo in {}", dir.display())]
+  NoJournal { dir: PathBuf },
+}
+
+impl LrcError {
+  /// The file the error is about, for the variants that name one.
+  pub fn path(&self) -> Option<&Path> {
+    match self {
+      LrcError::TagMissing { path }
+      | LrcError::ReadOnlyFile { path, .. }
+      | LrcError::CorruptAudio { path, .. }
+      | LrcError::LyricsTooLarge { path, .. }
+      | LrcError::NoMatchingLrc { path }
+      | LrcError::InvalidLrc { path, .. }
+      | LrcError::FileIo { path, .. }
+      | LrcError::BackupFailed { path, .. }
+      | LrcError::BackupFull { path, .. }
+      | LrcError::VerifyFailed { path, .. }
+      | LrcError::Transcription { path, .. }
+      | LrcError::Locked { path, .. } => Some(path),
+      _ => None,
+    }
+  }
+
+  /// What sort of failure this is, to tell files that need new format support from broken ones.
+  pub fn kind(&self) -> FailureKind {
+    match self {
+      LrcError::UnsupportedFormat(_) => FailureKind::Unsupported,
+      LrcError::TagMissing { .. } => FailureKind::NoTag,
+      LrcError::Io(_) | LrcError::FileIo { .. } | LrcError::ReadOnlyFile { .. } | LrcError::BackupFailed { .. } => {
+        FailureKind::Io
+      },
+      LrcError::Audio(_) | LrcError::CorruptAudio { .. } | LrcError::InvalidAsf(_) | LrcError::InvalidDsd(_) => {
+        FailureKind::Corrupt
+      },
+      LrcError::InvalidLrc { .. } => FailureKind::InvalidLrc,
+      _ => FailureKind::Other,
+    }
+  }
+
+  // Attaches the file an I/O or parsing error came from, so a large run's failures can be told apart
+  pub(crate) fn in_file(self, path: &Path) -> Self {
+    let path = path.to_path_buf();
+    match self {
+      LrcError::Io(source) if source.kind() == std::io::ErrorKind::PermissionDenied => {
+        LrcError::ReadOnlyFile { path, source }
+      },
+      LrcError::Io(source) => LrcError::FileIo { path, source },
+      // lofty opens the file again to save it, so a read-only file can fail there too
+      LrcError::Audio(error) => match error.kind() {
+        lofty::error::ErrorKind::Io(io) if io.kind() == std::io::ErrorKind::PermissionDenied => {
+          LrcError::ReadOnlyFile { path, source: std::io::Error::new(io.kind(), io.to_string()) }
+        },
+        // Otherwise lofty's I/O errors come from seeking around a file that isn't what it claims to be
+        _ => LrcError::CorruptAudio { path, source: Box::new(error) },
+      },
+      LrcError::InvalidAsf(message) | LrcError::InvalidDsd(message) => {
+        LrcError::CorruptAudio { path, source: message.into() }
+      },
+      other => other,
+    }
+  }
+}
+
+/// The broad sort of an [`LrcError`], as counted in the summary's breakdown of failures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FailureKind {
+  /// A format lyricsync can't write to
+  Unsupported,
+  /// A file with no tag to hold lyrics
+  NoTag,
+  /// Reading or writing a file failed, e.g. a read-only file or a full disk
+  Io,
+  /// A file that isn't the audio it claims to be
+  Corrupt,
+  /// An LRC file refused for failing [validation](lrc::validate)
+  InvalidLrc,
+  Other,
+}
+
+impl FailureKind {
+  /// How the summary names it.
+  pub fn label(self) -> &'static str {
+    match self {
+      FailureKind::Unsupported => "unsupported format",
+      FailureKind::NoTag => "no tag structure",
+      FailureKind::Io => "I/O error",
+      FailureKind::Corrupt => "corrupt file",
+      FailureKind::InvalidLrc => "invalid LRC file",
+      FailureKind::Other => "other",
+    }
+  }
+}
+
+/// Result type used throughout lyricsync.
+pub type Result<T> = std::result::Result<T, LrcError>;
+
+/// An audio format lyricsync can embed lyrics into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioFormat {
+  Flac,
+  Mp3,
+  M4a,
+  Opus,
+  Wav,
+  Aiff,
+  Ape,
+  WavPack,
+  Wma,
+  Dsf,
+  Dff,
+  // Only reached through content probing, e.g. ADTS streams misnamed as .mp3
+  Aac,
+}
+
+impl AudioFormat {
+  /// A short lowercase name, as used in reports.
+  pub fn name(self) -> &'static str {
+    match self {
+      AudioFormat::Flac => "flac",
+      AudioFormat::Mp3 => "mp3",
+      AudioFormat::M4a => "m4a",
+      AudioFormat::Opus => "opus",
+      AudioFormat::Wav => "wav",
+      AudioFormat::Aiff => "aiff",
+      AudioFormat::Ape => "ape",
+      AudioFormat::WavPack => "wavpack",
+      AudioFormat::Wma => "wma",
+      AudioFormat::Dsf => "dsf",
+      AudioFormat::Dff => "dff",
+      AudioFormat::Aac => "aac",
+    }
+  }
+
+  /// The kind of tag lyrics are stored in, as named in reports and journals.
+  pub fn tag_type(self) -> &'static str {
+    match self {
+      AudioFormat::Flac | AudioFormat::Opus => "Vorbis comments",
+      AudioFormat::M4a => "MP4 atoms",
+      AudioFormat::Ape | AudioFormat::WavPack => "APEv2",
+      AudioFormat::Wma => "ASF attributes",
+      AudioFormat::Mp3
+      | AudioFormat::Wav
+      | AudioFormat::Aiff
+      | AudioFormat::Dsf
+      | AudioFormat::Dff
+      | AudioFormat::Aac => "ID3v2",
+    }
+  }
+}
+
+// Language code of the lyrics frames this tool writes when none is given or detected
+const ID3_LANGUAGE: [u8; 3] = *b"eng";
+
+/// Description of the ID3v2 lyrics frames translations are written to, the versions labeled with
+/// another language than the one without a label, so players can tell them from the original.
+pub const TRANSLATION_DESCRIPTION: &str = "translation";
+
+/// Description of the ID3v2 lyrics frames [romanized](romanize) lyrics are written to.
+pub const ROMANIZATION_DESCRIPTION: &str = "romanization";
+
+/// Which MP4 atoms lyrics are written to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mp4Lyrics {
+  /// `©lyr` only
+  #[default]
+  Plain,
+  /// `----:com.apple.iTunes:LYRICS` only
+  Timed,
+  Both,
+}
+
+/// Which Vorbis comment keys lyrics are written to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VorbisKeys {
+  #[default]
+  Lyrics,
+  UnsyncedLyrics,
+  Both,
+}
+
+/// Which ID3v2 frames timestamped lyrics are written to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Id3Frames {
+  Uslt,
+  Sylt,
+  #[default]
+  Both,
+}
+
+/// What embedding does with word timestamps, the kind TTML files and enhanced LRC time words with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WordTiming {
+  /// Embed them, and time SYLT frames word by word, for players that highlight words as they're sung
+  Keep,
+  /// Time lines as a whole, which every player understands
+  #[default]
+  Strip,
+}
+
+/// What embedding does with translations, the `Song.zh.lrc` next to a `Song.lrc`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Translations {
+  /// Embed each in ID3v2 frames of its own, labeled with its language and described as a
+  /// [translation](TRANSLATION_DESCRIPTION), next to the original's; other formats only get the
+  /// original
+  #[default]
+  Frames,
+  /// [Interleave](lrc::merge_translation) them with the original, a translated line after each of
+  /// its lines, into the lyrics of every format
+  Merge,
+}
+
+/// Where [romanized](romanize) lyrics go, for Japanese, Chinese and Korean ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Romanization {
+  /// In ID3v2 frames of their own, labeled with the original's language and described as a
+  /// [romanization](ROMANIZATION_DESCRIPTION); other formats don't get them
+  Frames,
+  /// [Interleaved](lrc::merge_translation) with the original, a romanized line after each of its
+  /// lines, in every format. Only used by [`EmbedSession`]
+  Merge,
+}
+
+/// Which ID3v2 revision tags are saved as.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Id3Version {
+  /// ID3v2.3, for car stereos and older players that can't read v2.4
+  V3,
+  #[default]
+  V4,
+}
+
+/// Text encoding of the ID3v2 lyrics frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Id3Encoding {
+  Utf8,
+  /// UTF-16 with a byte order mark
+  Utf16,
+  Latin1,
+}
+
+/// Which existing lyrics fields embedding replaces.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReplacePolicy {
+  /// Every lyrics field, including ID3v2 frames with other descriptions
+  All,
+  /// The fields this tool writes, clearing the ones not selected this time so no stale lyrics remain
+  #[default]
+  Own,
+  /// Only the fields being written; the rest are left untouched
+  Matching,
+}
+
+/// What an [`EmbedSession`] does with files that already have embedded lyrics.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+  #[default]
+  Overwrite,
+  Skip,
+  /// Overwrite only when a sidecar was modified after the audio file
+  IfNewer,
+  /// Leave it to the session's conflict handler, skipping the file when there is none
+  Ask,
+}
+
+/// Settings that control how lyrics are written into tags. The fields from `conflict` to
+/// `translations` are about sidecars, which to embed and how to prepare their lyrics, and are only
+/// used by [`EmbedSession`].
+#[derive(Clone, Debug, Default)]
+pub struct EmbedOptions {
+  /// How audio files are mapped to formats
+  pub formats: FormatResolver,
+  pub replace: ReplacePolicy,
+  pub id3_frames: Id3Frames,
+  pub id3_version: Id3Version,
+  /// Defaults to UTF-8 for ID3v2.4 and UTF-16 for ID3v2.3
+  pub id3_encoding: Option<Id3Encoding>,
+  /// ISO 639-2 code for ID3v2 lyrics frames, detected from the lyrics when `None`
+  pub language: Option<[u8; 3]>,
+  /// Description of the ID3v2 lyrics frames to write and replace; empty when `None`
+  pub description: Option<String>,
+  pub vorbis_keys: VorbisKeys,
+  /// Also write timestamped lyrics to `SYNCEDLYRICS`
+  pub vorbis_synced_key: bool,
+  pub mp4_lyrics: Mp4Lyrics,
+  /// Put the audio file's modification and access times back after writing
+  pub preserve_mtime: bool,
+  /// Read the saved file back before it replaces the original, checking the lyrics and the audio
+  /// stream's properties
+  pub verify: bool,
+  /// [`embed_lyrics`] always overwrites
+  pub conflict: ConflictPolicy,
+  /// Milliseconds to [shift](lrc::shift) sidecars' timestamps by before embedding them, later when
+  /// positive
+  pub offset_ms: i64,
+  /// Keys of the LRC header tags to [leave out](lrc::strip_headers) of the embedded lyrics, like
+  /// `ar`
+  pub strip_headers: Vec<String>,
+  /// Lines to [leave out](credits::strip) of the embedded lyrics when they match any of these, like
+  /// [`credits::defaults`]
+  pub strip_lines: Vec<Regex>,
+  /// [Sort](lrc::sort_lines) the timed lines by time and merge exact duplicates before embedding
+  pub sort_lines: bool,
+  /// Whether word timestamps are embedded or [taken out](lrc::strip_word_timing) before embedding
+  pub word_timing: WordTiming,
+  /// Whether translations are embedded on their own or merged into the original lyrics
+  pub translations: Translations,
+  /// Where romanized lyrics go, left out when `None`. Builds without the `romanize` feature have
+  /// none to embed
+  pub romanization: Option<Romanization>,
+}
+
+impl EmbedOptions {
+  /// Starts from the defaults, which match the command line's.
+  pub fn builder() -> EmbedOptionsBuilder {
+    EmbedOptionsBuilder::default()
+  }
+
+  /// A sidecar's lyrics as an [`EmbedSession`] embeds them: shifted by `offset_ms`, without the
+  /// header tags, lines and word timestamps these options strip, with condensed lines
+  /// [expanded](lrc::expand) and, when `sort_lines` is set, sorted. What sorting changed is logged,
+  /// naming `source`, the file the lyrics come from.
+  pub fn prepare(&self, source: &Path, lyrics: &str, offset_ms: i64) -> String {
+    let mut lyrics = lrc::shift(lyrics, offset_ms);
+    if self.word_timing == WordTiming::Strip {
+      lyrics = lrc::strip_word_timing(&lyrics);
+    }
+    let lyrics = lrc::strip_headers(&lyrics, &self.strip_headers);
+    let lyrics = credits::strip(&lyrics, &self.strip_lines);
+    let lyrics = lrc::expand(&lyrics);
+    if !self.sort_lines {
+      return lyrics;
+    }
+    let (sorted, report) = lrc::sort_lines(&lyrics);
+    if report != lrc::SortReport::default() {
+      tracing::warn!(
+        path = %source.display(),
+        "sorted {} lines that were out of order and merged {} duplicates",
+        report.out_of_order,
+        report.duplicates
+      );
+    }
+    sorted
+  }
+
+  fn id3v2_write_options(&self) -> WriteOptions {
+    WriteOptions::default().use_id3v23(self.id3_version == Id3Version::V3)
+  }
+
+  fn id3v2_description(&self) -> &str {
+    self.description.as_deref().unwrap_or_default()
+  }
+
+  fn id3v2_language(&self, lyrics: &str) -> [u8; 3] {
+    self.language.or_else(|| language::detect(lyrics)).unwrap_or(ID3_LANGUAGE)
+  }
+
+  fn id3v2_text_encoding(&self, lyrics: &str) -> TextEncoding {
+    match (self.id3_encoding, self.id3_version) {
+      // Latin-1 can't hold every lyric, and lofty would mangle what doesn't fit
+      (Some(Id3Encoding::Latin1), _) if lyrics.chars().all(|c| u32::from(c) <= 0xFF) => TextEncoding::Latin1,
+      // ID3v2.3 has no UTF-8 encoding
+      (None | Some(Id3Encoding::Utf8), Id3Version::V4) => TextEncoding::UTF8,
+      _ => TextEncoding::UTF16,
+    }
+  }
+}
+
+/// Builds [`EmbedOptions`] one setting at a time.
+#[derive(Clone, Debug, Default)]
+pub struct EmbedOptionsBuilder {
+  options: EmbedOptions,
+}
+
+impl EmbedOptionsBuilder {
+  pub fn formats(mut self, formats: FormatResolver) -> Self {
+    self.options.formats = formats;
+    self
+  }
+
+  /// Detect formats from file contents instead of extensions.
+  pub fn probe(mut self, probe: bool) -> Self {
+    self.options.formats.probe = probe;
+    self
+  }
+
+  /// Treats files with `extension` as `format`, like `--ext-alias m4r=m4a`.
+  pub fn extension_alias(mut self, extension: &str, format: AudioFormat) -> Self {
+    self.options.formats.aliases.insert(extension.trim_start_matches('.').to_ascii_lowercase(), format);
+    self
+  }
+
+  pub fn replace(mut self, replace: ReplacePolicy) -> Self {
+    self.options.replace = replace;
+    self
+  }
+
+  pub fn id3_frames(mut self, id3_frames: Id3Frames) -> Self {
+    self.options.id3_frames = id3_frames;
+    self
+  }
+
+  pub fn id3_version(mut self, id3_version: Id3Version) -> Self {
+    self.options.id3_version = id3_version;
+    self
+  }
+
+  pub fn id3_encoding(mut self, id3_encoding: Id3Encoding) -> Self {
+    self.options.id3_encoding = Some(id3_encoding);
+    self
+  }
+
+  /// Sets the ISO 639-2 code of ID3v2 lyrics frames instead of detecting it.
+  pub fn language(mut self, language: [u8; 3]) -> Self {
+    self.options.language = Some(language);
+    self
+  }
+
+  pub fn description(mut self, description: impl Into<String>) -> Self {
+    self.options.description = Some(description.into());
+    self
+  }
+
+  pub fn vorbis_keys(mut self, vorbis_keys: VorbisKeys) -> Self {
+    self.options.vorbis_keys = vorbis_keys;
+    self
+  }
+
+  pub fn vorbis_synced_key(mut self, vorbis_synced_key: bool) -> Self {
+    self.options.vorbis_synced_key = vorbis_synced_key;
+    self
+  }
+
+  pub fn mp4_lyrics(mut self, mp4_lyrics: Mp4Lyrics) -> Self {
+    self.options.mp4_lyrics = mp4_lyrics;
+    self
+  }
+
+  pub fn preserve_mtime(mut self, preserve_mtime: bool) -> Self {
+    self.options.preserve_mtime = preserve_mtime;
+    self
+  }
+
+  pub fn verify(mut self, verify: bool) -> Self {
+    self.options.verify = verify;
+    self
+  }
+
+  pub fn conflict(mut self, conflict: ConflictPolicy) -> Self {
+    self.options.conflict = conflict;
+    self
+  }
+
+  pub fn offset_ms(mut self, offset_ms: i64) -> Self {
+    self.options.offset_ms = offset_ms;
+    self
+  }
+
+  pub fn strip_headers(mut self, keys: impl IntoIterator<Item = impl Into<String>>) -> Self {
+    self.options.strip_headers = keys.into_iter().map(Into::into).collect();
+    self
+  }
+
+  pub fn strip_lines(mut self, patterns: impl IntoIterator<Item = Regex>) -> Self {
+    self.options.strip_lines = patterns.into_iter().collect();
+    self
+  }
+
+  pub fn sort_lines(mut self, sort_lines: bool) -> Self {
+    self.options.sort_lines = sort_lines;
+    self
+  }
+
+  pub fn word_timing(mut self, word_timing: WordTiming) -> Self {
+    self.options.word_timing = word_timing;
+    self
+  }
+
+  pub fn translations(mut self, translations: Translations) -> Self {
+    self.options.translations = translations;
+    self
+  }
+
+  pub fn romanization(mut self, romanization: Romanization) -> Self {
+    self.options.romanization = Some(romanization);
+    self
+  }
+
+  pub fn build(self) -> EmbedOptions {
+    self.options
+  }
+}
+
+/// Maps audio files to formats, either by extension or by probing their contents.
+#[derive(Clone, Debug, Default)]
+pub struct FormatResolver {
+  /// Detect formats from file contents instead of extensions
+  pub probe: bool,
+  /// Extra extensions to treat as known formats, keyed by lowercase extension
+  pub aliases: HashMap<String, AudioFormat>,
+}
+
+impl FormatResolver {
+  /// Maps a path to a format by its extension alone, as used when scanning directories.
+  pub fn by_extension(&self, path: &Path) -> Option<AudioFormat> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    self.aliases.get(&extension).copied().or_else(|| format_for_extension(&extension))
+  }
+
+  /// Determines the format of a file, failing for anything lyricsync can't handle.
+  pub fn resolve(&self, path: &Path) -> Result<AudioFormat> {
+    if self.probe {
+      return probe_format(path);
+    }
+
+    self
+      .by_extension(path)
+      .ok_or_else(|| LrcError::UnsupportedFormat(path.extension().unwrap_or_default().to_string_lossy().to_string()))
+  }
+}
+
+/// Maps a lowercase file extension to the format lyricsync handles it as.
+pub fn format_for_extension(extension: &str) -> Option<AudioFormat> {
+  let format = match extension {
+    "flac" => AudioFormat::Flac,
+    "mp3" => AudioFormat::Mp3,
+    "m4a" | "mp4" | "m4b" => AudioFormat::M4a,
+    "opus" => AudioFormat::Opus,
+    "wav" => AudioFormat::Wav,
+    "aiff" | "aif" | "aifc" => AudioFormat::Aiff,
+    "ape" => AudioFormat::Ape,
+    "wv" => AudioFormat::WavPack,
+    "wma" => AudioFormat::Wma,
+    "dsf" => AudioFormat::Dsf,
+    "dff" => AudioFormat::Dff,
+    _ => return None,
+  };
+  Some(format)
+}
+
+fn probe_format(path: &Path) -> Result<AudioFormat> {
+  let probe = Probe::open(path)?.guess_file_type()?;
+  let format = match probe.file_type() {
+    Some(FileType::Flac) => AudioFormat::Flac,
+    Some(FileType::Mpeg) => AudioFormat::Mp3,
+    Some(FileType::Mp4) => AudioFormat::M4a,
+    Some(FileType::Opus) => AudioFormat::Opus,
+    Some(FileType::Wav) => AudioFormat::Wav,
+    Some(FileType::Aiff) => AudioFormat::Aiff,
+    Some(FileType::Ape) => AudioFormat::Ape,
+    Some(FileType::WavPack) => AudioFormat::WavPack,
+    Some(FileType::Aac) => AudioFormat::Aac,
+    Some(other) => return Err(LrcError::UnsupportedFormat(format!("{other:?}"))),
+    // lofty doesn't know ASF or DSD, so check their magic bytes ourselves
+    None => {
+      let mut magic = [0; 4];
+      OpenOptions::new().read(true).open(path)?.read_exact(&mut magic)?;
+      match &magic {
+        [0x30, 0x26, 0xB2, 0x75] => AudioFormat::Wma,
+        b"DSD " => AudioFormat::Dsf,
+        b"FRM8" => AudioFormat::Dff,
+        _ => return Err(LrcError::UnsupportedFormat("unrecognized file contents".to_string())),
+      }
+    },
+  };
+  Ok(format)
+}
+
+/// Which existing ID3v2 lyrics frames count as embedded lyrics.
+#[derive(Default)]
+pub struct FrameFilter<'a> {
+  /// Only frames with this description count
+  pub description: Option<&'a str>,
+  /// Each of these languages needs a frame of its own
+  pub languages: &'a [[u8; 3]],
+}
+
+/// Checks for embedded lyrics in any of the fields this tool reads.
+pub fn has_embedded_lyrics(audio_path: &Path, resolver: &FormatResolver) -> Result<bool> {
+  has_embedded_lyrics_matching(audio_path, resolver, &FrameFilter::default())
+}
+
+/// Like [`has_embedded_lyrics`], with `filter` narrowing down which ID3v2 lyrics frames count.
+/// Other formats have no such labels, so any lyrics count.
+pub fn has_embedded_lyrics_matching(
+  audio_path: &Path,
+  resolver: &FormatResolver,
+  filter: &FrameFilter<'_>,
+) -> Result<bool> {
+  let _span = tracing::info_span!("read").entered();
+  let detect = || backend::backend(resolver.resolve(audio_path)?).detect(audio_path, filter);
+  detect().map_err(|error| error.in_file(audio_path))
+}
+
+/// Whether the format stores lyrics in ID3v2 frames, the only ones labeled with a language.
+pub fn uses_id3v2(format: AudioFormat) -> bool {
+  matches!(
+    format,
+    AudioFormat::Mp3 | AudioFormat::Wav | AudioFormat::Aiff | AudioFormat::Dsf | AudioFormat::Dff | AudioFormat::Aac
+  )
+}
+
+fn dsd_kind(format: AudioFormat) -> DsdKind {
+  if format == AudioFormat::Dff { DsdKind::Dff } else { DsdKind::Dsf }
+}
+
+/// Reads the embedded lyrics of a file, preferring plain text over synchronized frames.
+///
+/// Synchronized-only frames (SYLT, `WM/Lyrics_Synchronised`) are rendered back into LRC text.
+pub fn extract_lyrics(audio_path: &Path, resolver: &FormatResolver) -> Result<Option<String>> {
+  let _span = tracing::info_span!("read").entered();
+  let read = || backend::backend(resolver.resolve(audio_path)?).read(audio_path);
+  read().map_err(|error| error.in_file(audio_path))
+}
+
+/// How a file's embedded lyrics compare to its sidecar LRC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LyricsStatus {
+  Match,
+  Differs,
+  EmbeddedOnly,
+  SidecarOnly,
+  Missing,
+}
+
+impl LyricsStatus {
+  /// The name `lyricsync check` prints for the status.
+  pub fn label(&self) -> &'static str {
+    match self {
+      LyricsStatus::Match => "MATCH",
+      LyricsStatus::Differs => "DIFFERS",
+      LyricsStatus::EmbeddedOnly => "EMBEDDED",
+      LyricsStatus::SidecarOnly => "SIDECAR",
+      LyricsStatus::Missing => "MISSING",
+    }
+  }
+}
+
+/// Compares the lyrics embedded in a file with its `.lrc` sidecar.
+pub fn check_lyrics(audio_path: &Path, resolver: &FormatResolver) -> Result<LyricsStatus> {
+  let embedded = extract_lyrics(audio_path, resolver)?.filter(|lyrics| !lyrics.trim().is_empty());
+  let sidecar = match lrc_sidecars(audio_path).into_iter().find(|sidecar| sidecar.language.is_none()) {
+    Some(sidecar) => Some(read_sidecar(&sidecar.path)?),
+    None => None,
+  };
+
+  let status = match (embedded, sidecar) {
+    (Some(embedded), Some(sidecar)) if lyrics_match(&embedded, &sidecar) => LyricsStatus::Match,
+    (Some(_), Some(_)) => LyricsStatus::Differs,
+    (Some(_), None) => LyricsStatus::EmbeddedOnly,
+    (None, Some(_)) => LyricsStatus::SidecarOnly,
+    (None, None) => LyricsStatus::Missing,
+  };
+  Ok(status)
+}
+
+// Ignores line-ending and trailing whitespace differences, which tags don't reliably preserve
+pub(crate) fn lyrics_match(a: &str, b: &str) -> bool {
+  normalize_lyrics(a) == normalize_lyrics(b)
+}
+
+fn normalize_lyrics(lyrics: &str) -> Vec<&str> {
+  let mut lines: Vec<&str> = lyrics.lines().map(str::trim_end).collect();
+  while lines.last().is_some_and(|line| line.is_empty()) {
+    lines.pop();
+  }
+  lines
+}
+
+/// One version of a song's lyrics, e.g. from `Song.ja.lrc`.
+#[derive(Clone, Debug)]
+pub struct Lyrics {
+  /// ISO 639-2 code of the language, e.g. from the sidecar name; `None` for a plain `Song.lrc`
+  pub language: Option<[u8; 3]>,
+  /// LRC or plain text
+  pub text: String,
+}
+
+impl Lyrics {
+  /// Lyrics without a known language, which is then taken from the options or detected.
+  pub fn new(text: impl Into<String>) -> Self {
+    Lyrics { language: None, text: text.into() }
+  }
+}
+
+/// Embeds lyrics into an audio file.
+pub fn embed_lyrics(audio_path: &Path, lyrics: &Lyrics, options: &EmbedOptions) -> Result<()> {
+  embed_lyrics_variants(audio_path, std::slice::from_ref(lyrics), options)
+}
+
+/// Embeds several versions of a song's lyrics, such as one per language, into an audio file.
+///
+/// ID3v2 formats get frames for each version; formats with a single lyrics field take the first.
+/// When there's a version without a language, the ones in other languages are taken for its
+/// translations, and their frames are described as [`TRANSLATION_DESCRIPTION`].
+pub fn embed_lyrics_variants(audio_path: &Path, lyrics: &[Lyrics], options: &EmbedOptions) -> Result<()> {
+  let times = if options.preserve_mtime { Some(file_times(audio_path)?) } else { None };
+  write_lyrics(audio_path, lyrics, options, times)
+}
+
+// Writes the lyrics, then puts back `times`, taken before anything read the file and bumped its
+// access time
+pub(crate) fn write_lyrics(
+  audio_path: &Path,
+  lyrics: &[Lyrics],
+  options: &EmbedOptions,
+  times: Option<FileTimes>,
+) -> Result<()> {
+  let _span = tracing::info_span!("write").entered();
+  let write = || -> Result<()> {
+    let format = options.formats.resolve(audio_path)?;
+    if lyrics.is_empty() {
+      return Ok(());
+    }
+    let backend = backend::backend(format);
+    save_atomically(audio_path, |temp_path| {
+      backend.write(temp_path, lyrics, options)?;
+      if options.verify {
+        verify_saved(audio_path, temp_path, backend, lyrics, options)?;
+      }
+      Ok(true)
+    })?;
+    if let Some(times) = times {
+      OpenOptions::new().write(true).open(audio_path)?.set_times(times)?;
+    }
+    Ok(())
+  };
+  write().map_err(|error| error.in_file(audio_path))
+}
+
+// Checks the copy at `saved_path` holds what was written and still has the audio of `audio_path`.
+// lofty can't read the properties of every format, so those only get the lyrics checked.
+fn verify_saved(
+  audio_path: &Path,
+  saved_path: &Path,
+  backend: &dyn backend::LyricsBackend,
+  lyrics: &[Lyrics],
+  options: &EmbedOptions,
+) -> Result<()> {
+  let failed = |reason: String| LrcError::VerifyFailed { path: audio_path.to_path_buf(), reason };
+  let read_back = backend.read_all(saved_path).map_err(|error| failed(format!("couldn't read it back: {error}")))?;
+  for written in backend.written(lyrics, options) {
+    if !written.trim().is_empty() && !read_back.iter().any(|value| lyrics_read_back(&written, value)) {
+      return Err(failed("the lyrics read back differ from the ones written".to_string()));
+    }
+  }
+
+  let properties = |path: &Path| -> Result<_> {
+    let file = Probe::open(path)?.guess_file_type()?.read()?;
+    Ok(file.properties().clone())
+  };
+  if let Ok(before) = properties(audio_path) {
+    let after = properties(saved_path).map_err(|error| failed(format!("couldn't read the audio stream: {error}")))?;
+    let stream = |p: &lofty::properties::FileProperties| (p.duration(), p.sample_rate(), p.channels(), p.bit_depth());
+    if stream(&before) != stream(&after) {
+      return Err(failed("the audio stream changed".to_string()));
+    }
+  }
+  Ok(())
+}
+
+// Whether `value` holds `written`, either as text or, for fields that only keep timestamped lines
+// such as SYLT frames, as the same timed lines and words
+fn lyrics_read_back(written: &str, value: &str) -> bool {
+  if lyrics_match(written, value) {
+    return true;
+  }
+  let timed_words = lrc::parse_timed_words(written);
+  !timed_words.is_empty() && lrc::parse_timed_words(value) == timed_words
+}
+
+pub(crate) fn file_times(path: &Path) -> Result<FileTimes> {
+  let times = || -> std::io::Result<FileTimes> {
+    let metadata = fs::metadata(path)?;
+    Ok(FileTimes::new().set_accessed(metadata.accessed()?).set_modified(metadata.modified()?))
+  };
+  times().map_err(|error| LrcError::from(error).in_file(path))
+}
+
+/// Removes every lyrics field this tool knows about, returning whether anything was removed.
+pub fn strip_lyrics(audio_path: &Path, resolver: &FormatResolver) -> Result<bool> {
+  let _span = tracing::info_span!("write").entered();
+  let remove = || {
+    let backend = backend::backend(resolver.resolve(audio_path)?);
+    save_atomically(audio_path, |temp_path| backend.remove(temp_path))
+  };
+  remove().map_err(|error| error.in_file(audio_path))
+}
+
+// Runs `update` on a copy of the file in the same directory and renames it over the original when
+// `update` saved something, so a crash mid-save leaves either the old file or the new one. The copy
+// is deleted if `update` fails or changes nothing.
+fn save_atomically(audio_path: &Path, update: impl FnOnce(&Path) -> Result<bool>) -> Result<bool> {
+  // Renaming over a symlink would replace the link rather than the file it points to
+  let audio_path = &fs::canonicalize(audio_path)?;
+  let directory = audio_path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+  let temp = tempfile::Builder::new().prefix(".lyricsync-").suffix(".tmp").tempfile_in(directory)?;
+  // Also copies the permissions, so read-only files still can't be written
+  fs::copy(audio_path, temp.path())?;
+  if !update(temp.path())? {
+    return Ok(false);
+  }
+  temp.as_file().sync_all()?;
+  temp.persist(audio_path).map_err(|error| error.error)?;
+  Ok(true)
+}
+
+/// Reads an LRC file, naming it in the error if that fails. Files that aren't UTF-8 are converted
+/// from the encoding they appear to be in, which is logged at info level, and the text comes back
+/// [normalized](lrc::normalize), with any `[offset:]` tag [baked into it](lrc::apply_offset).
+/// Subtitles, TTML and richsync come back converted to LRC.
+pub fn read_sidecar(lrc_path: &Path) -> Result<String> {
+  let bytes = fs::read(lrc_path).map_err(|error| LrcError::from(error).in_file(lrc_path))?;
+  let (text, encoding) = lrc::decode(&bytes);
+  if let Some(encoding) = encoding {
+    tracing::info!(path = %lrc_path.display(), encoding, "converted from {encoding} to UTF-8");
+  }
+  let text = lrc::normalize(&text);
+  let text = match sidecar_extension(lrc_path) {
+    Some("srt") => lrc::subtitles::from_srt(&text),
+    Some("vtt") => lrc::subtitles::from_vtt(&text),
+    Some("ttml") => lrc::ttml::from_ttml(&text),
+    Some("ass" | "ssa") => lrc::subtitles::from_ass(&text),
+    Some("json") => musixmatch::richsync_to_word_timed_lrc(&text).ok_or_else(|| LrcError::InvalidLrc {
+      path: lrc_path.to_path_buf(),
+      reason: "it isn't a Musixmatch richsync document".to_string(),
+    })?,
+    _ => text,
+  };
+  Ok(lrc::apply_offset(&text))
+}
+
+/// Fails with [`LrcError::InvalidLrc`] when the lyrics read from `lrc_path` don't pass
+/// [`lrc::validate`], naming the first problem found.
+pub fn check_lrc(lrc_path: &Path, lyrics: &str) -> Result<()> {
+  let issues = lrc::validate(lyrics);
+  let Some(issue) = issues.first() else {
+    return Ok(());
+  };
+  let mut reason = match issue.line {
+    0 => issue.message.clone(),
+    line => format!("line {line}: {}", issue.message),
+  };
+  if issues.len() > 1 {
+    reason.push_str(&format!(", and {} more", issues.len() - 1));
+  }
+  Err(LrcError::InvalidLrc { path: lrc_path.to_path_buf(), reason })
+}
+
+/// Lists the audio files in a directory that `resolver` recognizes by extension.
+pub fn collect_audio_files(directory: &Path, recursive: bool, resolver: &FormatResolver) -> Vec<PathBuf> {
+  collect_audio_files_matching(directory, recursive, resolver, &PathFilter::default())
+}
+
+/// Glob patterns limiting the files a walk lists, matched against their paths from the directory
+/// walked. `*` matches across folders, so `*.flac` is any FLAC file and `**/Podcasts/**` anything
+/// in a `Podcasts` folder.
+#[derive(Clone, Debug, Default)]
+pub struct PathFilter {
+  include: Option<GlobSet>,
+  exclude: Option<GlobSet>,
+}
+
+impl PathFilter {
+  /// Keeps the files matching any of `include`, or every file when it's empty, and drops the ones
+  /// matching any of `exclude`.
+  pub fn new(include: &[impl AsRef<str>], exclude: &[impl AsRef<str>]) -> Result<Self> {
+    Ok(PathFilter { include: glob_set(include)?, exclude: glob_set(exclude)? })
+  }
+
+  /// Whether a file at `relative_path` in the walked directory is listed.
+  pub fn is_match(&self, relative_path: &Path) -> bool {
+    self.include.as_ref().is_none_or(|include| include.is_match(relative_path)) && !self.is_excluded(relative_path)
+  }
+
+  fn is_excluded(&self, relative_path: &Path) -> bool {
+    self.exclude.as_ref().is_some_and(|exclude| exclude.is_match(relative_path))
+  }
+}
+
+fn glob_set(patterns: &[impl AsRef<str>]) -> Result<Option<GlobSet>> {
+  if patterns.is_empty() {
+    return Ok(None);
+  }
+  let mut builder = GlobSetBuilder::new();
+  for pattern in patterns {
+    builder.add(Glob::new(pattern.as_ref()).map_err(|error| LrcError::InvalidPattern(error.to_string()))?);
+  }
+  builder.build().map(Some).map_err(|error| LrcError::InvalidPattern(error.to_string()))
+}
+
+/// Lists the audio files in a directory that `resolver` recognizes by extension and `filter` keeps.
+/// Folders `filter` excludes aren't walked at all.
+pub fn collect_audio_files_matching(
+  directory: &Path,
+  recursive: bool,
+  resolver: &FormatResolver,
+  filter: &PathFilter,
+) -> Vec<PathBuf> {
+  let _span = tracing::info_span!("scan", directory = %directory.display()).entered();
+  let audio_files = walk_files(directory, recursive, filter, |path| resolver.by_extension(path).is_some());
+  tracing::debug!(count = audio_files.len(), "found audio files");
+  audio_files
+}
+
+/// Lists the `.lrc` files in a directory, under the same rules as the audio files.
+pub fn collect_lrc_files(directory: &Path, recursive: bool, filter: &PathFilter) -> Vec<PathBuf> {
+  let _span = tracing::info_span!("scan", directory = %directory.display()).entered();
+  let is_lrc = |path: &Path| path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("lrc"));
+  let lrc_files = walk_files(directory, recursive, filter, is_lrc);
+  tracing::debug!(count = lrc_files.len(), "found LRC files");
+  lrc_files
+}
+
+// The files under `directory` that `keep` accepts and `filter` lets through
+fn walk_files(directory: &Path, recursive: bool, filter: &PathFilter, keep: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+  let walker = if recursive { WalkDir::new(directory) } else { WalkDir::new(directory).max_depth(1) };
+  let relative = |path: &Path| path.strip_prefix(directory).unwrap_or(path).to_path_buf();
+  walker
+    .into_iter()
+    .filter_entry(|entry| {
+      entry.depth() == 0 || !entry.file_type().is_dir() || !filter.is_excluded(&relative(entry.path()))
+    })
+    .filter_map(|entry| entry.inspect_err(|error| tracing::warn!(%error, "skipping unreadable entry")).ok())
+    .filter(|entry| entry.file_type().is_file() && keep(entry.path()))
+    .filter(|entry| filter.is_match(&relative(entry.path())))
+    .map(|entry| entry.into_path())
+    .collect()
+}
+
+/// Extensions of the files read as sidecars, in the order they're looked for: LRC files, then SubRip
+/// and WebVTT subtitles [converted](lrc::subtitles) to LRC, then [TTML](lrc::ttml) lyrics, then
+/// ASS and SSA karaoke subtitles, then Musixmatch [richsync](musixmatch::richsync_to_word_timed_lrc)
+/// JSON.
+pub const SIDECAR_EXTENSIONS: [&str; 7] = ["lrc", "srt", "vtt", "ttml", "ass", "ssa", "json"];
+
+/// Whether `path` is named like a sidecar, with one of the [`SIDECAR_EXTENSIONS`].
+pub fn is_sidecar(path: &Path) -> bool {
+  sidecar_extension(path).is_some()
+}
+
+/// Whether `path` is a `.txt` file of plain lyrics, which
+/// [`SidecarMatcher::plain_text`](matching::SidecarMatcher::plain_text) falls back to and which has
+/// no timestamps to check.
+pub fn is_plain_text(path: &Path) -> bool {
+  path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("txt"))
+}
+
+fn sidecar_extension(path: &Path) -> Option<&'static str> {
+  let extension = path.extension()?.to_str()?;
+  SIDECAR_EXTENSIONS.into_iter().find(|known| extension.eq_ignore_ascii_case(known))
+}
+
+/// Returns the path of the plain `.lrc` sidecar of an audio file.
+pub fn lrc_sidecar_path(audio_path: &Path) -> PathBuf {
+  let file_name = audio_path.file_stem().unwrap_or_default();
+  audio_path.with_file_name(format!("{}.lrc", file_name.to_string_lossy()))
+}
+
+/// Returns where a translation of an audio file's lyrics goes, named for the language it's written
+/// in, like `Song.zh.lrc`, so it's embedded as that language's lyrics. Translations in Latin script,
+/// whose language there's no telling, are taken as English.
+pub fn translation_sidecar_path(audio_path: &Path, translation: &str) -> PathBuf {
+  let code = language::sidecar_code(language::detect(translation).unwrap_or(*b"eng"));
+  lrc_sidecar_path(audio_path).with_extension(format!("{code}.lrc"))
+}
+
+/// An LRC file next to an audio file: `Song.lrc`, or `Song.ja.lrc` for lyrics in one language. Any
+/// of the [`SIDECAR_EXTENSIONS`] is taken in place of `.lrc`.
+#[derive(Clone, Debug)]
+pub struct Sidecar {
+  pub path: PathBuf,
+  pub language: Option<[u8; 3]>,
+  /// How sure the match is, from 0 to 1; 1 for sidecars named like the audio file
+  pub confidence: f64,
+}
+
+/// Finds the LRC sidecars of an audio file, the plain one first and then by language code. Of
+/// sidecars for the same language, the first of the [`SIDECAR_EXTENSIONS`] is taken.
+pub fn lrc_sidecars(audio_path: &Path) -> Vec<Sidecar> {
+  let mut sidecars = Vec::new();
+  let plain = SIDECAR_EXTENSIONS.iter().map(|extension| lrc_sidecar_path(audio_path).with_extension(extension));
+  if let Some(lrc_path) = plain.into_iter().find(|path| path.exists()) {
+    sidecars.push(Sidecar { path: lrc_path, language: None, confidence: 1.0 });
+  }
+
+  let stem = audio_path.file_stem().unwrap_or_default().to_string_lossy();
+  let directory = audio_path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+  let mut labeled: Vec<Sidecar> = fs::read_dir(directory)
+    .into_iter()
+    .flatten()
+    .filter_map(|entry| {
+      let path = entry.ok()?.path();
+      let file_name = path.file_name()?.to_str()?;
+      let code = file_name.strip_prefix(stem.as_ref())?.strip_prefix('.')?;
+      let extension = sidecar_extension(&path)?;
+      let code = code.get(..code.len().checked_sub(extension.len() + 1)?)?;
+      let language = language::from_sidecar_code(code)?;
+      path.is_file().then_some(Sidecar { path, language: Some(language), confidence: 1.0 })
+    })
+    .collect();
+  let rank =
+    |sidecar: &Sidecar| SIDECAR_EXTENSIONS.iter().position(|known| sidecar_extension(&sidecar.path) == Some(known));
+  labeled.sort_by_key(|sidecar| (sidecar.language, rank(sidecar)));
+  labeled.dedup_by_key(|sidecar| sidecar.language);
+  sidecars.append(&mut labeled);
+
+  sidecars
+}
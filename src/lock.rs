@@ -0,0 +1,55 @@
+//! A lock that keeps two runs from writing to the same directory at once.
+//!
+//! Locks are files in the state directory named for a hash of the directory's canonical path, held
+//! with an OS file lock, so the lock of a run that crashed is released with it and never has to be
+//! cleaned up. The lock covers the directory it's taken for: a run over a parent directory doesn't
+//! see one held on a subdirectory.
+
+use crate::{LrcError, Result};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Held for as long as it's in scope.
+pub struct RunLock {
+  _file: File,
+}
+
+impl RunLock {
+  /// The per-user state directory locks go to, or the temporary directory on platforms without one.
+  pub fn default_dir() -> PathBuf {
+    dirs::state_dir().or_else(dirs::data_local_dir).unwrap_or_else(std::env::temp_dir).join("lyricsync").join("locks")
+  }
+
+  /// Locks `directory` with a lock file in `lock_dir`, failing with [`LrcError::Locked`] if another
+  /// run holds it.
+  pub fn acquire(lock_dir: &Path, directory: &Path) -> Result<Self> {
+    let directory = fs::canonicalize(directory).map_err(|error| LrcError::from(error).in_file(directory))?;
+    let path = lock_dir.join(format!("{:016x}.lock", fnv1a(directory.as_os_str().as_encoded_bytes())));
+    let failed = |error: std::io::Error| LrcError::from(error).in_file(&path);
+
+    fs::create_dir_all(lock_dir).map_err(failed)?;
+    // Not truncated until it's locked, as the holder's process ID is in it
+    let mut file =
+      OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path).map_err(failed)?;
+    match file.try_lock() {
+      Ok(()) => {},
+      Err(TryLockError::WouldBlock) => {
+        // Windows locks keep others from reading the file, so the holder may go unnamed
+        let mut holder = String::new();
+        let pid = file.read_to_string(&mut holder).ok().and_then(|_| holder.trim().parse().ok());
+        return Err(LrcError::Locked { path: directory, pid });
+      },
+      Err(TryLockError::Error(error)) => return Err(failed(error)),
+    }
+
+    file.set_len(0).and_then(|()| write!(file, "{}", std::process::id())).map_err(failed)?;
+    tracing::debug!(lock = %path.display(), "locked {}", directory.display());
+    Ok(RunLock { _file: file })
+  }
+}
+
+// Stable across builds, unlike std's hasher, so different versions of lyricsync agree on the name
+fn fnv1a(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
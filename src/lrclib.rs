@@ -0,0 +1,89 @@
+//! Client for the [LRCLIB](https://lrclib.net) lyrics database.
+
+use crate::Result;
+use crate::track::TrackInfo;
+use serde::Deserialize;
+use std::time::Duration;
+use ureq::Agent;
+
+pub const DEFAULT_BASE_URL: &str = "https://lrclib.net";
+
+// LRCLIB asks clients to identify themselves
+const USER_AGENT: &str = concat!("lyricsync/", env!("CARGO_PKG_VERSION"), " (https://github.com/Dantescur/lyricsync)");
+
+// Search results further than this from the file's duration are considered a different recording
+const DURATION_TOLERANCE_SECS: f64 = 2.0;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LrclibTrack {
+  duration: Option<f64>,
+  synced_lyrics: Option<String>,
+}
+
+pub struct LrclibClient {
+  base_url: String,
+  agent: Agent,
+}
+
+impl LrclibClient {
+  pub fn new(base_url: &str) -> Self {
+    let agent = Agent::config_builder()
+      .user_agent(USER_AGENT)
+      .timeout_global(Some(Duration::from_secs(15)))
+      .http_status_as_error(false)
+      .build()
+      .new_agent();
+    Self { base_url: base_url.trim_end_matches('/').to_string(), agent }
+  }
+
+  /// Looks up synced lyrics for a track, returning `None` if LRCLIB has none.
+  ///
+  /// The exact-match endpoint needs the album and duration; without them, or when it has no
+  /// synced lyrics, this falls back to a search by artist and title.
+  pub fn synced_lyrics(&self, track: &TrackInfo) -> Result<Option<String>> {
+    if let (Some(album), Some(duration)) = (&track.album, track.duration_secs) {
+      let request = self
+        .agent
+        .get(format!("{}/api/get", self.base_url))
+        .query("artist_name", &track.artist)
+        .query("track_name", &track.title)
+        .query("album_name", album)
+        .query("duration", duration.to_string());
+
+      let synced = self.send::<LrclibTrack>(request)?.and_then(|found| found.synced_lyrics);
+      if synced.is_some() {
+        return Ok(synced);
+      }
+    }
+
+    let request = self
+      .agent
+      .get(format!("{}/api/search", self.base_url))
+      .query("artist_name", &track.artist)
+      .query("track_name", &track.title);
+
+    let results = self.send::<Vec<LrclibTrack>>(request)?.unwrap_or_default();
+    let synced = results
+      .into_iter()
+      .filter(|result| match (track.duration_secs, result.duration) {
+        (Some(expected), Some(actual)) => (expected as f64 - actual).abs() <= DURATION_TOLERANCE_SECS,
+        _ => true,
+      })
+      .find_map(|result| result.synced_lyrics.filter(|lyrics| !lyrics.trim().is_empty()));
+
+    Ok(synced)
+  }
+
+  fn send<T: serde::de::DeserializeOwned>(
+    &self,
+    request: ureq::RequestBuilder<ureq::typestate::WithoutBody>,
+  ) -> Result<Option<T>> {
+    let mut response = request.call()?;
+    match response.status().as_u16() {
+      404 => Ok(None),
+      status if !response.status().is_success() => Err(ureq::Error::StatusCode(status).into()),
+      _ => Ok(Some(response.body_mut().read_json()?)),
+    }
+  }
+}
@@ -1,231 +1,646 @@
-use clap::{Arg, Command, ValueHint};
+mod cli;
+mod config;
+mod control;
+mod coverage;
+mod interrupt;
+mod karaoke;
+mod manifest;
+mod mapping;
+mod mpd;
+mod play;
+mod progress;
+mod report;
+mod review;
+mod serve;
+mod tap;
+mod watch;
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Command};
 use clap_complete::{Generator, Shell, generate};
-use indicatif::{ProgressBar, ProgressStyle};
-use lofty::{
-  TextEncoding,
-  config::{ParseOptions, WriteOptions},
-  file::AudioFile,
-  flac::FlacFile,
-  id3::v2::FrameId,
-  mp4::Mp4File,
-  mp4::{Atom, AtomData},
-  mpeg::MpegFile,
+use control::ControlSocket;
+use coverage::{CheckedFile, CoveragePage};
+use croner::Cron;
+use indicatif::ProgressBar;
+use lyricsync::backup::Backups;
+use lyricsync::cache::FetchCache;
+use lyricsync::genius::GeniusClient;
+use lyricsync::journal::{self, Journal, JournalEntry};
+use lyricsync::lock::RunLock;
+use lyricsync::lrclib::LrclibClient;
+use lyricsync::matching::{NameRule, SidecarMatcher, fuzzy_candidates};
+use lyricsync::musixmatch::MusixmatchClient;
+use lyricsync::netease::NeteaseClient;
+use lyricsync::provider::{FetchedLyrics, LyricsFilters, Provider, ProviderChain};
+use lyricsync::transcribe::{self, Transcriber};
+use lyricsync::{
+  AudioFormat, ConflictPolicy, EmbedEvent, EmbedOptions, EmbedOutcome, EmbedSession, FailureKind, FileResult,
+  FormatResolver, Id3Encoding, Id3Frames, Id3Version, LrcError, Lyrics, LyricsStatus, Mp4Lyrics, PathFilter,
+  ReplacePolicy, Result, Romanization, Sidecar, SkipReason, Translations, VorbisKeys, WordTiming,
+  collect_audio_files_matching, collect_lrc_files, format_for_extension, has_embedded_lyrics, lrc_sidecar_path,
+  read_sidecar, track,
 };
-use std::fs::{self, OpenOptions};
+use progress::{JsonEvent, LogWriter, new_progress_bar, new_worker_bars};
+use regex::Regex;
+use report::{FileReport, Report, ReportFormat, ReportTarget, Summary};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use thiserror::Error;
-use walkdir::WalkDir;
+use std::process::ExitCode;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::fmt;
+use tracing_subscriber::prelude::*;
+use watch::{Batch, Schedule, WatchOptions};
+
+// Exit codes besides 0, 1 for errors that stop a run and 2 for usage errors
+/// Some files failed
+const EXIT_FAILED: u8 = 3;
+/// The directory had no audio files in it
+const EXIT_NO_FILES: u8 = 4;
+/// The directory doesn't exist
+const EXIT_NO_DIRECTORY: u8 = 5;
+/// Some files were skipped, with `--fail-on skipped`
+const EXIT_SKIPPED: u8 = 6;
+/// The run was interrupted, as a shell reports a process killed by SIGINT
+const EXIT_INTERRUPTED: u8 = 130;
+
+// How often `now-playing` asks MPD where it is, to follow seeks, pauses and track changes
+const NOW_PLAYING_POLL: Duration = Duration::from_millis(500);
+
+// How often `play` looks at where the track is, so a line is highlighted no later than this
+const PLAY_TICK: Duration = Duration::from_millis(50);
 
-#[derive(Error, Debug)]
-pub enum LrcError {
-  #[error("IO error: {0}")]
-  Io(#[from] std::io::Error),
-  #[error("Audio file error: {0}")]
-  Audio(#[from] lofty::error::LoftyError),
-  #[error("Unsupported file format: {0}")]
-  UnsupportedFormat(String),
+// Other LRC files `--review` offers for a file, at most, and how like its name theirs have to be
+const REVIEW_CANDIDATES: usize = 10;
+const REVIEW_MIN_CONFIDENCE: f64 = 0.5;
+
+/// What happened to the files a command went through, which decides its exit code.
+#[derive(Default)]
+struct RunOutcome {
+  no_files: bool,
+  failed: usize,
+  skipped: usize,
+  /// Runs until it's stopped, so being interrupted is how it ends rather than a failure
+  until_stopped: bool,
 }
 
-type Result<T> = std::result::Result<T, LrcError>;
+impl RunOutcome {
+  fn files(total: usize, failed: usize, skipped: usize) -> Self {
+    RunOutcome { no_files: total == 0, failed, skipped, until_stopped: false }
+  }
+
+  fn exit_code(&self, fail_on: &str) -> ExitCode {
+    let code = if interrupt::interrupted() && !self.until_stopped {
+      EXIT_INTERRUPTED
+    } else if fail_on == "none" {
+      0
+    } else if self.failed > 0 {
+      EXIT_FAILED
+    } else if fail_on == "skipped" && self.skipped > 0 {
+      EXIT_SKIPPED
+    } else if self.no_files {
+      EXIT_NO_FILES
+    } else {
+      0
+    };
+    ExitCode::from(code)
+  }
+}
 
 struct EmbedStats {
   total_audio_files: usize,
   embedded_lyrics: usize,
-  failed_files: Vec<PathBuf>,
+  unchanged: usize,
+  failed_files: Vec<(PathBuf, LrcError)>,
+  /// Files an interrupted run didn't get to
+  remaining: Vec<PathBuf>,
+  /// Every file that was processed, for `--report`
+  files: Vec<FileReport>,
+  /// How the files of each format went, by format name
+  by_format: BTreeMap<&'static str, FormatCounts>,
+  failures_by_kind: BTreeMap<FailureKind, usize>,
 }
 
-fn has_embedded_lyrics(audio_path: &Path) -> Result<bool> {
-  let mut file_content = OpenOptions::new().read(true).open(audio_path)?;
+#[derive(Default)]
+struct FormatCounts {
+  files: usize,
+  embedded: usize,
+  unchanged: usize,
+  skipped: usize,
+  failed: usize,
+}
 
-  if audio_path.extension().is_some_and(|ext| ext == "flac") {
-    let flac_file = FlacFile::read_from(&mut file_content, ParseOptions::new())?;
-    if let Some(vorbis_comments) = flac_file.vorbis_comments() {
-      return Ok(vorbis_comments.get("LYRICS").is_some() || vorbis_comments.get("UNSYNCEDLYRICS").is_some());
-    }
-  } else if audio_path.extension().is_some_and(|ext| ext == "mp3") {
-    let mp3_file = MpegFile::read_from(&mut file_content, ParseOptions::new())?;
-    if let Some(id3v2) = mp3_file.id3v2() {
-      // Check for USLT (unsynchronized lyrics) or SYLT (synchronized lyrics) frames
-      let uslt_frame_id = FrameId::new("USLT").unwrap();
-      let sylt_frame_id = FrameId::new("SYLT").unwrap();
-      return Ok(id3v2.get(&uslt_frame_id).is_some() || id3v2.get(&sylt_frame_id).is_some());
-    }
-  } else if audio_path.extension().is_some_and(|ext| ext == "m4a") {
-    let mp4_file = Mp4File::read_from(&mut file_content, ParseOptions::new())?;
-    if let Some(ilst) = mp4_file.ilst() {
-      // Check for lyrics in MP4 metadata
-      let lyrics_ident = lofty::mp4::AtomIdent::Fourcc(*b"\xa9lyr");
-      return Ok(ilst.get(&lyrics_ident).is_some());
-    }
+// Parses an ISO 639-2 language code, or `auto` to detect it from the lyrics
+fn parse_language(value: &str) -> std::result::Result<Option<[u8; 3]>, String> {
+  if value.eq_ignore_ascii_case("auto") {
+    return Ok(None);
+  }
+
+  let code: [u8; 3] = value
+    .to_ascii_lowercase()
+    .into_bytes()
+    .try_into()
+    .map_err(|_| format!("expected a three-letter ISO 639-2 code (e.g. jpn) or auto, got '{value}'"))?;
+  if !code.iter().all(u8::is_ascii_lowercase) {
+    return Err(format!("expected a three-letter ISO 639-2 code (e.g. jpn) or auto, got '{value}'"));
   }
 
-  Ok(false)
+  Ok(Some(code))
 }
 
-fn embed_lrc_to_file(audio_path: &Path, lrc_path: &Path, reduce_lrc: bool, dry_run: bool) -> Result<()> {
-  let lyrics_content = fs::read_to_string(lrc_path)?;
+// Parses an `EXT=FORMAT` alias, where FORMAT is any extension lyricsync already knows
+fn parse_extension_alias(value: &str) -> std::result::Result<(String, AudioFormat), String> {
+  let (extension, target) =
+    value.split_once('=').ok_or_else(|| format!("expected EXT=FORMAT (e.g. m4r=m4a), got '{value}'"))?;
+  let extension = extension.trim().trim_start_matches('.').to_ascii_lowercase();
+  let target = target.trim().trim_start_matches('.').to_ascii_lowercase();
 
-  if dry_run {
-    // In dry-run mode, just validate the file format without making changes
-    if !audio_path.extension().is_some_and(|ext| matches!(ext.to_str(), Some("flac" | "mp3" | "m4a"))) {
-      return Err(LrcError::UnsupportedFormat(
-        audio_path.extension().unwrap_or_default().to_string_lossy().to_string(),
-      ));
-    }
-    // Skip actual embedding and file deletion in dry-run mode
-    return Ok(());
+  if extension.is_empty() {
+    return Err(format!("missing extension in '{value}'"));
   }
 
-  if audio_path.extension().is_some_and(|ext| ext == "flac") {
-    embed_lrc_to_flac(audio_path, &lyrics_content)?;
-  } else if audio_path.extension().is_some_and(|ext| ext == "mp3") {
-    embed_lrc_to_mp3(audio_path, &lyrics_content)?;
-  } else if audio_path.extension().is_some_and(|ext| ext == "m4a") {
-    embed_lrc_to_m4a(audio_path, &lyrics_content)?;
-  } else {
-    return Err(LrcError::UnsupportedFormat(audio_path.extension().unwrap_or_default().to_string_lossy().to_string()));
+  let format = format_for_extension(&target).ok_or_else(|| format!("unknown format '{target}'"))?;
+  Ok((extension, format))
+}
+
+// Parses a report format, optionally followed by `=PATH` to write it to a file
+fn parse_embed_report(value: &str) -> std::result::Result<ReportTarget, String> {
+  parse_report(value, &["json", "csv"])
+}
+
+fn parse_check_report(value: &str) -> std::result::Result<ReportTarget, String> {
+  parse_report(value, &["html"])
+}
+
+// Parses `FORMAT` or `FORMAT=PATH`, taking only the formats the command writes
+fn parse_report(value: &str, formats: &[&str]) -> std::result::Result<ReportTarget, String> {
+  let (format, path) = match value.split_once('=') {
+    Some((format, path)) if !path.is_empty() => (format, Some(PathBuf::from(path))),
+    Some(_) => return Err(format!("missing path in '{value}'")),
+    None => (value, None),
+  };
+  let format = format.to_ascii_lowercase();
+  let format = match format.as_str() {
+    "json" if formats.contains(&"json") => ReportFormat::Json,
+    "csv" if formats.contains(&"csv") => ReportFormat::Csv,
+    "html" if formats.contains(&"html") => ReportFormat::Html,
+    other => return Err(format!("unknown report format '{other}' (expected {})", formats.join(" or "))),
+  };
+  Ok(ReportTarget { format, path })
+}
+
+// Checks a glob pattern, which is compiled with the rest of them once the command line is parsed
+fn parse_glob(value: &str) -> std::result::Result<String, String> {
+  globset::Glob::new(value).map(|_| value.to_string()).map_err(|error| error.to_string())
+}
+
+// Parses a file name rule, `PATTERN=>REPLACEMENT` with a regular expression and what it becomes
+fn parse_name_rule(value: &str) -> std::result::Result<NameRule, String> {
+  let (pattern, replacement) =
+    value.split_once("=>").ok_or_else(|| format!("expected PATTERN=>REPLACEMENT, got '{value}'"))?;
+  let pattern = Regex::new(pattern).map_err(|error| error.to_string())?;
+  Ok(NameRule::new(pattern, replacement))
+}
+
+// Parses a `--anchor` pair of LRC and actual times, in milliseconds
+fn parse_anchor(value: &str) -> std::result::Result<(u32, u32), String> {
+  let invalid = || format!("expected LRC=ACTUAL times like 00:10.50=00:11.20, got '{value}'");
+  let (lrc_time, actual) = value.split_once('=').ok_or_else(invalid)?;
+  let parse = |time: &str| lyricsync::lrc::parse_timestamp(time.trim()).ok_or_else(invalid);
+  Ok((parse(lrc_time)?, parse(actual)?))
+}
+
+// Parses a regular expression for `--strip-line`
+fn parse_pattern(value: &str) -> std::result::Result<Regex, String> {
+  Regex::new(value).map_err(|error| error.to_string())
+}
+
+// Parses a cron expression for scheduled scans, with five fields or an alias like `@daily`
+fn parse_cron(value: &str) -> std::result::Result<Cron, String> {
+  value.parse::<Cron>().map_err(|error| error.to_string())
+}
+
+// Parses a match confidence, from 0 to 1
+fn parse_confidence(value: &str) -> std::result::Result<f64, String> {
+  let confidence: f64 = value.trim().parse().map_err(|_| format!("expected a number from 0 to 1, got '{value}'"))?;
+  if !(0.0..=1.0).contains(&confidence) {
+    return Err(format!("expected a number from 0 to 1, got '{value}'"));
   }
+  Ok(confidence)
+}
 
-  if reduce_lrc {
-    fs::remove_file(lrc_path)?;
+// Parses a byte count with an optional K, M, G or T suffix (powers of 1024)
+fn parse_size(value: &str) -> std::result::Result<u64, String> {
+  let invalid = || format!("expected a size like 500M or 2G, got '{value}'");
+  let trimmed = value.trim().trim_end_matches(['b', 'B']);
+  let (number, shift) = match trimmed.char_indices().last() {
+    Some((index, unit)) if unit.is_ascii_alphabetic() => {
+      let shift = match unit.to_ascii_uppercase() {
+        'K' => 10,
+        'M' => 20,
+        'G' => 30,
+        'T' => 40,
+        _ => return Err(invalid()),
+      };
+      (&trimmed[..index], shift)
+    },
+    _ => (trimmed, 0),
+  };
+  let number: u64 = number.trim().parse().map_err(|_| invalid())?;
+  number.checked_mul(1 << shift).ok_or_else(invalid)
+}
+
+enum PromptAnswer {
+  Yes,
+  No,
+  All,
+  None,
+}
+
+impl PromptAnswer {
+  // Whether to go ahead, with "always" and "never" kept in `settled` for the rest of the run
+  fn decide(self, settled: &mut Option<bool>) -> bool {
+    match self {
+      PromptAnswer::Yes => true,
+      PromptAnswer::No => false,
+      PromptAnswer::All => *settled.insert(true),
+      PromptAnswer::None => *settled.insert(false),
+    }
   }
+}
 
-  Ok(())
+fn prompt(question: &str) -> PromptAnswer {
+  loop {
+    print!("{question} [y]es/[N]o/[a]lways/n[e]ver: ");
+    let _ = std::io::stdout().flush();
+
+    let mut answer = String::new();
+    // Stop asking once stdin is closed
+    if std::io::stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+      return PromptAnswer::None;
+    }
+
+    match answer.trim().to_ascii_lowercase().as_str() {
+      "y" | "yes" => return PromptAnswer::Yes,
+      "" | "n" | "no" => return PromptAnswer::No,
+      "a" | "all" | "always" => return PromptAnswer::All,
+      "e" | "never" | "o" | "none" => return PromptAnswer::None,
+      _ => {},
+    }
+  }
+}
+
+// How an `embed` run works through the library, beside the tag options
+struct EmbedRun<'a> {
+  reduce: bool,
+  normalize_sidecars: bool,
+  reject_invalid: bool,
+  dry_run: bool,
+  rewrite: bool,
+  /// 0 for one worker per CPU core
+  jobs: usize,
+  /// Print events as JSON lines, for `--progress jsonl`, instead of drawing bars
+  jsonl: bool,
+  /// Ask before embedding a loose match, for `--interactive`
+  interactive: bool,
+  guards: WriteGuards<'a>,
+  matcher: &'a SidecarMatcher,
+  /// The `--map` offsets of the files that have one
+  file_offsets: &'a [(PathBuf, i64)],
 }
 
-fn embed_lrc_to_flac(audio_path: &Path, lyrics: &str) -> Result<()> {
-  let mut file_content = OpenOptions::new().read(true).write(true).open(audio_path)?;
-  let mut flac_file = FlacFile::read_from(&mut file_content, ParseOptions::new())?;
+// What's kept before a file is modified, so the change can be reverted
+#[derive(Clone, Copy, Default)]
+struct WriteGuards<'a> {
+  backups: Option<&'a Backups>,
+  journal: Option<&'a Journal>,
+}
 
-  if let Some(vorbis_comments) = flac_file.vorbis_comments_mut() {
-    vorbis_comments.insert("LYRICS".to_string(), lyrics.to_string());
-    flac_file.save_to_path(audio_path, WriteOptions::default())?;
+impl WriteGuards<'_> {
+  fn is_empty(&self) -> bool {
+    self.backups.is_none() && self.journal.is_none()
   }
 
-  Ok(())
+  fn before_write(&self, audio_path: &Path, resolver: &FormatResolver) -> Result<()> {
+    if let Some(backups) = self.backups {
+      backups.save(audio_path)?;
+    }
+    if let Some(journal) = self.journal {
+      journal.record_lyrics(audio_path, resolver)?;
+    }
+    Ok(())
+  }
 }
 
-fn embed_lrc_to_mp3(audio_path: &Path, lyrics: &str) -> Result<()> {
-  let mut file_content = OpenOptions::new().read(true).write(true).open(audio_path)?;
-  let mut mp3_file = MpegFile::read_from(&mut file_content, ParseOptions::new())?;
+fn embed_lrc(
+  audio_files: Vec<PathBuf>,
+  run: &EmbedRun,
+  options: &EmbedOptions,
+  directory_options: Vec<(PathBuf, EmbedOptions)>,
+) -> Result<EmbedStats> {
+  let EmbedRun {
+    reduce,
+    normalize_sidecars,
+    reject_invalid,
+    dry_run,
+    rewrite,
+    jobs,
+    jsonl,
+    interactive,
+    guards,
+    matcher,
+    file_offsets,
+  } = *run;
+  let mut stats = EmbedStats {
+    total_audio_files: 0,
+    embedded_lyrics: 0,
+    unchanged: 0,
+    failed_files: Vec::new(),
+    remaining: Vec::new(),
+    files: Vec::new(),
+    by_format: BTreeMap::new(),
+    failures_by_kind: BTreeMap::new(),
+  };
+
+  stats.total_audio_files = audio_files.len();
 
-  if let Some(id3v2) = mp3_file.id3v2_mut() {
-    use lofty::id3::v2::{Frame, UnsynchronizedTextFrame};
+  let pb = if jsonl { ProgressBar::hidden() } else { new_progress_bar(audio_files.len()) };
+  let workers = match jobs {
+    _ if jsonl || jobs == 1 => Vec::new(),
+    0 => new_worker_bars(std::thread::available_parallelism().map_or(1, usize::from)),
+    jobs => new_worker_bars(jobs),
+  };
 
-    let uslt_frame = UnsynchronizedTextFrame::new(
-      TextEncoding::UTF8,
-      [b'e', b'n', b'g'], // Language: eng
-      "".to_string(),     // Description
-      lyrics.to_string(),
-    );
-    id3v2.insert(Frame::UnsynchronizedText(uslt_frame));
+  // Answering "always" or "never" at a prompt settles it for the rest of the run
+  let mut settled = None;
+  let mut settled_matches = None;
+  let mut session = EmbedSession::new(options.clone())
+    .directory_options(directory_options)
+    .matcher(matcher.clone())
+    .file_offsets(file_offsets.iter().cloned())
+    .reduce(reduce)
+    .normalize_sidecars(normalize_sidecars)
+    .reject_invalid(reject_invalid)
+    .dry_run(dry_run)
+    .rewrite_unchanged(rewrite)
+    .stop_on(&interrupt::INTERRUPTED);
+  if let Some(backups) = guards.backups {
+    session = session.backups(backups);
+  }
+  if let Some(journal) = guards.journal {
+    session = session.journal(journal);
+  }
+  if interactive {
+    session = session.on_loose_match(|audio_path, sidecar| {
+      if let Some(embed) = settled_matches {
+        return embed;
+      }
+      let question = format!(
+        "{} is only a {:.0}% match for {}. Embed it?",
+        sidecar.path.display(),
+        sidecar.confidence * 100.0,
+        audio_path.display()
+      );
+      progress::suspend(|| prompt(&question)).decide(&mut settled_matches)
+    });
+  }
+  let session = session
+    .on_conflict(|audio_path| {
+      if let Some(overwrite) = settled {
+        return overwrite;
+      }
+      let question = format!("{} already has embedded lyrics. Overwrite?", audio_path.display());
+      progress::suspend(|| prompt(&question)).decide(&mut settled)
+    })
+    .on_event(|event| {
+      // Events come from the worker thread handling the file
+      if let Some(bar) = rayon::current_thread_index().and_then(|index| workers.get(index)) {
+        match event {
+          EmbedEvent::Started { path } => bar.set_message(path.display().to_string()),
+          EmbedEvent::Matched { .. } => {},
+          _ => bar.set_message("idle"),
+        }
+      }
+      if jsonl {
+        progress::emit_jsonl(&embed_event_json(event));
+      }
+      report_embed_event(event, dry_run, &pb);
+    });
+
+  // Workers finish files in any order, so the tallies are shared behind a lock
+  let shared_stats = Mutex::new((stats, HashSet::new()));
+  session.run_parallel(audio_files.clone(), jobs, |FileResult { path: audio_path, outcome, elapsed }| {
+    let (stats, finished) = &mut *shared_stats.lock().unwrap_or_else(PoisonError::into_inner);
+    finished.insert(audio_path.clone());
+    let file = FileReport::new(audio_path.clone(), &outcome, elapsed, &options.formats);
+    let counts = stats.by_format.entry(file.format.unwrap_or("unknown")).or_default();
+    stats.files.push(file);
+    counts.files += 1;
+    match outcome {
+      Ok(EmbedOutcome::Embedded) => {
+        stats.embedded_lyrics += 1;
+        counts.embedded += 1;
+      },
+      Ok(EmbedOutcome::Skipped(SkipReason::Unchanged)) => {
+        stats.unchanged += 1;
+        counts.unchanged += 1;
+      },
+      Ok(EmbedOutcome::Skipped(_)) => counts.skipped += 1,
+      Err(error) => {
+        counts.failed += 1;
+        *stats.failures_by_kind.entry(error.kind()).or_default() += 1;
+        // Only rename failed LRC files if not in dry-run mode, and only those named like the file:
+        // ones matched by tags or mapped can be in a shared folder other files take theirs from
+        if !dry_run {
+          for sidecar in lyricsync::lrc_sidecars(&audio_path) {
+            // `song.srt.failed` for subtitles, so they're put back as what they were
+            let extension = sidecar.path.extension().unwrap_or_default().to_string_lossy();
+            let failed_lrc_path = sidecar.path.with_extension(format!("{extension}.failed"));
+            if let Err(e) = fs::rename(&sidecar.path, &failed_lrc_path) {
+              tracing::warn!(path = %sidecar.path.display(), "Failed to rename the LRC file: {}", e);
+            }
+          }
+        }
+        stats.failed_files.push((audio_path, error));
+      },
+    }
+
+    pb.inc(1);
+  })?;
 
-    mp3_file.save_to_path(audio_path, WriteOptions::default())?;
+  let (mut stats, finished) = shared_stats.into_inner().unwrap_or_else(PoisonError::into_inner);
+  stats.failed_files.sort_by(|(a, _), (b, _)| a.cmp(b));
+  stats.files.sort_by(|a, b| a.path.cmp(&b.path));
+  stats.remaining = audio_files.into_iter().filter(|audio_path| !finished.contains(audio_path)).collect();
+  for bar in &workers {
+    bar.finish_and_clear();
   }
 
-  Ok(())
+  pb.finish_with_message(finish_message(dry_run));
+  Ok(stats)
 }
 
-fn embed_lrc_to_m4a(audio_path: &Path, lyrics: &str) -> Result<()> {
-  let mut file_content = OpenOptions::new().read(true).write(true).open(audio_path)?;
-  let mut mp4_file = Mp4File::read_from(&mut file_content, ParseOptions::new())?;
+fn report_embed_event(event: &EmbedEvent<'_>, dry_run: bool, pb: &ProgressBar) {
+  let (action, path) = match *event {
+    EmbedEvent::Skipped { path, reason: SkipReason::HasLyrics | SkipReason::Declined } => ("skip", path),
+    EmbedEvent::Skipped { path, reason: SkipReason::Unchanged } => ("leave unchanged", path),
+    EmbedEvent::Embedded { path } => ("embed", path),
+    EmbedEvent::Failed { path, error } => {
+      tracing::error!("Failed to embed LRC: {}", failure_message(path, error));
+      return;
+    },
+    _ => return,
+  };
 
-  if let Some(ilst) = mp4_file.ilst_mut() {
-    // Create lyrics atom for MP4
-    let lyrics_ident = lofty::mp4::AtomIdent::Fourcc(*b"\xa9lyr");
-    let lyrics_atom = Atom::new(lyrics_ident, AtomData::UTF8(lyrics.to_string()));
-    ilst.insert(lyrics_atom);
+  let msg = if dry_run {
+    format!("[DRY RUN] Would {action}: {}", path.display())
+  } else {
+    let done = match action {
+      "skip" => "Skipped",
+      "embed" => "Embedded",
+      _ => "Unchanged",
+    };
+    format!("{done}: {}", path.display())
+  };
+  progress::print_plain(&msg);
+  pb.set_message(msg);
+}
 
-    mp4_file.save_to_path(audio_path, WriteOptions::default())?;
+fn embed_event_json<'e>(event: &EmbedEvent<'e>) -> JsonEvent<'e> {
+  match *event {
+    EmbedEvent::Started { path } => JsonEvent::Started { path },
+    EmbedEvent::Matched { path, sidecars } => {
+      JsonEvent::Matched { path, sidecars: sidecars.iter().map(|sidecar| sidecar.path.as_path()).collect() }
+    },
+    EmbedEvent::Skipped { path, reason } => {
+      let reason = match reason {
+        SkipReason::NoSidecar => "no-lrc",
+        SkipReason::HasLyrics => "has-lyrics",
+        SkipReason::Unchanged => "unchanged",
+        SkipReason::Declined => "declined",
+      };
+      JsonEvent::Skipped { path, reason }
+    },
+    EmbedEvent::Embedded { path } => JsonEvent::Embedded { path },
+    EmbedEvent::Failed { path, error } => JsonEvent::Failed { path, error: failure_message(path, error) },
   }
+}
 
-  Ok(())
+// Errors about a particular file already name it
+pub(crate) fn failure_message(path: &Path, error: &LrcError) -> String {
+  if error.path().is_some() { error.to_string() } else { format!("{}: {}", path.display(), error) }
 }
 
-fn embed_lrc(
-  directory: &Path,
-  skip_existing: bool,
-  reduce_lrc: bool,
-  recursive: bool,
-  dry_run: bool,
-) -> Result<EmbedStats> {
-  let mut stats = EmbedStats { total_audio_files: 0, embedded_lyrics: 0, failed_files: Vec::new() };
+fn print_failures(heading: &str, failures: &[(PathBuf, LrcError)]) {
+  if failures.is_empty() {
+    return;
+  }
+  println!("\n{heading}:");
+  for (path, error) in failures {
+    println!("  {}", failure_message(path, error));
+  }
+}
 
-  if dry_run {
+// With the bar hidden, the same messages go to the log instead, and to stdout when it isn't a terminal
+fn report(pb: &ProgressBar, msg: String) {
+  tracing::info!("{msg}");
+  progress::print_plain(&msg);
+  pb.set_message(msg);
+}
+
+fn print_dry_run_banner(dry_run: bool) {
+  // The per-file lines of plain output all say [DRY RUN] already
+  if dry_run && !progress::quiet() && !progress::plain() {
     println!("[DRY RUN] No files will be modified");
   }
+}
 
-  let walker = if recursive { WalkDir::new(directory) } else { WalkDir::new(directory).max_depth(1) };
+fn finish_message(dry_run: bool) -> &'static str {
+  match (interrupt::interrupted(), dry_run) {
+    (true, _) => "Interrupted",
+    (false, true) => "[DRY RUN] Completed!",
+    (false, false) => "Completed!",
+  }
+}
 
-  let audio_files: Vec<PathBuf> = walker
-    .into_iter()
-    .filter_map(|e| e.ok())
-    .filter(|entry| {
-      entry.file_type().is_file()
-        && entry.path().extension().is_some_and(|ext| matches!(ext.to_str(), Some("flac" | "mp3" | "m4a")))
-    })
-    .map(|entry| entry.into_path())
-    .collect();
+fn print_completions<G: Generator>(generator: G, cmd: &mut Command) {
+  generate(generator, cmd, cmd.get_name().to_string(), &mut std::io::stdout());
+}
+
+struct ExtractStats {
+  total_audio_files: usize,
+  extracted_lyrics: usize,
+  skipped_existing: usize,
+  failed_files: Vec<(PathBuf, LrcError)>,
+}
+
+fn extract_lrc(
+  directory: &Path,
+  recursive: bool,
+  filter: &PathFilter,
+  overwrite: bool,
+  dry_run: bool,
+  resolver: &FormatResolver,
+) -> Result<ExtractStats> {
+  let mut stats =
+    ExtractStats { total_audio_files: 0, extracted_lyrics: 0, skipped_existing: 0, failed_files: Vec::new() };
+
+  print_dry_run_banner(dry_run);
 
+  let audio_files = collect_audio_files_matching(directory, recursive, resolver, filter);
   stats.total_audio_files = audio_files.len();
 
-  let pb = ProgressBar::new(audio_files.len() as u64);
-  pb.set_style(
-    ProgressStyle::default_bar()
-      .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}")
-      .unwrap()
-      .progress_chars("#>-"),
-  );
+  let pb = new_progress_bar(audio_files.len());
 
   for audio_path in audio_files {
+    let _span = tracing::info_span!("file", path = %audio_path.display()).entered();
+    let lyrics = match lyricsync::extract_lyrics(&audio_path, resolver) {
+      Ok(Some(lyrics)) if !lyrics.trim().is_empty() => lyrics,
+      Ok(_) => {
+        report(&pb, format!("No lyrics: {}", audio_path.display()));
+        pb.inc(1);
+        continue;
+      },
+      Err(e) => {
+        tracing::error!("Failed to read lyrics: {}", failure_message(&audio_path, &e));
+        stats.failed_files.push((audio_path, e));
+        pb.inc(1);
+        continue;
+      },
+    };
+
+    // Unsynced lyrics go to a .txt sidecar so players don't mistake them for LRC
+    let extension = if lyricsync::lrc::parse_timed_lines(&lyrics).is_empty() { "txt" } else { "lrc" };
     let file_name = audio_path.file_stem().unwrap_or_default();
-    let lrc_path = audio_path.with_file_name(format!("{}.lrc", file_name.to_string_lossy()));
+    let sidecar_path = audio_path.with_file_name(format!("{}.{}", file_name.to_string_lossy(), extension));
 
-    if !lrc_path.exists() {
+    if sidecar_path.exists() && !overwrite {
+      stats.skipped_existing += 1;
+      report(&pb, format!("Skipped: {}", sidecar_path.display()));
       pb.inc(1);
       continue;
     }
 
-    if skip_existing {
-      match has_embedded_lyrics(&audio_path) {
-        Ok(true) => {
-          let msg = if dry_run {
-            format!("[DRY RUN] Would skip: {}", audio_path.display())
-          } else {
-            format!("Skipped: {}", audio_path.display())
-          };
-          pb.set_message(msg);
-          pb.inc(1);
-          continue;
-        },
-        Ok(false) => {}, // Continue with embedding
-        Err(e) => {
-          eprintln!("Error checking lyrics for {}: {}", audio_path.display(), e);
-        },
-      }
+    if dry_run {
+      stats.extracted_lyrics += 1;
+      report(&pb, format!("[DRY RUN] Would extract: {}", sidecar_path.display()));
+      pb.inc(1);
+      continue;
     }
 
-    match embed_lrc_to_file(&audio_path, &lrc_path, reduce_lrc, dry_run) {
+    match fs::write(&sidecar_path, lyrics) {
       Ok(()) => {
-        stats.embedded_lyrics += 1;
-        let msg = if dry_run {
-          format!("[DRY RUN] Would embed: {}", audio_path.display())
-        } else {
-          format!("Embedded: {}", audio_path.display())
-        };
-        pb.set_message(msg);
+        stats.extracted_lyrics += 1;
+        report(&pb, format!("Extracted: {}", sidecar_path.display()));
       },
       Err(e) => {
-        eprintln!("Error embedding LRC for {}: {}", audio_path.display(), e);
-        stats.failed_files.push(audio_path.clone());
-
-        // Only rename failed LRC file if not in dry-run mode
-        if !dry_run {
-          let failed_lrc_path = lrc_path.with_extension("lrc.failed");
-          if let Err(e) = fs::rename(&lrc_path, &failed_lrc_path) {
-            eprintln!("Error renaming failed LRC file: {}", e);
-          }
-        }
+        let error = LrcError::FileIo { path: sidecar_path, source: e };
+        tracing::error!("Failed to write lyrics: {error}");
+        stats.failed_files.push((audio_path, error));
       },
     }
 
@@ -237,103 +652,1590 @@ fn embed_lrc(
   Ok(stats)
 }
 
-fn print_completions<G: Generator>(generator: G, cmd: &mut Command) {
-  generate(generator, cmd, cmd.get_name().to_string(), &mut std::io::stdout());
+struct StripStats {
+  total_audio_files: usize,
+  stripped_lyrics: usize,
+  failed_files: Vec<(PathBuf, LrcError)>,
+  remaining: Vec<PathBuf>,
 }
 
-fn main() -> Result<()> {
-  let mut cmd = Command::new("lyricsync")
-    .version(env!("CARGO_PKG_VERSION"))
-    .author("Daniel")
-    .about("Embed LRC lyrics into audio files (FLAC, MP3, M4A)")
-    .arg(
-      Arg::new("directory")
-        .short('d')
-        .long("directory")
-        .value_name("DIRECTORY")
-        .help("Directory containing audio and LRC files")
-        .required(true)
-        .value_hint(ValueHint::DirPath),
-    )
-    .arg(
-      Arg::new("skip")
-        .short('s')
-        .long("skip")
-        .help("Skip files that already have embedded lyrics")
-        .action(clap::ArgAction::SetTrue),
-    )
-    .arg(
-      Arg::new("reduce")
-        .short('r')
-        .long("reduce")
-        .help("Delete LRC files after successful embedding")
-        .action(clap::ArgAction::SetTrue),
-    )
-    .arg(
-      Arg::new("recursive")
-        .short('R')
-        .long("recursive")
-        .help("Process subdirectories recursively")
-        .action(clap::ArgAction::SetTrue),
-    )
-    .arg(
-      Arg::new("dry-run")
-        .long("dry-run")
-        .help("Show what would be done without making any changes")
-        .action(clap::ArgAction::SetTrue),
-    )
-    .arg(
-      Arg::new("generate-completion")
-        .long("generate-completion")
-        .value_name("SHELL")
-        .value_parser(["bash", "zsh", "fish", "powershell", "elvish"])
-        .help("Generate shell completion script"),
-    );
+fn strip_library(
+  audio_files: Vec<PathBuf>,
+  dry_run: bool,
+  guards: WriteGuards<'_>,
+  resolver: &FormatResolver,
+) -> Result<StripStats> {
+  let mut stats =
+    StripStats { total_audio_files: 0, stripped_lyrics: 0, failed_files: Vec::new(), remaining: Vec::new() };
 
-  let matches = cmd.clone().get_matches();
+  print_dry_run_banner(dry_run);
 
-  // Handle completion generation first
-  if let Some(generator) = matches.get_one::<String>("generate-completion") {
-    match generator.as_str() {
-      "bash" => print_completions(Shell::Bash, &mut cmd),
-      "zsh" => print_completions(Shell::Zsh, &mut cmd),
-      "fish" => print_completions(Shell::Fish, &mut cmd),
-      "powershell" => print_completions(Shell::PowerShell, &mut cmd),
-      "elvish" => print_completions(Shell::Elvish, &mut cmd),
-      _ => unreachable!(),
+  stats.total_audio_files = audio_files.len();
+
+  let pb = new_progress_bar(audio_files.len());
+
+  let mut audio_files = audio_files.into_iter();
+  for audio_path in audio_files.by_ref() {
+    if interrupt::interrupted() {
+      stats.remaining.push(audio_path);
+      break;
     }
-    return Ok(());
+    let _span = tracing::info_span!("file", path = %audio_path.display()).entered();
+    let strip = || {
+      // Only files that have lyrics get modified, so only those are backed up and journaled
+      if !guards.is_empty() {
+        if !has_embedded_lyrics(&audio_path, resolver)? {
+          return Ok(false);
+        }
+        guards.before_write(&audio_path, resolver)?;
+      }
+      lyricsync::strip_lyrics(&audio_path, resolver)
+    };
+    let result = if dry_run { has_embedded_lyrics(&audio_path, resolver) } else { strip() };
+
+    match result {
+      Ok(true) => {
+        stats.stripped_lyrics += 1;
+        let action = if dry_run { "[DRY RUN] Would strip" } else { "Stripped" };
+        report(&pb, format!("{}: {}", action, audio_path.display()));
+      },
+      Ok(false) => {
+        report(&pb, format!("No lyrics: {}", audio_path.display()));
+      },
+      Err(e) => {
+        tracing::error!("Failed to strip lyrics: {}", failure_message(&audio_path, &e));
+        stats.failed_files.push((audio_path, e));
+      },
+    }
+
+    pb.inc(1);
   }
+  stats.remaining.extend(audio_files);
 
-  let directory = matches.get_one::<String>("directory").unwrap();
-  let skip_existing = matches.get_flag("skip");
-  let reduce_lrc = matches.get_flag("reduce");
-  let recursive = matches.get_flag("recursive");
-  let dry_run = matches.get_flag("dry-run");
+  pb.finish_with_message(finish_message(dry_run));
+  Ok(stats)
+}
 
-  let stats = embed_lrc(Path::new(directory), skip_existing, reduce_lrc, recursive, dry_run)?;
+struct CheckStats {
+  total_audio_files: usize,
+  with_embedded: usize,
+  with_sidecar: usize,
+  with_any: usize,
+  differing: usize,
+  failed_files: Vec<(PathBuf, LrcError)>,
+  /// Every file checked, for `--report`
+  files: Vec<CheckedFile>,
+}
 
-  let percentage = if stats.total_audio_files > 0 {
-    (stats.embedded_lyrics as f64 / stats.total_audio_files as f64) * 100.0
-  } else {
-    0.0
+fn check_library(
+  directory: &Path,
+  recursive: bool,
+  filter: &PathFilter,
+  list: bool,
+  resolver: &FormatResolver,
+) -> CheckStats {
+  let mut stats = CheckStats {
+    total_audio_files: 0,
+    with_embedded: 0,
+    with_sidecar: 0,
+    with_any: 0,
+    differing: 0,
+    failed_files: Vec::new(),
+    files: Vec::new(),
+  };
+
+  let audio_files = collect_audio_files_matching(directory, recursive, resolver, filter);
+  stats.total_audio_files = audio_files.len();
+
+  for audio_path in audio_files {
+    let _span = tracing::info_span!("file", path = %audio_path.display()).entered();
+    let status = match lyricsync::check_lyrics(&audio_path, resolver) {
+      Ok(status) => status,
+      Err(e) => {
+        tracing::error!("Failed to check lyrics: {}", failure_message(&audio_path, &e));
+        stats.files.push(CheckedFile { path: audio_path.clone(), status: Err(failure_message(&audio_path, &e)) });
+        stats.failed_files.push((audio_path, e));
+        continue;
+      },
+    };
+
+    let (embedded, sidecar) = match status {
+      LyricsStatus::Match => (true, true),
+      LyricsStatus::Differs => {
+        stats.differing += 1;
+        (true, true)
+      },
+      LyricsStatus::EmbeddedOnly => (true, false),
+      LyricsStatus::SidecarOnly => (false, true),
+      LyricsStatus::Missing => (false, false),
+    };
+    stats.with_embedded += embedded as usize;
+    stats.with_sidecar += sidecar as usize;
+    stats.with_any += (embedded || sidecar) as usize;
+
+    if list {
+      println!("{:<8}  {}", status.label(), audio_path.display());
+    }
+    stats.files.push(CheckedFile { path: audio_path, status: Ok(status) });
+  }
+
+  stats
+}
+
+struct FetchStats {
+  total_audio_files: usize,
+  fetched_lyrics: usize,
+  transcribed: usize,
+  not_found: usize,
+  failed_files: Vec<(PathBuf, LrcError)>,
+  remaining: Vec<PathBuf>,
+}
+
+fn fetch_lyrics(
+  audio_files: Vec<PathBuf>,
+  embed: Option<(&EmbedOptions, WriteGuards<'_>)>,
+  save_translation: bool,
+  dry_run: bool,
+  resolver: &FormatResolver,
+  providers: &ProviderChain,
+  transcriber: Option<&Transcriber>,
+) -> Result<FetchStats> {
+  let mut stats = FetchStats {
+    total_audio_files: 0,
+    fetched_lyrics: 0,
+    transcribed: 0,
+    not_found: 0,
+    failed_files: Vec::new(),
+    remaining: Vec::new(),
+  };
+
+  print_dry_run_banner(dry_run);
+
+  stats.total_audio_files = audio_files.len();
+
+  let pb = new_progress_bar(audio_files.len());
+
+  let mut audio_files = audio_files.into_iter();
+  for audio_path in audio_files.by_ref() {
+    if interrupt::interrupted() {
+      stats.remaining.push(audio_path);
+      break;
+    }
+    let _span = tracing::info_span!("file", path = %audio_path.display()).entered();
+    let lrc_path = lrc_sidecar_path(&audio_path);
+    let txt_path = lrc_path.with_extension("txt");
+
+    // Only look up files that have no lyrics at all
+    if lrc_path.exists() || txt_path.exists() || has_embedded_lyrics(&audio_path, resolver).unwrap_or(false) {
+      tracing::info!("skipped, the file already has lyrics");
+      pb.inc(1);
+      continue;
+    }
+
+    match fetch_lyrics_for_file(&audio_path, resolver, providers, transcriber, embed, save_translation, dry_run) {
+      Ok(Fetched::Online) => {
+        stats.fetched_lyrics += 1;
+        let action = match (dry_run, embed.is_some()) {
+          (true, _) => "[DRY RUN] Would save",
+          (false, true) => "Embedded",
+          (false, false) => "Saved",
+        };
+        report(&pb, format!("{}: {}", action, audio_path.display()));
+      },
+      Ok(Fetched::Transcribed) => {
+        stats.transcribed += 1;
+        let action = if dry_run { "[DRY RUN] Would transcribe" } else { "Transcribed" };
+        report(&pb, format!("{}: {}", action, audio_path.display()));
+      },
+      Ok(Fetched::NotFound) => {
+        stats.not_found += 1;
+        report(&pb, format!("Not found: {}", audio_path.display()));
+      },
+      Err(e) => {
+        tracing::error!("Failed to fetch lyrics: {}", failure_message(&audio_path, &e));
+        stats.failed_files.push((audio_path, e));
+      },
+    }
+
+    pb.inc(1);
+  }
+  stats.remaining.extend(audio_files);
+
+  pb.finish_with_message(finish_message(dry_run));
+  Ok(stats)
+}
+
+// Where a file's lyrics came from, if anywhere
+enum Fetched {
+  Online,
+  Transcribed,
+  NotFound,
+}
+
+fn fetch_lyrics_for_file(
+  audio_path: &Path,
+  resolver: &FormatResolver,
+  providers: &ProviderChain,
+  transcriber: Option<&Transcriber>,
+  embed: Option<(&EmbedOptions, WriteGuards<'_>)>,
+  save_translation: bool,
+  dry_run: bool,
+) -> Result<Fetched> {
+  let format = resolver.resolve(audio_path)?;
+  let found = match track::read_track_info(audio_path, format)? {
+    Some(track) => providers.lyrics(&track)?,
+    None => None,
+  };
+
+  let (lyrics, fetched) = match (found, transcriber) {
+    (Some(lyrics), _) => (lyrics, Fetched::Online),
+    (None, None) => return Ok(Fetched::NotFound),
+    // Transcribing takes a while, so a dry run only says it would
+    (None, Some(_)) if dry_run => return Ok(Fetched::Transcribed),
+    (None, Some(transcriber)) => match transcriber.transcribe(audio_path)? {
+      Some(text) => (FetchedLyrics { text, synced: true, translation: None }, Fetched::Transcribed),
+      None => return Ok(Fetched::NotFound),
+    },
   };
 
-  println!("\nSummary:");
   if dry_run {
-    println!("[DRY RUN] Would embed lyrics in {} audio files", stats.embedded_lyrics);
+    return Ok(fetched);
+  }
+
+  if let Some((options, guards)) = embed {
+    guards.before_write(audio_path, resolver)?;
+    let text = options.prepare(audio_path, &lyrics.text, options.offset_ms);
+    lyricsync::embed_lyrics(audio_path, &Lyrics::new(text), options)?;
   } else {
-    println!("Embedded lyrics in {} audio files", stats.embedded_lyrics);
+    // Plain lyrics go to a .txt sidecar, as with `extract`
+    let extension = if lyrics.synced { "lrc" } else { "txt" };
+    let path = lrc_sidecar_path(audio_path).with_extension(extension);
+    fs::write(&path, lyrics.text).map_err(|source| LrcError::FileIo { path, source })?;
   }
-  println!("Total audio files: {}", stats.total_audio_files);
-  println!("Success rate: {:.2}%", percentage);
 
-  if !stats.failed_files.is_empty() {
-    println!("\nFailed to embed LRC for the following files:");
-    for file in stats.failed_files {
-      println!("  {}", file.display());
+  if save_translation && let Some(translation) = lyrics.translation {
+    let path = lyricsync::translation_sidecar_path(audio_path, &translation);
+    fs::write(&path, translation).map_err(|source| LrcError::FileIo { path, source })?;
+  }
+  Ok(fetched)
+}
+
+fn embed_options(matches: &ArgMatches) -> EmbedOptions {
+  let replace = match matches.get_one::<String>("replace").map(String::as_str) {
+    Some("all") => ReplacePolicy::All,
+    Some("matching") => ReplacePolicy::Matching,
+    _ => ReplacePolicy::Own,
+  };
+  let id3_frames = match matches.get_one::<String>("id3-frames").map(String::as_str) {
+    Some("uslt") => Id3Frames::Uslt,
+    Some("sylt") => Id3Frames::Sylt,
+    _ => Id3Frames::Both,
+  };
+  let id3_version = match matches.get_one::<String>("id3-version").map(String::as_str) {
+    Some("2.3") => Id3Version::V3,
+    _ => Id3Version::V4,
+  };
+  let id3_encoding = matches.get_one::<String>("encoding").map(|encoding| match encoding.as_str() {
+    "utf16" => Id3Encoding::Utf16,
+    "latin1" => Id3Encoding::Latin1,
+    _ => Id3Encoding::Utf8,
+  });
+  let language = matches.get_one::<Option<[u8; 3]>>("language").copied().flatten();
+  let description = matches.get_one::<String>("description").cloned();
+  let vorbis_keys = match matches.get_one::<String>("vorbis-key").map(String::as_str) {
+    Some("unsyncedlyrics") => VorbisKeys::UnsyncedLyrics,
+    Some("both") => VorbisKeys::Both,
+    _ => VorbisKeys::Lyrics,
+  };
+  let vorbis_synced_key = matches.get_flag("synced-lyrics-key");
+  let mp4_lyrics = match matches.get_one::<String>("mp4-lyrics").map(String::as_str) {
+    Some("timed") => Mp4Lyrics::Timed,
+    Some("both") => Mp4Lyrics::Both,
+    _ => Mp4Lyrics::Plain,
+  };
+  let word_timing = match matches.get_one::<String>("word-timing").map(String::as_str) {
+    Some("keep") => WordTiming::Keep,
+    _ => WordTiming::Strip,
+  };
+  let translations = match matches.get_one::<String>("translations").map(String::as_str) {
+    Some("merge") => Translations::Merge,
+    _ => Translations::Frames,
+  };
+  let romanization = matches.get_one::<String>("romanize").map(|mode| match mode.as_str() {
+    "merge" => Romanization::Merge,
+    _ => Romanization::Frames,
+  });
+  if romanization.is_some() && !lyricsync::romanize::AVAILABLE {
+    let message = "this lyricsync was built without the romanize feature, which --romanize needs\n";
+    clap::Error::raw(clap::error::ErrorKind::InvalidValue, message).exit();
+  }
+  let mut strip_lines = if matches.get_flag("strip-credits") { lyricsync::credits::defaults() } else { Vec::new() };
+  strip_lines.extend(matches.get_many::<Regex>("strip-line").into_iter().flatten().cloned());
+  EmbedOptions {
+    formats: format_resolver(matches),
+    replace,
+    id3_frames,
+    id3_version,
+    id3_encoding,
+    language,
+    description,
+    vorbis_keys,
+    vorbis_synced_key,
+    mp4_lyrics,
+    preserve_mtime: matches.get_flag("preserve-mtime"),
+    offset_ms: *matches.get_one::<i64>("offset").unwrap(),
+    strip_headers: matches.get_many::<String>("strip-headers").into_iter().flatten().cloned().collect(),
+    strip_lines,
+    sort_lines: matches.get_flag("sort-lines"),
+    word_timing,
+    translations,
+    romanization,
+    verify: !matches.get_flag("no-verify"),
+    ..EmbedOptions::default()
+  }
+}
+
+// The options of the folders with a config file of their own, for the files in them and below
+fn directory_options(
+  root: &Path,
+  audio_files: &[PathBuf],
+  options: &EmbedOptions,
+  command_line: &config::CommandLine,
+) -> Result<Vec<(PathBuf, EmbedOptions)>> {
+  // Retried files are listed by absolute path whatever the directory was given as
+  let root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+  let mut seen = HashSet::new();
+  let mut directories = BTreeSet::new();
+  for audio_path in audio_files {
+    for directory in audio_path.ancestors().skip(1) {
+      let inside = fs::canonicalize(directory).is_ok_and(|directory| directory.starts_with(&root));
+      // The folders above one already looked at have been too
+      if !inside || !seen.insert(directory.to_path_buf()) {
+        break;
+      }
+      if directory.join(config::DIRECTORY_FILE).is_file() {
+        directories.insert(directory.to_path_buf());
+      }
     }
   }
 
-  Ok(())
+  // Parents sort before their subfolders, so the config files of outer folders are applied first
+  directories
+    .iter()
+    .map(|directory| {
+      let files: Vec<PathBuf> = directories
+        .iter()
+        .filter(|outer| directory.starts_with(outer))
+        .map(|outer| outer.join(config::DIRECTORY_FILE))
+        .collect();
+      let matches = command_line.with_directory_files(&files)?;
+      let matches = matches.subcommand().map_or(&matches, |(_, sub_matches)| sub_matches);
+      tracing::debug!(directory = %directory.display(), "using the folder's config file");
+      Ok((directory.clone(), EmbedOptions { conflict: options.conflict, ..embed_options(matches) }))
+    })
+    .collect()
+}
+
+// The pairs of the `--map` file, if there is one
+fn read_mapping(matches: &ArgMatches) -> Result<Vec<mapping::Pair>> {
+  match matches.get_one::<PathBuf>("map") {
+    Some(path) => mapping::read(path).map_err(|source| LrcError::FileIo { path: path.clone(), source }),
+    None => Ok(Vec::new()),
+  }
+}
+
+fn sidecar_matcher(matches: &ArgMatches) -> Result<SidecarMatcher> {
+  mapped_sidecar_matcher(matches).map(|(matcher, _)| matcher)
+}
+
+// The sidecar matcher, with the audio files whose pair in the `--map` file shifts their sidecar by
+// an offset of its own, both from one reading of the file so they can't disagree
+fn mapped_sidecar_matcher(matches: &ArgMatches) -> Result<(SidecarMatcher, Vec<(PathBuf, i64)>)> {
+  let pairs = read_mapping(matches)?;
+  let offsets = pairs.iter().filter_map(|pair| Some((pair.audio_path.clone(), pair.offset_ms?))).collect();
+  let mut matcher = SidecarMatcher::default().mapping(pairs.into_iter().map(|pair| (pair.audio_path, pair.lrc_path)));
+  for rule in matches.get_many::<NameRule>("name-rule").into_iter().flatten() {
+    matcher = matcher.name_rule(rule.clone());
+  }
+  matcher = matcher.plain_text(matches.get_flag("txt-fallback"));
+  if matches.get_flag("fuzzy") {
+    matcher = matcher.fuzzy(*matches.get_one::<f64>("min-confidence").unwrap());
+  }
+  if matches.get_flag("by-tags") {
+    // Without lyrics folders of its own, the LRC files are looked for among the music
+    let directories = match matches.get_many::<PathBuf>("lyrics-dir") {
+      Some(directories) => directories.cloned().collect(),
+      None => vec![PathBuf::from(matches.get_one::<String>("directory").unwrap())],
+    };
+    matcher = matcher.by_tags(directories);
+  }
+  Ok((matcher, offsets))
+}
+
+fn format_resolver(matches: &ArgMatches) -> FormatResolver {
+  let probe = matches.get_flag("probe");
+  let aliases = matches.get_many::<(String, AudioFormat)>("ext-alias").into_iter().flatten().cloned().collect();
+  FormatResolver { probe, aliases }
+}
+
+// Keeps another run from writing to the same directory until the lock is dropped. Dry runs don't
+// write, so they don't need it, and neither does a directory that doesn't exist.
+fn lock_directory(directory: &Path, dry_run: bool) -> Result<Option<RunLock>> {
+  if dry_run || !directory.exists() {
+    return Ok(None);
+  }
+  RunLock::acquire(&RunLock::default_dir(), directory).map(Some)
+}
+
+// Prunes the backup directory up front, so the budget is free for this run's backups
+fn backups(matches: &ArgMatches, directory: &Path, dry_run: bool) -> Result<Option<Backups>> {
+  let Some(dir) = matches.get_one::<PathBuf>("backup-dir") else {
+    return Ok(None);
+  };
+  let mut backups = Backups::new(dir, directory);
+  if let Some(&size) = matches.get_one::<u64>("backup-max-size") {
+    backups = backups.max_size(size);
+  }
+  if let Some(&days) = matches.get_one::<u64>("backup-max-age") {
+    backups = backups.max_age(Duration::from_secs(days * 24 * 60 * 60));
+  }
+  if !dry_run {
+    let pruned = backups.prune()?;
+    if pruned > 0 {
+      tracing::info!("pruned {pruned} old backup(s)");
+    }
+  }
+  Ok(Some(backups))
+}
+
+// Journals go to the state directory unless --journal-dir says otherwise; dry runs change nothing
+fn start_journal(matches: &ArgMatches, command: &str, directory: &Path, dry_run: bool) -> Option<Journal> {
+  if dry_run || matches.get_flag("no-journal") {
+    return None;
+  }
+  let dir = matches.get_one::<PathBuf>("journal-dir").cloned().or_else(Journal::default_dir)?;
+  // Relative paths would break once undo runs from another directory
+  let directory = fs::canonicalize(directory).unwrap_or_else(|_| directory.to_path_buf());
+  Some(Journal::new(&dir, command, &directory))
+}
+
+// The audio files a command works through: every one in the directory, the ones an interrupted run
+// didn't get to with `--resume`, or the ones listed in a failure manifest with `--retry`
+fn library_files(
+  matches: &ArgMatches,
+  command: &str,
+  directory: &Path,
+  resolver: &FormatResolver,
+) -> Result<Vec<PathBuf>> {
+  if let Some(path) = matches.get_one::<PathBuf>("retry") {
+    let listed = manifest::read(path).map_err(|source| LrcError::FileIo { path: path.clone(), source })?;
+    return Ok(listed.into_iter().filter(|audio_path| audio_path.exists()).collect());
+  }
+  if matches.get_flag("resume") {
+    match interrupt::read_marker(directory, command) {
+      Some(remaining) => return Ok(remaining.into_iter().filter(|audio_path| audio_path.exists()).collect()),
+      None => {
+        tracing::warn!("no interrupted {command} run to resume in {}; processing every file", directory.display())
+      },
+    }
+  }
+  let filter = path_filter(matches)?;
+  Ok(collect_audio_files_matching(directory, matches.get_flag("recursive"), resolver, &filter))
+}
+
+fn path_filter(matches: &ArgMatches) -> Result<PathFilter> {
+  let patterns = |id: &str| matches.get_many::<String>(id).into_iter().flatten().collect::<Vec<_>>();
+  PathFilter::new(&patterns("include"), &patterns("exclude"))
+}
+
+// Puts back the sidecars an earlier run set aside as `.lrc.failed` (or `.srt.failed` and so on),
+// so retrying finds them again
+fn restore_failed_sidecars(audio_files: &[PathBuf]) {
+  for audio_path in audio_files {
+    let stem = audio_path.file_stem().unwrap_or_default().to_string_lossy();
+    let directory = audio_path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    for entry in fs::read_dir(directory).into_iter().flatten().filter_map(|entry| entry.ok()) {
+      let failed = entry.path();
+      let Some(name) = failed.file_name().and_then(|name| name.to_str()) else {
+        continue;
+      };
+      let restored = failed.with_extension("");
+      if name.starts_with(&format!("{stem}."))
+        && name.ends_with(".failed")
+        && (lyricsync::is_sidecar(&restored) || lyricsync::is_plain_text(&restored))
+        && !restored.exists()
+        && let Err(error) = fs::rename(&failed, &restored)
+      {
+        tracing::warn!(path = %failed.display(), %error, "couldn't restore the LRC file");
+      }
+    }
+  }
+}
+
+// Leaves the failures where `--failed-list` says, for a later `--retry`
+fn write_failed_list(matches: &ArgMatches, command: &str, failures: &[(PathBuf, LrcError)]) -> Result<()> {
+  let Some(path) = matches.get_one::<PathBuf>("failed-list") else {
+    return Ok(());
+  };
+  manifest::write(path, command, failures).map_err(|source| LrcError::FileIo { path: path.clone(), source })
+}
+
+// Leaves a marker for `--resume` when the run was interrupted, and clears an old one when it
+// wasn't. Returns whether a marker was left.
+fn save_resume_marker(command: &str, directory: &Path, remaining: &[PathBuf], dry_run: bool) -> bool {
+  if dry_run {
+    return false;
+  }
+  if !interrupt::interrupted() {
+    interrupt::clear_marker(directory);
+    return false;
+  }
+  interrupt::write_marker(directory, command, remaining)
+    .inspect_err(|error| tracing::warn!(%error, "couldn't save the files left for --resume"))
+    .is_ok()
+}
+
+fn print_interruption(command: &str, directory: &Path, remaining: &[PathBuf], dry_run: bool) {
+  let saved = save_resume_marker(command, directory, remaining, dry_run);
+  if interrupt::interrupted() {
+    println!("Interrupted with {} files left", remaining.len());
+  }
+  if saved {
+    println!("Resume with: lyricsync {command} --resume --directory {}", directory.display());
+  }
+}
+
+fn print_journal(journal: Option<&Journal>) {
+  if let Some(journal) = journal.filter(|journal| journal.is_written()) {
+    println!("Undo with: lyricsync undo {}", journal.path().display());
+  }
+}
+
+// What `--review` shows for each audio file there's an LRC file for: the one matched, if any, then
+// others it might be, for a wrong match to be swapped
+fn review_matches(audio_files: &[PathBuf], formats: &FormatResolver, matcher: &SidecarMatcher) -> Vec<review::Match> {
+  audio_files
+    .iter()
+    .filter_map(|audio_path| {
+      let matched = matcher.find(audio_path, formats);
+      let mut candidates: Vec<Sidecar> = matched.iter().take(1).cloned().collect();
+      let others =
+        matcher.candidates(audio_path, formats).into_iter().chain(fuzzy_candidates(audio_path, REVIEW_MIN_CONFIDENCE));
+      for other in others {
+        let known = candidates.iter().chain(&matched).any(|sidecar| sidecar.path == other.path);
+        if !known && candidates.len() < REVIEW_CANDIDATES {
+          candidates.push(other);
+        }
+      }
+      if candidates.is_empty() {
+        return None;
+      }
+      let embedded = has_embedded_lyrics(audio_path, formats).unwrap_or(false);
+      Some(review::Match::new(audio_path.clone(), candidates, !matched.is_empty(), embedded))
+    })
+    .collect()
+}
+
+// What to do about files that already have lyrics, asking when the flags don't say and `ask` allows
+fn conflict_policy(matches: &ArgMatches, ask: bool) -> ConflictPolicy {
+  if matches.get_flag("skip") {
+    ConflictPolicy::Skip
+  } else if matches.get_flag("force") {
+    ConflictPolicy::Overwrite
+  } else if matches.get_flag("if-newer") {
+    ConflictPolicy::IfNewer
+  } else if matches.get_flag("dry-run") {
+    // Nothing gets written in a dry run, so there's nothing to ask about
+    ConflictPolicy::Overwrite
+  } else if ask {
+    ConflictPolicy::Ask
+  } else {
+    // Scripts and cron jobs can't answer a prompt, so they keep overwriting as before
+    ConflictPolicy::Overwrite
+  }
+}
+
+fn run_embed(matches: &ArgMatches, command_line: &config::CommandLine) -> Result<RunOutcome> {
+  let directory = matches.get_one::<String>("directory").unwrap();
+  let dry_run = matches.get_flag("dry-run");
+  let jsonl = matches.get_one::<String>("progress").is_some_and(|progress| progress == "jsonl");
+  if jsonl && matches.get_one::<ReportTarget>("report").is_some_and(|report| report.path.is_none()) {
+    let message = "--report needs a PATH with --progress jsonl, which already uses stdout\n";
+    clap::Error::raw(clap::error::ErrorKind::ArgumentConflict, message).exit();
+  }
+  let mut options = embed_options(matches);
+  // Wrappers reading the JSON lines on stdout can't answer a prompt written there either
+  options.conflict = conflict_policy(matches, std::io::stdin().is_terminal() && !jsonl);
+
+  let _lock = lock_directory(Path::new(directory), dry_run)?;
+  let backups = backups(matches, Path::new(directory), dry_run)?;
+  let journal = start_journal(matches, "embed", Path::new(directory), dry_run);
+  if jsonl {
+    progress::emit_jsonl(&JsonEvent::Scanning { directory: Path::new(directory), dry_run });
+  }
+  let (mut matcher, file_offsets) = mapped_sidecar_matcher(matches)?;
+  let mut audio_files = library_files(matches, "embed", Path::new(directory), &options.formats)?;
+  if matches.get_flag("interactive") && !std::io::stdin().is_terminal() {
+    clap::Error::raw(clap::error::ErrorKind::ArgumentConflict, "--interactive needs a terminal to ask on\n").exit();
+  }
+  if matches.get_flag("review") {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+      clap::Error::raw(clap::error::ErrorKind::ArgumentConflict, "--review needs a terminal\n").exit();
+    }
+    let candidates = review_matches(&audio_files, &options.formats, &matcher);
+    let Some(approved) = review::review(Path::new(directory), candidates)? else {
+      println!("Review quit; nothing was written");
+      return Ok(RunOutcome::default());
+    };
+    if approved.is_empty() {
+      println!("No matches approved; nothing was written");
+      return Ok(RunOutcome::default());
+    }
+    let picked: Vec<(PathBuf, PathBuf)> =
+      approved.iter().filter_map(|(audio_path, lrc_path)| Some((audio_path.clone(), lrc_path.clone()?))).collect();
+    matcher = matcher.mapping(picked);
+    audio_files = approved.into_iter().map(|(audio_path, _)| audio_path).collect();
+  }
+  if matches.contains_id("retry") && !dry_run {
+    restore_failed_sidecars(&audio_files);
+  }
+  let directory_options = directory_options(Path::new(directory), &audio_files, &options, command_line)?;
+  if jsonl {
+    progress::emit_jsonl(&JsonEvent::Scanned { files: audio_files.len() });
+  }
+  let run = EmbedRun {
+    reduce: matches.get_flag("reduce"),
+    normalize_sidecars: matches.get_flag("normalize-sidecars"),
+    reject_invalid: matches.get_flag("reject-invalid"),
+    dry_run,
+    rewrite: matches.get_flag("rewrite"),
+    jobs: *matches.get_one::<usize>("jobs").unwrap(),
+    jsonl,
+    interactive: matches.get_flag("interactive"),
+    guards: WriteGuards { backups: backups.as_ref(), journal: journal.as_ref() },
+    matcher: &matcher,
+    file_offsets: &file_offsets,
+  };
+  // Without a path the report takes the place of everything else on stdout, as do progress events
+  let report = matches.get_one::<ReportTarget>("report");
+  let text = report.is_none_or(|report| report.path.is_some()) && !jsonl;
+  if text {
+    print_dry_run_banner(dry_run);
+  } else {
+    progress::set_plain(false);
+  }
+
+  let started = Instant::now();
+  interrupt::install();
+  let stats = embed_lrc(audio_files, &run, &options, directory_options)?;
+  write_failed_list(matches, "embed", &stats.failed_files)?;
+  let summary = Summary::of(&stats.files, stats.remaining.len());
+  // Files with no sidecar are skipped too, but not ones already up to date
+  let outcome = RunOutcome::files(stats.total_audio_files, summary.failed, summary.skipped + summary.no_lrc);
+
+  if jsonl {
+    progress::emit_jsonl(&JsonEvent::Finished { interrupted: interrupt::interrupted(), summary });
+  }
+  if let Some(target) = report {
+    let report = Report {
+      command: "embed",
+      directory: PathBuf::from(directory),
+      dry_run,
+      interrupted: interrupt::interrupted(),
+      duration_ms: report::duration_ms(started.elapsed()),
+      journal: journal.as_ref().filter(|journal| journal.is_written()).map(|journal| journal.path().to_path_buf()),
+      summary,
+      files: stats.files,
+    };
+    report.write(target).map_err(|error| match &target.path {
+      Some(path) => LrcError::FileIo { path: path.clone(), source: error },
+      None => error.into(),
+    })?;
+  }
+  if !text {
+    save_resume_marker("embed", Path::new(directory), &stats.remaining, dry_run);
+    return Ok(outcome);
+  }
+
+  // Files that already had these lyrics are as done as the ones written this run
+  let percentage = if stats.total_audio_files > 0 {
+    ((stats.embedded_lyrics + stats.unchanged) as f64 / stats.total_audio_files as f64) * 100.0
+  } else {
+    0.0
+  };
+
+  println!("\nSummary:");
+  if dry_run {
+    println!("[DRY RUN] Would embed lyrics in {} audio files", stats.embedded_lyrics);
+  } else {
+    println!("Embedded lyrics in {} audio files", stats.embedded_lyrics);
+  }
+  println!("Already up to date: {}", stats.unchanged);
+  println!("Total audio files: {}", stats.total_audio_files);
+  println!("Success rate: {:.2}%", percentage);
+  // Counted apart from other failures, as they point at a problem writing tags rather than the files
+  let unverified =
+    stats.failed_files.iter().filter(|(_, error)| matches!(error, LrcError::VerifyFailed { .. })).count();
+  if unverified > 0 {
+    println!("Failed verification: {unverified}");
+  }
+  print_interruption("embed", Path::new(directory), &stats.remaining, dry_run);
+
+  print_journal(journal.as_ref());
+
+  print_breakdown(&stats.by_format, &stats.failures_by_kind);
+  print_failures("Failed to embed LRC for the following files", &stats.failed_files);
+
+  Ok(outcome)
+}
+
+// Embeds the files that settle in the directory, a batch at a time, until the run is stopped, for
+// `watch` and for `daemon`, which is a watch with scans on a schedule
+fn run_watch(matches: &ArgMatches, command: &str, command_line: &config::CommandLine) -> Result<RunOutcome> {
+  let directory = matches.get_one::<String>("directory").unwrap();
+  // Events name files by the path that's watched, which is then the same however it was given
+  let directory = fs::canonicalize(directory).map_err(|source| LrcError::FileIo { path: directory.into(), source })?;
+  let dry_run = matches.get_flag("dry-run");
+  let mut options = embed_options(matches);
+  // Nobody is there to answer a prompt when a file turns up
+  options.conflict = conflict_policy(matches, false);
+  let backups = backups(matches, &directory, dry_run)?;
+  let journal = start_journal(matches, command, &directory, dry_run);
+  let (matcher, file_offsets) = mapped_sidecar_matcher(matches)?;
+  let filter = path_filter(matches)?;
+  let run = EmbedRun {
+    reduce: matches.get_flag("reduce"),
+    normalize_sidecars: matches.get_flag("normalize-sidecars"),
+    reject_invalid: matches.get_flag("reject-invalid"),
+    dry_run,
+    rewrite: matches.get_flag("rewrite"),
+    jobs: 1,
+    jsonl: false,
+    interactive: false,
+    guards: WriteGuards { backups: backups.as_ref(), journal: journal.as_ref() },
+    matcher: &matcher,
+    file_offsets: &file_offsets,
+  };
+  let recursive = matches.get_flag("recursive");
+  let schedule = match (matches.try_get_one::<Cron>("cron"), matches.try_get_one::<u64>("interval")) {
+    (Ok(Some(cron)), _) => Some(Schedule::Cron(Box::new(cron.clone()))),
+    (_, Ok(Some(minutes))) => Some(Schedule::Every(Duration::from_secs(minutes * 60))),
+    _ => None,
+  };
+  let control = match matches.try_get_one::<PathBuf>("socket") {
+    Ok(Some(path)) => {
+      Some(ControlSocket::bind(path, &directory).map_err(|source| LrcError::FileIo { path: path.clone(), source })?)
+    },
+    _ => None,
+  };
+  let watch_options = WatchOptions {
+    recursive,
+    debounce: Duration::from_millis(*matches.get_one::<u64>("debounce").unwrap()),
+    schedule,
+    control,
+  };
+
+  let (mut total, mut embedded, mut failed) = (0, 0, 0);
+  // The files gone through, by the modification time they were left with, so the events of saving
+  // them don't set them off again
+  let mut seen: HashMap<PathBuf, SystemTime> = HashMap::new();
+  print_dry_run_banner(dry_run);
+  interrupt::install();
+  let watched = watch::watch(&directory, &watch_options, &options.formats, &filter, |batch| {
+    let audio_files = match &batch {
+      Batch::Settled(settled) => settled_audio_files(&directory, settled, &matcher, &options.formats, &filter, &seen),
+      Batch::Scan => {
+        tracing::info!("scanning {}", directory.display());
+        collect_audio_files_matching(&directory, recursive, &options.formats, &filter)
+      },
+    };
+    if audio_files.is_empty() {
+      return None;
+    }
+    let _lock = match lock_directory(&directory, dry_run) {
+      Ok(lock) => lock,
+      // The files wait for the other run to finish
+      Err(error @ LrcError::Locked { .. }) => {
+        tracing::warn!("{error}; trying again later");
+        return Some(batch);
+      },
+      Err(error) => {
+        tracing::error!("{error}");
+        return None;
+      },
+    };
+    let stats = directory_options(&directory, &audio_files, &options, command_line)
+      .and_then(|directory_options| embed_lrc(audio_files.clone(), &run, &options, directory_options));
+    match stats {
+      Ok(stats) => {
+        total += stats.total_audio_files;
+        embedded += stats.embedded_lyrics;
+        failed += stats.failed_files.len();
+        watch_options.report(|status| (status.checked, status.embedded, status.failed) = (total, embedded, failed));
+        print_failures("Failed to embed LRC for the following files", &stats.failed_files);
+      },
+      Err(error) => tracing::error!("{error}"),
+    }
+    for audio_path in audio_files {
+      if let Ok(modified) = fs::metadata(&audio_path).and_then(|metadata| metadata.modified()) {
+        seen.insert(audio_path, modified);
+      }
+    }
+    None
+  });
+  watched.map_err(|error| LrcError::FileIo { path: directory.clone(), source: std::io::Error::other(error) })?;
+
+  println!("\nSummary:");
+  if dry_run {
+    println!("[DRY RUN] Would embed lyrics in {embedded} audio files");
+  } else {
+    println!("Embedded lyrics in {embedded} audio files");
+  }
+  println!("Audio files checked: {total}");
+  print_journal(journal.as_ref());
+  Ok(RunOutcome { failed, until_stopped: true, ..RunOutcome::default() })
+}
+
+fn run_serve(matches: &ArgMatches) -> Result<RunOutcome> {
+  let directory = matches.get_one::<String>("directory").unwrap();
+  // Requested paths are resolved and checked against the library's own
+  let directory = fs::canonicalize(directory).map_err(|source| LrcError::FileIo { path: directory.into(), source })?;
+  let address = matches.get_one::<String>("listen").unwrap();
+  let mut library = serve::Library::open(
+    directory.clone(),
+    matches.get_flag("recursive"),
+    format_resolver(matches),
+    path_filter(matches)?,
+    sidecar_matcher(matches)?,
+  );
+  let server = tiny_http::Server::http(address)
+    .map_err(|error| LrcError::FileIo { path: directory.clone(), source: std::io::Error::other(error) })?;
+  match server.server_addr().to_ip() {
+    Some(address) => println!("Serving lyrics from {} on http://{address}", directory.display()),
+    None => println!("Serving lyrics from {}", directory.display()),
+  }
+  interrupt::install();
+  serve::serve(&server, &mut library, matches.get_flag("lrclib-api"));
+  Ok(RunOutcome { until_stopped: true, ..RunOutcome::default() })
+}
+
+// An MPD option, or MPD's own environment variable when it's left at its default, as MPD's clients do
+fn mpd_option<T: Clone + Send + Sync + std::str::FromStr + 'static>(matches: &ArgMatches, id: &str, env: &str) -> T {
+  let given = matches.get_one::<T>(id).unwrap().clone();
+  match matches.value_source(id) {
+    Some(ValueSource::DefaultValue) => std::env::var(env).ok().and_then(|value| value.parse().ok()).unwrap_or(given),
+    _ => given,
+  }
+}
+
+fn run_now_playing(matches: &ArgMatches) -> Result<RunOutcome> {
+  let directory = PathBuf::from(matches.get_one::<String>("directory").unwrap());
+  let host: String = mpd_option(matches, "mpd-host", "MPD_HOST");
+  let port: u16 = mpd_option(matches, "mpd-port", "MPD_PORT");
+  let formats = format_resolver(matches);
+  let matcher = sidecar_matcher(matches)?;
+  // The password isn't shown, if the host came with one
+  let server = format!("{}:{port}", host.rsplit_once('@').map_or(host.as_str(), |(_, host)| host));
+  let mpd_error =
+    |error: std::io::Error| LrcError::Io(std::io::Error::new(error.kind(), format!("MPD at {server}: {error}")));
+  let mut mpd = mpd::Mpd::connect(&host, port).map_err(mpd_error)?;
+
+  // The lyrics of the song loaded, found when it changes
+  let load = |song: &mpd::Song| {
+    let audio_path = directory.join(&song.file);
+    if !audio_path.is_file() {
+      tracing::warn!("{}: not a file in the music directory", song.file);
+      return None;
+    }
+    karaoke::find(&audio_path, &formats, &matcher)
+  };
+  let heading = |song: &mpd::Song| match (&song.artist, &song.title) {
+    (Some(artist), Some(title)) => format!("{artist} - {title}"),
+    _ => song.file.clone(),
+  };
+
+  if matches.get_flag("once") {
+    let status = mpd.status().map_err(mpd_error)?;
+    let song = mpd.current_song().map_err(mpd_error)?.filter(|_| status.state != mpd::State::Stop);
+    let Some(song) = song else {
+      eprintln!("Nothing is playing");
+      return Ok(RunOutcome { no_files: true, ..RunOutcome::default() });
+    };
+    let Some(lyrics) = load(&song) else {
+      eprintln!("No lyrics for {}", heading(&song));
+      return Ok(RunOutcome { no_files: true, ..RunOutcome::default() });
+    };
+    if !lyrics.synced {
+      println!("{}", lyrics.text.trim_end());
+    } else {
+      let timeline = karaoke::Timeline::new(&lyrics.text);
+      let elapsed_ms = u32::try_from(status.elapsed.as_millis()).unwrap_or(u32::MAX);
+      if let Some(line) = timeline.line_at(elapsed_ms) {
+        println!("{}", timeline.lines()[line].text);
+      }
+    }
+    return Ok(RunOutcome::default());
+  }
+
+  interrupt::install();
+  // The file playing, its synced lyrics if it has any, and the line last printed
+  let mut playing: Option<(String, Option<karaoke::Timeline>)> = None;
+  let mut shown: Option<usize> = None;
+  while !interrupt::interrupted() {
+    let status = mpd.status().map_err(mpd_error)?;
+    let song = mpd.current_song().map_err(mpd_error)?;
+    let polled = Instant::now();
+    let file = song.as_ref().map(|song| song.file.clone());
+    if playing.as_ref().map(|(file, _)| file) != file.as_ref() {
+      shown = None;
+      playing = song.map(|song| {
+        println!("\n♪ {}", heading(&song));
+        let timeline = match load(&song) {
+          Some(lyrics) if lyrics.synced => Some(karaoke::Timeline::new(&lyrics.text)),
+          Some(lyrics) => {
+            println!("{}", lyrics.text.trim_end());
+            None
+          },
+          None => {
+            println!("(no lyrics)");
+            None
+          },
+        };
+        (song.file, timeline)
+      });
+    }
+
+    // Between polls, the lines are timed by the clock from where the player said it was
+    let deadline = polled + NOW_PLAYING_POLL;
+    let timeline =
+      playing.as_ref().and_then(|(_, timeline)| timeline.as_ref()).filter(|_| status.state != mpd::State::Stop);
+    let Some(timeline) = timeline else {
+      std::thread::sleep(NOW_PLAYING_POLL);
+      continue;
+    };
+    while Instant::now() < deadline && !interrupt::interrupted() {
+      let elapsed = status.elapsed + if status.state == mpd::State::Play { polled.elapsed() } else { Duration::ZERO };
+      let elapsed_ms = u32::try_from(elapsed.as_millis()).unwrap_or(u32::MAX);
+      let line = timeline.line_at(elapsed_ms);
+      if let Some(index) = line
+        && line != shown
+      {
+        println!("{}", timeline.lines()[index].text);
+        shown = line;
+      }
+      let until_next = timeline
+        .next_start(elapsed_ms)
+        .filter(|_| status.state == mpd::State::Play)
+        .map(|start| Duration::from_millis(u64::from(start - elapsed_ms)));
+      let until_deadline = deadline.saturating_duration_since(Instant::now());
+      std::thread::sleep(until_next.map_or(until_deadline, |until_next| until_next.min(until_deadline)));
+    }
+  }
+  Ok(RunOutcome { until_stopped: true, ..RunOutcome::default() })
+}
+
+fn run_play(matches: &ArgMatches) -> Result<RunOutcome> {
+  let audio_path = matches.get_one::<PathBuf>("file").unwrap();
+  let silent = matches.get_flag("silent");
+  if !silent && !play::AVAILABLE {
+    let message =
+      "this lyricsync was built without the playback feature; pass --silent to show the lyrics without sound\n";
+    clap::Error::raw(clap::error::ErrorKind::MissingRequiredArgument, message).exit();
+  }
+  let formats = format_resolver(matches);
+  let format = formats.resolve(audio_path)?;
+  let Some(lyrics) = karaoke::find(audio_path, &formats, &sidecar_matcher(matches)?) else {
+    eprintln!("No lyrics for {}", audio_path.display());
+    return Ok(RunOutcome { no_files: true, ..RunOutcome::default() });
+  };
+  let timeline = karaoke::Timeline::new(&lyrics.text);
+
+  interrupt::install();
+  let playback = if silent {
+    // The clock runs for the track's length, and at least until the last line
+    let last_line =
+      timeline.lines().last().map_or(Duration::ZERO, |line| Duration::from_millis(u64::from(line.time_ms)));
+    let length = track::read_duration(audio_path, format).ok().flatten().unwrap_or_default().max(last_line);
+    play::Playback::silent(length)
+  } else {
+    play::Playback::start(audio_path).map_err(|source| LrcError::FileIo { path: audio_path.clone(), source })?
+  };
+  match track::read_track_info(audio_path, format).ok().flatten() {
+    Some(info) => println!("♪ {} - {}", info.artist, info.title),
+    None => println!("♪ {}", audio_path.display()),
+  }
+  if !lyrics.synced {
+    // There's nothing to follow, but the track is still played through
+    println!("{}", lyrics.text.trim_end());
+  }
+
+  let mut display = karaoke::Display::new(progress::color());
+  loop {
+    let elapsed_ms = u32::try_from(playback.elapsed().as_millis()).unwrap_or(u32::MAX);
+    if lyrics.synced {
+      display.show(&timeline, timeline.line_at(elapsed_ms))?;
+    }
+    if playback.finished() || interrupt::interrupted() {
+      break;
+    }
+    std::thread::sleep(PLAY_TICK);
+  }
+  Ok(RunOutcome { until_stopped: interrupt::interrupted(), ..RunOutcome::default() })
+}
+
+fn run_tap(matches: &ArgMatches) -> Result<RunOutcome> {
+  let audio_path = matches.get_one::<PathBuf>("file").unwrap();
+  let silent = matches.get_flag("silent");
+  if !silent && !play::AVAILABLE {
+    let message =
+      "this lyricsync was built without the playback feature; pass --silent to time the lyrics without sound\n";
+    clap::Error::raw(clap::error::ErrorKind::MissingRequiredArgument, message).exit();
+  }
+  let output = matches.get_one::<PathBuf>("output").cloned().unwrap_or_else(|| lrc_sidecar_path(audio_path));
+  // Checked before the timing, which would be lost
+  if output.exists() && !matches.get_flag("overwrite") {
+    let source = std::io::Error::new(std::io::ErrorKind::AlreadyExists, "already exists; --overwrite replaces it");
+    return Err(LrcError::FileIo { path: output, source });
+  }
+  if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+    clap::Error::raw(clap::error::ErrorKind::ArgumentConflict, "tap needs a terminal\n").exit();
+  }
+  let formats = format_resolver(matches);
+  let format = formats.resolve(audio_path)?;
+  let lyrics = match matches.get_one::<PathBuf>("lyrics") {
+    Some(path) => Some(read_sidecar(path)?),
+    None => karaoke::find(audio_path, &formats, &sidecar_matcher(matches)?).map(|lyrics| lyrics.text),
+  };
+  let mut tapping = tap::Tapping::new(lyrics.as_deref().unwrap_or_default());
+  if tapping.lines() == 0 {
+    eprintln!("No lyrics for {} to time; --lyrics gives a file of them", audio_path.display());
+    return Ok(RunOutcome { no_files: true, ..RunOutcome::default() });
+  }
+
+  let mut playback = if silent {
+    play::Playback::silent(track::read_duration(audio_path, format).ok().flatten().unwrap_or(Duration::MAX))
+  } else {
+    play::Playback::start(audio_path).map_err(|source| LrcError::FileIo { path: audio_path.clone(), source })?
+  };
+  let title = match track::read_track_info(audio_path, format).ok().flatten() {
+    Some(info) => format!("{} - {}", info.artist, info.title),
+    None => audio_path.display().to_string(),
+  };
+  match tap::tap(&title, &mut tapping, &mut playback)? {
+    Some(lrc) => {
+      fs::write(&output, lrc).map_err(|source| LrcError::FileIo { path: output.clone(), source })?;
+      println!("Saved {} timed lines to {}", tapping.lines(), output.display());
+    },
+    None => println!("Nothing was saved"),
+  }
+  Ok(RunOutcome::default())
+}
+
+// The audio files to embed for files that settled: audio files themselves, unless they're as this
+// run left them, and the ones a new LRC file belongs to
+fn settled_audio_files(
+  root: &Path,
+  settled: &[PathBuf],
+  matcher: &SidecarMatcher,
+  resolver: &FormatResolver,
+  filter: &PathFilter,
+  seen: &HashMap<PathBuf, SystemTime>,
+) -> Vec<PathBuf> {
+  let mut audio_files = BTreeSet::new();
+  for path in settled {
+    if !watch::is_lrc(path) {
+      let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+      if modified.is_none() || seen.get(path) != modified.as_ref() {
+        audio_files.insert(path.clone());
+      }
+      continue;
+    }
+    let Some(directory) = path.parent() else {
+      continue;
+    };
+    for audio_path in collect_audio_files_matching(directory, false, resolver, &PathFilter::default()) {
+      let relative = audio_path.strip_prefix(root).unwrap_or(&audio_path);
+      if filter.is_match(relative) && matcher.find(&audio_path, resolver).iter().any(|sidecar| sidecar.path == *path) {
+        audio_files.insert(audio_path);
+      }
+    }
+  }
+  audio_files.into_iter().collect()
+}
+
+// Tells files that need a format lyricsync doesn't support apart from broken ones
+fn print_breakdown(by_format: &BTreeMap<&str, FormatCounts>, failures_by_kind: &BTreeMap<FailureKind, usize>) {
+  if !by_format.is_empty() {
+    println!("\nBy format:");
+    println!(
+      "  {:<8} {:>6} {:>9} {:>11} {:>8} {:>7}",
+      "Format", "Files", "Embedded", "Up to date", "Skipped", "Failed"
+    );
+    for (format, counts) in by_format {
+      println!(
+        "  {:<8} {:>6} {:>9} {:>11} {:>8} {:>7}",
+        format.to_ascii_uppercase(),
+        counts.files,
+        counts.embedded,
+        counts.unchanged,
+        counts.skipped,
+        counts.failed
+      );
+    }
+  }
+  if !failures_by_kind.is_empty() {
+    println!("\nFailures by reason:");
+    for (kind, count) in failures_by_kind {
+      println!("  {:<20} {count:>6}", kind.label());
+    }
+  }
+}
+
+fn run_extract(matches: &ArgMatches) -> Result<RunOutcome> {
+  let directory = matches.get_one::<String>("directory").unwrap();
+  let recursive = matches.get_flag("recursive");
+  let overwrite = matches.get_flag("overwrite");
+  let dry_run = matches.get_flag("dry-run");
+  let resolver = format_resolver(matches);
+
+  let filter = path_filter(matches)?;
+
+  let stats = extract_lrc(Path::new(directory), recursive, &filter, overwrite, dry_run, &resolver)?;
+
+  println!("\nSummary:");
+  if dry_run {
+    println!("[DRY RUN] Would extract lyrics from {} audio files", stats.extracted_lyrics);
+  } else {
+    println!("Extracted lyrics from {} audio files", stats.extracted_lyrics);
+  }
+  println!("Skipped (sidecar exists): {}", stats.skipped_existing);
+  println!("Total audio files: {}", stats.total_audio_files);
+
+  print_failures("Failed to extract lyrics from the following files", &stats.failed_files);
+
+  Ok(RunOutcome::files(stats.total_audio_files, stats.failed_files.len(), stats.skipped_existing))
+}
+
+fn run_strip(matches: &ArgMatches) -> Result<RunOutcome> {
+  let directory = matches.get_one::<String>("directory").unwrap();
+  let dry_run = matches.get_flag("dry-run");
+  let resolver = format_resolver(matches);
+  let _lock = lock_directory(Path::new(directory), dry_run)?;
+  let backups = backups(matches, Path::new(directory), dry_run)?;
+  let journal = start_journal(matches, "strip", Path::new(directory), dry_run);
+  let guards = WriteGuards { backups: backups.as_ref(), journal: journal.as_ref() };
+
+  let audio_files = library_files(matches, "strip", Path::new(directory), &resolver)?;
+
+  interrupt::install();
+  let stats = strip_library(audio_files, dry_run, guards, &resolver)?;
+  write_failed_list(matches, "strip", &stats.failed_files)?;
+
+  println!("\nSummary:");
+  if dry_run {
+    println!("[DRY RUN] Would strip lyrics from {} audio files", stats.stripped_lyrics);
+  } else {
+    println!("Stripped lyrics from {} audio files", stats.stripped_lyrics);
+  }
+  println!("Total audio files: {}", stats.total_audio_files);
+  print_interruption("strip", Path::new(directory), &stats.remaining, dry_run);
+
+  print_journal(journal.as_ref());
+
+  print_failures("Failed to strip lyrics from the following files", &stats.failed_files);
+
+  Ok(RunOutcome::files(stats.total_audio_files, stats.failed_files.len(), 0))
+}
+
+fn run_check(matches: &ArgMatches) -> Result<RunOutcome> {
+  let directory = matches.get_one::<String>("directory").unwrap();
+  let recursive = matches.get_flag("recursive");
+  let resolver = format_resolver(matches);
+
+  // Without a path the page takes the place of the listing and summary on stdout
+  let report = matches.get_one::<ReportTarget>("report");
+  let text = report.is_none_or(|report| report.path.is_some());
+  let filter = path_filter(matches)?;
+  let stats = check_library(Path::new(directory), recursive, &filter, text && !progress::quiet(), &resolver);
+  let outcome = RunOutcome::files(stats.total_audio_files, stats.failed_files.len(), 0);
+
+  if let Some(target) = report {
+    CoveragePage::new(Path::new(directory), &stats.files, &resolver).write(target).map_err(|error| {
+      match &target.path {
+        Some(path) => LrcError::FileIo { path: path.clone(), source: error },
+        None => error.into(),
+      }
+    })?;
+  }
+  if !text {
+    return Ok(outcome);
+  }
+
+  let coverage = |count: usize| {
+    let percentage =
+      if stats.total_audio_files > 0 { (count as f64 / stats.total_audio_files as f64) * 100.0 } else { 0.0 };
+    format!("{}/{} ({:.2}%)", count, stats.total_audio_files, percentage)
+  };
+
+  println!("\nSummary:");
+  println!("Embedded lyrics: {}", coverage(stats.with_embedded));
+  println!("Sidecar LRC: {}", coverage(stats.with_sidecar));
+  println!("Any lyrics: {}", coverage(stats.with_any));
+  println!("Embedded and sidecar differ: {}", stats.differing);
+  println!("Total audio files: {}", stats.total_audio_files);
+
+  print_failures("Failed to read the following files", &stats.failed_files);
+
+  Ok(outcome)
+}
+
+fn run_validate(matches: &ArgMatches) -> Result<RunOutcome> {
+  let directory = Path::new(matches.get_one::<String>("directory").unwrap());
+  let lrc_files = collect_lrc_files(directory, matches.get_flag("recursive"), &path_filter(matches)?);
+
+  let mut invalid = 0;
+  let mut failed_files = Vec::new();
+  for lrc_path in &lrc_files {
+    let issues = match read_sidecar(lrc_path) {
+      Ok(lyrics) => lyricsync::lrc::validate(&lyrics),
+      Err(error) => {
+        failed_files.push((lrc_path.clone(), error));
+        continue;
+      },
+    };
+    if issues.is_empty() {
+      continue;
+    }
+    invalid += 1;
+    if progress::quiet() {
+      continue;
+    }
+    println!("{}", lrc_path.strip_prefix(directory).unwrap_or(lrc_path).display());
+    for issue in issues {
+      match issue.line {
+        0 => println!("  {}", issue.message),
+        line => println!("  line {line}: {}", issue.message),
+      }
+    }
+  }
+
+  println!("\nSummary:");
+  println!("Valid LRC files: {}", lrc_files.len() - invalid - failed_files.len());
+  println!("Invalid LRC files: {invalid}");
+  println!("Total LRC files: {}", lrc_files.len());
+
+  print_failures("Failed to read the following files", &failed_files);
+
+  Ok(RunOutcome::files(lrc_files.len(), invalid + failed_files.len(), 0))
+}
+
+fn run_shift(matches: &ArgMatches) -> Result<RunOutcome> {
+  let lrc_path = matches.get_one::<PathBuf>("file").unwrap();
+  let anchors: Vec<(u32, u32)> = matches.get_many("anchor").unwrap().copied().collect();
+  let Ok(anchors) = <[(u32, u32); 2]>::try_from(anchors) else {
+    clap::Error::raw(clap::error::ErrorKind::WrongNumberOfValues, "shift needs --anchor given exactly twice\n").exit();
+  };
+  let lyrics = read_sidecar(lrc_path)?;
+  let Some(shifted) = lyricsync::lrc::rescale(&lyrics, anchors) else {
+    clap::Error::raw(clap::error::ErrorKind::ValueValidation, "the two anchors need different LRC times\n").exit();
+  };
+
+  if matches.get_flag("dry-run") {
+    print!("{shifted}");
+    return Ok(RunOutcome::default());
+  }
+  let output = matches.get_one::<PathBuf>("output").unwrap_or(lrc_path);
+  fs::write(output, shifted).map_err(|source| LrcError::FileIo { path: output.clone(), source })?;
+  println!("Saved the retimed lyrics to {}", output.display());
+  Ok(RunOutcome::default())
+}
+
+fn run_undo(matches: &ArgMatches) -> Result<RunOutcome> {
+  let dry_run = matches.get_flag("dry-run");
+  let resolver = format_resolver(matches);
+  let dir = matches.get_one::<PathBuf>("journal-dir").cloned().or_else(Journal::default_dir);
+  let journals = dir.as_deref().map(journal::journals).unwrap_or_default();
+
+  if matches.get_flag("list") {
+    for path in &journals {
+      let entries = journal::read_journal(path)?;
+      let changes = entries.iter().filter(|entry| !matches!(entry, JournalEntry::Run { .. })).count();
+      if let Some(JournalEntry::Run { command, directory, .. }) = entries.first() {
+        println!("{}  {} {} ({} changes)", path.display(), command, directory.display(), changes);
+      }
+    }
+    return Ok(RunOutcome::default());
+  }
+
+  let path = match matches.get_one::<PathBuf>("journal") {
+    Some(path) => path.clone(),
+    None => journals.last().cloned().ok_or_else(|| LrcError::NoJournal { dir: dir.unwrap_or_default() })?,
+  };
+  let entries = journal::read_journal(&path)?;
+  let _lock = match entries.first() {
+    Some(JournalEntry::Run { directory, .. }) => lock_directory(directory, dry_run)?,
+    _ => None,
+  };
+
+  print_dry_run_banner(dry_run);
+
+  let mut undone = 0;
+  let mut failed_files = Vec::new();
+  for entry in entries.iter().rev() {
+    let (action, file) = match entry {
+      JournalEntry::Run { .. } => continue,
+      JournalEntry::Lyrics { path, previous: Some(_), .. } => ("Restore lyrics", path),
+      JournalEntry::Lyrics { path, previous: None, .. } => ("Remove lyrics", path),
+      JournalEntry::SidecarDeleted { path, .. } => ("Re-create", path),
+      JournalEntry::SidecarRewritten { path, .. } => ("Restore", path),
+    };
+    if dry_run {
+      if !progress::quiet() {
+        println!("[DRY RUN] Would {}: {}", action.to_ascii_lowercase(), file.display());
+      }
+      undone += 1;
+      continue;
+    }
+    match journal::undo_entry(entry, &resolver) {
+      Ok(()) => {
+        undone += 1;
+        if !progress::quiet() {
+          println!("{action}: {}", file.display());
+        }
+      },
+      Err(error) => {
+        tracing::error!("Failed to undo: {}", failure_message(file, &error));
+        failed_files.push((file.clone(), error));
+      },
+    }
+  }
+
+  // A journal with failures is kept, so the undo can be retried once they're fixed
+  if !dry_run && failed_files.is_empty() {
+    journal::mark_undone(&path)?;
+  }
+
+  println!("\nSummary:");
+  if dry_run {
+    println!("[DRY RUN] Would undo {undone} changes from {}", path.display());
+  } else {
+    println!("Undid {undone} changes from {}", path.display());
+  }
+
+  print_failures("Failed to undo the changes to the following files", &failed_files);
+
+  Ok(RunOutcome { failed: failed_files.len(), ..RunOutcome::default() })
+}
+
+fn build_provider_chain(matches: &ArgMatches) -> Result<ProviderChain> {
+  let mut names: Vec<&str> = matches.get_many::<String>("provider").into_iter().flatten().map(String::as_str).collect();
+  // Genius only has plain lyrics, so it's a last resort behind an explicit opt-in
+  if matches.get_flag("allow-unsynced-fetch") {
+    names.push("genius");
+  }
+
+  let disabled: Vec<&str> =
+    matches.get_many::<String>("disable-provider").into_iter().flatten().map(String::as_str).collect();
+  let mut chain: Vec<&str> = Vec::new();
+  for name in names {
+    if !disabled.contains(&name) && !chain.contains(&name) {
+      chain.push(name);
+    }
+  }
+
+  let token = |arg: &str, provider: &str| {
+    matches.get_one::<String>(arg).ok_or_else(|| LrcError::Provider(format!("{provider} requires --{arg}")))
+  };
+
+  let mut providers = Vec::with_capacity(chain.len());
+  for name in chain {
+    let provider = match name {
+      "musixmatch" => Provider::Musixmatch(MusixmatchClient::new(
+        matches.get_one::<String>("musixmatch-url").unwrap(),
+        token("musixmatch-token", "Musixmatch")?,
+      )),
+      "netease" => Provider::Netease(NeteaseClient::new(matches.get_one::<String>("netease-url").unwrap())),
+      "genius" => Provider::Genius(GeniusClient::new(
+        matches.get_one::<String>("genius-api-url").unwrap(),
+        matches.get_one::<String>("genius-web-url").unwrap(),
+        token("genius-token", "Genius")?,
+      )),
+      _ => Provider::Lrclib(LrclibClient::new(matches.get_one::<String>("lrclib-url").unwrap())),
+    };
+    providers.push(provider);
+  }
+
+  let filters = LyricsFilters {
+    synced_only: matches.get_flag("synced-only"),
+    min_lines: matches.get_one::<usize>("min-lines").copied().unwrap_or(0),
+  };
+
+  let cache = if matches.get_flag("no-cache") {
+    None
+  } else {
+    let dir = matches.get_one::<PathBuf>("cache-dir").cloned().or_else(FetchCache::default_dir);
+    let days = |arg: &str| Duration::from_secs(matches.get_one::<u64>(arg).copied().unwrap_or(0) * 24 * 60 * 60);
+    dir.map(|dir| FetchCache::new(dir, days("cache-ttl"), days("negative-cache-ttl")))
+  };
+
+  Ok(ProviderChain::new(providers, filters, cache))
+}
+
+fn run_fetch(matches: &ArgMatches) -> Result<RunOutcome> {
+  let directory = matches.get_one::<String>("directory").unwrap();
+  let embed_options = embed_options(matches);
+  let save_translation = matches.get_flag("save-translation");
+  let dry_run = matches.get_flag("dry-run");
+  let _lock = lock_directory(Path::new(directory), dry_run)?;
+  let backups = backups(matches, Path::new(directory), dry_run)?;
+  let journal = start_journal(matches, "fetch", Path::new(directory), dry_run);
+  let guards = WriteGuards { backups: backups.as_ref(), journal: journal.as_ref() };
+  let embed = matches.get_flag("embed").then_some((&embed_options, guards));
+  let resolver = format_resolver(matches);
+  let providers = build_provider_chain(matches)?;
+  let transcriber = matches.get_one::<PathBuf>("transcribe").map(|model| {
+    if !transcribe::AVAILABLE {
+      let message = "this lyricsync was built without the transcribe feature, which --transcribe needs\n";
+      clap::Error::raw(clap::error::ErrorKind::InvalidValue, message).exit();
+    }
+    Transcriber::new(matches.get_one::<String>("whisper-cli").unwrap(), model)
+  });
+
+  let audio_files = library_files(matches, "fetch", Path::new(directory), &resolver)?;
+
+  interrupt::install();
+  let stats = fetch_lyrics(audio_files, embed, save_translation, dry_run, &resolver, &providers, transcriber.as_ref())?;
+  write_failed_list(matches, "fetch", &stats.failed_files)?;
+
+  println!("\nSummary:");
+  if dry_run {
+    println!("[DRY RUN] Would fetch lyrics for {} audio files", stats.fetched_lyrics);
+  } else {
+    println!("Fetched lyrics for {} audio files", stats.fetched_lyrics);
+  }
+  if transcriber.is_some() {
+    println!("Transcribed: {}", stats.transcribed);
+  }
+  println!("Not found: {}", stats.not_found);
+  println!("Total audio files: {}", stats.total_audio_files);
+  print_interruption("fetch", Path::new(directory), &stats.remaining, dry_run);
+
+  print_journal(journal.as_ref());
+
+  print_failures("Failed to fetch lyrics for the following files", &stats.failed_files);
+
+  Ok(RunOutcome::files(stats.total_audio_files, stats.failed_files.len(), stats.not_found))
+}
+
+fn init_logging(matches: &ArgMatches) -> Result<()> {
+  let quiet = matches.get_flag("quiet");
+  if quiet {
+    progress::set_quiet();
+  }
+  let level = match (matches.get_count("verbose"), matches.get_one::<String>("log-level").map(String::as_str)) {
+    (1, _) => LevelFilter::INFO,
+    (2, _) => LevelFilter::DEBUG,
+    (3.., _) => LevelFilter::TRACE,
+    (_, Some("off")) => LevelFilter::OFF,
+    (_, Some("error")) => LevelFilter::ERROR,
+    (_, Some("info")) => LevelFilter::INFO,
+    (_, Some("debug")) => LevelFilter::DEBUG,
+    (_, Some("trace")) => LevelFilter::TRACE,
+    // Failures still make it into the summary, so --quiet only keeps errors
+    _ if quiet && matches.value_source("log-level") == Some(ValueSource::DefaultValue) => LevelFilter::ERROR,
+    _ => LevelFilter::WARN,
+  };
+  // Per-file log messages take the place of the bars, which would only garble them
+  if level >= LevelFilter::INFO {
+    progress::hide_bars();
+  }
+  // A bar redrawn in place leaves its control characters all through a file or pipe
+  progress::set_plain(!std::io::stdout().is_terminal());
+
+  let color = match matches.get_one::<String>("color").map(String::as_str) {
+    Some("always") => true,
+    Some("never") => false,
+    // https://no-color.org: set to anything but the empty string
+    _ => std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && std::io::stderr().is_terminal(),
+  };
+  progress::set_color(color);
+
+  let console = fmt::layer().with_writer(LogWriter::default).with_ansi(color);
+  let console = if matches.get_one::<String>("log-format").is_some_and(|format| format == "json") {
+    console.json().with_current_span(true).with_span_list(true).boxed()
+  } else {
+    // Text logs are read next to the command's own output, where timestamps and module paths are noise
+    console.without_time().with_target(false).boxed()
+  };
+
+  // The log file is read after the run, so it keeps the timestamps and every file's outcome whatever
+  // the console shows
+  let file = match matches.get_one::<PathBuf>("log-file") {
+    Some(path) => {
+      let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|source| LrcError::FileIo { path: path.clone(), source })?;
+      let layer = fmt::layer().with_writer(Mutex::new(file)).with_ansi(false).with_target(false);
+      Some(layer.with_filter(level.max(LevelFilter::INFO)))
+    },
+    None => None,
+  };
+
+  tracing_subscriber::registry().with(console.with_filter(level)).with(file).init();
+  Ok(())
+}
+
+fn main() -> Result<ExitCode> {
+  let args: Vec<OsString> = std::env::args_os().collect();
+  let mut cmd = config::apply(cli::build_cli(), &args).unwrap_or_else(|error| {
+    clap::Error::raw(clap::error::ErrorKind::InvalidValue, format!("{error}\n")).exit();
+  });
+  let matches = cmd.clone().get_matches_from(&args);
+  let command_line = config::CommandLine::new(cmd.clone(), args);
+  init_logging(&matches)?;
+
+  // Handle completion generation first
+  if let Some(generator) = matches.get_one::<String>("generate-completion") {
+    match generator.as_str() {
+      "bash" => print_completions(Shell::Bash, &mut cmd),
+      "zsh" => print_completions(Shell::Zsh, &mut cmd),
+      "fish" => print_completions(Shell::Fish, &mut cmd),
+      "powershell" => print_completions(Shell::PowerShell, &mut cmd),
+      "elvish" => print_completions(Shell::Elvish, &mut cmd),
+      _ => unreachable!(),
+    }
+    return Ok(ExitCode::SUCCESS);
+  }
+
+  // No subcommand: the top-level flags are the embed flags
+  let (command, sub_matches) = matches.subcommand().unwrap_or(("embed", &matches));
+  // Heads each run in a log file shared by many of them
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  tracing::info!(version = env!("CARGO_PKG_VERSION"), "lyricsync {}", args.join(" "));
+  // Undo works on a journal rather than a directory
+  if let Ok(Some(directory)) = sub_matches.try_get_one::<String>("directory")
+    && !Path::new(directory).is_dir()
+  {
+    eprintln!("Error: {directory}: no such directory");
+    return Ok(ExitCode::from(EXIT_NO_DIRECTORY));
+  }
+
+  let outcome = match command {
+    "extract" => run_extract(sub_matches),
+    "strip" => run_strip(sub_matches),
+    "check" => run_check(sub_matches),
+    "validate" => run_validate(sub_matches),
+    "shift" => run_shift(sub_matches),
+    "fetch" => run_fetch(sub_matches),
+    "undo" => run_undo(sub_matches),
+    "watch" | "daemon" => run_watch(sub_matches, command, &command_line),
+    "serve" => run_serve(sub_matches),
+    "now-playing" => run_now_playing(sub_matches),
+    "play" => run_play(sub_matches),
+    "tap" => run_tap(sub_matches),
+    _ => run_embed(sub_matches, &command_line),
+  }?;
+  Ok(outcome.exit_code(sub_matches.get_one::<String>("fail-on").unwrap()))
 }
@@ -0,0 +1,71 @@
+//! The files a run failed on, written with `--failed-list` and fed back in with `--retry`.
+//!
+//! A `.json` manifest holds the command and a `failed` array of `{path, error}` objects. Any other
+//! name gets text: a `#` comment line, then one file per line, its path and error split by a tab.
+//! Paths are absolute, so a retry can run from any directory.
+
+use crate::failure_message;
+use lyricsync::LrcError;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+  command: String,
+  failed: Vec<Failure>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Failure {
+  path: PathBuf,
+  error: String,
+}
+
+fn is_json(path: &Path) -> bool {
+  path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+/// Writes the `failures` of a `command` run to `path`, replacing the list of an earlier run.
+pub fn write(path: &Path, command: &str, failures: &[(PathBuf, LrcError)]) -> io::Result<()> {
+  let failed: Vec<Failure> = failures
+    .iter()
+    .map(|(file, error)| {
+      // The path has a column of its own, so errors about the file itself don't repeat it
+      let message = failure_message(file, error);
+      let error = message.strip_prefix(&format!("{}: ", file.display())).unwrap_or(&message).to_string();
+      Failure { path: fs::canonicalize(file).unwrap_or_else(|_| file.clone()), error }
+    })
+    .collect();
+
+  let contents = if is_json(path) {
+    let mut contents = serde_json::to_vec_pretty(&Manifest { command: command.to_string(), failed })?;
+    contents.push(b'\n');
+    contents
+  } else {
+    let mut contents = format!("# lyricsync {command}: {} failed file(s)\n", failed.len());
+    for Failure { path, error } in &failed {
+      // A line break in an error would start what looks like another file
+      contents.push_str(&format!("{}\t{}\n", path.display(), error.replace(['\r', '\n'], " ")));
+    }
+    contents.into_bytes()
+  };
+  fs::write(path, contents)
+}
+
+/// The files listed in a manifest written by [`write`].
+pub fn read(path: &Path) -> io::Result<Vec<PathBuf>> {
+  let contents = fs::read_to_string(path)?;
+  if is_json(path) {
+    let manifest: Manifest = serde_json::from_str(&contents)?;
+    return Ok(manifest.failed.into_iter().map(|failure| failure.path).collect());
+  }
+  Ok(
+    contents
+      .lines()
+      .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+      .map(|line| PathBuf::from(line.split_once('\t').map_or(line, |(path, _)| path)))
+      .collect(),
+  )
+}
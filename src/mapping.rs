@@ -0,0 +1,103 @@
+//! Audio files paired with LRC files by hand, read with `--map`, for the ones no automatic match
+//! gets right.
+//!
+//! A `.json` mapping is an array of `{audio_path, lrc_path}` objects. A `.tsv` one has a pair per
+//! line split by a tab, and any other name is read as CSV, quoted as RFC 4180 describes. Either may
+//! start with an `audio_path`/`lrc_path` header row, and blank lines and `#` comments are skipped.
+//! Relative paths are taken from the mapping file's directory, so it can sit in the library.
+//!
+//! A pair can also shift its LRC file's timestamps, overriding `--offset`: with an `offset_ms` in
+//! JSON, or a third column in CSV and TSV (`offset_ms` in the header row).
+
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// An audio file and the LRC file paired with it.
+#[derive(Deserialize)]
+pub struct Pair {
+  pub audio_path: PathBuf,
+  pub lrc_path: PathBuf,
+  /// Milliseconds to move the LRC file's timestamps by, later when positive
+  #[serde(default)]
+  pub offset_ms: Option<i64>,
+}
+
+fn invalid(message: String) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The audio and LRC files paired in the mapping at `path`.
+pub fn read(path: &Path) -> io::Result<Vec<Pair>> {
+  let contents = fs::read_to_string(path)?;
+  let extension = path.extension().map(|extension| extension.to_ascii_lowercase());
+  let pairs = match extension.as_ref().and_then(|extension| extension.to_str()) {
+    Some("json") => serde_json::from_str(&contents)?,
+    Some("tsv") => read_rows(&contents, |line| Ok(line.split('\t').map(str::to_string).collect()))?,
+    _ => read_rows(&contents, csv_row)?,
+  };
+  let base = path.parent().unwrap_or(Path::new(""));
+  Ok(
+    pairs
+      .into_iter()
+      .map(|pair| Pair { audio_path: base.join(pair.audio_path), lrc_path: base.join(pair.lrc_path), ..pair })
+      .collect(),
+  )
+}
+
+fn read_rows(contents: &str, split: impl Fn(&str) -> io::Result<Vec<String>>) -> io::Result<Vec<Pair>> {
+  let mut pairs = Vec::new();
+  let mut header = true;
+  for (index, line) in contents.lines().enumerate() {
+    let line = line.trim_end_matches('\r');
+    if line.trim().is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let at_line = |message: String| invalid(format!("line {}: {message}", index + 1));
+    let fields = split(line).map_err(|error| at_line(error.to_string()))?;
+    let pair = |audio: &str, lrc: &str, offset_ms| Pair {
+      audio_path: PathBuf::from(audio),
+      lrc_path: PathBuf::from(lrc),
+      offset_ms,
+    };
+    match fields.as_slice() {
+      [audio, lrc] | [audio, lrc, _] if header && audio == "audio_path" && lrc == "lrc_path" => {},
+      [audio, lrc] => pairs.push(pair(audio, lrc, None)),
+      [audio, lrc, offset] if offset.trim().is_empty() => pairs.push(pair(audio, lrc, None)),
+      [audio, lrc, offset] => {
+        let offset_ms =
+          offset.trim().parse().map_err(|_| at_line(format!("'{offset}' isn't an offset in milliseconds")))?;
+        pairs.push(pair(audio, lrc, Some(offset_ms)));
+      },
+      _ => return Err(at_line("expected an audio path and an LRC path, and optionally an offset".to_string())),
+    }
+    header = false;
+  }
+  Ok(pairs)
+}
+
+// Splits a CSV line into its fields, unquoting them. A quoted field can't span lines, which no
+// path needs.
+fn csv_row(line: &str) -> io::Result<Vec<String>> {
+  let mut fields = vec![String::new()];
+  let mut chars = line.chars().peekable();
+  let mut quoted = false;
+  while let Some(c) = chars.next() {
+    let field = fields.last_mut().unwrap();
+    match c {
+      '"' if quoted && chars.peek() == Some(&'"') => {
+        chars.next();
+        field.push('"');
+      },
+      '"' if quoted => quoted = false,
+      '"' if field.is_empty() => quoted = true,
+      ',' if !quoted => fields.push(String::new()),
+      _ => field.push(c),
+    }
+  }
+  if quoted {
+    return Err(invalid("unterminated quote".to_string()));
+  }
+  Ok(fields)
+}
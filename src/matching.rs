@@ -0,0 +1,302 @@
+//! Pairing audio files with LRC files that aren't named exactly like them.
+//!
+//! [`lrc_sidecars`](crate::lrc_sidecars) only finds `Song.lrc` (and `Song.ja.lrc`) next to
+//! `Song.mp3`, with the name looked for rewritten by any [`NameRule`]s, e.g. to drop an `[Explicit]`
+//! suffix, and pairs given with [`SidecarMatcher::mapping`] come before those. When neither finds
+//! one, a [`SidecarMatcher`] can fall back to looser matches, each scored with a confidence from 0
+//! to 1: LRC files named after the audio file's artist and title tags (`Artist - Title.lrc` or
+//! `Title.lrc`) anywhere in the library, then ones next to it with a similar name, so
+//! `01 - Song.mp3` finds `Song.lrc`.
+//!
+//! Of several loose matches, the one whose last timestamp comes closest to the end of the track is
+//! taken, and ones that go on past it can't be the same recording (a radio edit for an album cut).
+//! With [`SidecarMatcher::plain_text`], a `Song.txt` of plain lyrics is taken when nothing else is.
+
+use crate::{FormatResolver, Sidecar, track};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+use walkdir::WalkDir;
+
+/// The confidence of an LRC file named `Artist - Title` after the audio file's tags.
+pub const ARTIST_TITLE_CONFIDENCE: f64 = 0.95;
+/// The confidence of one named after the title alone, which other artists' songs can share.
+pub const TITLE_CONFIDENCE: f64 = 0.85;
+
+/// How far past the end of a track lyrics can run and still be its own, since lengths are
+/// sometimes estimated from the bitrate and the last line's timestamp may be set by hand.
+pub const LENGTH_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// The confidence a loose match needs by default, which lets a typo or two through.
+pub const DEFAULT_MIN_CONFIDENCE: f64 = 0.8;
+
+/// Turns an audio file's name into the name of its LRC file, without extensions, by replacing what
+/// `pattern` matches with `replacement`, where `$1` or `${name}` stand for the groups it captured.
+#[derive(Clone, Debug)]
+pub struct NameRule {
+  pub pattern: Regex,
+  pub replacement: String,
+}
+
+impl NameRule {
+  pub fn new(pattern: Regex, replacement: impl Into<String>) -> Self {
+    NameRule { pattern, replacement: replacement.into() }
+  }
+
+  /// `stem` with every match of the pattern replaced.
+  pub fn apply(&self, stem: &str) -> String {
+    self.pattern.replace_all(stem, self.replacement.as_str()).into_owned()
+  }
+}
+
+/// Finds the LRC files an audio file's lyrics are in.
+#[derive(Clone, Debug, Default)]
+pub struct SidecarMatcher {
+  /// Match LRC files whose names are only similar to the audio file's, at least this confident,
+  /// when none is named like it
+  pub min_confidence: Option<f64>,
+  /// Look for LRC files named after the audio file's tags anywhere under these directories
+  pub tag_search: Vec<PathBuf>,
+  /// LRC files paired with audio files by hand, both by their canonical paths
+  pub pairs: HashMap<PathBuf, PathBuf>,
+  /// Rules applied in turn to an audio file's name for the LRC file's, tried after the name itself
+  pub name_rules: Vec<NameRule>,
+  /// Take a `.txt` file named like the audio file when there's no LRC file for it at all
+  pub plain_text: bool,
+  // The LRC files under `tag_search` by normalized name, listed the first time one is looked up
+  index: OnceLock<HashMap<String, Vec<PathBuf>>>,
+}
+
+impl SidecarMatcher {
+  /// Matches files by name loosely, at least `min_confidence` sure.
+  pub fn fuzzy(mut self, min_confidence: f64) -> Self {
+    self.min_confidence = Some(min_confidence);
+    self
+  }
+
+  /// Also looks for LRC files named as `rule` rewrites audio files' names, after the earlier rules.
+  pub fn name_rule(mut self, rule: NameRule) -> Self {
+    self.name_rules.push(rule);
+    self
+  }
+
+  /// Falls back to plain lyrics in a `.txt` file named like the audio file, when nothing else
+  /// matches.
+  pub fn plain_text(mut self, plain_text: bool) -> Self {
+    self.plain_text = plain_text;
+    self
+  }
+
+  /// Looks for LRC files named after audio files' artist and title tags under `directories`.
+  pub fn by_tags(mut self, directories: Vec<PathBuf>) -> Self {
+    self.tag_search = directories;
+    self.index = OnceLock::new();
+    self
+  }
+
+  /// Pairs each audio file with an LRC file, whatever else would match it.
+  pub fn mapping(mut self, pairs: impl IntoIterator<Item = (PathBuf, PathBuf)>) -> Self {
+    self.pairs.extend(pairs.into_iter().map(|(audio_path, lrc_path)| (canonical(&audio_path), canonical(&lrc_path))));
+    self
+  }
+
+  /// The sidecars of `audio_path`: the one it's paired with, the ones named like it or as the name
+  /// rules rewrite its name, or failing those the best loose match, and then its `.txt` when
+  /// falling back to plain text. `formats` is how its tags are read.
+  pub fn find(&self, audio_path: &Path, formats: &FormatResolver) -> Vec<Sidecar> {
+    if !self.pairs.is_empty()
+      && let Some(lrc_path) = self.pairs.get(&canonical(audio_path))
+    {
+      return vec![Sidecar { path: lrc_path.clone(), language: None, confidence: 1.0 }];
+    }
+    let sidecars = crate::lrc_sidecars(audio_path);
+    if !sidecars.is_empty() {
+      return sidecars;
+    }
+    if let Some(stem) = self.rewritten_stem(audio_path) {
+      // Named as the file would be, for the language sidecars to be found the same way
+      let sidecars = crate::lrc_sidecars(&audio_path.with_file_name(format!("{stem}.lrc")));
+      if !sidecars.is_empty() {
+        return sidecars;
+      }
+    }
+    let candidates: Vec<Sidecar> = self.candidates(audio_path, formats).into_iter().take(1).collect();
+    if candidates.is_empty() && self.plain_text {
+      let txt_path = crate::lrc_sidecar_path(audio_path).with_extension("txt");
+      if txt_path.is_file() {
+        return vec![Sidecar { path: txt_path, language: None, confidence: 1.0 }];
+      }
+    }
+    candidates
+  }
+
+  /// Every loose match for `audio_path`, the best first: the one ending closest to the end of the
+  /// track when its length is known, otherwise the most confident.
+  pub fn candidates(&self, audio_path: &Path, formats: &FormatResolver) -> Vec<Sidecar> {
+    let mut candidates = if self.tag_search.is_empty() { Vec::new() } else { self.tag_candidates(audio_path, formats) };
+    if let Some(min_confidence) = self.min_confidence {
+      for candidate in fuzzy_candidates(audio_path, min_confidence) {
+        if !candidates.iter().any(|other| other.path == candidate.path) {
+          candidates.push(candidate);
+        }
+      }
+    }
+    // An LRC file paired with another audio file is that file's
+    if !self.pairs.is_empty() {
+      candidates.retain(|candidate| !self.pairs.values().any(|lrc_path| *lrc_path == canonical(&candidate.path)));
+    }
+    candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    if candidates.is_empty() {
+      return candidates;
+    }
+    match formats.resolve(audio_path).ok().and_then(|format| track::read_duration(audio_path, format).ok()) {
+      Some(Some(duration)) => by_duration(candidates, duration),
+      _ => candidates,
+    }
+  }
+
+  // The audio file's name as the rules rewrite it, if any of them changes it
+  fn rewritten_stem(&self, audio_path: &Path) -> Option<String> {
+    let stem = audio_path.file_stem()?.to_str()?;
+    let rewritten = self.name_rules.iter().fold(stem.to_string(), |stem, rule| rule.apply(&stem));
+    (rewritten != stem && !rewritten.is_empty()).then_some(rewritten)
+  }
+
+  fn tag_candidates(&self, audio_path: &Path, formats: &FormatResolver) -> Vec<Sidecar> {
+    // Files that can't be read have no tags to go by, and fail when embedding if they're matched
+    let track = formats.resolve(audio_path).ok().and_then(|format| track::read_track_info(audio_path, format).ok());
+    let Some(track) = track.flatten() else {
+      return Vec::new();
+    };
+    let index = self.index.get_or_init(|| index_lrc_files(&self.tag_search));
+    let names = [
+      (normalize(&format!("{} - {}", track.artist, track.title)), ARTIST_TITLE_CONFIDENCE),
+      (normalize(&track.title), TITLE_CONFIDENCE),
+    ];
+    let directory = audio_path.parent().unwrap_or(Path::new(""));
+    let mut candidates: Vec<Sidecar> = names
+      .iter()
+      .flat_map(|(name, confidence)| index.get(name).into_iter().flatten().map(move |path| (path, *confidence)))
+      .filter(|(path, _)| !has_own_audio(path))
+      .map(|(path, confidence)| Sidecar { path: path.clone(), language: None, confidence })
+      .collect();
+    // Of equally good matches, the ones nearest the audio file come first
+    candidates.sort_by_key(|sidecar| (sidecar.path.parent() != Some(directory), sidecar.path.components().count()));
+    candidates.dedup_by(|a, b| a.path == b.path);
+    candidates
+  }
+}
+
+// Drops the candidates whose lyrics end after the track does, give or take the tolerance, and puts
+// the rest in order of how close they end to it. Ones without timestamps, or that can't be read,
+// can't be told apart and come last, as confident as they were.
+fn by_duration(candidates: Vec<Sidecar>, duration: Duration) -> Vec<Sidecar> {
+  let track_ms = duration.as_millis();
+  let limit_ms = (duration + LENGTH_TOLERANCE).as_millis();
+  let mut timed: Vec<(u128, Sidecar)> = Vec::new();
+  let mut untimed = Vec::new();
+  for candidate in candidates {
+    match crate::read_sidecar(&candidate.path).ok().and_then(|lyrics| crate::lrc::last_timestamp(&lyrics)) {
+      Some(end_ms) if u128::from(end_ms) > limit_ms => {
+        tracing::debug!(sidecar = %candidate.path.display(), "rejected, the lyrics go on past the end of the track");
+      },
+      Some(end_ms) => timed.push((track_ms.abs_diff(u128::from(end_ms)), candidate)),
+      None => untimed.push(candidate),
+    }
+  }
+  timed.sort_by_key(|(distance, _)| *distance);
+  timed.into_iter().map(|(_, candidate)| candidate).chain(untimed).collect()
+}
+
+// Paths are compared once links and `..` are resolved, so a mapping can name files any way
+fn canonical(path: &Path) -> PathBuf {
+  fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn index_lrc_files(directories: &[PathBuf]) -> HashMap<String, Vec<PathBuf>> {
+  let mut index: HashMap<String, Vec<PathBuf>> = HashMap::new();
+  for directory in directories {
+    for entry in WalkDir::new(directory).follow_links(true).into_iter().filter_map(|entry| entry.ok()) {
+      let path = entry.path();
+      if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str())
+        && crate::is_sidecar(path)
+        && entry.file_type().is_file()
+      {
+        index.entry(normalize(stem)).or_default().push(path.to_path_buf());
+      }
+    }
+  }
+  index
+}
+
+// Whether an LRC file is named exactly like an audio file next to it, whose lyrics it has
+fn has_own_audio(lrc_path: &Path) -> bool {
+  let Some(stem) = lrc_path.file_stem() else {
+    return false;
+  };
+  let directory = lrc_path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+  fs::read_dir(directory).into_iter().flatten().filter_map(|entry| entry.ok()).any(|entry| {
+    let path = entry.path();
+    path.file_stem() == Some(stem)
+      && path.extension().and_then(|extension| extension.to_str()).is_some_and(is_audio_extension)
+  })
+}
+
+fn is_audio_extension(extension: &str) -> bool {
+  crate::format_for_extension(&extension.to_ascii_lowercase()).is_some()
+}
+
+/// How alike an audio file's name is to an LRC file's, from 0 to 1, once leading track numbers,
+/// case, punctuation and spacing are set aside. Both are file names without their extension.
+pub fn name_confidence(audio_stem: &str, lrc_stem: &str) -> f64 {
+  let (audio, lrc) = (normalize(audio_stem), normalize(lrc_stem));
+  if audio.is_empty() || lrc.is_empty() {
+    return 0.0;
+  }
+  strsim::normalized_levenshtein(&audio, &lrc)
+}
+
+fn normalize(stem: &str) -> String {
+  let words: Vec<String> =
+    stem.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).map(str::to_lowercase).collect();
+  // A title that's only a number, like "1979", is kept
+  let numbers = words.iter().take_while(|word| word.chars().all(|c| c.is_ascii_digit())).count();
+  words[numbers.min(words.len().saturating_sub(1))..].join(" ")
+}
+
+/// The LRC files next to `audio_path` whose names are at least `min_confidence` like its, the
+/// closest first. Ones named exactly like another audio file belong to that file and aren't
+/// counted, and neither is JSON, which is only taken as lyrics when it's named like the audio file:
+/// the `Song.info.json` downloaders leave next to a track has a name as close.
+pub fn fuzzy_candidates(audio_path: &Path, min_confidence: f64) -> Vec<Sidecar> {
+  let stem = audio_path.file_stem().unwrap_or_default().to_string_lossy();
+  let directory = audio_path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+  let files: Vec<PathBuf> =
+    fs::read_dir(directory).into_iter().flatten().filter_map(|entry| Some(entry.ok()?.path())).collect();
+
+  let audio_stems: HashSet<&str> = files
+    .iter()
+    .filter(|path| path.extension().and_then(|extension| extension.to_str()).is_some_and(is_audio_extension))
+    .filter_map(|path| path.file_stem()?.to_str())
+    .collect();
+
+  let mut candidates: Vec<Sidecar> = files
+    .iter()
+    .filter(|path| {
+      crate::is_sidecar(path) && !path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+    })
+    .filter_map(|path| {
+      let lrc_stem = path.file_stem()?.to_str()?;
+      if audio_stems.contains(lrc_stem) || !path.is_file() {
+        return None;
+      }
+      let confidence = name_confidence(&stem, lrc_stem);
+      (confidence >= min_confidence).then(|| Sidecar { path: path.clone(), language: None, confidence })
+    })
+    .collect();
+  candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence).then_with(|| a.path.cmp(&b.path)));
+  candidates
+}
@@ -0,0 +1,112 @@
+//! Just enough of the [MPD protocol](https://mpd.readthedocs.io/en/latest/protocol.html) to ask
+//! what's playing and how far into it the player is.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+pub const DEFAULT_PORT: u16 = 6600;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum State {
+  Play,
+  Pause,
+  Stop,
+}
+
+/// Where the player is.
+pub struct Status {
+  pub state: State,
+  pub elapsed: Duration,
+}
+
+/// The track the player has loaded.
+pub struct Song {
+  /// Relative to MPD's music directory, or a URL for a stream
+  pub file: String,
+  pub artist: Option<String>,
+  pub title: Option<String>,
+}
+
+pub struct Mpd {
+  reader: BufReader<TcpStream>,
+}
+
+impl Mpd {
+  /// Connects to MPD at `host`, which may start with `password@` as in `MPD_HOST`.
+  pub fn connect(host: &str, port: u16) -> io::Result<Self> {
+    let (password, host) = match host.rsplit_once('@') {
+      Some((password, host)) => (Some(password), host),
+      None => (None, host),
+    };
+    let stream = TcpStream::connect((host, port))?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut mpd = Mpd { reader: BufReader::new(stream) };
+    let greeting = mpd.read_line()?;
+    if !greeting.starts_with("OK MPD ") {
+      return Err(io::Error::new(io::ErrorKind::InvalidData, format!("not an MPD server: {greeting}")));
+    }
+    if let Some(password) = password {
+      mpd.command(&format!("password {}", quote(password)))?;
+    }
+    Ok(mpd)
+  }
+
+  pub fn status(&mut self) -> io::Result<Status> {
+    let mut status = Status { state: State::Stop, elapsed: Duration::ZERO };
+    for (key, value) in self.command("status")? {
+      match key.as_str() {
+        "state" if value == "play" => status.state = State::Play,
+        "state" if value == "pause" => status.state = State::Pause,
+        "elapsed" => status.elapsed = value.parse().map(Duration::from_secs_f64).unwrap_or_default(),
+        _ => {},
+      }
+    }
+    Ok(status)
+  }
+
+  /// The track loaded, or `None` when the playlist is empty.
+  pub fn current_song(&mut self) -> io::Result<Option<Song>> {
+    let mut song = Song { file: String::new(), artist: None, title: None };
+    for (key, value) in self.command("currentsong")? {
+      match key.as_str() {
+        "file" => song.file = value,
+        "Artist" => song.artist = Some(value),
+        "Title" => song.title = Some(value),
+        _ => {},
+      }
+    }
+    Ok((!song.file.is_empty()).then_some(song))
+  }
+
+  // Sends a command and reads the `key: value` lines of its answer, up to the closing `OK`
+  fn command(&mut self, command: &str) -> io::Result<Vec<(String, String)>> {
+    writeln!(self.reader.get_mut(), "{command}")?;
+    let mut pairs = Vec::new();
+    loop {
+      let line = self.read_line()?;
+      if line == "OK" {
+        return Ok(pairs);
+      }
+      if let Some(error) = line.strip_prefix("ACK ") {
+        return Err(io::Error::other(format!("MPD: {error}")));
+      }
+      if let Some((key, value)) = line.split_once(": ") {
+        pairs.push((key.to_string(), value.to_string()));
+      }
+    }
+  }
+
+  fn read_line(&mut self) -> io::Result<String> {
+    let mut line = String::new();
+    if self.reader.read_line(&mut line)? == 0 {
+      return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "MPD closed the connection"));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+  }
+}
+
+// Arguments are quoted, with backslashes before quotes and backslashes
+fn quote(argument: &str) -> String {
+  format!("\"{}\"", argument.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,190 @@
+//! Client for the [Musixmatch](https://developer.musixmatch.com) lyrics API.
+
+use crate::lrc::{self, TimedLine};
+use crate::track::TrackInfo;
+use crate::{LrcError, Result};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+use ureq::Agent;
+
+pub const DEFAULT_BASE_URL: &str = "https://api.musixmatch.com/ws/1.1";
+
+// Musixmatch always answers 200 and reports the real status in the message header
+#[derive(Deserialize)]
+struct Envelope {
+  message: Message,
+}
+
+#[derive(Deserialize)]
+struct Message {
+  header: Header,
+  body: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct Header {
+  status_code: u16,
+}
+
+#[derive(Deserialize)]
+struct TrackBody {
+  track: Track,
+}
+
+#[derive(Deserialize)]
+struct Track {
+  track_id: u64,
+  has_richsync: u8,
+  has_subtitles: u8,
+}
+
+#[derive(Deserialize)]
+struct RichsyncBody {
+  richsync: Richsync,
+}
+
+#[derive(Deserialize)]
+struct Richsync {
+  richsync_body: String,
+}
+
+#[derive(Deserialize)]
+struct SubtitleBody {
+  subtitle: Subtitle,
+}
+
+#[derive(Deserialize)]
+struct Subtitle {
+  subtitle_body: String,
+}
+
+/// One line of a richsync document: when it starts and ends, its text, and its words, each timed
+/// from the start of the line. Fetched lyrics are line-level, so the words are dropped there.
+#[derive(Deserialize)]
+struct RichsyncLine {
+  ts: f64,
+  #[serde(default)]
+  te: Option<f64>,
+  x: String,
+  #[serde(default)]
+  l: Vec<RichsyncWord>,
+}
+
+#[derive(Deserialize)]
+struct RichsyncWord {
+  c: String,
+  o: f64,
+}
+
+pub struct MusixmatchClient {
+  base_url: String,
+  api_key: String,
+  agent: Agent,
+}
+
+impl MusixmatchClient {
+  pub fn new(base_url: &str, api_key: &str) -> Self {
+    let agent = Agent::config_builder().timeout_global(Some(Duration::from_secs(15))).build().new_agent();
+    Self { base_url: base_url.trim_end_matches('/').to_string(), api_key: api_key.to_string(), agent }
+  }
+
+  /// Looks up synced lyrics for a track, preferring richsync over line-synced subtitles.
+  pub fn synced_lyrics(&self, track: &TrackInfo) -> Result<Option<String>> {
+    let mut request = self
+      .agent
+      .get(format!("{}/matcher.track.get", self.base_url))
+      .query("apikey", &self.api_key)
+      .query("q_artist", &track.artist)
+      .query("q_track", &track.title);
+    if let Some(album) = &track.album {
+      request = request.query("q_album", album);
+    }
+
+    let Some(TrackBody { track: found }) = self.send(request)? else {
+      return Ok(None);
+    };
+    let track_id = found.track_id.to_string();
+
+    if found.has_richsync != 0 {
+      let request = self
+        .agent
+        .get(format!("{}/track.richsync.get", self.base_url))
+        .query("apikey", &self.api_key)
+        .query("track_id", &track_id);
+      if let Some(RichsyncBody { richsync }) = self.send(request)? {
+        return Ok(richsync_to_lrc(&richsync.richsync_body));
+      }
+    }
+
+    if found.has_subtitles != 0 {
+      let request = self
+        .agent
+        .get(format!("{}/track.subtitle.get", self.base_url))
+        .query("apikey", &self.api_key)
+        .query("track_id", &track_id);
+      if let Some(SubtitleBody { subtitle }) = self.send(request)? {
+        return Ok(Some(subtitle.subtitle_body).filter(|lyrics| !lyrics.trim().is_empty()));
+      }
+    }
+
+    Ok(None)
+  }
+
+  fn send<T: DeserializeOwned>(
+    &self,
+    request: ureq::RequestBuilder<ureq::typestate::WithoutBody>,
+  ) -> Result<Option<T>> {
+    let envelope: Envelope = request.call()?.body_mut().read_json()?;
+    match envelope.message.header.status_code {
+      200 => serde_json::from_value(envelope.message.body)
+        .map(Some)
+        .map_err(|e| LrcError::Provider(format!("unexpected Musixmatch response: {e}"))),
+      404 => Ok(None),
+      401 => Err(LrcError::Provider("Musixmatch rejected the API token".to_string())),
+      status => Err(LrcError::Provider(format!("Musixmatch returned status {status}"))),
+    }
+  }
+}
+
+/// Converts a Musixmatch richsync document into line-level LRC.
+fn richsync_to_lrc(body: &str) -> Option<String> {
+  let lines: Vec<RichsyncLine> = serde_json::from_str(body).ok()?;
+  let timed_lines: Vec<TimedLine> =
+    lines.into_iter().map(|line| TimedLine { time_ms: millis(line.ts), text: line.x }).collect();
+  (!timed_lines.is_empty()).then(|| lrc::format_timed_lines(&timed_lines))
+}
+
+/// Converts a Musixmatch richsync document, as other tools export it, into LRC with a
+/// [word timestamp](lrc::has_word_timing) in front of each word and one where the line ends.
+/// `None` when `body` isn't one.
+pub fn richsync_to_word_timed_lrc(body: &str) -> Option<String> {
+  let lines: Vec<RichsyncLine> = serde_json::from_str(body).ok()?;
+  let timed_lines: Vec<TimedLine> = lines
+    .into_iter()
+    .map(|line| {
+      if line.l.is_empty() {
+        return TimedLine { time_ms: millis(line.ts), text: line.x };
+      }
+      let mut text = String::new();
+      // The spaces between words are words of their own, which aren't worth a timestamp
+      for word in &line.l {
+        if !word.c.trim().is_empty() {
+          text.push_str(&lrc::format_word_timestamp(millis(line.ts + word.o)));
+        }
+        text.push_str(&word.c);
+      }
+      let mut text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+      if let Some(end) = line.te {
+        text.push_str(&lrc::format_word_timestamp(millis(end)));
+      }
+      TimedLine { time_ms: millis(line.ts), text }
+    })
+    .collect();
+  (!timed_lines.is_empty()).then(|| lrc::format_timed_lines(&timed_lines))
+}
+
+// Richsync times are in seconds
+fn millis(seconds: f64) -> u32 {
+  (seconds * 1000.0).round() as u32
+}
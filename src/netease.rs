@@ -0,0 +1,137 @@
+//! Client for the NetEase Cloud Music web API.
+//!
+//! NetEase serves the original lyrics and an optional translation as two separate LRC
+//! documents, so both are returned.
+
+use crate::Result;
+use crate::track::TrackInfo;
+use serde::Deserialize;
+use std::time::Duration;
+use ureq::Agent;
+
+pub const DEFAULT_BASE_URL: &str = "https://music.163.com";
+
+// Search results further than this from the file's duration are considered a different recording
+const DURATION_TOLERANCE_MS: u64 = 3000;
+
+#[derive(Deserialize)]
+struct SearchEnvelope {
+  result: Option<SearchResult>,
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+  #[serde(default)]
+  songs: Vec<Song>,
+}
+
+#[derive(Deserialize)]
+struct Song {
+  id: u64,
+  name: String,
+  artists: Vec<Artist>,
+  duration: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Artist {
+  name: String,
+}
+
+#[derive(Deserialize)]
+struct LyricEnvelope {
+  lrc: Option<LyricStream>,
+  tlyric: Option<LyricStream>,
+}
+
+#[derive(Deserialize)]
+struct LyricStream {
+  lyric: Option<String>,
+}
+
+/// Synced lyrics from NetEase, with the translation when one exists.
+pub struct NeteaseLyrics {
+  pub lyrics: String,
+  pub translation: Option<String>,
+}
+
+pub struct NeteaseClient {
+  base_url: String,
+  agent: Agent,
+}
+
+impl NeteaseClient {
+  pub fn new(base_url: &str) -> Self {
+    let agent = Agent::config_builder().timeout_global(Some(Duration::from_secs(15))).build().new_agent();
+    Self { base_url: base_url.trim_end_matches('/').to_string(), agent }
+  }
+
+  /// Searches for the track and returns its synced lyrics, or `None` if no song matches.
+  pub fn synced_lyrics(&self, track: &TrackInfo) -> Result<Option<NeteaseLyrics>> {
+    let search: SearchEnvelope = self
+      .agent
+      .get(format!("{}/api/search/get", self.base_url))
+      .header("Referer", "https://music.163.com/")
+      .query("s", format!("{} {}", track.artist, track.title))
+      .query("type", "1")
+      .query("limit", "10")
+      .call()?
+      .body_mut()
+      .read_json()?;
+
+    let songs = search.result.map(|result| result.songs).unwrap_or_default();
+    let Some(song) = best_match(&songs, track) else {
+      return Ok(None);
+    };
+
+    let lyric: LyricEnvelope = self
+      .agent
+      .get(format!("{}/api/song/lyric", self.base_url))
+      .header("Referer", "https://music.163.com/")
+      .query("id", song.id.to_string())
+      .query("lv", "1")
+      .query("tv", "-1")
+      .call()?
+      .body_mut()
+      .read_json()?;
+
+    let Some(lyrics) = lyric.lrc.and_then(clean_stream) else {
+      return Ok(None);
+    };
+    let translation = lyric.tlyric.and_then(clean_stream);
+    Ok(Some(NeteaseLyrics { lyrics, translation }))
+  }
+}
+
+// Requires the title and one of the artists to match, then picks the closest duration
+fn best_match<'a>(songs: &'a [Song], track: &TrackInfo) -> Option<&'a Song> {
+  let title = normalize(&track.title);
+  let artist = normalize(&track.artist);
+  let expected_ms = track.duration_secs.map(|secs| secs * 1000);
+
+  songs
+    .iter()
+    .filter(|song| normalize(&song.name) == title)
+    .filter(|song| song.artists.iter().any(|candidate| normalize(&candidate.name) == artist))
+    .filter_map(|song| match (expected_ms, song.duration) {
+      (Some(expected), Some(actual)) => {
+        let distance = expected.abs_diff(actual);
+        (distance <= DURATION_TOLERANCE_MS).then_some((distance, song))
+      },
+      _ => Some((0, song)),
+    })
+    .min_by_key(|(distance, _)| *distance)
+    .map(|(_, song)| song)
+}
+
+fn normalize(value: &str) -> String {
+  value.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+// Newer responses prefix the LRC with JSON credit lines like `{"t":0,"c":[...]}`
+fn clean_stream(stream: LyricStream) -> Option<String> {
+  let lyric = stream.lyric?;
+  let lines: Vec<&str> = lyric.lines().filter(|line| !line.trim_start().starts_with('{')).collect();
+  let cleaned = lines.join("\n");
+  (!crate::lrc::parse_timed_lines(&cleaned).is_empty()).then(|| cleaned + "\n")
+}
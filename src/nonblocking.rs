@@ -0,0 +1,85 @@
+//! Async versions of the library API, for use from a tokio runtime.
+//!
+//! lofty and the provider clients do blocking I/O, so each call runs on tokio's blocking thread pool.
+//! That lets slow network shares and lyrics lookups overlap instead of queueing behind each other.
+
+use crate::provider::{FetchedLyrics, ProviderChain};
+use crate::track::TrackInfo;
+use crate::{EmbedOptions, EmbedSession, FileResult, FormatResolver, LrcError, Lyrics, Result};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::task::JoinSet;
+
+// Runs blocking work off the async threads, passing panics on to the caller
+async fn unblock<T: Send + 'static>(work: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+  match tokio::task::spawn_blocking(work).await {
+    Ok(result) => result,
+    Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+    Err(error) => Err(LrcError::Io(std::io::Error::other(error))),
+  }
+}
+
+/// Async [`crate::embed_lyrics`].
+pub async fn embed_lyrics(audio_path: impl Into<PathBuf>, lyrics: Lyrics, options: EmbedOptions) -> Result<()> {
+  let audio_path = audio_path.into();
+  unblock(move || crate::embed_lyrics(&audio_path, &lyrics, &options)).await
+}
+
+/// Async [`crate::has_embedded_lyrics`].
+pub async fn has_embedded_lyrics(audio_path: impl Into<PathBuf>, resolver: FormatResolver) -> Result<bool> {
+  let audio_path = audio_path.into();
+  unblock(move || crate::has_embedded_lyrics(&audio_path, &resolver)).await
+}
+
+/// Async [`crate::extract_lyrics`].
+pub async fn extract_lyrics(audio_path: impl Into<PathBuf>, resolver: FormatResolver) -> Result<Option<String>> {
+  let audio_path = audio_path.into();
+  unblock(move || crate::extract_lyrics(&audio_path, &resolver)).await
+}
+
+/// Async [`crate::strip_lyrics`].
+pub async fn strip_lyrics(audio_path: impl Into<PathBuf>, resolver: FormatResolver) -> Result<bool> {
+  let audio_path = audio_path.into();
+  unblock(move || crate::strip_lyrics(&audio_path, &resolver)).await
+}
+
+/// Async [`ProviderChain::lyrics`].
+pub async fn fetch_lyrics(chain: Arc<ProviderChain>, track: TrackInfo) -> Result<Option<FetchedLyrics>> {
+  unblock(move || chain.lyrics(&track)).await
+}
+
+/// Embeds the LRC sidecars of each file like an [`EmbedSession`], working on up to `jobs` files at
+/// once. Results come back in the order files finish.
+///
+/// There's no conflict handler here, so [`crate::ConflictPolicy::Ask`] skips files that already
+/// have lyrics.
+pub async fn embed_files<I>(paths: I, options: EmbedOptions, jobs: usize) -> Vec<FileResult>
+where
+  I: IntoIterator<Item = PathBuf>,
+{
+  let mut paths = paths.into_iter();
+  let mut running = JoinSet::new();
+  let mut results = Vec::new();
+
+  loop {
+    while running.len() < jobs.max(1) {
+      let Some(path) = paths.next() else {
+        break;
+      };
+      let options = options.clone();
+      running.spawn_blocking(move || {
+        let started = Instant::now();
+        let outcome = EmbedSession::new(options).embed_file(&path);
+        FileResult { path, outcome, elapsed: started.elapsed() }
+      });
+    }
+
+    match running.join_next().await {
+      Some(Ok(result)) => results.push(result),
+      Some(Err(error)) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+      Some(Err(_)) => {},
+      None => return results,
+    }
+  }
+}
@@ -0,0 +1,119 @@
+//! The sound of `lyricsync play` and `tap`, decoded by Symphonia through rodio in builds with the
+//! `playback` feature, and the clock the lyrics are timed by with or without it.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+#[cfg(feature = "playback")]
+use std::{fs::File, io};
+
+/// A track playing, or the time it would have been playing for with `--silent`.
+pub struct Playback {
+  #[cfg(feature = "playback")]
+  output: Option<(rodio::MixerDeviceSink, rodio::Player)>,
+  clock: Clock,
+  // How long the clock runs for when there's no sound to run out
+  length: Duration,
+}
+
+// Time kept without a sound: how far it had got when last paused or moved, and since when it's run
+struct Clock {
+  offset: Duration,
+  since: Option<Instant>,
+}
+
+impl Clock {
+  fn elapsed(&self) -> Duration {
+    self.offset + self.since.map_or(Duration::ZERO, |since| since.elapsed())
+  }
+}
+
+impl Playback {
+  /// Keeps time from now for `length`, without making a sound.
+  pub fn silent(length: Duration) -> Self {
+    Playback {
+      #[cfg(feature = "playback")]
+      output: None,
+      clock: Clock { offset: Duration::ZERO, since: Some(Instant::now()) },
+      length,
+    }
+  }
+
+  /// Starts playing `audio_path` on the default output device.
+  #[cfg(feature = "playback")]
+  pub fn start(audio_path: &Path) -> io::Result<Self> {
+    let decoder = rodio::Decoder::try_from(File::open(audio_path)?)
+      .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("couldn't decode it ({error})")))?;
+    let mut sink = rodio::DeviceSinkBuilder::open_default_sink()
+      .map_err(|error| io::Error::other(format!("no sound output ({error})")))?;
+    // Dropping the sink at the end of the track is expected, not worth a line on stderr
+    sink.log_on_drop(false);
+    let player = rodio::Player::connect_new(sink.mixer());
+    player.append(decoder);
+    let clock = Clock { offset: Duration::ZERO, since: Some(Instant::now()) };
+    Ok(Playback { output: Some((sink, player)), clock, length: Duration::MAX })
+  }
+
+  /// Always fails: this build can't make a sound.
+  #[cfg(not(feature = "playback"))]
+  pub fn start(_audio_path: &Path) -> std::io::Result<Self> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "built without the playback feature"))
+  }
+
+  /// How far into the track it is.
+  pub fn elapsed(&self) -> Duration {
+    #[cfg(feature = "playback")]
+    if let Some((_, player)) = &self.output {
+      return player.get_pos();
+    }
+    self.clock.elapsed().min(self.length)
+  }
+
+  /// Whether the track is over.
+  pub fn finished(&self) -> bool {
+    #[cfg(feature = "playback")]
+    if let Some((_, player)) = &self.output {
+      return player.empty();
+    }
+    self.clock.elapsed() >= self.length
+  }
+
+  pub fn paused(&self) -> bool {
+    #[cfg(feature = "playback")]
+    if let Some((_, player)) = &self.output {
+      return player.is_paused();
+    }
+    self.clock.since.is_none()
+  }
+
+  /// Pauses the track, or picks it up again.
+  pub fn toggle_pause(&mut self) {
+    #[cfg(feature = "playback")]
+    if let Some((_, player)) = &self.output {
+      if player.is_paused() {
+        player.play()
+      } else {
+        player.pause()
+      }
+      return;
+    }
+    self.clock = match self.clock.since {
+      Some(_) => Clock { offset: self.clock.elapsed(), since: None },
+      None => Clock { offset: self.clock.offset, since: Some(Instant::now()) },
+    };
+  }
+
+  /// Moves to `position` in the track, as near as the format allows.
+  pub fn seek(&mut self, position: Duration) {
+    #[cfg(feature = "playback")]
+    if let Some((_, player)) = &self.output {
+      if let Err(error) = player.try_seek(position) {
+        tracing::warn!(%error, "couldn't seek");
+      }
+      return;
+    }
+    self.clock = Clock { offset: position.min(self.length), since: self.clock.since.map(|_| Instant::now()) };
+  }
+}
+
+/// Whether this build can play sound at all.
+pub const AVAILABLE: bool = cfg!(feature = "playback");
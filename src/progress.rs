@@ -0,0 +1,150 @@
+//! Progress bars, the log writer that keeps log lines from tearing through them, and the JSON Lines
+//! events printed in their place with `--progress jsonl`.
+
+use crate::report::Summary;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Every bar is drawn through this, so anything else written to the terminal can pause it first
+static PROGRESS: LazyLock<MultiProgress> = LazyLock::new(MultiProgress::new);
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static PLAIN: AtomicBool = AtomicBool::new(false);
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+/// Hides the bars and what's printed about single files for `--quiet`, leaving the summaries.
+pub fn set_quiet() {
+  QUIET.store(true, Ordering::Relaxed);
+  hide_bars();
+}
+
+/// Keeps the bars off the terminal, e.g. when per-file log messages take their place.
+pub fn hide_bars() {
+  PROGRESS.set_draw_target(ProgressDrawTarget::hidden());
+}
+
+/// Whether `--quiet` was given.
+pub fn quiet() -> bool {
+  QUIET.load(Ordering::Relaxed)
+}
+
+/// Prints a line per file on stdout in place of the bars, for output that isn't going to a terminal.
+pub fn set_plain(plain: bool) {
+  PLAIN.store(plain, Ordering::Relaxed);
+  if plain {
+    hide_bars();
+  }
+}
+
+/// Whether output is plain lines rather than a live display.
+pub fn plain() -> bool {
+  PLAIN.load(Ordering::Relaxed)
+}
+
+/// Prints `msg` about a single file, when plain output has taken the place of the bars.
+pub fn print_plain(msg: &str) {
+  if plain() && !quiet() {
+    println!("{msg}");
+  }
+}
+
+/// Draws the bars in color, or without it for `--color never` and `NO_COLOR`.
+pub fn set_color(color: bool) {
+  COLOR.store(color, Ordering::Relaxed);
+}
+
+/// Whether output is in color.
+pub fn color() -> bool {
+  COLOR.load(Ordering::Relaxed)
+}
+
+/// The overall bar for a command working through `len` files.
+pub fn new_progress_bar(len: usize) -> ProgressBar {
+  let pb = PROGRESS.add(ProgressBar::new(len as u64));
+  pb.set_style(
+    ProgressStyle::default_bar()
+      .template(if color() {
+        "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}"
+      } else {
+        "{spinner} [{elapsed_precise}] [{bar:40}] {pos}/{len} {msg}"
+      })
+      .unwrap()
+      .progress_chars("#>-"),
+  );
+  pb
+}
+
+/// One line per worker thread under the overall bar, showing the file it's on.
+pub fn new_worker_bars(workers: usize) -> Vec<ProgressBar> {
+  (0..workers)
+    .map(|worker| {
+      let bar = PROGRESS.add(ProgressBar::new_spinner());
+      let template = if color() {
+        "  {spinner:.blue} worker {prefix}: {wide_msg}"
+      } else {
+        "  {spinner} worker {prefix}: {wide_msg}"
+      };
+      bar.set_style(ProgressStyle::with_template(template).unwrap());
+      bar.set_prefix((worker + 1).to_string());
+      bar.set_message("idle");
+      bar
+    })
+    .collect()
+}
+
+/// Runs `interaction` with the bars cleared from the terminal, e.g. to prompt the user.
+pub fn suspend<R>(interaction: impl FnOnce() -> R) -> R {
+  PROGRESS.suspend(interaction)
+}
+
+/// A `tracing` writer for stderr that clears the bars while a log line is printed.
+#[derive(Default)]
+pub struct LogWriter(Vec<u8>);
+
+impl Write for LogWriter {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.0.extend_from_slice(buf);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    if self.0.is_empty() {
+      return Ok(());
+    }
+    let line = std::mem::take(&mut self.0);
+    PROGRESS.suspend(|| io::stderr().write_all(&line))
+  }
+}
+
+// The formatter writes one event per writer and then drops it
+impl Drop for LogWriter {
+  fn drop(&mut self) {
+    let _ = self.flush();
+  }
+}
+
+/// One line of `--progress jsonl` output.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum JsonEvent<'a> {
+  Scanning { directory: &'a Path, dry_run: bool },
+  Scanned { files: usize },
+  Started { path: &'a Path },
+  Matched { path: &'a Path, sidecars: Vec<&'a Path> },
+  Skipped { path: &'a Path, reason: &'static str },
+  Embedded { path: &'a Path },
+  Failed { path: &'a Path, error: String },
+  Finished { interrupted: bool, summary: Summary },
+}
+
+/// Prints `event` as a line of JSON on stdout, flushed at once so wrappers see it straight away.
+pub fn emit_jsonl(event: &JsonEvent<'_>) {
+  let mut stdout = io::stdout().lock();
+  let line = serde_json::to_string(event).expect("progress events always serialize");
+  // A wrapper that stopped reading shouldn't take the run down with it
+  let _ = writeln!(stdout, "{line}").and_then(|()| stdout.flush());
+}
@@ -0,0 +1,134 @@
+//! Online lyrics sources used by `fetch`.
+
+use crate::cache::FetchCache;
+use crate::genius::GeniusClient;
+use crate::lrclib::LrclibClient;
+use crate::musixmatch::MusixmatchClient;
+use crate::netease::NeteaseClient;
+use crate::track::TrackInfo;
+use crate::{Result, lrc};
+use serde::{Deserialize, Serialize};
+
+pub enum Provider {
+  Lrclib(LrclibClient),
+  Musixmatch(MusixmatchClient),
+  Netease(NeteaseClient),
+  Genius(GeniusClient),
+}
+
+/// Lyrics returned by a provider.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FetchedLyrics {
+  pub text: String,
+  /// Whether `text` is timestamped LRC rather than plain lyrics
+  pub synced: bool,
+  /// Translated LRC, for providers that publish one alongside the original
+  pub translation: Option<String>,
+}
+
+impl Provider {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Provider::Lrclib(_) => "lrclib",
+      Provider::Musixmatch(_) => "musixmatch",
+      Provider::Netease(_) => "netease",
+      Provider::Genius(_) => "genius",
+    }
+  }
+
+  /// Looks up lyrics for a track, returning `None` if the provider has none.
+  pub fn lyrics(&self, track: &TrackInfo) -> Result<Option<FetchedLyrics>> {
+    let synced = |text: Option<String>| text.map(|text| FetchedLyrics { text, synced: true, translation: None });
+
+    let fetched = match self {
+      Provider::Lrclib(client) => synced(client.synced_lyrics(track)?),
+      Provider::Musixmatch(client) => synced(client.synced_lyrics(track)?),
+      Provider::Netease(client) => client.synced_lyrics(track)?.map(|found| FetchedLyrics {
+        text: found.lyrics,
+        synced: true,
+        translation: found.translation,
+      }),
+      Provider::Genius(client) => {
+        client.plain_lyrics(track)?.map(|text| FetchedLyrics { text, synced: false, translation: None })
+      },
+    };
+    Ok(fetched)
+  }
+}
+
+/// Requirements a provider's result must meet before it's accepted.
+pub struct LyricsFilters {
+  pub synced_only: bool,
+  pub min_lines: usize,
+}
+
+impl LyricsFilters {
+  pub fn accepts(&self, lyrics: &FetchedLyrics) -> bool {
+    if self.synced_only && !lyrics.synced {
+      return false;
+    }
+
+    let line_count = if lyrics.synced {
+      lrc::parse_timed_lines(&lyrics.text).iter().filter(|line| !line.text.trim().is_empty()).count()
+    } else {
+      lyrics.text.lines().filter(|line| !line.trim().is_empty()).count()
+    };
+    line_count >= self.min_lines
+  }
+}
+
+/// An ordered list of providers, queried until one returns lyrics that pass the filters.
+pub struct ProviderChain {
+  providers: Vec<Provider>,
+  filters: LyricsFilters,
+  cache: Option<FetchCache>,
+}
+
+impl ProviderChain {
+  pub fn new(providers: Vec<Provider>, filters: LyricsFilters, cache: Option<FetchCache>) -> Self {
+    Self { providers, filters, cache }
+  }
+
+  /// Looks up a track, answering from the cache when possible. Errors are never cached.
+  pub fn lyrics(&self, track: &TrackInfo) -> Result<Option<FetchedLyrics>> {
+    let Some(cache) = &self.cache else {
+      return self.query(track);
+    };
+
+    let signature = self.signature();
+    if let Some(cached) = cache.get(track, &signature) {
+      return Ok(cached);
+    }
+
+    let lyrics = self.query(track)?;
+    cache.put(track, &signature, lyrics.as_ref())?;
+    Ok(lyrics)
+  }
+
+  // Different chains or filters can give different answers, so they're part of the cache key
+  fn signature(&self) -> String {
+    let names: Vec<&str> = self.providers.iter().map(Provider::name).collect();
+    format!("{};synced_only={};min_lines={}", names.join(","), self.filters.synced_only, self.filters.min_lines)
+  }
+
+  /// Walks the chain for a track. A failing provider doesn't stop the walk; its error is only
+  /// returned if no later provider has acceptable lyrics.
+  fn query(&self, track: &TrackInfo) -> Result<Option<FetchedLyrics>> {
+    let mut first_error = None;
+
+    for provider in &self.providers {
+      match provider.lyrics(track) {
+        Ok(Some(lyrics)) if self.filters.accepts(&lyrics) => return Ok(Some(lyrics)),
+        Ok(_) => {},
+        Err(e) => {
+          first_error.get_or_insert(e);
+        },
+      }
+    }
+
+    match first_error {
+      Some(e) => Err(e),
+      None => Ok(None),
+    }
+  }
+}
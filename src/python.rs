@@ -0,0 +1,106 @@
+//! The `lyricsync` Python module, built with maturin (see `pyproject.toml`).
+//!
+//! Failures raise `lyricsync.LyricsyncError` with the same message the command line prints.
+
+use crate::{EmbedOptions, EmbedOutcome, EmbedSession, FormatResolver, LrcError, Lyrics, SkipReason};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use std::path::PathBuf;
+
+create_exception!(lyricsync, LyricsyncError, PyException, "Raised when lyrics can't be read or written.");
+
+fn to_py_err(error: LrcError) -> PyErr {
+  LyricsyncError::new_err(error.to_string())
+}
+
+/// Embeds LRC or plain-text lyrics into an audio file, or its `.lrc` sidecars when `lyrics` is
+/// omitted.
+///
+/// `language` is an ISO 639-2 code for ID3v2 frames, detected from the lyrics when omitted.
+#[pyfunction]
+#[pyo3(signature = (path, lyrics = None, *, language = None, description = None))]
+fn embed(
+  py: Python<'_>,
+  path: PathBuf,
+  lyrics: Option<String>,
+  language: Option<&str>,
+  description: Option<String>,
+) -> PyResult<()> {
+  let language = language.map(parse_language).transpose()?;
+  let options = EmbedOptions { language, description, ..EmbedOptions::default() };
+  py.detach(|| match lyrics {
+    Some(lyrics) => crate::embed_lyrics(&path, &Lyrics::new(lyrics), &options),
+    None => match EmbedSession::new(options).embed_file(&path)? {
+      EmbedOutcome::Skipped(SkipReason::NoSidecar) => Err(LrcError::NoMatchingLrc { path }),
+      _ => Ok(()),
+    },
+  })
+  .map_err(to_py_err)
+}
+
+fn parse_language(code: &str) -> PyResult<[u8; 3]> {
+  let invalid = || PyValueError::new_err(format!("expected a three-letter ISO 639-2 code, got '{code}'"));
+  let language: [u8; 3] = code.to_ascii_lowercase().into_bytes().try_into().map_err(|_| invalid())?;
+  if !language.iter().all(u8::is_ascii_lowercase) {
+    return Err(invalid());
+  }
+  Ok(language)
+}
+
+/// Returns the embedded lyrics of an audio file, or `None` if it has none.
+#[pyfunction]
+fn extract(py: Python<'_>, path: PathBuf) -> PyResult<Option<String>> {
+  py.detach(|| crate::extract_lyrics(&path, &FormatResolver::default())).map_err(to_py_err)
+}
+
+/// Returns whether an audio file has embedded lyrics.
+#[pyfunction]
+fn has_lyrics(py: Python<'_>, path: PathBuf) -> PyResult<bool> {
+  py.detach(|| crate::has_embedded_lyrics(&path, &FormatResolver::default())).map_err(to_py_err)
+}
+
+/// One file in the report returned by `check`.
+#[pyclass(frozen, get_all)]
+struct CheckedFile {
+  path: PathBuf,
+  /// `match`, `differs`, `embedded`, `sidecar` or `missing`, as `lyricsync check` reports them, or
+  /// `error` when the file couldn't be read
+  status: String,
+  error: Option<String>,
+}
+
+#[pymethods]
+impl CheckedFile {
+  fn __repr__(&self) -> String {
+    format!("CheckedFile(path={:?}, status={:?})", self.path, self.status)
+  }
+}
+
+/// Compares the embedded and sidecar lyrics of every audio file in a directory, like
+/// `lyricsync check`.
+#[pyfunction]
+#[pyo3(signature = (directory, recursive = false))]
+fn check(py: Python<'_>, directory: PathBuf, recursive: bool) -> Vec<CheckedFile> {
+  py.detach(|| {
+    let resolver = FormatResolver::default();
+    crate::collect_audio_files(&directory, recursive, &resolver)
+      .into_iter()
+      .map(|path| match crate::check_lyrics(&path, &resolver) {
+        Ok(status) => CheckedFile { path, status: status.label().to_ascii_lowercase(), error: None },
+        Err(error) => CheckedFile { path, status: "error".to_string(), error: Some(error.to_string()) },
+      })
+      .collect()
+  })
+}
+
+#[pymodule]
+pub fn lyricsync(module: &Bound<'_, PyModule>) -> PyResult<()> {
+  module.add("LyricsyncError", module.py().get_type::<LyricsyncError>())?;
+  module.add_class::<CheckedFile>()?;
+  module.add_function(wrap_pyfunction!(embed, module)?)?;
+  module.add_function(wrap_pyfunction!(extract, module)?)?;
+  module.add_function(wrap_pyfunction!(has_lyrics, module)?)?;
+  module.add_function(wrap_pyfunction!(check, module)?)?;
+  Ok(())
+}
@@ -0,0 +1,175 @@
+//! Machine-readable reports of an embed run, written with `--report`.
+//!
+//! JSON carries the whole report; CSV has one row per file, for triaging results in spreadsheets.
+
+use lyricsync::{EmbedOutcome, FormatResolver, LrcError, SkipReason};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+  Json,
+  Csv,
+  /// The coverage page of `check`
+  Html,
+}
+
+/// Where `--report` sends the report: a file, or stdout in place of the text summary.
+#[derive(Clone, Debug)]
+pub struct ReportTarget {
+  pub format: ReportFormat,
+  pub path: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileStatus {
+  Embedded,
+  /// Kept its existing lyrics under the conflict policy
+  Skipped,
+  /// Its lyrics already matched the sidecar
+  Unchanged,
+  NoLrc,
+  Failed,
+}
+
+impl FileStatus {
+  /// The name it's reported as.
+  pub fn as_str(self) -> &'static str {
+    match self {
+      FileStatus::Embedded => "embedded",
+      FileStatus::Skipped => "skipped",
+      FileStatus::Unchanged => "unchanged",
+      FileStatus::NoLrc => "no-lrc",
+      FileStatus::Failed => "failed",
+    }
+  }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+  pub path: PathBuf,
+  pub status: FileStatus,
+  /// `None` when the file's format couldn't be told
+  pub format: Option<&'static str>,
+  /// The tag lyrics were written to, for embedded files
+  pub tag_type: Option<&'static str>,
+  pub duration_ms: u64,
+  pub error: Option<String>,
+}
+
+impl FileReport {
+  pub fn new(
+    path: PathBuf,
+    outcome: &Result<EmbedOutcome, LrcError>,
+    elapsed: Duration,
+    resolver: &FormatResolver,
+  ) -> Self {
+    let status = match outcome {
+      Ok(EmbedOutcome::Embedded) => FileStatus::Embedded,
+      Ok(EmbedOutcome::Skipped(SkipReason::HasLyrics | SkipReason::Declined)) => FileStatus::Skipped,
+      Ok(EmbedOutcome::Skipped(SkipReason::Unchanged)) => FileStatus::Unchanged,
+      Ok(EmbedOutcome::Skipped(SkipReason::NoSidecar)) => FileStatus::NoLrc,
+      Err(_) => FileStatus::Failed,
+    };
+    // Probing reads the file, which the embed has just done anyway
+    let format = resolver.resolve(&path).ok();
+    FileReport {
+      status,
+      format: format.map(|format| format.name()),
+      tag_type: format.filter(|_| matches!(status, FileStatus::Embedded)).map(|format| format.tag_type()),
+      duration_ms: duration_ms(elapsed),
+      error: outcome.as_ref().err().map(|error| crate::failure_message(&path, error)),
+      path,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Summary {
+  pub total: usize,
+  pub embedded: usize,
+  pub unchanged: usize,
+  pub skipped: usize,
+  pub no_lrc: usize,
+  pub failed: usize,
+  /// Files an interrupted run didn't get to
+  pub remaining: usize,
+}
+
+impl Summary {
+  /// Totals `files`, with `remaining` more left by an interruption.
+  pub fn of(files: &[FileReport], remaining: usize) -> Self {
+    let count = |status: fn(&FileStatus) -> bool| files.iter().filter(|file| status(&file.status)).count();
+    Summary {
+      total: files.len() + remaining,
+      embedded: count(|status| matches!(status, FileStatus::Embedded)),
+      unchanged: count(|status| matches!(status, FileStatus::Unchanged)),
+      skipped: count(|status| matches!(status, FileStatus::Skipped)),
+      no_lrc: count(|status| matches!(status, FileStatus::NoLrc)),
+      failed: count(|status| matches!(status, FileStatus::Failed)),
+      remaining,
+    }
+  }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+  pub command: &'static str,
+  pub directory: PathBuf,
+  pub dry_run: bool,
+  pub interrupted: bool,
+  pub duration_ms: u64,
+  /// The journal `lyricsync undo` reverts the run with, if anything changed
+  pub journal: Option<PathBuf>,
+  pub summary: Summary,
+  pub files: Vec<FileReport>,
+}
+
+impl Report {
+  pub fn write(&self, target: &ReportTarget) -> io::Result<()> {
+    match &target.path {
+      Some(path) => self.write_to(target.format, BufWriter::new(File::create(path)?)),
+      None => self.write_to(target.format, io::stdout().lock()),
+    }
+  }
+
+  fn write_to(&self, format: ReportFormat, mut writer: impl Write) -> io::Result<()> {
+    match format {
+      ReportFormat::Json => {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+      },
+      ReportFormat::Csv => self.write_csv(&mut writer)?,
+      ReportFormat::Html => unreachable!("embed doesn't accept --report html"),
+    }
+    writer.flush()
+  }
+
+  fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+    write!(writer, "path,format,action,error\r\n")?;
+    for file in &self.files {
+      let fields = [
+        &*file.path.to_string_lossy(),
+        file.format.unwrap_or_default(),
+        file.status.as_str(),
+        file.error.as_deref().unwrap_or_default(),
+      ];
+      let row: Vec<_> = fields.into_iter().map(csv_field).collect();
+      write!(writer, "{}\r\n", row.join(","))?;
+    }
+    Ok(())
+  }
+}
+
+// Quotes fields that need it, as RFC 4180 describes
+fn csv_field(field: &str) -> String {
+  if field.contains([',', '"', '\r', '\n']) { format!("\"{}\"", field.replace('"', "\"\"")) } else { field.to_string() }
+}
+
+pub fn duration_ms(duration: Duration) -> u64 {
+  duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
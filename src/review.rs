@@ -0,0 +1,157 @@
+//! `--review`: a full-screen list of the matches an `embed` run is about to make, each audio file
+//! with the LRC file picked for it, how sure the match is and whether the file already has lyrics,
+//! for them to be approved, rejected or swapped for another LRC file before anything is written.
+
+use lyricsync::Sidecar;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Decision {
+  Approved,
+  Rejected,
+  Undecided,
+}
+
+/// An audio file and the LRC files it could be paired with.
+pub struct Match {
+  audio_path: PathBuf,
+  /// The LRC files to pick from, the best first
+  candidates: Vec<Sidecar>,
+  /// Whether the first candidate is the one the run would take anyway, language sidecars and all,
+  /// rather than the best of some that weren't close enough to be taken
+  matched: bool,
+  /// Whether the file has embedded lyrics already
+  embedded: bool,
+  choice: usize,
+  decision: Decision,
+}
+
+impl Match {
+  /// A match to review, approved up front when it's certain.
+  pub fn new(audio_path: PathBuf, candidates: Vec<Sidecar>, matched: bool, embedded: bool) -> Self {
+    let certain = matched && candidates.first().is_some_and(|sidecar| sidecar.confidence >= 1.0);
+    let decision = if certain { Decision::Approved } else { Decision::Undecided };
+    Match { audio_path, candidates, matched, embedded, choice: 0, decision }
+  }
+}
+
+/// What was decided: the approved audio files, each with the LRC file picked for it when that isn't
+/// the one the run would take anyway. `None` when the review was quit without applying it.
+pub type Approved = Option<Vec<(PathBuf, Option<PathBuf>)>>;
+
+/// Shows `matches`, found in `directory`, until they're applied with Enter or the review is quit.
+pub fn review(directory: &Path, mut matches: Vec<Match>) -> io::Result<Approved> {
+  let mut terminal = ratatui::try_init()?;
+  let applied = run(&mut terminal, directory, &mut matches);
+  ratatui::restore();
+  if !applied? {
+    return Ok(None);
+  }
+  let approved = matches
+    .into_iter()
+    .filter(|candidate| candidate.decision == Decision::Approved)
+    .map(|candidate| {
+      let picked =
+        (candidate.choice > 0 || !candidate.matched).then(|| candidate.candidates[candidate.choice].path.clone());
+      (candidate.audio_path, picked)
+    })
+    .collect();
+  Ok(Some(approved))
+}
+
+// Takes keystrokes until the matches are applied (true) or the review is quit (false)
+fn run(terminal: &mut DefaultTerminal, directory: &Path, matches: &mut [Match]) -> io::Result<bool> {
+  let mut state = TableState::default().with_selected((!matches.is_empty()).then_some(0));
+  loop {
+    terminal.draw(|frame| draw(frame, directory, matches, &mut state))?;
+    let Event::Key(key) = event::read()? else {
+      continue;
+    };
+    if key.kind != KeyEventKind::Press {
+      continue;
+    }
+    let selected = state.selected().and_then(|index| matches.get_mut(index));
+    match (key.code, selected) {
+      (KeyCode::Enter, _) => return Ok(true),
+      (KeyCode::Char('q') | KeyCode::Esc, _) => return Ok(false),
+      (KeyCode::Down | KeyCode::Char('j'), _) => state.select_next(),
+      (KeyCode::Up | KeyCode::Char('k'), _) => state.select_previous(),
+      (KeyCode::PageDown, _) => state.scroll_down_by(10),
+      (KeyCode::PageUp, _) => state.scroll_up_by(10),
+      (KeyCode::Home | KeyCode::Char('g'), _) => state.select_first(),
+      (KeyCode::End | KeyCode::Char('G'), _) => state.select_last(),
+      (KeyCode::Char('Y'), _) => {
+        for candidate in matches.iter_mut().filter(|candidate| candidate.decision == Decision::Undecided) {
+          candidate.decision = Decision::Approved;
+        }
+      },
+      (KeyCode::Char('y'), Some(selected)) => {
+        selected.decision = Decision::Approved;
+        state.select_next();
+      },
+      (KeyCode::Char('n'), Some(selected)) => {
+        selected.decision = Decision::Rejected;
+        state.select_next();
+      },
+      // Picking another LRC file is a decision to be made again
+      (KeyCode::Right | KeyCode::Tab | KeyCode::Char('l'), Some(selected)) if selected.candidates.len() > 1 => {
+        selected.choice = (selected.choice + 1) % selected.candidates.len();
+        selected.decision = Decision::Undecided;
+      },
+      (KeyCode::Left | KeyCode::BackTab | KeyCode::Char('h'), Some(selected)) if selected.candidates.len() > 1 => {
+        selected.choice = (selected.choice + selected.candidates.len() - 1) % selected.candidates.len();
+        selected.decision = Decision::Undecided;
+      },
+      _ => {},
+    }
+  }
+}
+
+fn draw(frame: &mut Frame, directory: &Path, matches: &[Match], state: &mut TableState) {
+  let [list, help] = Layout::vertical([Constraint::Min(3), Constraint::Length(2)]).areas(frame.area());
+  let relative = |path: &Path| path.strip_prefix(directory).unwrap_or(path).display().to_string();
+  let rows = matches.iter().map(|candidate| {
+    let sidecar = &candidate.candidates[candidate.choice];
+    let (mark, color) = match candidate.decision {
+      Decision::Approved => ("✓", Color::Green),
+      Decision::Rejected => ("✗", Color::Red),
+      Decision::Undecided => ("?", Color::Yellow),
+    };
+    let lrc = match candidate.candidates.len() {
+      1 => relative(&sidecar.path),
+      count => format!("{} ({}/{count})", relative(&sidecar.path), candidate.choice + 1),
+    };
+    Row::new([
+      Cell::from(mark).style(Style::new().fg(color)),
+      Cell::from(relative(&candidate.audio_path)),
+      Cell::from(lrc),
+      Cell::from(format!("{:.0}%", sidecar.confidence * 100.0)),
+      Cell::from(if candidate.embedded { "yes" } else { "no" }),
+    ])
+  });
+  let table = Table::new(
+    rows,
+    [Constraint::Length(1), Constraint::Fill(1), Constraint::Fill(1), Constraint::Length(10), Constraint::Length(8)],
+  )
+  .header(Row::new(["", "Audio file", "LRC file", "Confidence", "Embedded"]).style(Style::new().bold()))
+  .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+  .block(Block::bordered().title(format!(" Review matches in {} ", directory.display())));
+  frame.render_stateful_widget(table, list, state);
+
+  let count = |decision| matches.iter().filter(|candidate| candidate.decision == decision).count();
+  let summary = format!(
+    "{} approved, {} rejected, {} undecided; undecided files aren't embedded",
+    count(Decision::Approved),
+    count(Decision::Rejected),
+    count(Decision::Undecided),
+  );
+  let keys = "y approve  n reject  ←/→ other LRC file  Y approve the undecided  Enter apply  q quit";
+  frame.render_widget(Paragraph::new(vec![Line::from(summary), Line::from(keys).style(Style::new().dim())]), help);
+}
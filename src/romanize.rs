@@ -0,0 +1,144 @@
+//! Romanized lyrics, for listeners who can't read the script a song is written in. Japanese kana,
+//! Chinese characters and Korean Hangul are transliterated by `any_ascii` in builds with the
+//! `romanize` feature, each line keeping its timestamps and word timestamps.
+//!
+//! The transliteration goes a character at a time, so Japanese kanji are left as they are: their
+//! readings depend on the words they're in, and the Chinese ones they'd be given would be wrong.
+
+#[cfg(feature = "romanize")]
+use crate::lrc;
+
+/// Whether this build can romanize lyrics at all.
+pub const AVAILABLE: bool = cfg!(feature = "romanize");
+
+/// The romanization of Japanese, Chinese or Korean lyrics, a line for each of theirs at the same
+/// time. `None` for lyrics in other scripts, which don't need one.
+#[cfg(feature = "romanize")]
+pub fn romanize(lyrics: &str) -> Option<String> {
+  let language = crate::language::detect(lyrics).filter(|language| [b"jpn", b"chi", b"kor"].contains(&language))?;
+  Some(lrc::map_text(lyrics, |text| romanize_text(text, language)))
+}
+
+/// Always `None`: this build can't romanize.
+#[cfg(not(feature = "romanize"))]
+pub fn romanize(_lyrics: &str) -> Option<String> {
+  None
+}
+
+#[cfg(feature = "romanize")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Script {
+  Han,
+  Kana,
+  Hangul,
+  // Full-width and ideographic punctuation, which has ASCII equivalents
+  Punctuation,
+}
+
+#[cfg(feature = "romanize")]
+fn script(c: char) -> Option<Script> {
+  match c {
+    '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' => Some(Script::Han),
+    '\u{3041}'..='\u{30FF}' if c != '\u{30FB}' => Some(Script::Kana),
+    '\u{AC00}'..='\u{D7A3}' => Some(Script::Hangul),
+    '\u{3000}'..='\u{303F}' | '\u{30FB}' | '\u{FF00}'..='\u{FFEF}' => Some(Script::Punctuation),
+    _ => None,
+  }
+}
+
+// Transliterates the line text `text` out of the script of `language`, passing the rest through
+#[cfg(feature = "romanize")]
+fn romanize_text(text: &str, language: [u8; 3]) -> String {
+  let mut romanized = String::new();
+  // Where a space goes if a word follows: after a Chinese syllable, each a word of its own, or
+  // punctuation written without one
+  let mut space_at: Option<usize> = None;
+  // A small tsu doubles the consonant after it
+  let mut doubled = false;
+  let mut chars = text.chars();
+  while let Some(c) = chars.next() {
+    // Word timestamps are kept as they are
+    if c == '<' && chars.as_str().starts_with(|c: char| c.is_ascii_digit()) {
+      romanized.push(c);
+      for c in chars.by_ref() {
+        romanized.push(c);
+        if c == '>' {
+          break;
+        }
+      }
+      continue;
+    }
+    let chinese = language == *b"chi" && script(c) == Some(Script::Han);
+    if c.is_whitespace() || c == '・' {
+      space_at = None;
+    } else if let Some(at) = space_at.take()
+      && c.is_alphanumeric()
+      && romanized.is_char_boundary(at)
+    {
+      romanized.insert(at, ' ');
+    }
+    let syllable = match script(c) {
+      _ if chinese => any_ascii::any_ascii_char(c).to_lowercase(),
+      Some(Script::Kana) if language == *b"jpn" => {
+        push_kana(&mut romanized, c, &mut doubled);
+        continue;
+      },
+      Some(Script::Hangul) if language == *b"kor" => any_ascii::any_ascii_char(c).to_lowercase(),
+      // The katakana middle dot parts the words of a name
+      Some(Script::Punctuation) if c == '・' => " ".to_string(),
+      Some(Script::Punctuation) => {
+        let ascii = any_ascii::any_ascii_char(c);
+        romanized.push_str(ascii);
+        space_at = ascii.ends_with([',', '.', '!', '?', ':', ';']).then_some(romanized.len());
+        continue;
+      },
+      _ => c.to_string(),
+    };
+    if chinese && romanized.ends_with(char::is_alphanumeric) {
+      romanized.push(' ');
+    }
+    romanized.push_str(&syllable);
+    if chinese {
+      space_at = Some(romanized.len());
+    }
+  }
+  romanized.split(' ').filter(|word| !word.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+// Adds the romaji of a kana to `romanized`: a small ya, yu or yo joins the kana before it, as in
+// kya, and a small tsu doubles the consonant after it, as in gakkou
+#[cfg(feature = "romanize")]
+fn push_kana(romanized: &mut String, c: char, doubled: &mut bool) {
+  let romaji = any_ascii::any_ascii_char(c);
+  match c {
+    'っ' | 'ッ' => *doubled = true,
+    // The long vowel mark only draws out the vowel before it
+    'ー' => {},
+    'ゃ' | 'ゅ' | 'ょ' | 'ャ' | 'ュ' | 'ョ' if romanized.ends_with('i') => {
+      romanized.pop();
+      if !["sh", "ch", "j"].iter().any(|consonant| romanized.ends_with(consonant)) {
+        romanized.push('y');
+      }
+      romanized.push_str(&romaji[1..]);
+    },
+    // A small vowel takes the place of the vowel before it, as in fa or ti, after a consonant
+    'ぁ' | 'ぃ' | 'ぅ' | 'ぇ' | 'ぉ' | 'ァ' | 'ィ' | 'ゥ' | 'ェ' | 'ォ' if after_consonant(romanized) => {
+      romanized.pop();
+      romanized.push_str(romaji);
+    },
+    _ => {
+      if std::mem::take(doubled) {
+        romanized.push(if romaji.starts_with("ch") { 't' } else { romaji.chars().next().unwrap_or_default() });
+      }
+      romanized.push_str(romaji);
+    },
+  }
+}
+
+// Whether `romanized` ends with a consonant and a vowel
+#[cfg(feature = "romanize")]
+fn after_consonant(romanized: &str) -> bool {
+  let mut last = romanized.chars().rev();
+  let vowel = |c: char| "aeiou".contains(c);
+  last.next().is_some_and(vowel) && last.next().is_some_and(|c| c.is_ascii_alphabetic() && !vowel(c))
+}
@@ -0,0 +1,309 @@
+//! `lyricsync serve`: answers HTTP requests for the lyrics of a library's files, read from their
+//! tags or their sidecars, so a player doesn't need a tag reader of its own.
+//!
+//! `GET /lyrics` takes either `path`, a file's path in the library, or `artist` and `title` with an
+//! optional `duration` in seconds to tell apart tracks that share them. The lyrics come back as
+//! text, synced LRC when there is any, or plain text with `format=plain`.
+//!
+//! With `--lrclib-api`, `GET /api/get` (and `/api/get-cached`) answers as LRCLIB's does, so player
+//! plugins written for LRCLIB can be pointed at the library. Tracks without lyrics are reported as
+//! not found, which sends those plugins on to LRCLIB itself.
+
+use crate::interrupt;
+use crate::karaoke::{self, Lyrics};
+use lyricsync::matching::{LENGTH_TOLERANCE, SidecarMatcher};
+use lyricsync::track::{self, TrackInfo};
+use lyricsync::{FormatResolver, PathFilter, collect_audio_files_matching, lrc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+// How often a quiet server looks up from waiting, to notice it's been asked to stop
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// A lookup by tags that finds nothing reads the library's tags again, at most this often
+const REINDEX_AFTER: Duration = Duration::from_secs(60);
+
+/// An audio file of the library and its tags.
+pub struct Track {
+  pub path: PathBuf,
+  pub info: TrackInfo,
+}
+
+/// The audio files served, with their tags read up front to look them up by.
+pub struct Library {
+  root: PathBuf,
+  recursive: bool,
+  formats: FormatResolver,
+  filter: PathFilter,
+  matcher: SidecarMatcher,
+  tracks: Vec<Track>,
+  indexed_at: Instant,
+}
+
+impl Library {
+  /// Reads the tags of every audio file in `root`, which has to be canonical.
+  pub fn open(
+    root: PathBuf,
+    recursive: bool,
+    formats: FormatResolver,
+    filter: PathFilter,
+    matcher: SidecarMatcher,
+  ) -> Self {
+    let mut library =
+      Library { root, recursive, formats, filter, matcher, tracks: Vec::new(), indexed_at: Instant::now() };
+    library.index();
+    library
+  }
+
+  fn index(&mut self) {
+    let audio_files = collect_audio_files_matching(&self.root, self.recursive, &self.formats, &self.filter);
+    // Files without an artist or title can still be asked for by path
+    self.tracks = audio_files
+      .into_iter()
+      .filter_map(|path| {
+        let format = self.formats.resolve(&path).ok()?;
+        let info = track::read_track_info(&path, format).ok().flatten()?;
+        Some(Track { path, info })
+      })
+      .collect();
+    self.indexed_at = Instant::now();
+    tracing::info!(tracks = self.tracks.len(), "indexed {}", self.root.display());
+  }
+
+  /// The audio file at `path`, relative to the library, if it's one of the library's. Paths that
+  /// lead out of it are turned down.
+  pub fn by_path(&self, path: &str) -> Option<PathBuf> {
+    let audio_path = fs::canonicalize(self.root.join(path.trim_start_matches('/'))).ok()?;
+    let relative = audio_path.strip_prefix(&self.root).ok()?;
+    let in_library = self.recursive || relative.components().count() == 1;
+    (in_library
+      && audio_path.is_file()
+      && self.formats.by_extension(&audio_path).is_some()
+      && self.filter.is_match(relative))
+    .then_some(audio_path)
+  }
+
+  /// Where in the library the track tagged with `artist` and `title` is, ignoring case and spacing,
+  /// and with `album` when both it and the track have one. With a `duration`, tracks more than the
+  /// length tolerance away from it are passed over and the closest is picked.
+  pub fn by_tags(&mut self, artist: &str, title: &str, album: Option<&str>, duration: Option<f64>) -> Option<usize> {
+    if self.find(artist, title, album, duration).is_none() && self.indexed_at.elapsed() >= REINDEX_AFTER {
+      self.index();
+    }
+    self.find(artist, title, album, duration)
+  }
+
+  pub fn track(&self, index: usize) -> &Track {
+    &self.tracks[index]
+  }
+
+  fn find(&self, artist: &str, title: &str, album: Option<&str>, duration: Option<f64>) -> Option<usize> {
+    let (artist, title, album) = (fold(artist), fold(title), album.map(fold));
+    let matches = self.tracks.iter().enumerate().filter(|(_, track)| {
+      let same_album = match (&album, &track.info.album) {
+        (Some(album), Some(tagged)) => fold(tagged) == *album,
+        _ => true,
+      };
+      fold(&track.info.artist) == artist && fold(&track.info.title) == title && same_album
+    });
+    let Some(duration) = duration else {
+      return matches.map(|(index, _)| index).next();
+    };
+    // Tracks whose length isn't known come after the ones that are close enough
+    let distance = |track: &Track| track.info.duration_secs.map(|secs| (secs as f64 - duration).abs());
+    matches
+      .filter(|(_, track)| distance(track).is_none_or(|distance| distance <= LENGTH_TOLERANCE.as_secs_f64()))
+      .min_by(|(_, a), (_, b)| {
+        let (a, b) = (distance(a).unwrap_or(f64::MAX), distance(b).unwrap_or(f64::MAX));
+        a.total_cmp(&b)
+      })
+      .map(|(index, _)| index)
+  }
+
+  pub fn lyrics(&self, audio_path: &Path) -> Option<Lyrics> {
+    karaoke::find(audio_path, &self.formats, &self.matcher)
+  }
+}
+
+// Tags as a player and a file may write them differently: case and runs of spaces aside
+fn fold(tag: &str) -> String {
+  tag.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Answers requests until the run is interrupted, LRCLIB's as well with `lrclib_api`.
+pub fn serve(server: &Server, library: &mut Library, lrclib_api: bool) {
+  while !interrupt::interrupted() {
+    match server.recv_timeout(POLL_INTERVAL) {
+      Ok(Some(request)) => {
+        let response = respond(library, &request, lrclib_api);
+        let status = response.status_code().0;
+        tracing::info!(method = %request.method(), url = request.url(), status, "request");
+        if let Err(error) = request.respond(response) {
+          tracing::warn!(%error, "couldn't send the response");
+        }
+      },
+      Ok(None) => {},
+      Err(error) => tracing::warn!(%error, "couldn't receive a request"),
+    }
+  }
+}
+
+type TextResponse = Response<Cursor<Vec<u8>>>;
+
+fn respond(library: &mut Library, request: &Request, lrclib_api: bool) -> TextResponse {
+  if !matches!(request.method(), Method::Get | Method::Head) {
+    return text(405, "only GET requests are answered\n".to_string());
+  }
+  let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+  let query = parse_query(query);
+  match path {
+    "/lyrics" => lyrics_response(library, &query),
+    "/api/get" | "/api/get-cached" if lrclib_api => lrclib_response(library, &query),
+    _ => text(404, "not found\n".to_string()),
+  }
+}
+
+fn lyrics_response(library: &mut Library, query: &HashMap<String, String>) -> TextResponse {
+  let plain = match query.get("format").map(String::as_str) {
+    None | Some("lrc") => false,
+    Some("plain") => true,
+    Some(format) => return text(400, format!("unknown format '{format}'; expected lrc or plain\n")),
+  };
+  let Ok(duration) = parse_duration(query) else {
+    return text(400, "duration should be a number of seconds\n".to_string());
+  };
+  let album = query.get("album").map(String::as_str);
+  let audio_path = match (query.get("path"), query.get("artist"), query.get("title")) {
+    (Some(path), _, _) => library.by_path(path),
+    (None, Some(artist), Some(title)) => {
+      library.by_tags(artist, title, album, duration).map(|index| library.track(index).path.clone())
+    },
+    _ => return text(400, "expected a path, or an artist and a title\n".to_string()),
+  };
+  let Some(audio_path) = audio_path else {
+    return text(404, "no such track\n".to_string());
+  };
+  let Some(lyrics) = library.lyrics(&audio_path) else {
+    return text(404, "no lyrics\n".to_string());
+  };
+  let body = if plain { lrc::to_plain_text(&lyrics.text) } else { lyrics.text };
+  text(200, body)
+    .with_header(header("X-Lyrics-Synced", if lyrics.synced && !plain { "true" } else { "false" }))
+    .with_header(header("X-Lyrics-Source", lyrics.source.name()))
+}
+
+// A track as LRCLIB describes one
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LrclibTrack<'t> {
+  id: usize,
+  track_name: &'t str,
+  artist_name: &'t str,
+  album_name: Option<&'t str>,
+  duration: Option<u64>,
+  instrumental: bool,
+  plain_lyrics: String,
+  synced_lyrics: Option<String>,
+}
+
+// Errors as LRCLIB words them
+#[derive(Serialize)]
+struct LrclibError {
+  code: u16,
+  name: &'static str,
+  message: String,
+}
+
+fn lrclib_response(library: &mut Library, query: &HashMap<String, String>) -> TextResponse {
+  let error = |code, name, message: &str| json(code, &LrclibError { code, name, message: message.to_string() });
+  let (Some(artist), Some(title)) = (query.get("artist_name"), query.get("track_name")) else {
+    return error(400, "QueryParamsError", "artist_name and track_name are required");
+  };
+  let Ok(duration) = parse_duration(query) else {
+    return error(400, "QueryParamsError", "duration should be a number of seconds");
+  };
+  let album = query.get("album_name").map(String::as_str);
+  let Some(index) = library.by_tags(artist, title, album, duration) else {
+    return error(404, "TrackNotFound", "Failed to find specified track");
+  };
+  let track = library.track(index);
+  let Some(lyrics) = library.lyrics(&track.path) else {
+    return error(404, "TrackNotFound", "Failed to find specified track");
+  };
+  let info = &track.info;
+  json(
+    200,
+    &LrclibTrack {
+      // The track's place in the library, which holds until it's read again
+      id: index + 1,
+      track_name: &info.title,
+      artist_name: &info.artist,
+      album_name: info.album.as_deref(),
+      duration: info.duration_secs,
+      instrumental: false,
+      plain_lyrics: lrc::to_plain_text(&lyrics.text),
+      synced_lyrics: lyrics.synced.then_some(lyrics.text),
+    },
+  )
+}
+
+// The `duration` parameter, in seconds if it's there
+fn parse_duration(query: &HashMap<String, String>) -> Result<Option<f64>, ()> {
+  match query.get("duration").map(|duration| duration.parse::<f64>()) {
+    None => Ok(None),
+    Some(Ok(duration)) if duration.is_finite() && duration >= 0.0 => Ok(Some(duration)),
+    Some(_) => Err(()),
+  }
+}
+
+fn json(status: u16, body: &impl Serialize) -> TextResponse {
+  let body = serde_json::to_string(body).expect("responses serialize");
+  Response::from_string(body).with_status_code(status).with_header(header("Content-Type", "application/json"))
+}
+
+fn text(status: u16, body: String) -> TextResponse {
+  Response::from_string(body).with_status_code(status).with_header(header("Content-Type", "text/plain; charset=utf-8"))
+}
+
+fn header(name: &str, value: &str) -> Header {
+  Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("header names and values are ASCII")
+}
+
+// The parameters of a query string, decoded
+fn parse_query(query: &str) -> HashMap<String, String> {
+  query
+    .split('&')
+    .filter(|pair| !pair.is_empty())
+    .map(|pair| {
+      let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+      (decode(name), decode(value))
+    })
+    .collect()
+}
+
+// Undoes form encoding: `+` for a space and `%XX` for any other byte
+fn decode(component: &str) -> String {
+  let bytes = component.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut index = 0;
+  while index < bytes.len() {
+    let hex =
+      bytes.get(index + 1..index + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+    match (bytes[index], hex) {
+      (b'%', Some(byte)) => {
+        decoded.push(byte);
+        index += 3;
+        continue;
+      },
+      (b'+', _) => decoded.push(b' '),
+      (byte, _) => decoded.push(byte),
+    }
+    index += 1;
+  }
+  String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,510 @@
+//! Batch embedding of LRC sidecars, one file at a time or on a pool of worker threads.
+
+use crate::backup::Backups;
+use crate::journal::{Journal, JournalEntry};
+use crate::matching::SidecarMatcher;
+use crate::{
+  AudioFormat, ConflictPolicy, EmbedOptions, FrameFilter, LrcError, Lyrics, Result, Romanization, Sidecar,
+  Translations, has_embedded_lyrics_matching, lrc, uses_id3v2,
+};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// What happened to one audio file in an [`EmbedSession`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmbedOutcome {
+  /// Its sidecars were embedded, or would have been in a dry run
+  Embedded,
+  Skipped(SkipReason),
+}
+
+/// Why an [`EmbedSession`] left a file alone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+  /// It has no LRC sidecar
+  NoSidecar,
+  /// It already had lyrics and the conflict policy kept them
+  HasLyrics,
+  /// Its embedded lyrics already match the sidecar, so writing would change nothing
+  Unchanged,
+  /// Its sidecar was only a loose match, and the loose match handler turned it down
+  Declined,
+}
+
+/// Progress reported by an [`EmbedSession`] as it works through a file.
+///
+/// Every file gets a `Started` event and then exactly one of `Skipped`, `Embedded` or `Failed`, with
+/// `Matched` in between once its sidecars are found.
+#[derive(Debug)]
+pub enum EmbedEvent<'e> {
+  Started {
+    path: &'e Path,
+  },
+  Matched {
+    path: &'e Path,
+    sidecars: &'e [Sidecar],
+  },
+  Skipped {
+    path: &'e Path,
+    reason: SkipReason,
+  },
+  /// Also sent in a dry run, where nothing is actually written
+  Embedded {
+    path: &'e Path,
+  },
+  Failed {
+    path: &'e Path,
+    error: &'e LrcError,
+  },
+}
+
+/// The outcome of embedding into one audio file.
+#[derive(Debug)]
+pub struct FileResult {
+  pub path: PathBuf,
+  pub outcome: Result<EmbedOutcome>,
+  /// How long the file took, including any time spent waiting on a conflict prompt
+  pub elapsed: Duration,
+}
+
+// Decides whether to overwrite the lyrics already embedded in a file. Handlers sit behind a mutex
+// so parallel runs call them one at a time, which also keeps conflict prompts from overlapping.
+type ConflictHandler<'a> = Mutex<Box<dyn FnMut(&Path) -> bool + Send + 'a>>;
+type LooseMatchHandler<'a> = Mutex<Box<dyn FnMut(&Path, &Sidecar) -> bool + Send + 'a>>;
+type EventHandler<'a> = Mutex<Box<dyn FnMut(&EmbedEvent<'_>) + Send + 'a>>;
+
+/// Embeds the LRC sidecars of audio files, following the conflict policy of its options.
+pub struct EmbedSession<'a> {
+  options: EmbedOptions,
+  directory_options: Vec<(PathBuf, EmbedOptions)>,
+  matcher: SidecarMatcher,
+  // Keyed by canonical audio path
+  file_offsets: HashMap<PathBuf, i64>,
+  reduce: bool,
+  normalize_sidecars: bool,
+  reject_invalid: bool,
+  dry_run: bool,
+  rewrite_unchanged: bool,
+  backups: Option<&'a Backups>,
+  journal: Option<&'a Journal>,
+  stop: Option<&'a AtomicBool>,
+  conflict_handler: Option<ConflictHandler<'a>>,
+  loose_match_handler: Option<LooseMatchHandler<'a>>,
+  event_handler: Option<EventHandler<'a>>,
+}
+
+impl<'a> EmbedSession<'a> {
+  pub fn new(options: EmbedOptions) -> Self {
+    EmbedSession {
+      options,
+      directory_options: Vec::new(),
+      matcher: SidecarMatcher::default(),
+      file_offsets: HashMap::new(),
+      reduce: false,
+      normalize_sidecars: false,
+      reject_invalid: false,
+      dry_run: false,
+      rewrite_unchanged: false,
+      backups: None,
+      journal: None,
+      stop: None,
+      conflict_handler: None,
+      loose_match_handler: None,
+      event_handler: None,
+    }
+  }
+
+  /// Finds each file's sidecars with `matcher`, rather than only the ones named like the file.
+  pub fn matcher(mut self, matcher: SidecarMatcher) -> Self {
+    self.matcher = matcher;
+    self
+  }
+
+  /// Shift the sidecars of these audio files by their own number of milliseconds, rather than the
+  /// options' `offset_ms`.
+  pub fn file_offsets(mut self, offsets: impl IntoIterator<Item = (PathBuf, i64)>) -> Self {
+    self.file_offsets.extend(
+      offsets
+        .into_iter()
+        .map(|(audio_path, offset_ms)| (fs::canonicalize(&audio_path).unwrap_or(audio_path), offset_ms)),
+    );
+    self
+  }
+
+  /// Delete sidecars once they're embedded.
+  pub fn reduce(mut self, reduce: bool) -> Self {
+    self.reduce = reduce;
+    self
+  }
+
+  /// Write sidecars back as the text they're embedded as, UTF-8 and [normalized](crate::lrc::normalize),
+  /// once they're embedded or found to be already. Reducing wins, deleting them instead.
+  pub fn normalize_sidecars(mut self, normalize_sidecars: bool) -> Self {
+    self.normalize_sidecars = normalize_sidecars;
+    self
+  }
+
+  /// Fail files whose sidecars don't pass [validation](crate::lrc::validate), rather than embed
+  /// whatever they hold.
+  pub fn reject_invalid(mut self, reject_invalid: bool) -> Self {
+    self.reject_invalid = reject_invalid;
+    self
+  }
+
+  /// Go through every check without writing or deleting anything.
+  pub fn dry_run(mut self, dry_run: bool) -> Self {
+    self.dry_run = dry_run;
+    self
+  }
+
+  /// Write files whose embedded lyrics already match their sidecar, e.g. to apply new tag options.
+  /// Otherwise they're skipped, leaving their modification time alone.
+  pub fn rewrite_unchanged(mut self, rewrite_unchanged: bool) -> Self {
+    self.rewrite_unchanged = rewrite_unchanged;
+    self
+  }
+
+  /// Copy each file into `backups` before writing to it. A file that can't be backed up is left
+  /// alone and fails.
+  pub fn backups(mut self, backups: &'a Backups) -> Self {
+    self.backups = Some(backups);
+    self
+  }
+
+  /// Record each change in `journal` before making it, so it can be undone.
+  pub fn journal(mut self, journal: &'a Journal) -> Self {
+    self.journal = Some(journal);
+    self
+  }
+
+  /// Stop starting new files once `stop` is set, e.g. by a signal handler. Files already under way
+  /// are finished, but keep their sidecars even when reducing.
+  pub fn stop_on(mut self, stop: &'a AtomicBool) -> Self {
+    self.stop = Some(stop);
+    self
+  }
+
+  /// Decides, under [`ConflictPolicy::Ask`], whether to overwrite the lyrics already in a file.
+  pub fn on_conflict(mut self, handler: impl FnMut(&Path) -> bool + Send + 'a) -> Self {
+    self.conflict_handler = Some(Mutex::new(Box::new(handler)));
+    self
+  }
+
+  /// Decides whether to embed a sidecar the matcher is less than sure of, i.e. one not named like
+  /// the audio file or paired with it. Without a handler, loose matches are embedded.
+  pub fn on_loose_match(mut self, handler: impl FnMut(&Path, &Sidecar) -> bool + Send + 'a) -> Self {
+    self.loose_match_handler = Some(Mutex::new(Box::new(handler)));
+    self
+  }
+
+  /// Calls `handler` with each [`EmbedEvent`] as files are processed.
+  pub fn on_event(mut self, handler: impl FnMut(&EmbedEvent<'_>) + Send + 'a) -> Self {
+    self.event_handler = Some(Mutex::new(Box::new(handler)));
+    self
+  }
+
+  /// Embeds the files under each directory with its own options instead, those of the deepest
+  /// directory holding a file winning.
+  pub fn directory_options(mut self, options: Vec<(PathBuf, EmbedOptions)>) -> Self {
+    self.directory_options = options;
+    self
+  }
+
+  pub fn options(&self) -> &EmbedOptions {
+    &self.options
+  }
+
+  /// The options `audio_path` is embedded with.
+  pub fn options_for(&self, audio_path: &Path) -> &EmbedOptions {
+    self
+      .directory_options
+      .iter()
+      .filter(|(directory, _)| audio_path.starts_with(directory))
+      .max_by_key(|(directory, _)| directory.components().count())
+      .map_or(&self.options, |(_, options)| options)
+  }
+
+  // The mapping's offset for the file, if it gives one
+  fn offset_for(&self, audio_path: &Path) -> Option<i64> {
+    if self.file_offsets.is_empty() {
+      return None;
+    }
+    let audio_path = fs::canonicalize(audio_path).unwrap_or_else(|_| audio_path.to_path_buf());
+    self.file_offsets.get(&audio_path).copied()
+  }
+
+  /// Embeds into each of `paths` in turn, yielding their results as it goes.
+  pub fn run<I>(&mut self, paths: I) -> impl Iterator<Item = FileResult>
+  where
+    I: IntoIterator<Item = PathBuf>,
+  {
+    paths.into_iter().map_while(|path| {
+      if self.stopped() {
+        return None;
+      }
+      let started = Instant::now();
+      let outcome = self.embed_file(&path);
+      Some(FileResult { path, outcome, elapsed: started.elapsed() })
+    })
+  }
+
+  /// Embeds into `paths` on `jobs` worker threads, or one per CPU core if `jobs` is 0, calling
+  /// `on_result` with each file as it finishes. Files finish in no particular order, except with a
+  /// single job, which works through them in turn on the calling thread like [`EmbedSession::run`].
+  /// Files not started before the session was stopped get no result.
+  pub fn run_parallel<F>(&self, paths: Vec<PathBuf>, jobs: usize, on_result: F) -> Result<()>
+  where
+    F: Fn(FileResult) + Sync,
+  {
+    let embed = |path: PathBuf| {
+      if self.stopped() {
+        return;
+      }
+      let started = Instant::now();
+      let outcome = self.embed_shared(&path);
+      on_result(FileResult { path, outcome, elapsed: started.elapsed() });
+    };
+
+    if jobs == 1 {
+      paths.into_iter().for_each(embed);
+      return Ok(());
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build().map_err(std::io::Error::other)?;
+    pool.install(|| paths.into_par_iter().for_each(embed));
+    Ok(())
+  }
+
+  /// Embeds the sidecars of a single audio file.
+  pub fn embed_file(&mut self, audio_path: &Path) -> Result<EmbedOutcome> {
+    self.embed_shared(audio_path)
+  }
+
+  fn embed_shared(&self, audio_path: &Path) -> Result<EmbedOutcome> {
+    self.emit(EmbedEvent::Started { path: audio_path });
+    let span = tracing::info_span!("file", path = %audio_path.display());
+    let outcome = span.in_scope(|| self.embed_sidecars(audio_path));
+    match &outcome {
+      Ok(EmbedOutcome::Embedded) => self.emit(EmbedEvent::Embedded { path: audio_path }),
+      Ok(EmbedOutcome::Skipped(reason)) => self.emit(EmbedEvent::Skipped { path: audio_path, reason: *reason }),
+      Err(error) => self.emit(EmbedEvent::Failed { path: audio_path, error }),
+    }
+    outcome
+  }
+
+  fn embed_sidecars(&self, audio_path: &Path) -> Result<EmbedOutcome> {
+    let match_span = tracing::info_span!("match").entered();
+    let sidecars = self.matcher.find(audio_path, &self.options_for(audio_path).formats);
+    if sidecars.is_empty() {
+      tracing::info!(expected = %crate::lrc_sidecar_path(audio_path).display(), "skipped, no LRC sidecar");
+      return Ok(EmbedOutcome::Skipped(SkipReason::NoSidecar));
+    }
+    tracing::debug!(count = sidecars.len(), "found LRC sidecars");
+    for sidecar in &sidecars {
+      let language = sidecar.language.as_ref().map(|code| String::from_utf8_lossy(code).into_owned());
+      tracing::debug!(
+        sidecar = %sidecar.path.display(),
+        language = language.as_deref().unwrap_or("none"),
+        confidence = sidecar.confidence,
+        "matched"
+      );
+    }
+    self.emit(EmbedEvent::Matched { path: audio_path, sidecars: &sidecars });
+    if let (Some(handler), [sidecar, ..]) = (&self.loose_match_handler, sidecars.as_slice())
+      && sidecar.confidence < 1.0
+      && !(handler.lock().unwrap_or_else(PoisonError::into_inner))(audio_path, sidecar)
+    {
+      tracing::info!("skipped, the loose match was declined");
+      return Ok(EmbedOutcome::Skipped(SkipReason::Declined));
+    }
+    drop(match_span);
+
+    let options = self.options_for(audio_path);
+    // Taken before probing or comparing reads the file and updates its access time
+    let times = if options.preserve_mtime { Some(crate::file_times(audio_path)?) } else { None };
+    let format = options.formats.resolve(audio_path)?;
+    let (embedded, translations) = match (options.translations, sidecars.split_first()) {
+      (Translations::Merge, Some((original, translations))) if original.language.is_none() => {
+        (std::slice::from_ref(original), translations)
+      },
+      _ => (embedded_sidecars(format, &sidecars), &[][..]),
+    };
+    if embedded.len() + translations.len() < sidecars.len() {
+      tracing::debug!("{} tags have no language labels, so only the first sidecar is embedded", format.tag_type());
+    }
+    let offset_ms = self.offset_for(audio_path).unwrap_or(options.offset_ms);
+    // Translations being merged are read along with the sidecars they're merged into
+    let read: Vec<Sidecar> = embedded.iter().chain(translations).cloned().collect();
+    let texts = tracing::info_span!("read").in_scope(|| {
+      read
+        .iter()
+        .map(|sidecar| Ok(options.prepare(&sidecar.path, &crate::read_sidecar(&sidecar.path)?, offset_ms)))
+        .collect::<Result<Vec<_>>>()
+    })?;
+    if self.reject_invalid {
+      // Plain lyrics are untimed by design
+      for (sidecar, text) in read.iter().zip(&texts).filter(|(sidecar, _)| !crate::is_plain_text(&sidecar.path)) {
+        crate::check_lrc(&sidecar.path, text)?;
+      }
+    }
+    let (own, translated) = texts.split_at(embedded.len());
+    let lyrics: Vec<Lyrics> = embedded
+      .iter()
+      .zip(own)
+      .enumerate()
+      .map(|(index, (sidecar, text))| {
+        // Only the original is romanized, before any translation is merged into it
+        let romanized = (index == 0 && options.romanization == Some(Romanization::Merge))
+          .then(|| crate::romanize::romanize(text))
+          .flatten();
+        let text = romanized.map_or_else(|| text.clone(), |romanized| lrc::merge_translation(text, &romanized));
+        let text = translated.iter().fold(text, |text, translation| lrc::merge_translation(&text, translation));
+        Lyrics { language: sidecar.language, text }
+      })
+      .collect();
+
+    // Checked before the conflict policy, so nobody is asked about a file that wouldn't change
+    if !self.rewrite_unchanged && is_unchanged(audio_path, &lyrics, options) {
+      tracing::info!("skipped, the embedded lyrics already match");
+      self.normalize(&read)?;
+      return Ok(EmbedOutcome::Skipped(SkipReason::Unchanged));
+    }
+
+    if !tracing::info_span!("match").in_scope(|| self.should_embed(audio_path, &sidecars, embedded, options)) {
+      tracing::info!("skipped, the file already has lyrics");
+      return Ok(EmbedOutcome::Skipped(SkipReason::HasLyrics));
+    }
+
+    if self.dry_run {
+      tracing::info!("would embed {} sidecar(s)", read.len());
+      return Ok(EmbedOutcome::Embedded);
+    }
+
+    if let Some(backups) = self.backups {
+      backups.save(audio_path)?;
+    }
+    if let Some(journal) = self.journal {
+      journal.record_lyrics(audio_path, &options.formats)?;
+    }
+    crate::write_lyrics(audio_path, &lyrics, options, times)?;
+    tracing::info!("embedded {} sidecar(s)", read.len());
+    self.normalize(&read)?;
+
+    // Deleting sidecars can wait for a run that isn't being interrupted
+    if self.reduce && !self.stopped() {
+      for sidecar in &read {
+        let in_file = |error: std::io::Error| LrcError::from(error).in_file(&sidecar.path);
+        if let Some(journal) = self.journal {
+          // The file as it was, not the lyrics made of it, so undo brings back its tags and encoding
+          let content = fs::read(&sidecar.path).map_err(in_file)?;
+          journal.record(&JournalEntry::SidecarDeleted { path: sidecar.path.clone(), content })?;
+        }
+        fs::remove_file(&sidecar.path).map_err(in_file)?;
+      }
+    }
+
+    Ok(EmbedOutcome::Embedded)
+  }
+
+  // Writes sidecars whose lyrics are in the file back as UTF-8 and normalized, when normalizing and
+  // that isn't what they hold already. Their offset tags are kept, so they still say what they did.
+  fn normalize(&self, sidecars: &[Sidecar]) -> Result<()> {
+    if !self.normalize_sidecars || self.reduce || self.dry_run {
+      return Ok(());
+    }
+    for sidecar in sidecars {
+      let in_file = |error: std::io::Error| LrcError::from(error).in_file(&sidecar.path);
+      let content = fs::read(&sidecar.path).map_err(in_file)?;
+      let text = crate::lrc::normalize(&crate::lrc::decode(&content).0);
+      if content == text.as_bytes() {
+        continue;
+      }
+      if let Some(journal) = self.journal {
+        journal.record(&JournalEntry::SidecarRewritten { path: sidecar.path.clone(), content })?;
+      }
+      fs::write(&sidecar.path, text).map_err(in_file)?;
+      tracing::info!(sidecar = %sidecar.path.display(), "normalized the sidecar");
+    }
+    Ok(())
+  }
+
+  fn stopped(&self) -> bool {
+    self.stop.is_some_and(|stop| stop.load(Ordering::Relaxed))
+  }
+
+  fn emit(&self, event: EmbedEvent<'_>) {
+    if let Some(handler) = &self.event_handler {
+      (handler.lock().unwrap_or_else(PoisonError::into_inner))(&event);
+    }
+  }
+
+  // Only the languages of the `embedded` sidecars need frames of their own, but any of the
+  // `sidecars` being newer counts
+  fn should_embed(
+    &self,
+    audio_path: &Path,
+    sidecars: &[Sidecar],
+    embedded: &[Sidecar],
+    options: &EmbedOptions,
+  ) -> bool {
+    if options.conflict == ConflictPolicy::Overwrite {
+      return true;
+    }
+
+    let languages: Vec<[u8; 3]> = embedded.iter().filter_map(|sidecar| sidecar.language).collect();
+    let filter = FrameFilter { description: options.description.as_deref(), languages: &languages };
+    // A file that can't be read fails when embedding, which reports the error
+    if !has_embedded_lyrics_matching(audio_path, &options.formats, &filter).unwrap_or(false) {
+      return true;
+    }
+
+    tracing::debug!(policy = ?options.conflict, "the file already has lyrics");
+    match options.conflict {
+      ConflictPolicy::Overwrite => true,
+      ConflictPolicy::Skip => false,
+      ConflictPolicy::IfNewer => sidecar_is_newer(audio_path, sidecars),
+      ConflictPolicy::Ask => self
+        .conflict_handler
+        .as_ref()
+        .is_some_and(|handler| (handler.lock().unwrap_or_else(PoisonError::into_inner))(audio_path)),
+    }
+  }
+}
+
+// Only a single version can be compared, as files with several languages read back as one of them.
+// Other tag options aren't compared; `rewrite_unchanged` applies those.
+fn is_unchanged(audio_path: &Path, lyrics: &[Lyrics], options: &EmbedOptions) -> bool {
+  let [lyrics] = lyrics else {
+    return false;
+  };
+  // The frames being written need to be there, not just the same text under another label
+  let languages: Vec<[u8; 3]> = lyrics.language.or(options.language).into_iter().collect();
+  let filter = FrameFilter { description: options.description.as_deref(), languages: &languages };
+  // A file that can't be read fails when embedding, which reports the error
+  has_embedded_lyrics_matching(audio_path, &options.formats, &filter).unwrap_or(false)
+    && crate::extract_lyrics(audio_path, &options.formats)
+      .ok()
+      .flatten()
+      .is_some_and(|embedded| crate::lyrics_match(&embedded, &lyrics.text))
+}
+
+// Only ID3v2 frames are labeled with a language, so other formats take the first sidecar alone
+fn embedded_sidecars(format: AudioFormat, sidecars: &[Sidecar]) -> &[Sidecar] {
+  if uses_id3v2(format) { sidecars } else { &sidecars[..sidecars.len().min(1)] }
+}
+
+fn sidecar_is_newer(audio_path: &Path, sidecars: &[Sidecar]) -> bool {
+  let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+  let Some(audio_modified) = modified(audio_path) else {
+    return false;
+  };
+  sidecars
+    .iter()
+    .filter_map(|sidecar| modified(&sidecar.path))
+    .any(|sidecar_modified| sidecar_modified > audio_modified)
+}
@@ -0,0 +1,169 @@
+//! `lyricsync tap`: times plain lyrics by hand. The track plays with its lines on screen, and a key
+//! pressed as each line starts stamps it with the time, which makes a synced LRC file out of lyrics
+//! that had none.
+
+use crate::play::Playback;
+use lyricsync::lrc::{self, TimedLine};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::io;
+use std::time::Duration;
+
+// How often the clock on screen is brought up to date
+const TICK: Duration = Duration::from_millis(50);
+
+// How far back undoing a stamp goes from it, to hear the line start again
+const REWIND: Duration = Duration::from_secs(3);
+
+// How far the arrow keys move through the track
+const SKIP: Duration = Duration::from_secs(5);
+
+/// The lines being timed and the stamps given so far.
+pub struct Tapping {
+  lines: Vec<String>,
+  // When each stamp was given, and the line it starts or `None` for a break between lines
+  stamps: Vec<(u32, Option<usize>)>,
+}
+
+impl Tapping {
+  /// The lines of `lyrics` to time, without blank lines or any timestamps they already have.
+  pub fn new(lyrics: &str) -> Self {
+    let text = lrc::to_plain_text(lyrics);
+    let lines = text.lines().map(str::trim).filter(|line| !line.is_empty()).map(String::from).collect();
+    Tapping { lines, stamps: Vec::new() }
+  }
+
+  pub fn lines(&self) -> usize {
+    self.lines.len()
+  }
+
+  /// The lines with the times they were stamped at, and a blank line for each break.
+  pub fn to_lrc(&self) -> String {
+    let lines: Vec<TimedLine> = self
+      .stamps
+      .iter()
+      .map(|&(time_ms, line)| TimedLine {
+        time_ms,
+        text: line.map_or_else(String::new, |line| self.lines[line].clone()),
+      })
+      .collect();
+    lrc::format_timed_lines(&lines)
+  }
+
+  // The line the next stamp is for
+  fn next(&self) -> usize {
+    self.stamps.iter().filter(|(_, line)| line.is_some()).count()
+  }
+
+  fn done(&self) -> bool {
+    self.next() == self.lines.len()
+  }
+
+  fn stamp(&mut self, time_ms: u32) {
+    if !self.done() {
+      self.stamps.push((time_ms, Some(self.next())));
+    }
+  }
+
+  // Ends the line being sung without starting the next, for an instrumental part
+  fn pause(&mut self, time_ms: u32) {
+    if self.stamps.last().is_some_and(|(_, line)| line.is_some()) && !self.done() {
+      self.stamps.push((time_ms, None));
+    }
+  }
+
+  // Takes back the last stamp, giving its time
+  fn undo(&mut self) -> Option<u32> {
+    self.stamps.pop().map(|(time_ms, _)| time_ms)
+  }
+}
+
+/// Plays the track through `playback` with the lines of `tapping` on screen, until they're all
+/// timed and saved with Enter, or given up with `q`. The LRC is given back when saved.
+pub fn tap(title: &str, tapping: &mut Tapping, playback: &mut Playback) -> io::Result<Option<String>> {
+  let mut terminal = ratatui::try_init()?;
+  let saved = run(&mut terminal, title, tapping, playback);
+  ratatui::restore();
+  Ok(saved?.then(|| tapping.to_lrc()))
+}
+
+// Takes keystrokes until the lyrics are saved (true) or given up (false)
+fn run(
+  terminal: &mut DefaultTerminal,
+  title: &str,
+  tapping: &mut Tapping,
+  playback: &mut Playback,
+) -> io::Result<bool> {
+  let mut state = ListState::default();
+  loop {
+    terminal.draw(|frame| draw(frame, title, tapping, playback, &mut state))?;
+    if !event::poll(TICK)? {
+      continue;
+    }
+    let Event::Key(key) = event::read()? else {
+      continue;
+    };
+    if key.kind != KeyEventKind::Press {
+      continue;
+    }
+    let elapsed = playback.elapsed();
+    let elapsed_ms = u32::try_from(elapsed.as_millis()).unwrap_or(u32::MAX);
+    match key.code {
+      KeyCode::Char(' ') => tapping.stamp(elapsed_ms),
+      KeyCode::Char('b') => tapping.pause(elapsed_ms),
+      KeyCode::Backspace | KeyCode::Char('u') => {
+        if let Some(time_ms) = tapping.undo() {
+          playback.seek(Duration::from_millis(u64::from(time_ms)).saturating_sub(REWIND));
+        }
+      },
+      KeyCode::Char('p') => playback.toggle_pause(),
+      KeyCode::Left => playback.seek(elapsed.saturating_sub(SKIP)),
+      KeyCode::Right => playback.seek(elapsed + SKIP),
+      KeyCode::Enter if tapping.done() => return Ok(true),
+      KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+      _ => {},
+    }
+  }
+}
+
+fn draw(frame: &mut Frame, title: &str, tapping: &Tapping, playback: &Playback, state: &mut ListState) {
+  let [list, help] = Layout::vertical([Constraint::Min(3), Constraint::Length(2)]).areas(frame.area());
+  let stamp = |time_ms: u32| lrc::format_timestamp(time_ms);
+  let mut items: Vec<ListItem> = tapping
+    .stamps
+    .iter()
+    .map(|&(time_ms, line)| match line {
+      Some(line) => ListItem::new(format!("{} {}", stamp(time_ms), tapping.lines[line])),
+      None => ListItem::new(format!("{} (break)", stamp(time_ms))).style(Style::new().dim()),
+    })
+    .collect();
+  // The line to stamp next is highlighted, with the ones to come after it
+  let next = items.len();
+  items.extend(tapping.lines[tapping.next()..].iter().map(|line| ListItem::new(format!("{:10} {line}", ""))));
+  state.select((!tapping.done()).then_some(next));
+
+  let elapsed_ms = u32::try_from(playback.elapsed().as_millis()).unwrap_or(u32::MAX);
+  let clock = match (playback.paused(), playback.finished()) {
+    (true, _) => format!(" {} paused ", stamp(elapsed_ms)),
+    (false, true) => format!(" {} ended ", stamp(elapsed_ms)),
+    (false, false) => format!(" {} ", stamp(elapsed_ms)),
+  };
+  let block = Block::bordered()
+    .title(format!(" Tap: {title} "))
+    .title_bottom(Line::from(clock).right_aligned())
+    .title_bottom(format!(" {}/{} lines ", tapping.next(), tapping.lines.len()));
+  let list_widget = List::new(items).block(block).highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+  frame.render_stateful_widget(list_widget, list, state);
+
+  let status = if tapping.done() {
+    "Every line is timed: Enter saves the LRC file, u takes back the last stamp"
+  } else {
+    "Press space as the highlighted line starts"
+  };
+  let keys = "space stamp  b break  u undo  p pause  ←/→ 5s  Enter save  q quit";
+  frame.render_widget(Paragraph::new(vec![Line::from(status), Line::from(keys).style(Style::new().dim())]), help);
+}
@@ -0,0 +1,102 @@
+//! Track metadata used to look up lyrics online.
+
+use crate::asf::{AsfFile, ContentDescriptor};
+use crate::dsd::DsdFile;
+use crate::{AudioFormat, Result, dsd_kind};
+use lofty::{file::FileType, file::TaggedFileExt, prelude::AudioFile, probe::Probe, tag::Accessor};
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::time::Duration;
+
+/// The tags a lyrics provider needs to identify a track.
+pub struct TrackInfo {
+  pub artist: String,
+  pub title: String,
+  pub album: Option<String>,
+  pub duration_secs: Option<u64>,
+}
+
+/// Reads artist, title, album and duration from the file's tags.
+///
+/// Returns `None` when the artist or title is missing, since no provider can match without them.
+pub fn read_track_info(audio_path: &Path, format: AudioFormat) -> Result<Option<TrackInfo>> {
+  let file_type = match lofty_file_type(format) {
+    Some(file_type) => file_type,
+    None if format == AudioFormat::Wma => return read_asf_track_info(audio_path),
+    None => {
+      let mut file_content = OpenOptions::new().read(true).open(audio_path)?;
+      let dsd_file = DsdFile::read_from(&mut file_content, dsd_kind(format))?;
+      let Some(id3v2) = dsd_file.id3v2() else {
+        return Ok(None);
+      };
+      return Ok(track_info(
+        id3v2.artist().map(|artist| artist.to_string()),
+        id3v2.title().map(|title| title.to_string()),
+        id3v2.album().map(|album| album.to_string()),
+        None,
+      ));
+    },
+  };
+
+  let tagged_file = Probe::open(audio_path)?.set_file_type(file_type).read()?;
+  let Some(tag) = tagged_file.primary_tag().or_else(|| tagged_file.first_tag()) else {
+    return Ok(None);
+  };
+
+  let duration = tagged_file.properties().duration().as_secs();
+  Ok(track_info(
+    tag.artist().map(|artist| artist.to_string()),
+    tag.title().map(|title| title.to_string()),
+    tag.album().map(|album| album.to_string()),
+    (duration > 0).then_some(duration),
+  ))
+}
+
+/// Reads how long the track plays, or `None` for formats whose length isn't read (WMA and DSD).
+pub fn read_duration(audio_path: &Path, format: AudioFormat) -> Result<Option<Duration>> {
+  let Some(file_type) = lofty_file_type(format) else {
+    return Ok(None);
+  };
+  let tagged_file = Probe::open(audio_path)?.set_file_type(file_type).read()?;
+  let duration = tagged_file.properties().duration();
+  Ok((!duration.is_zero()).then_some(duration))
+}
+
+// The formats lofty reads; WMA and DSD have readers of their own
+fn lofty_file_type(format: AudioFormat) -> Option<FileType> {
+  match format {
+    AudioFormat::Flac => Some(FileType::Flac),
+    AudioFormat::Mp3 => Some(FileType::Mpeg),
+    AudioFormat::M4a => Some(FileType::Mp4),
+    AudioFormat::Opus => Some(FileType::Opus),
+    AudioFormat::Wav => Some(FileType::Wav),
+    AudioFormat::Aiff => Some(FileType::Aiff),
+    AudioFormat::Ape => Some(FileType::Ape),
+    AudioFormat::WavPack => Some(FileType::WavPack),
+    AudioFormat::Aac => Some(FileType::Aac),
+    AudioFormat::Wma | AudioFormat::Dsf | AudioFormat::Dff => None,
+  }
+}
+
+fn read_asf_track_info(audio_path: &Path) -> Result<Option<TrackInfo>> {
+  let mut file_content = OpenOptions::new().read(true).open(audio_path)?;
+  let asf_file = AsfFile::read_from(&mut file_content)?;
+
+  let (title, author) = asf_file.content_description().unwrap_or_default();
+  let artist = Some(author)
+    .filter(|author| !author.is_empty())
+    .or_else(|| asf_file.attribute("WM/AlbumArtist").and_then(ContentDescriptor::as_text));
+  let album = asf_file.attribute("WM/AlbumTitle").and_then(ContentDescriptor::as_text);
+
+  Ok(track_info(artist, Some(title), album, None))
+}
+
+fn track_info(
+  artist: Option<String>,
+  title: Option<String>,
+  album: Option<String>,
+  duration_secs: Option<u64>,
+) -> Option<TrackInfo> {
+  let non_empty = |value: Option<String>| value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+  Some(TrackInfo { artist: non_empty(artist)?, title: non_empty(title)?, album: non_empty(album), duration_secs })
+}
@@ -0,0 +1,114 @@
+//! Lyrics transcribed from the audio itself, for tracks no provider has lyrics for, like obscure
+//! live recordings. In builds with the `transcribe` feature, whisper.cpp listens to the track with
+//! a model of the user's choosing and each segment it hears becomes a line timed at its start.
+//!
+//! whisper.cpp is run as its `whisper-cli` program rather than linked in, so lyricsync builds
+//! without its C++ toolchain and uses whichever build of it (CPU, CUDA, Metal) is installed. It
+//! reads WAV, MP3 and FLAC itself; other formats are decoded to WAV by `ffmpeg` first.
+
+use crate::Result;
+#[cfg(feature = "transcribe")]
+use crate::{LrcError, lrc};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "transcribe")]
+use std::{fs, process::Command};
+
+/// Whether this build can transcribe lyrics at all.
+pub const AVAILABLE: bool = cfg!(feature = "transcribe");
+
+/// The header transcribed lyrics start with, so they can be told from lyrics someone wrote. `re` is
+/// the LRC tag for the program that made a file.
+pub const PROVENANCE: &str = "[re:whisper.cpp transcription]";
+
+/// The program whisper.cpp installs, run when no other is given.
+pub const DEFAULT_PROGRAM: &str = "whisper-cli";
+
+// The formats whisper.cpp decodes without ffmpeg
+#[cfg(feature = "transcribe")]
+const READABLE_EXTENSIONS: [&str; 3] = ["wav", "mp3", "flac"];
+
+/// A whisper.cpp program and the model it transcribes with, a `ggml-*.bin` file.
+#[cfg_attr(not(feature = "transcribe"), allow(dead_code))]
+pub struct Transcriber {
+  program: PathBuf,
+  model: PathBuf,
+}
+
+impl Transcriber {
+  pub fn new(program: impl Into<PathBuf>, model: impl Into<PathBuf>) -> Self {
+    Self { program: program.into(), model: model.into() }
+  }
+
+  /// Transcribes the track at `audio_path` into LRC headed by [`PROVENANCE`], with the language
+  /// left for whisper to tell. Segments that are only a noise tag like `[Music]` or `(applause)`
+  /// are left out, and `None` is returned when nothing else was heard, as with instrumentals.
+  #[cfg(feature = "transcribe")]
+  pub fn transcribe(&self, audio_path: &Path) -> Result<Option<String>> {
+    let scratch = tempfile::tempdir()?;
+    let readable = audio_path
+      .extension()
+      .and_then(|extension| extension.to_str())
+      .is_some_and(|extension| READABLE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()));
+    let input = if readable { audio_path.to_path_buf() } else { decode(audio_path, scratch.path())? };
+
+    // whisper-cli adds the extension to the name it's given
+    let output = scratch.path().join("transcript");
+    let mut command = Command::new(&self.program);
+    command.arg("--model").arg(&self.model).arg("--file").arg(&input);
+    command.args(["--language", "auto", "--output-lrc", "--no-prints", "--output-file"]).arg(&output);
+    run(&mut command, audio_path)?;
+
+    let path = output.with_extension("lrc");
+    let transcript = fs::read_to_string(&path).map_err(|source| LrcError::FileIo { path, source })?;
+    Ok(tidy(&transcript))
+  }
+
+  /// Always `None`: this build can't transcribe.
+  #[cfg(not(feature = "transcribe"))]
+  pub fn transcribe(&self, _audio_path: &Path) -> Result<Option<String>> {
+    Ok(None)
+  }
+}
+
+// Decodes `audio_path` to the 16 kHz mono WAV whisper listens to, in `scratch`
+#[cfg(feature = "transcribe")]
+fn decode(audio_path: &Path, scratch: &Path) -> Result<PathBuf> {
+  let wav = scratch.join("audio.wav");
+  let mut command = Command::new("ffmpeg");
+  command.args(["-nostdin", "-loglevel", "error", "-i"]).arg(audio_path);
+  command.args(["-ar", "16000", "-ac", "1"]).arg(&wav);
+  run(&mut command, audio_path)?;
+  Ok(wav)
+}
+
+// Runs a step of transcribing `audio_path`, failing with the last thing it printed on stderr
+#[cfg(feature = "transcribe")]
+fn run(command: &mut Command, audio_path: &Path) -> Result<()> {
+  let program = command.get_program().to_string_lossy().into_owned();
+  let failed = |reason: String| LrcError::Transcription { path: audio_path.to_path_buf(), reason };
+  let output = command.output().map_err(|source| failed(format!("couldn't run {program}: {source}")))?;
+  if output.status.success() {
+    return Ok(());
+  }
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  let message = stderr.lines().rev().map(str::trim).find(|line| !line.is_empty());
+  Err(failed(format!("{program} failed: {}", message.map_or_else(|| output.status.to_string(), str::to_string))))
+}
+
+// whisper's LRC as lyrics, with its own headers taken out
+#[cfg(feature = "transcribe")]
+fn tidy(transcript: &str) -> Option<String> {
+  let lines: Vec<lrc::TimedLine> = lrc::parse_timed_lines(transcript)
+    .into_iter()
+    .map(|line| lrc::TimedLine { text: line.text.trim().to_string(), ..line })
+    .filter(|line| !is_noise(&line.text))
+    .collect();
+  (!lines.is_empty()).then(|| format!("{PROVENANCE}\n{}", lrc::format_timed_lines(&lines)))
+}
+
+// What whisper writes for what isn't words: `[BLANK_AUDIO]`, `(upbeat music)`, a line of `♪`
+#[cfg(feature = "transcribe")]
+fn is_noise(text: &str) -> bool {
+  let tag = |open: char, close: char| text.starts_with(open) && text.ends_with(close);
+  tag('[', ']') || tag('(', ')') || !text.chars().any(char::is_alphanumeric)
+}
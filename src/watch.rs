@@ -0,0 +1,184 @@
+//! `lyricsync watch`: notices audio and LRC files turning up in a directory and hands them over
+//! once they've been left alone for a while, so files a downloader drops in are embedded without
+//! rescanning the library.
+//!
+//! `lyricsync daemon` watches the same way and goes through the whole directory on a schedule as
+//! well, for changes no event was seen for: ones made while it wasn't running, or over a network
+//! share that doesn't send any.
+
+use crate::control::{self, Activity, ControlSocket, Status};
+use crate::interrupt;
+use chrono::Local;
+use croner::Cron;
+use lyricsync::{FormatResolver, PathFilter};
+use notify::event::{EventKind, ModifyKind};
+use notify::{RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+
+// How often a quiet watch looks up from waiting, to notice it's been asked to stop
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// When the whole directory is gone through.
+pub enum Schedule {
+  /// Every so often, counted from the end of the last scan.
+  Every(Duration),
+  /// At the times a cron expression matches, in local time.
+  Cron(Box<Cron>),
+}
+
+impl Schedule {
+  // How long until the next scan, if there's ever one
+  fn until_next(&self) -> Option<Duration> {
+    match self {
+      Schedule::Every(interval) => Some(*interval),
+      Schedule::Cron(cron) => {
+        let now = Local::now();
+        match cron.find_next_occurrence(&now, false) {
+          Ok(next) => Some((next - now).to_std().unwrap_or_default()),
+          Err(error) => {
+            tracing::warn!(%error, "no more scheduled scans");
+            None
+          },
+        }
+      },
+    }
+  }
+}
+
+/// What there is to embed.
+pub enum Batch {
+  /// Audio and LRC files that turned up and have settled.
+  Settled(Vec<PathBuf>),
+  /// Everything in the directory, as scheduled.
+  Scan,
+}
+
+/// How a directory is watched.
+pub struct WatchOptions {
+  pub recursive: bool,
+  /// How long a file has to go without changing before it's handled
+  pub debounce: Duration,
+  /// When to scan the whole directory, if ever
+  pub schedule: Option<Schedule>,
+  /// Where the daemon is told what to do and asked how it's doing
+  pub control: Option<ControlSocket>,
+}
+
+impl WatchOptions {
+  /// Brings the control socket's status up to date, if there's a socket.
+  pub fn report(&self, change: impl FnOnce(&mut Status)) {
+    if let Some(control) = &self.control {
+      control.update(change);
+    }
+  }
+}
+
+/// Watches `directory` until the run is interrupted. Once an audio or LRC file has gone `debounce`
+/// without changing, it's passed to `handle` with the others that settled at the same time. With a
+/// schedule, `handle` is also asked to scan the directory: at the start, and then whenever the
+/// schedule says or a client of the control socket asks. `handle` gives back a batch it couldn't
+/// get to, which is tried again after another `debounce`.
+pub fn watch(
+  directory: &Path,
+  options: &WatchOptions,
+  resolver: &FormatResolver,
+  filter: &PathFilter,
+  mut handle: impl FnMut(Batch) -> Option<Batch>,
+) -> notify::Result<()> {
+  let debounce = options.debounce;
+  let (sender, receiver) = mpsc::channel();
+  let mut watcher = notify::recommended_watcher(sender)?;
+  let mode = if options.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+  watcher.watch(directory, mode)?;
+  tracing::info!(directory = %directory.display(), "watching for new files");
+
+  let is_watched = |path: &Path| {
+    let relative = path.strip_prefix(directory).unwrap_or(path);
+    (is_lrc(path) || resolver.by_extension(path).is_some()) && filter.is_match(relative)
+  };
+  // When each file waiting to settle last changed
+  let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+  let mut next_scan = options.schedule.as_ref().map(|_| Instant::now());
+  options.report(|status| status.next_scan = next_scan.map(|_| control::unix_seconds(SystemTime::now())));
+  while !interrupt::interrupted() {
+    match receiver.recv_timeout(POLL_INTERVAL) {
+      Ok(Ok(event)) if is_addition(&event.kind) => {
+        for path in event.paths.into_iter().filter(|path| is_watched(path)) {
+          pending.insert(path, Instant::now());
+        }
+      },
+      Ok(Ok(_)) | Err(mpsc::RecvTimeoutError::Timeout) => {},
+      Ok(Err(error)) => tracing::warn!(%error, "watch error"),
+      Err(mpsc::RecvTimeoutError::Disconnected) => break,
+    }
+
+    options.report(|status| status.pending = pending.len());
+    // Events are still taken while paused, so nothing that turns up in the meantime is missed
+    if options.control.as_ref().is_some_and(ControlSocket::paused) {
+      continue;
+    }
+    if options.control.as_ref().is_some_and(ControlSocket::take_rescan) {
+      next_scan = Some(Instant::now());
+    }
+
+    if let Some(scan_at) = next_scan
+      && scan_at <= Instant::now()
+    {
+      options.report(|status| status.activity = Activity::Scanning);
+      let retry = handle(Batch::Scan).is_some();
+      let wait = match (retry, &options.schedule) {
+        (true, _) => Some(debounce),
+        (false, Some(schedule)) => schedule.until_next(),
+        (false, None) => None,
+      };
+      next_scan = wait.map(|wait| Instant::now() + wait);
+      options.report(|status| {
+        status.activity = Activity::Idle;
+        if !retry {
+          status.last_scan = Some(control::unix_seconds(SystemTime::now()));
+        }
+        status.next_scan = wait.map(|wait| control::unix_seconds(SystemTime::now() + wait));
+      });
+      if let Some(wait) = wait {
+        tracing::info!("next scan in {}s", wait.as_secs());
+      }
+    }
+
+    let settled: Vec<PathBuf> =
+      pending.iter().filter(|(_, changed)| changed.elapsed() >= debounce).map(|(path, _)| path.clone()).collect();
+    if settled.is_empty() {
+      continue;
+    }
+    for path in &settled {
+      pending.remove(path);
+    }
+    // Files removed again before they settled, like a download's temporary files, are let go
+    let settled: Vec<PathBuf> = settled.into_iter().filter(|path| path.is_file()).collect();
+    if settled.is_empty() {
+      continue;
+    }
+    options.report(|status| status.activity = Activity::Embedding);
+    if let Some(Batch::Settled(settled)) = handle(Batch::Settled(settled)) {
+      for path in settled {
+        pending.insert(path, Instant::now());
+      }
+    }
+    options.report(|status| {
+      status.activity = Activity::Idle;
+      status.pending = pending.len();
+    });
+  }
+  Ok(())
+}
+
+// Files being created, written or moved in; removals and metadata changes leave nothing to embed
+fn is_addition(kind: &EventKind) -> bool {
+  matches!(kind, EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Name(_) | ModifyKind::Any))
+}
+
+pub fn is_lrc(path: &Path) -> bool {
+  lyricsync::is_sidecar(path)
+}
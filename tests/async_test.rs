@@ -0,0 +1,46 @@
+use lyricsync::{EmbedOptions, EmbedOutcome, FormatResolver, Lyrics, nonblocking};
+use std::fs;
+use std::path::Path;
+
+// Helper function to copy a fixture into a temporary directory
+fn copy_fixture(fixture: &str, test_dir: &tempfile::TempDir) -> std::path::PathBuf {
+  let dest = test_dir.path().join(Path::new(fixture).file_name().unwrap());
+  fs::copy(fixture, &dest).expect("Failed to copy test file");
+  dest
+}
+
+#[tokio::test]
+async fn test_async_round_trip() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let audio_path = copy_fixture("tests/fixtures/silence.opus", &test_dir);
+  let formats = FormatResolver::default();
+
+  nonblocking::embed_lyrics(&audio_path, Lyrics::new("[00:01.00]Async lyrics"), EmbedOptions::default()).await.unwrap();
+  assert!(nonblocking::has_embedded_lyrics(&audio_path, formats.clone()).await.unwrap());
+  assert_eq!(
+    nonblocking::extract_lyrics(&audio_path, formats.clone()).await.unwrap().as_deref(),
+    Some("[00:01.00]Async lyrics")
+  );
+  assert!(nonblocking::strip_lyrics(&audio_path, formats).await.unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_async_embed_files() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let fixtures = ["tests/fixtures/silence.opus", "tests/fixtures/silence.wav", "tests/fixtures/silence.m4a"];
+  let mut paths: Vec<_> = fixtures.iter().map(|fixture| copy_fixture(fixture, &test_dir)).collect();
+  paths.push(test_dir.path().join("missing.flac"));
+  fs::write(test_dir.path().join("silence.lrc"), "[00:01.00]Shared lyrics").unwrap();
+
+  let results = nonblocking::embed_files(paths.clone(), EmbedOptions::default(), 2).await;
+  assert_eq!(results.len(), 4);
+  for result in results {
+    let embedded = result.outcome.is_ok_and(|outcome| outcome == EmbedOutcome::Embedded);
+    assert_eq!(embedded, result.path != paths[3], "unexpected outcome for {}", result.path.display());
+  }
+
+  for path in &paths[..3] {
+    let lyrics = lyricsync::extract_lyrics(path, &FormatResolver::default()).unwrap();
+    assert_eq!(lyrics.as_deref(), Some("[00:01.00]Shared lyrics"));
+  }
+}
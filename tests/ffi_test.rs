@@ -0,0 +1,44 @@
+use lyricsync::ffi::{
+  LYRICSYNC_ERROR, LYRICSYNC_OK, lyricsync_embed_file, lyricsync_extract, lyricsync_free_string, lyricsync_has_lyrics,
+  lyricsync_last_error,
+};
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::ptr;
+
+#[test]
+fn test_ffi_round_trip() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let audio_path = test_dir.path().join("silence.wav");
+  fs::copy("tests/fixtures/silence.wav", &audio_path).expect("Failed to copy test file");
+  let c_path = CString::new(audio_path.to_str().unwrap()).unwrap();
+  let lyrics = CString::new("[00:01.00]C lyrics").unwrap();
+
+  unsafe {
+    assert_eq!(lyricsync_has_lyrics(c_path.as_ptr()), 0);
+    assert!(lyricsync_extract(c_path.as_ptr()).is_null());
+    assert!(lyricsync_last_error().is_null());
+
+    assert_eq!(lyricsync_embed_file(c_path.as_ptr(), lyrics.as_ptr()), LYRICSYNC_OK);
+    assert_eq!(lyricsync_has_lyrics(c_path.as_ptr()), 1);
+
+    let extracted = lyricsync_extract(c_path.as_ptr());
+    assert_eq!(CStr::from_ptr(extracted).to_str().unwrap(), "[00:01.00]C lyrics");
+    lyricsync_free_string(extracted);
+  }
+}
+
+#[test]
+fn test_ffi_errors() {
+  let missing = CString::new("missing.xyz").unwrap();
+
+  unsafe {
+    assert_eq!(lyricsync_has_lyrics(missing.as_ptr()), LYRICSYNC_ERROR);
+    let message = CStr::from_ptr(lyricsync_last_error()).to_str().unwrap();
+    assert!(message.contains("Unsupported file format"), "unexpected error: {message}");
+
+    assert_eq!(lyricsync_embed_file(ptr::null(), missing.as_ptr()), LYRICSYNC_ERROR);
+    assert_eq!(CStr::from_ptr(lyricsync_last_error()).to_str().unwrap(), "audio_path is null");
+    lyricsync_free_string(ptr::null_mut());
+  }
+}
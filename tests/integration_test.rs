@@ -1,10 +1,21 @@
 use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
 use std::path::Path;
 use std::process::Command;
+use std::sync::{Arc, Mutex};
 
 // Helper function to get the path to the lyricsync binary
 fn lyricsync_bin() -> Command {
-  Command::new(env!("CARGO_BIN_EXE_lyricsync"))
+  let mut command = Command::new(env!("CARGO_BIN_EXE_lyricsync"));
+  // Keep run journals out of the real state directory
+  command.env("XDG_STATE_HOME", std::env::temp_dir().join("lyricsync-test-state"));
+  // and the tests clear of the user's config file
+  command.env("XDG_CONFIG_HOME", std::env::temp_dir().join("lyricsync-test-config"));
+  for (name, _) in std::env::vars_os().filter(|(name, _)| name.to_string_lossy().starts_with("LYRICSYNC_")) {
+    command.env_remove(name);
+  }
+  command
 }
 
 // Helper function to create a temporary test directory
@@ -17,6 +28,67 @@ fn copy_test_file(source: &Path, dest: &Path) {
   fs::copy(source, dest).expect("Failed to copy test file");
 }
 
+// Helper function to embed the sample LRC into an audio fixture, then check that
+// a second run with --skip detects the embedded lyrics
+fn assert_embeds_and_detects(fixture: &str, extension: &str) {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let lrc_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc");
+
+  copy_test_file(Path::new(fixture), &test_dir_path.join(format!("04 Avril Lavigne - I'm With You.{extension}")));
+  copy_test_file(lrc_source, &test_dir_path.join("04 Avril Lavigne - I'm With You.lrc"));
+
+  // First run: embed lyrics
+  let output1 = lyricsync_bin().arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+
+  assert!(output1.status.success(), "First run should succeed");
+  let stdout1 = String::from_utf8_lossy(&output1.stdout);
+  assert!(stdout1.contains("Embedded lyrics in 1 audio files"), "{extension} file should be embedded");
+
+  // Second run with --skip: the embedded lyrics should be detected
+  let output2 =
+    lyricsync_bin().arg("--directory").arg(test_dir_path).arg("--skip").output().expect("Failed to execute lyricsync");
+
+  assert!(output2.status.success(), "Second run should succeed");
+  let stdout2 = String::from_utf8_lossy(&output2.stdout);
+  assert!(stdout2.contains("Embedded lyrics in 0 audio files"), "{extension} file should be skipped");
+}
+
+// Helper function to serve canned lyrics provider responses on a local port. `respond` maps a request
+// path to a status code and JSON body; every request line is recorded for assertions.
+fn spawn_provider_mock(respond: fn(&str) -> (u16, &'static str)) -> (String, Arc<Mutex<Vec<String>>>) {
+  let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind mock server");
+  let url = format!("http://{}", listener.local_addr().unwrap());
+  let requests = Arc::new(Mutex::new(Vec::new()));
+  let recorded = Arc::clone(&requests);
+
+  std::thread::spawn(move || {
+    for stream in listener.incoming() {
+      let mut stream = stream.unwrap();
+      let mut reader = BufReader::new(stream.try_clone().unwrap());
+      let mut request_line = String::new();
+      reader.read_line(&mut request_line).unwrap();
+      let mut header = String::new();
+      while reader.read_line(&mut header).unwrap() > 2 {
+        header.clear();
+      }
+
+      let path = request_line.split_whitespace().nth(1).unwrap_or_default().to_string();
+      let (status, body) = respond(&path);
+      recorded.lock().unwrap().push(path);
+      write!(
+        stream,
+        "HTTP/1.1 {status} OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+      )
+      .unwrap();
+    }
+  });
+
+  (url, requests)
+}
+
 #[test]
 fn test_embed_mp3_lyrics() {
   let test_dir = create_test_dir();
@@ -185,7 +257,7 @@ fn test_recursive_flag() {
   // Run without --recursive: should not find files in subdirectory
   let output1 = lyricsync_bin().arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
 
-  assert!(output1.status.success(), "Should succeed");
+  assert_eq!(output1.status.code(), Some(4), "Finding no audio files should exit with 4");
   let stdout1 = String::from_utf8_lossy(&output1.stdout);
   assert!(
     stdout1.contains("0") || stdout1.contains("Total audio files: 0"),
@@ -213,12 +285,2971 @@ fn test_invalid_directory() {
     .output()
     .expect("Failed to execute lyricsync");
 
-  // Program doesn't fail on invalid directories, it just processes 0 files
-  assert!(output.status.success(), "Should succeed even with invalid directory");
+  assert_eq!(output.status.code(), Some(5), "A missing directory should exit with 5");
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(stderr.contains("/nonexistent/directory/path: no such directory"), "The directory should be named");
+}
 
-  let stdout = String::from_utf8_lossy(&output.stdout);
+#[test]
+fn test_fail_on_policy() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+  // One file without a sidecar, which is skipped
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &test_dir_path.join("song.opus"));
+
+  let exit_code = |fail_on: &str| {
+    let output = lyricsync_bin()
+      .arg("--directory")
+      .arg(test_dir_path)
+      .args(["--fail-on", fail_on])
+      .output()
+      .expect("Failed to execute lyricsync");
+    output.status.code()
+  };
+
+  assert_eq!(exit_code("errors"), Some(0), "Skipped files aren't errors");
+  assert_eq!(exit_code("skipped"), Some(6), "--fail-on skipped should count skipped files");
+
+  // The sidecar of a file that fails is set aside, so it's put back before every run
+  fs::write(test_dir_path.join("broken.flac"), "not a FLAC file").unwrap();
+  let failing = |fail_on: &str| {
+    copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc"), &test_dir_path.join("broken.lrc"));
+    exit_code(fail_on)
+  };
+  assert_eq!(failing("errors"), Some(3), "A failed file should exit with 3");
+  assert_eq!(failing("skipped"), Some(3), "Failures come before skipped files");
+  assert_eq!(failing("none"), Some(0), "--fail-on none should ignore what happened to files");
+}
+
+#[test]
+fn test_embed_opus_lyrics() {
+  assert_embeds_and_detects("tests/fixtures/silence.opus", "opus");
+}
+
+#[test]
+fn test_embed_wav_lyrics() {
+  assert_embeds_and_detects("tests/fixtures/silence.wav", "wav");
+}
+
+#[test]
+fn test_embed_aiff_lyrics() {
+  assert_embeds_and_detects("tests/fixtures/silence.aiff", "aiff");
+  assert_embeds_and_detects("tests/fixtures/silence.aiff", "aif");
+}
+
+#[test]
+fn test_embed_ape_lyrics() {
+  assert_embeds_and_detects("tests/fixtures/silence.ape", "ape");
+}
+
+#[test]
+fn test_embed_wavpack_lyrics() {
+  assert_embeds_and_detects("tests/fixtures/silence.wv", "wv");
+}
+
+#[test]
+fn test_embed_m4a_lyrics() {
+  assert_embeds_and_detects("tests/fixtures/silence.m4a", "m4a");
+}
+
+#[test]
+fn test_embed_wma_lyrics() {
+  assert_embeds_and_detects("tests/fixtures/silence.wma", "wma");
+}
+
+#[test]
+fn test_embed_dsd_lyrics() {
+  assert_embeds_and_detects("tests/fixtures/silence.dsf", "dsf");
+  assert_embeds_and_detects("tests/fixtures/silence.dff", "dff");
+}
+
+#[test]
+fn test_embed_gbk_sidecar() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  // "[00:12.00]你问我爱你有多深\n[00:18.00]我爱你有几分\n" in GBK, as lyrics packs often have it
+  let gbk = b"[00:12.00]\xc4\xe3\xce\xca\xce\xd2\xb0\xae\xc4\xe3\xd3\xd0\xb6\xe0\xc9\xee\n[00:18.00]\xce\xd2\xb0\xae\xc4\xe3\xd3\xd0\xbc\xb8\xb7\xd6\n";
+  fs::write(test_dir.path().join("song.lrc"), gbk).unwrap();
+
+  let output = lyricsync_bin().args(["-v", "-d"]).arg(test_dir.path()).output().unwrap();
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
   assert!(
-    stdout.contains("0") || stdout.contains("Total audio files: 0"),
-    "Should report 0 files for invalid directory"
+    String::from_utf8_lossy(&output.stderr).contains("converted from GBK to UTF-8"),
+    "-v should log the conversion"
+  );
+  let embedded = lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap();
+  assert_eq!(embedded.as_deref(), Some("[00:12.00]你问我爱你有多深\n[00:18.00]我爱你有几分\n"));
+}
+
+#[test]
+fn test_embed_applies_offset_tag() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.mp3");
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &audio_path);
+  fs::write(test_dir.path().join("song.lrc"), "[ti:Song]\n[offset:1000]\n[00:05.00]First\n[00:07.50]Second\n").unwrap();
+
+  let embed = |frames: &str| {
+    let output = lyricsync_bin().args(["--rewrite", "--id3-frames", frames, "-d"]).arg(test_dir.path()).output();
+    assert!(output.unwrap().status.success());
+    lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap().unwrap()
+  };
+  assert_eq!(embed("uslt"), "[ti:Song]\n[00:04.00]First\n[00:06.50]Second\n", "The offset should be baked in");
+  let sylt = embed("sylt");
+  assert!(sylt.contains("[00:04.00]First\n[00:06.50]Second"), "SYLT should be timed with the offset: {sylt}");
+}
+
+#[test]
+fn test_embed_expands_condensed_lines() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.mp3");
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &audio_path);
+  fs::write(test_dir.path().join("song.lrc"), "[ti:Song]\n[00:01.00][00:05.00]Chorus\n[00:03.00]Verse\n").unwrap();
+
+  let output = lyricsync_bin().args(["--id3-frames", "sylt", "-d"]).arg(test_dir.path()).output().unwrap();
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  let sylt = lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap().unwrap();
+  assert!(sylt.contains("[00:01.00]Chorus\n[00:03.00]Verse\n[00:05.00]Chorus"), "SYLT should be in time order: {sylt}");
+}
+
+#[test]
+fn test_sort_lines() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  let lrc = "[ar:Artist]\n[00:03.00]Third\n[00:01.00]First\n[00:01.00]First\n[00:02.00]Second\n";
+  fs::write(test_dir.path().join("song.lrc"), lrc).unwrap();
+  let embed = |args: &[&str]| {
+    let output = lyricsync_bin().args(["--rewrite", "-d"]).arg(test_dir.path()).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let embedded = lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap().unwrap();
+    (embedded, String::from_utf8_lossy(&output.stderr).into_owned())
+  };
+
+  assert_eq!(embed(&[]).0, lrc, "Lines are embedded in their order unless asked otherwise");
+  let (embedded, log) = embed(&["--sort-lines"]);
+  assert_eq!(embedded, "[ar:Artist]\n[00:01.00]First\n[00:02.00]Second\n[00:03.00]Third\n");
+  assert!(log.contains("sorted 1 lines that were out of order and merged 1 duplicates"), "{log}");
+  assert!(log.contains("song.lrc"), "The report should name the LRC file: {log}");
+}
+
+#[test]
+fn test_txt_fallback() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  fs::write(test_dir.path().join("song.txt"), "First line\nSecond line\n").unwrap();
+  let embed = |args: &[&str]| {
+    let output = lyricsync_bin().args(["--rewrite", "-d"]).arg(test_dir.path()).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap()
+  };
+
+  assert_eq!(embed(&[]), None, "Text files are left alone unless asked for");
+  let lyrics = embed(&["--txt-fallback", "--reject-invalid", "--vorbis-key", "unsyncedlyrics"]);
+  assert_eq!(lyrics.as_deref(), Some("First line\nSecond line\n"));
+  fs::write(test_dir.path().join("song.lrc"), "[00:01.00]Timed\n").unwrap();
+  assert_eq!(embed(&["--txt-fallback"]).as_deref(), Some("[00:01.00]Timed\n"), "An LRC file is taken over the text");
+}
+
+#[test]
+fn test_embed_srt_sidecar() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  let srt =
+    "1\r\n00:00:01,000 --> 00:00:02,000\r\nFirst <b>line</b>\r\n\r\n2\r\n00:00:02,500 --> 00:00:04,000\r\nSecond\r\n";
+  fs::write(test_dir.path().join("song.srt"), srt).unwrap();
+  let embed = || {
+    let output = lyricsync_bin().args(["--rewrite", "-d"]).arg(test_dir.path()).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap().unwrap()
+  };
+
+  assert_eq!(embed(), "[00:01.00]First line\n[00:02.50]Second\n", "Cues are embedded as LRC lines");
+  fs::write(test_dir.path().join("song.lrc"), "[00:01.00]From the LRC file\n").unwrap();
+  assert_eq!(embed(), "[00:01.00]From the LRC file\n", "An LRC file is taken over the subtitles");
+}
+
+#[test]
+fn test_embed_vtt_sidecar() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  let vtt = "WEBVTT\n\nNOTE made by hand\n\n00:01.000 --> 00:02.000 line:0\n<v Singer>First &amp; <i>only</i></v>\n";
+  fs::write(test_dir.path().join("song.vtt"), vtt).unwrap();
+
+  let output = lyricsync_bin().args(["--rewrite", "-d"]).arg(test_dir.path()).output().unwrap();
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  let lyrics = lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap().unwrap();
+  assert_eq!(lyrics, "[00:01.00]First & only\n");
+}
+
+#[test]
+fn test_embed_ttml_sidecar() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.mp3");
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &audio_path);
+  let ttml = "<tt><body><div><p begin=\"1.0s\" end=\"2.5s\"><span begin=\"00:01.000\" end=\"00:01.400\">Hello</span> \
+              <span begin=\"00:01.400\" end=\"00:02.500\">world</span></p></div></body></tt>";
+  fs::write(test_dir.path().join("song.ttml"), ttml).unwrap();
+  let embed = |args: &[&str]| {
+    let output = lyricsync_bin().args(["--rewrite", "-d"]).arg(test_dir.path()).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap().unwrap()
+  };
+
+  assert_eq!(embed(&[]), "[00:01.00]Hello world\n", "Lines are timed as a whole by default");
+  let words = "[00:01.00]<00:01.00>Hello <00:01.40>world<00:02.50>\n";
+  assert_eq!(embed(&["--word-timing", "keep"]), words);
+  // Read back from the SYLT frame alone, its entries timed word by word, which can't hold when the
+  // last word ends
+  let sylt = embed(&["--word-timing", "keep", "--id3-frames", "sylt"]);
+  assert_eq!(sylt, "[00:01.00]<00:01.00>Hello <00:01.40>world\n");
+}
+
+#[test]
+fn test_embed_ass_sidecar() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  let ass = "[Script Info]\nScriptType: v4.00+\n\n[Events]\n\
+             Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+             Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,karaoke,{\\k40}Sing{\\k60}ing\n";
+  fs::write(test_dir.path().join("song.ass"), ass).unwrap();
+  let embed = |args: &[&str]| {
+    let output = lyricsync_bin().args(["--rewrite", "-d"]).arg(test_dir.path()).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap().unwrap()
+  };
+
+  assert_eq!(embed(&[]), "[00:01.00]Singing\n");
+  assert_eq!(embed(&["--word-timing", "keep"]), "[00:01.00]<00:01.00>Sing<00:01.40>ing<00:02.00>\n");
+}
+
+#[test]
+fn test_embed_richsync_sidecar() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  let richsync = r#"[{"ts":1,"te":2.2,"l":[{"c":"Word","o":0},{"c":" ","o":0.4},{"c":"by","o":0.5},{"c":" ","o":0.7},{"c":"word","o":0.8}],"x":"Word by word"},{"ts":3,"te":4,"l":[],"x":"Whole line"}]"#;
+  fs::write(test_dir.path().join("song.json"), richsync).unwrap();
+  let embed = |args: &[&str]| {
+    let output = lyricsync_bin().args(["--rewrite", "-d"]).arg(test_dir.path()).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap().unwrap()
+  };
+
+  assert_eq!(embed(&[]), "[00:01.00]Word by word\n[00:03.00]Whole line\n");
+  assert_eq!(
+    embed(&["--word-timing", "keep"]),
+    "[00:01.00]<00:01.00>Word <00:01.50>by <00:01.80>word<00:02.20>\n[00:03.00]Whole line\n"
+  );
+}
+
+#[test]
+fn test_enhanced_lrc() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  let lrc = "[00:01.00]<00:01.00>Word <00:01.50>by <00:01.80>word<00:02.20>\n[00:03.00]Whole line\n";
+  fs::write(test_dir.path().join("song.lrc"), lrc).unwrap();
+  let embed = |args: &[&str]| {
+    let output = lyricsync_bin().args(["--rewrite", "-d"]).arg(test_dir.path()).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap().unwrap()
+  };
+
+  assert_eq!(embed(&[]), "[00:01.00]Word by word\n[00:03.00]Whole line\n", "Word timestamps are taken out");
+  assert_eq!(embed(&["--word-timing", "keep"]), lrc);
+  assert_eq!(
+    embed(&["--word-timing", "keep", "--offset", "1000"]),
+    "[00:02.00]<00:02.00>Word <00:02.50>by <00:02.80>word<00:03.20>\n[00:04.00]Whole line\n",
+    "Word timestamps move with the lines"
+  );
+}
+
+#[test]
+fn test_offset() {
+  let test_dir = create_test_dir();
+  for name in ["first.opus", "second.opus"] {
+    copy_test_file(Path::new("tests/fixtures/silence.opus"), &test_dir.path().join(name));
+    fs::write(test_dir.path().join(name).with_extension("lrc"), "[00:01.00]First\n[00:02.50]Second\n").unwrap();
+  }
+  let lyrics = |name: &str| {
+    let path = test_dir.path().join(name);
+    lyricsync::extract_lyrics(&path, &lyricsync::FormatResolver::default()).unwrap().unwrap()
+  };
+  let run = |args: &[&str]| {
+    let output = lyricsync_bin().args(["--rewrite", "-d"]).arg(test_dir.path()).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  };
+
+  run(&["--offset", "1500"]);
+  assert_eq!(lyrics("first.opus"), "[00:02.50]First\n[00:04.00]Second\n");
+  run(&["--offset", "-1200"]);
+  assert_eq!(lyrics("first.opus"), "[00:00.00]First\n[00:01.30]Second\n", "Times shouldn't go below zero");
+
+  // A mapping's offset wins over --offset, for the files it gives one
+  let csv = test_dir.path().join("pairs.csv");
+  fs::write(&csv, "audio_path,lrc_path,offset_ms\nfirst.opus,first.lrc,250\nsecond.opus,second.lrc,\n").unwrap();
+  run(&["--offset", "-500", "--map", csv.to_str().unwrap()]);
+  assert_eq!(lyrics("first.opus"), "[00:01.25]First\n[00:02.75]Second\n");
+  assert_eq!(lyrics("second.opus"), "[00:00.50]First\n[00:02.00]Second\n");
+
+  fs::write(&csv, "first.opus,first.lrc,soon\n").unwrap();
+  let output = lyricsync_bin().arg("-d").arg(test_dir.path()).arg("--map").arg(&csv).output().unwrap();
+  assert!(String::from_utf8_lossy(&output.stderr).contains("line 1: 'soon' isn't an offset in milliseconds"));
+}
+
+#[test]
+fn test_strip_headers() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  let lrc = "[ar:Artist]\n[ti:Title]\n[al:Album]\n[by:Someone]\n[re:Editor]\n[ve:1.0]\n[00:01.00]First\n";
+  fs::write(test_dir.path().join("song.lrc"), lrc).unwrap();
+  let embed = |args: &[&str]| {
+    let output = lyricsync_bin().args(["--rewrite", "-d"]).arg(test_dir.path()).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap().unwrap()
+  };
+
+  assert_eq!(embed(&[]), lrc, "Headers are embedded unless asked otherwise");
+  assert_eq!(embed(&["--strip-headers"]), "[al:Album]\n[00:01.00]First\n");
+  assert_eq!(embed(&["--strip-headers=al,BY"]), "[ar:Artist]\n[ti:Title]\n[re:Editor]\n[ve:1.0]\n[00:01.00]First\n");
+  assert_eq!(fs::read_to_string(test_dir.path().join("song.lrc")).unwrap(), lrc, "The LRC file keeps them");
+}
+
+#[test]
+fn test_strip_credits() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  let lrc = "[ar:Artist]\n[00:00.00]作词 : Someone\n[00:00.50]Lyrics by LyricsSite.com\n[00:01.00]First line\n\
+             [00:02.00]Synced by uploader42\n[00:03.00]Second line\n[00:04.00]-- ripped by DJ Nobody --\n";
+  fs::write(test_dir.path().join("song.lrc"), lrc).unwrap();
+  let embed = |args: &[&str]| {
+    let mut command = lyricsync_bin();
+    let output = command.args(["--rewrite", "-v", "-d"]).arg(test_dir.path()).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let embedded = lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap().unwrap();
+    (embedded, String::from_utf8_lossy(&output.stderr).into_owned())
+  };
+
+  assert_eq!(embed(&[]).0, lrc, "Credits are embedded unless asked otherwise");
+  let (embedded, log) = embed(&["--strip-credits"]);
+  assert_eq!(
+    embedded,
+    "[ar:Artist]\n[00:01.00]First line\n[00:03.00]Second line\n[00:04.00]-- ripped by DJ Nobody --\n"
+  );
+  assert!(log.contains("left out a credit line") && log.contains("Lyrics by LyricsSite.com"), "{log}");
+  let (embedded, _) = embed(&["--strip-credits", "--strip-line", "(?i)ripped by"]);
+  assert_eq!(embedded, "[ar:Artist]\n[00:01.00]First line\n[00:03.00]Second line\n");
+
+  let output = lyricsync_bin().args(["--strip-line", "(unclosed", "-d"]).arg(test_dir.path()).output().unwrap();
+  assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_normalize_sidecars() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.opus");
+  let lrc_path = test_dir.path().join("song.lrc");
+  let journal_dir = test_dir.path().join("journals");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  let original = "\u{feff}[00:01.00]First\r\n[00:02.00]Second\r\n";
+  fs::write(&lrc_path, original).unwrap();
+  let run = |args: &[&str]| {
+    let mut command = lyricsync_bin();
+    command.args(args).arg("--journal-dir").arg(&journal_dir).arg("-d").arg(test_dir.path());
+    let output = command.output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  };
+
+  run(&["embed"]);
+  let embedded = lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap();
+  assert_eq!(embedded.as_deref(), Some("[00:01.00]First\n[00:02.00]Second\n"), "No BOM or CR should be embedded");
+  assert_eq!(fs::read_to_string(&lrc_path).unwrap(), original, "The LRC file is left alone by default");
+
+  // Already embedded, but still written back
+  run(&["embed", "--normalize-sidecars"]);
+  assert_eq!(fs::read_to_string(&lrc_path).unwrap(), "[00:01.00]First\n[00:02.00]Second\n");
+  let output = lyricsync_bin().arg("undo").arg("--journal-dir").arg(&journal_dir).output().unwrap();
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  assert_eq!(fs::read_to_string(&lrc_path).unwrap(), original, "Undo should restore the LRC file's old bytes");
+}
+
+#[test]
+fn test_probe_detects_misnamed_files() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  // An Opus stream with an .mp3 extension
+  let opus_source = Path::new("tests/fixtures/silence.opus");
+  let lrc_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc");
+
+  copy_test_file(opus_source, &test_dir_path.join("04 Avril Lavigne - I'm With You.mp3"));
+  copy_test_file(lrc_source, &test_dir_path.join("04 Avril Lavigne - I'm With You.lrc"));
+
+  // Run lyricsync with --probe: the file should be routed to the Opus embedder
+  let output1 =
+    lyricsync_bin().arg("--directory").arg(test_dir_path).arg("--probe").output().expect("Failed to execute lyricsync");
+
+  assert!(output1.status.success(), "lyricsync should succeed");
+  let stdout1 = String::from_utf8_lossy(&output1.stdout);
+  assert!(stdout1.contains("Embedded lyrics in 1 audio files"), "Misnamed file should be embedded");
+
+  // Second run with --probe --skip: the embedded lyrics should be detected
+  let output2 = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--probe")
+    .arg("--skip")
+    .output()
+    .expect("Failed to execute lyricsync");
+
+  assert!(output2.status.success(), "Second run should succeed");
+  let stdout2 = String::from_utf8_lossy(&output2.stdout);
+  assert!(stdout2.contains("Embedded lyrics in 0 audio files"), "Misnamed file should be skipped");
+}
+
+#[test]
+fn test_uppercase_and_alternate_extensions() {
+  // Uppercase extensions and built-in aliases should be picked up by the walker
+  assert_embeds_and_detects("tests/fixtures/04 Avril Lavigne - I'm With You.mp3", "MP3");
+  assert_embeds_and_detects("tests/fixtures/silence.aiff", "Aifc");
+}
+
+#[test]
+fn test_ext_alias_flag() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let opus_source = Path::new("tests/fixtures/silence.opus");
+  let lrc_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc");
+
+  copy_test_file(opus_source, &test_dir_path.join("04 Avril Lavigne - I'm With You.ogg"));
+  copy_test_file(lrc_source, &test_dir_path.join("04 Avril Lavigne - I'm With You.lrc"));
+
+  // Without an alias, .ogg files are not picked up
+  let output1 = lyricsync_bin().arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+
+  assert_eq!(output1.status.code(), Some(4), "Finding no audio files should exit with 4");
+  let stdout1 = String::from_utf8_lossy(&output1.stdout);
+  assert!(stdout1.contains("Total audio files: 0"), "Unknown extensions should be ignored");
+
+  // With --ext-alias ogg=opus, the file is embedded as Opus
+  let output2 = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--ext-alias")
+    .arg("ogg=opus")
+    .output()
+    .expect("Failed to execute lyricsync");
+
+  assert!(output2.status.success(), "lyricsync should succeed");
+  let stdout2 = String::from_utf8_lossy(&output2.stdout);
+  assert!(stdout2.contains("Embedded lyrics in 1 audio files"), "Aliased file should be embedded");
+
+  // Invalid aliases are rejected by the argument parser
+  let output3 = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--ext-alias")
+    .arg("ogg=vorbis")
+    .output()
+    .expect("Failed to execute lyricsync");
+
+  assert!(!output3.status.success(), "Unknown alias targets should be rejected");
+}
+
+#[test]
+fn test_embed_subcommand() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let mp3_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3");
+  let lrc_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc");
+
+  copy_test_file(mp3_source, &test_dir_path.join("04 Avril Lavigne - I'm With You.mp3"));
+  copy_test_file(lrc_source, &test_dir_path.join("04 Avril Lavigne - I'm With You.lrc"));
+
+  // `embed` accepts the same flags as the bare invocation
+  let output = lyricsync_bin()
+    .arg("embed")
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--dry-run")
+    .output()
+    .expect("Failed to execute lyricsync");
+
+  assert!(output.status.success(), "lyricsync embed should succeed");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("[DRY RUN] Would embed lyrics in 1 audio files"), "embed should find the file");
+
+  // Top-level flags can't be mixed with a subcommand
+  let output = lyricsync_bin()
+    .arg("--skip")
+    .arg("embed")
+    .arg("--directory")
+    .arg(test_dir_path)
+    .output()
+    .expect("Failed to execute lyricsync");
+
+  assert!(!output.status.success(), "Mixing top-level flags and subcommands should fail");
+}
+
+#[test]
+fn test_generate_completion() {
+  let output = lyricsync_bin().arg("--generate-completion").arg("bash").output().expect("Failed to execute lyricsync");
+
+  assert!(output.status.success(), "Completion generation should not require --directory");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("embed"), "Completions should include subcommands");
+}
+
+#[test]
+fn test_extract_subcommand() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let mp3_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3");
+  let lrc_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc");
+  let lrc_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.lrc");
+
+  copy_test_file(mp3_source, &test_dir_path.join("04 Avril Lavigne - I'm With You.mp3"));
+  copy_test_file(lrc_source, &lrc_dest);
+
+  // Embed, then drop the sidecar so extract has something to recreate
+  let output = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--reduce")
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "Embedding should succeed");
+  assert!(!lrc_dest.exists(), "LRC file should be removed with --reduce");
+
+  let output =
+    lyricsync_bin().arg("extract").arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+
+  assert!(output.status.success(), "lyricsync extract should succeed");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Extracted lyrics from 1 audio files"), "Lyrics should be extracted");
+  assert_eq!(
+    fs::read_to_string(&lrc_dest).expect("LRC file should be recreated"),
+    fs::read_to_string(lrc_source).unwrap(),
+    "Extracted lyrics should match the original LRC"
   );
+
+  // An existing sidecar is left alone unless --overwrite is given
+  fs::write(&lrc_dest, "edited").unwrap();
+  let output =
+    lyricsync_bin().arg("extract").arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Skipped (sidecar exists): 1"), "Existing sidecar should be skipped");
+  assert_eq!(fs::read_to_string(&lrc_dest).unwrap(), "edited");
+
+  let output = lyricsync_bin()
+    .arg("extract")
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--overwrite")
+    .output()
+    .expect("Failed to execute lyricsync");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Extracted lyrics from 1 audio files"), "--overwrite should replace the sidecar");
+  assert_ne!(fs::read_to_string(&lrc_dest).unwrap(), "edited");
+}
+
+#[test]
+fn test_extract_unsynced_lyrics_to_txt() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  copy_test_file(Path::new("tests/fixtures/silence.wma"), &test_dir_path.join("song.wma"));
+  fs::write(test_dir_path.join("song.lrc"), "Plain lyrics\nwithout timestamps\n").unwrap();
+
+  let output = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--reduce")
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "Embedding should succeed");
+
+  let output =
+    lyricsync_bin().arg("extract").arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+
+  assert!(output.status.success(), "lyricsync extract should succeed");
+  assert!(!test_dir_path.join("song.lrc").exists(), "Unsynced lyrics shouldn't be written as LRC");
+  assert_eq!(fs::read_to_string(test_dir_path.join("song.txt")).unwrap(), "Plain lyrics\nwithout timestamps\n");
+}
+
+#[test]
+fn test_strip_subcommand() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let lrc_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc");
+
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"),
+    &test_dir_path.join("04 Avril Lavigne - I'm With You.mp3"),
+  );
+  copy_test_file(Path::new("tests/fixtures/silence.wma"), &test_dir_path.join("04 Avril Lavigne - I'm With You.wma"));
+  copy_test_file(lrc_source, &test_dir_path.join("04 Avril Lavigne - I'm With You.lrc"));
+
+  let output = lyricsync_bin().arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "Embedding should succeed");
+
+  // Dry run reports the files but leaves the lyrics in place
+  let output = lyricsync_bin()
+    .arg("strip")
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--dry-run")
+    .output()
+    .expect("Failed to execute lyricsync");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("[DRY RUN] Would strip lyrics from 2 audio files"), "Both files should have lyrics");
+
+  let output =
+    lyricsync_bin().arg("strip").arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "lyricsync strip should succeed");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Stripped lyrics from 2 audio files"), "Both files should be stripped");
+
+  // Nothing left to strip, and --skip no longer sees embedded lyrics
+  let output =
+    lyricsync_bin().arg("strip").arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Stripped lyrics from 0 audio files"), "Lyrics should already be gone");
+
+  let output = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--skip")
+    .arg("--dry-run")
+    .output()
+    .expect("Failed to execute lyricsync");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(
+    stdout.contains("[DRY RUN] Would embed lyrics in 2 audio files"),
+    "Stripped files should be embeddable again"
+  );
+}
+
+#[test]
+fn test_check_subcommand() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let lrc_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc");
+
+  // One file in sync, one out of sync, one sidecar-only, one with nothing
+  copy_test_file(Path::new("tests/fixtures/silence.wma"), &test_dir_path.join("match.wma"));
+  copy_test_file(lrc_source, &test_dir_path.join("match.lrc"));
+  copy_test_file(Path::new("tests/fixtures/silence.wma"), &test_dir_path.join("differs.wma"));
+  copy_test_file(lrc_source, &test_dir_path.join("differs.lrc"));
+  copy_test_file(Path::new("tests/fixtures/silence.wav"), &test_dir_path.join("sidecar.wav"));
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &test_dir_path.join("missing.opus"));
+
+  let output = lyricsync_bin().arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "Embedding should succeed");
+  fs::write(test_dir_path.join("differs.lrc"), "[00:01.00]Something else\n").unwrap();
+  copy_test_file(lrc_source, &test_dir_path.join("sidecar.lrc"));
+
+  let output =
+    lyricsync_bin().arg("check").arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+
+  assert!(output.status.success(), "lyricsync check should succeed");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("MATCH") && stdout.contains("match.wma"), "In-sync file should be reported");
+  assert!(stdout.contains("DIFFERS") && stdout.contains("differs.wma"), "Out-of-sync file should be reported");
+  assert!(stdout.contains("SIDECAR") && stdout.contains("sidecar.wav"), "Sidecar-only file should be reported");
+  assert!(stdout.contains("MISSING") && stdout.contains("missing.opus"), "File without lyrics should be reported");
+  assert!(stdout.contains("Embedded lyrics: 2/4 (50.00%)"), "Embedded coverage should be reported");
+  assert!(stdout.contains("Sidecar LRC: 3/4 (75.00%)"), "Sidecar coverage should be reported");
+  assert!(stdout.contains("Any lyrics: 3/4 (75.00%)"), "Overall coverage should be reported");
+  assert!(stdout.contains("Embedded and sidecar differ: 1"), "Differences should be counted");
+}
+
+#[test]
+fn test_validate_subcommand() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc"), &test_dir_path.join("good.lrc"));
+  fs::write(test_dir_path.join("bad.lrc"), "[ar:A]\n[ar:B]\n[00:05.00]First\n[00:04.00]Second\n[00:61.00]Third\n")
+    .unwrap();
+  fs::write(test_dir_path.join("empty.lrc"), "").unwrap();
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &test_dir_path.join("empty.opus"));
+
+  let output = lyricsync_bin().args(["validate", "-d"]).arg(test_dir_path).output().unwrap();
+  assert_eq!(output.status.code(), Some(3), "Invalid LRC files count as failures");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("bad.lrc\n  line 2: duplicate [ar:] header tag, first given on line 1\n"), "{stdout}");
+  assert!(stdout.contains("  line 4: timestamp [00:04.00] is earlier than the line before it\n"), "{stdout}");
+  assert!(stdout.contains("  line 5: invalid timestamp [00:61.00]\n"), "{stdout}");
+  assert!(stdout.contains("empty.lrc\n  the lyrics are empty\n"), "{stdout}");
+  assert!(!stdout.contains("good.lrc"), "Valid files aren't listed");
+  assert!(stdout.contains("Valid LRC files: 1\nInvalid LRC files: 2\nTotal LRC files: 3"), "{stdout}");
+
+  // Embedding refuses the invalid one, but only when asked to
+  let output = lyricsync_bin().args(["--reject-invalid", "-d"]).arg(test_dir_path).output().unwrap();
+  assert_eq!(output.status.code(), Some(3));
+  assert!(String::from_utf8_lossy(&output.stdout).contains("invalid LRC file (the lyrics are empty)"));
+  let audio_path = test_dir_path.join("empty.opus");
+  assert_eq!(lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap(), None);
+
+  let output = lyricsync_bin().args(["validate", "-d"]).arg(test_dir_path.join("missing")).output().unwrap();
+  assert_eq!(output.status.code(), Some(5));
+}
+
+#[test]
+fn test_shift_subcommand() {
+  let test_dir = create_test_dir();
+  let lrc_path = test_dir.path().join("song.lrc");
+  fs::write(&lrc_path, "[ti:Song]\n[00:10.00]Verse\n[01:40.00]Chorus\n[02:00.00]Outro\n").unwrap();
+  let shift = |args: &[&str]| lyricsync_bin().arg("shift").arg(&lrc_path).args(args).output().unwrap();
+  let anchors = ["--anchor", "00:10.00=00:10.20", "--anchor", "01:40=01:42.00"];
+  let retimed = "[ti:Song]\n[00:10.20]Verse\n[01:42.00]Chorus\n[02:02.40]Outro\n";
+
+  let output = shift(&[&anchors[..], &["--dry-run"]].concat());
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  assert_eq!(String::from_utf8_lossy(&output.stdout), retimed);
+  assert!(fs::read_to_string(&lrc_path).unwrap().contains("[00:10.00]Verse"), "A dry run saves nothing");
+
+  assert!(shift(&anchors).status.success());
+  assert_eq!(fs::read_to_string(&lrc_path).unwrap(), retimed);
+
+  assert_eq!(shift(&anchors[..2]).status.code(), Some(2), "One anchor isn't enough to scale by");
+  let output = shift(&["--anchor", "00:10=00:11", "--anchor", "00:10=00:12"]);
+  assert_eq!(output.status.code(), Some(2));
+  assert!(String::from_utf8_lossy(&output.stderr).contains("the two anchors need different LRC times"));
+  assert_eq!(shift(&["--anchor", "10s=11s", "--anchor", "01:40=01:42"]).status.code(), Some(2));
+}
+
+#[test]
+fn test_check_html_report() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path().join("library");
+  let album = library.join("album");
+  fs::create_dir_all(&album).unwrap();
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &album.join("song.mp3"));
+  fs::write(album.join("song.lrc"), "[00:01.00]Covered\n").unwrap();
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &library.join("Tom & Jerry.opus"));
+  fs::write(library.join("broken.flac"), "not a FLAC file").unwrap();
+
+  let page_path = test_dir.path().join("coverage.html");
+  let output = lyricsync_bin()
+    .args(["check", "--recursive", "--report"])
+    .arg(format!("html={}", page_path.display()))
+    .arg("--directory")
+    .arg(&library)
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert_eq!(output.status.code(), Some(3), "The broken file should fail the check");
+  assert!(String::from_utf8_lossy(&output.stdout).contains("Summary:"), "With a path the summary is still printed");
+
+  let page = fs::read_to_string(&page_path).expect("The page should be written");
+  assert!(page.starts_with("<!DOCTYPE html>"));
+  assert!(page.contains("1 of 3 files have lyrics (33.33%)"));
+  assert!(page.contains("<tr><td>album</td><td data-sort=\"1\">1</td><td data-sort=\"1\">1</td>"), "{page}");
+  assert!(page.contains("<td>Let Go</td><td>Avril Lavigne</td>"), "Albums should be grouped by their tags");
+  assert!(page.contains("Tom &amp; Jerry.opus</td><td>Unknown artist</td>"), "Missing files should be escaped");
+  assert!(page.contains("broken.flac: corrupt audio file"), "Failures should be listed");
+  assert!(page.contains("<script>"), "The tables should sort without anything else to load");
+
+  // Without a path the page is all that's printed
+  let output = lyricsync_bin()
+    .args(["check", "--report", "html", "--directory"])
+    .arg(&library)
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(String::from_utf8_lossy(&output.stdout).starts_with("<!DOCTYPE html>"));
+
+  let output = lyricsync_bin().args(["check", "--report", "json", "-d"]).arg(&library).output().unwrap();
+  assert!(!output.status.success(), "check only writes HTML reports");
+}
+
+const LRCLIB_TRACK: &str = r#"{"id":1,"trackName":"I'm With You","artistName":"Avril Lavigne","albumName":"Let Go","duration":223,"instrumental":false,"plainLyrics":"I'm standing on a bridge","syncedLyrics":"[00:27.93]I'm standing on a bridge\n"}"#;
+
+#[test]
+fn test_fetch_from_lrclib() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let (url, requests) =
+    spawn_provider_mock(|path| if path.starts_with("/api/search") { (200, "[]") } else { (200, LRCLIB_TRACK) });
+
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"),
+    &test_dir_path.join("04 Avril Lavigne - I'm With You.mp3"),
+  );
+  // Files without artist/title tags can't be looked up
+  copy_test_file(Path::new("tests/fixtures/silence.wav"), &test_dir_path.join("untagged.wav"));
+
+  let output = lyricsync_bin()
+    .arg("fetch")
+    .arg("--no-cache")
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--lrclib-url")
+    .arg(&url)
+    .output()
+    .expect("Failed to execute lyricsync");
+
+  assert!(output.status.success(), "lyricsync fetch should succeed");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Fetched lyrics for 1 audio files"), "Tagged file should be fetched");
+  assert!(stdout.contains("Not found: 1"), "Untagged file should be reported as not found");
+  assert_eq!(
+    fs::read_to_string(test_dir_path.join("04 Avril Lavigne - I'm With You.lrc")).unwrap(),
+    "[00:27.93]I'm standing on a bridge\n"
+  );
+
+  let requests = requests.lock().unwrap();
+  assert_eq!(requests.len(), 1, "Only the tagged file should be looked up");
+  assert!(requests[0].contains("artist_name=Avril") && requests[0].contains("track_name=I"), "Query should use tags");
+
+  drop(requests);
+
+  // A second run finds the sidecar and doesn't query again
+  let output = lyricsync_bin()
+    .arg("fetch")
+    .arg("--no-cache")
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--lrclib-url")
+    .arg(&url)
+    .output()
+    .expect("Failed to execute lyricsync");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Fetched lyrics for 0 audio files"), "Files with a sidecar should be skipped");
+}
+
+#[test]
+fn test_fetch_embed_and_not_found() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"),
+    &test_dir_path.join("04 Avril Lavigne - I'm With You.mp3"),
+  );
+
+  let (missing_url, _) = spawn_provider_mock(|path| {
+    if path.starts_with("/api/search") { (200, "[]") } else { (404, r#"{"code":404,"name":"TrackNotFound"}"#) }
+  });
+
+  let output = lyricsync_bin()
+    .arg("fetch")
+    .arg("--no-cache")
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--lrclib-url")
+    .arg(&missing_url)
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "A missing track shouldn't be an error");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Not found: 1"), "Track should be reported as not found");
+
+  let (url, _) =
+    spawn_provider_mock(|path| if path.starts_with("/api/search") { (200, "[]") } else { (200, LRCLIB_TRACK) });
+
+  let output = lyricsync_bin()
+    .arg("fetch")
+    .arg("--no-cache")
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--lrclib-url")
+    .arg(&url)
+    .arg("--embed")
+    .output()
+    .expect("Failed to execute lyricsync");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Fetched lyrics for 1 audio files"), "Track should be fetched");
+  assert!(!test_dir_path.join("04 Avril Lavigne - I'm With You.lrc").exists(), "--embed shouldn't write a sidecar");
+
+  let output =
+    lyricsync_bin().arg("check").arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("EMBEDDED"), "Fetched lyrics should be embedded");
+}
+
+#[test]
+fn test_fetch_from_musixmatch_richsync() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let (url, requests) = spawn_provider_mock(|path| {
+    if path.starts_with("/matcher.track.get") {
+      (
+        200,
+        r#"{"message":{"header":{"status_code":200},"body":{"track":{"track_id":42,"has_richsync":1,"has_subtitles":1}}}}"#,
+      )
+    } else if path.starts_with("/track.richsync.get") {
+      (
+        200,
+        r#"{"message":{"header":{"status_code":200},"body":{"richsync":{"richsync_body":"[{\"ts\":27.93,\"te\":30.5,\"l\":[{\"c\":\"I'm\",\"o\":0}],\"x\":\"I'm standing on a bridge\"},{\"ts\":61.2,\"te\":63,\"l\":[],\"x\":\"I'm looking for a place to go\"}]"}}}}"#,
+      )
+    } else {
+      (200, r#"{"message":{"header":{"status_code":404},"body":""}}"#)
+    }
+  });
+
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"),
+    &test_dir_path.join("04 Avril Lavigne - I'm With You.mp3"),
+  );
+
+  // The token is mandatory for Musixmatch
+  let output = lyricsync_bin()
+    .arg("fetch")
+    .arg("--no-cache")
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--provider")
+    .arg("musixmatch")
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(!output.status.success(), "Musixmatch without a token should fail");
+
+  let output = lyricsync_bin()
+    .arg("fetch")
+    .arg("--no-cache")
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--provider")
+    .arg("musixmatch")
+    .arg("--musixmatch-token")
+    .arg("secret")
+    .arg("--musixmatch-url")
+    .arg(&url)
+    .output()
+    .expect("Failed to execute lyricsync");
+
+  assert!(output.status.success(), "lyricsync fetch should succeed");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Fetched lyrics for 1 audio files"), "Track should be fetched");
+  assert_eq!(
+    fs::read_to_string(test_dir_path.join("04 Avril Lavigne - I'm With You.lrc")).unwrap(),
+    "[00:27.93]I'm standing on a bridge\n[01:01.20]I'm looking for a place to go\n",
+    "Richsync should be converted to line-level LRC"
+  );
+
+  let requests = requests.lock().unwrap();
+  assert!(requests.iter().all(|request| request.contains("apikey=secret")), "Every request should carry the token");
+}
+
+#[test]
+fn test_fetch_unsynced_fallback_from_genius() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let (url, _) = spawn_provider_mock(|path| {
+    if path.starts_with("/api/search") {
+      (200, "[]")
+    } else if path.starts_with("/api/get") {
+      (404, r#"{"code":404,"name":"TrackNotFound"}"#)
+    } else if path.starts_with("/search") {
+      (
+        200,
+        r#"{"meta":{"status":200},"response":{"hits":[{"type":"song","result":{"title":"Other","path":"/Someone-else-lyrics","primary_artist":{"name":"Someone Else"}}},{"type":"song","result":{"title":"I'm With You","path":"/Avril-lavigne-im-with-you-lyrics","primary_artist":{"name":"Avril Lavigne"}}}]}}"#,
+      )
+    } else if path == "/Avril-lavigne-im-with-you-lyrics" {
+      (
+        200,
+        r#"<html><body><div data-lyrics-container="true" class="Lyrics"><div data-exclude-from-selection="true"><span>42 Contributors</span></div>[Verse 1]<br/>I&#x27;m standing on a bridge<br><a href="/x"><span>I&#x27;m waiting in the dark</span></a></div><div data-lyrics-container="true">I thought that you&#39;d be here by now</div></body></html>"#,
+      )
+    } else {
+      (404, "{}")
+    }
+  });
+
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"),
+    &test_dir_path.join("04 Avril Lavigne - I'm With You.mp3"),
+  );
+
+  // Without the opt-in, nothing is found
+  let output = lyricsync_bin()
+    .arg("fetch")
+    .arg("--no-cache")
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--lrclib-url")
+    .arg(&url)
+    .output()
+    .expect("Failed to execute lyricsync");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Not found: 1"), "Synced providers have nothing");
+
+  // The opt-in needs a Genius token
+  let output = lyricsync_bin()
+    .arg("fetch")
+    .arg("--no-cache")
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--allow-unsynced-fetch")
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(!output.status.success(), "--allow-unsynced-fetch without a token should fail");
+
+  let output = lyricsync_bin()
+    .arg("fetch")
+    .arg("--no-cache")
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--lrclib-url")
+    .arg(&url)
+    .arg("--allow-unsynced-fetch")
+    .arg("--genius-token")
+    .arg("secret")
+    .arg("--genius-api-url")
+    .arg(&url)
+    .arg("--genius-web-url")
+    .arg(&url)
+    .output()
+    .expect("Failed to execute lyricsync");
+
+  assert!(output.status.success(), "lyricsync fetch should succeed");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Fetched lyrics for 1 audio files"), "Genius should provide plain lyrics");
+  assert!(
+    !test_dir_path.join("04 Avril Lavigne - I'm With You.lrc").exists(),
+    "Plain lyrics shouldn't be saved as LRC"
+  );
+  assert_eq!(
+    fs::read_to_string(test_dir_path.join("04 Avril Lavigne - I'm With You.txt")).unwrap(),
+    "[Verse 1]\nI'm standing on a bridge\nI'm waiting in the dark\nI thought that you'd be here by now"
+  );
+}
+
+#[test]
+fn test_fetch_from_netease_with_translation() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let (url, requests) = spawn_provider_mock(|path| {
+    if path.starts_with("/api/search/get") {
+      (
+        200,
+        r#"{"result":{"songs":[{"id":1,"name":"I'm With You (Live)","artists":[{"name":"Avril Lavigne"}],"duration":null},{"id":2,"name":"I'm With You","artists":[{"name":"Cover Band"}],"duration":null},{"id":3,"name":"I'm with you","artists":[{"name":"Avril Lavigne"}],"duration":null}]},"code":200}"#,
+      )
+    } else if path.starts_with("/api/song/lyric") && path.contains("id=3") {
+      (
+        200,
+        r#"{"lrc":{"version":1,"lyric":"{\"t\":0,\"c\":[{\"tx\":\"Producer\"}]}\n[00:27.93]I'm standing on a bridge"},"tlyric":{"version":1,"lyric":"[00:27.93]我站在桥上"},"code":200}"#,
+      )
+    } else {
+      (200, r#"{"nolyric":true,"code":200}"#)
+    }
+  });
+
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"),
+    &test_dir_path.join("04 Avril Lavigne - I'm With You.mp3"),
+  );
+
+  let output = lyricsync_bin()
+    .arg("fetch")
+    .arg("--no-cache")
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--provider")
+    .arg("netease")
+    .arg("--netease-url")
+    .arg(&url)
+    .arg("--save-translation")
+    .output()
+    .expect("Failed to execute lyricsync");
+
+  assert!(output.status.success(), "lyricsync fetch should succeed");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Fetched lyrics for 1 audio files"), "Track should be fetched");
+  assert_eq!(
+    fs::read_to_string(test_dir_path.join("04 Avril Lavigne - I'm With You.lrc")).unwrap(),
+    "[00:27.93]I'm standing on a bridge\n",
+    "Credit lines should be dropped"
+  );
+  assert_eq!(
+    fs::read_to_string(test_dir_path.join("04 Avril Lavigne - I'm With You.zh.lrc")).unwrap(),
+    "[00:27.93]我站在桥上\n",
+    "The translation should be named for its language, to be embedded with the lyrics"
+  );
+
+  let requests = requests.lock().unwrap();
+  assert!(requests.iter().any(|request| request.contains("id=3")), "Only the matching song should be used");
+
+  let output = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .args(["--translations", "merge"])
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "Embedding should succeed");
+  let mp3_path = test_dir_path.join("04 Avril Lavigne - I'm With You.mp3");
+  assert_eq!(
+    lyricsync::extract_lyrics(&mp3_path, &lyricsync::FormatResolver::default()).unwrap().as_deref(),
+    Some("[00:27.93]I'm standing on a bridge\n[00:27.93]我站在桥上\n"),
+    "The saved translation should be merged in"
+  );
+}
+
+#[test]
+fn test_fetch_provider_chain_and_filters() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  // LRCLIB has a one-line stub, NetEase has the full lyrics
+  let (url, _) = spawn_provider_mock(|path| {
+    if path.starts_with("/api/search/get") {
+      (
+        200,
+        r#"{"result":{"songs":[{"id":3,"name":"I'm With You","artists":[{"name":"Avril Lavigne"}],"duration":null}]},"code":200}"#,
+      )
+    } else if path.starts_with("/api/song/lyric") {
+      (200, r#"{"lrc":{"lyric":"[00:27.93]I'm standing on a bridge\n[00:31.00]I'm waiting in the dark"},"code":200}"#)
+    } else if path.starts_with("/api/search") {
+      (200, r#"[{"duration":null,"syncedLyrics":"[00:27.93]I'm standing on a bridge\n"}]"#)
+    } else {
+      (404, r#"{"code":404,"name":"TrackNotFound"}"#)
+    }
+  });
+
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"),
+    &test_dir_path.join("04 Avril Lavigne - I'm With You.mp3"),
+  );
+  let lrc_path = test_dir_path.join("04 Avril Lavigne - I'm With You.lrc");
+
+  let fetch = |extra: &[&str]| {
+    let output = lyricsync_bin()
+      .arg("fetch")
+      .arg("--no-cache")
+      .arg("--directory")
+      .arg(test_dir_path)
+      .arg("--lrclib-url")
+      .arg(&url)
+      .arg("--netease-url")
+      .arg(&url)
+      .arg("--provider")
+      .arg("lrclib,netease")
+      .args(extra)
+      .output()
+      .expect("Failed to execute lyricsync");
+    assert!(output.status.success(), "lyricsync fetch should succeed");
+    String::from_utf8_lossy(&output.stdout).to_string()
+  };
+
+  // With NetEase disabled, the stub fails the line requirement and nothing is accepted
+  let stdout = fetch(&["--min-lines", "2", "--disable-provider", "netease", "--dry-run"]);
+  assert!(stdout.contains("Not found: 1"), "The stub should be rejected");
+
+  // With the whole chain, the walk continues past LRCLIB to NetEase
+  let stdout = fetch(&["--min-lines", "2"]);
+  assert!(stdout.contains("Fetched lyrics for 1 audio files"), "NetEase should satisfy the filter");
+  assert!(fs::read_to_string(&lrc_path).unwrap().contains("waiting in the dark"), "NetEase lyrics should be saved");
+
+  // Without a line requirement, the first provider in the chain wins
+  fs::remove_file(&lrc_path).unwrap();
+  fetch(&[]);
+  assert!(!fs::read_to_string(&lrc_path).unwrap().contains("waiting in the dark"), "LRCLIB lyrics should be saved");
+}
+
+#[test]
+fn test_fetch_cache_with_negative_caching() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+  let cache_dir = create_test_dir();
+
+  let (found_url, found_requests) =
+    spawn_provider_mock(|path| if path.starts_with("/api/search") { (200, "[]") } else { (200, LRCLIB_TRACK) });
+  let (missing_url, missing_requests) = spawn_provider_mock(|path| {
+    if path.starts_with("/api/search") { (200, "[]") } else { (404, r#"{"code":404,"name":"TrackNotFound"}"#) }
+  });
+
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"),
+    &test_dir_path.join("04 Avril Lavigne - I'm With You.mp3"),
+  );
+  let lrc_path = test_dir_path.join("04 Avril Lavigne - I'm With You.lrc");
+
+  let fetch = |url: &str, extra: &[&str]| {
+    let output = lyricsync_bin()
+      .arg("fetch")
+      .arg("--directory")
+      .arg(test_dir_path)
+      .arg("--lrclib-url")
+      .arg(url)
+      .arg("--cache-dir")
+      .arg(cache_dir.path())
+      .args(extra)
+      .output()
+      .expect("Failed to execute lyricsync");
+    assert!(output.status.success(), "lyricsync fetch should succeed");
+    String::from_utf8_lossy(&output.stdout).to_string()
+  };
+
+  // A miss is cached, so the second run doesn't query again
+  fetch(&missing_url, &[]);
+  let queried = missing_requests.lock().unwrap().len();
+  assert!(queried > 0, "The first run should query the provider");
+  let stdout = fetch(&missing_url, &[]);
+  assert!(stdout.contains("Not found: 1"), "The cached miss should be reported");
+  assert_eq!(missing_requests.lock().unwrap().len(), queried, "The cached miss shouldn't be queried again");
+
+  // An expired miss is queried again; a hit is cached for later runs
+  let stdout = fetch(&found_url, &["--negative-cache-ttl", "0"]);
+  assert!(stdout.contains("Fetched lyrics for 1 audio files"), "The expired miss should be re-queried");
+  let queried = found_requests.lock().unwrap().len();
+
+  fs::remove_file(&lrc_path).unwrap();
+  let stdout = fetch(&found_url, &[]);
+  assert!(stdout.contains("Fetched lyrics for 1 audio files"), "Lyrics should come from the cache");
+  assert!(lrc_path.exists(), "Cached lyrics should be saved");
+  assert_eq!(found_requests.lock().unwrap().len(), queried, "The cached hit shouldn't be queried again");
+
+  // --no-cache always goes to the provider
+  fs::remove_file(&lrc_path).unwrap();
+  fetch(&found_url, &["--no-cache"]);
+  assert!(found_requests.lock().unwrap().len() > queried, "--no-cache should bypass the cache");
+}
+
+#[test]
+fn test_id3_frames_option() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let mp3_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.mp3");
+  let lrc_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc");
+  let lrc_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.lrc");
+
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &mp3_dest);
+  copy_test_file(lrc_source, &lrc_dest);
+
+  let contains = |data: &[u8], needle: &[u8]| data.windows(needle.len()).any(|window| window == needle);
+
+  // The default writes both frames
+  let output = lyricsync_bin().arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "Embedding should succeed");
+  let data = fs::read(&mp3_dest).unwrap();
+  assert!(contains(&data, b"USLT") && contains(&data, b"SYLT"), "Both frames should be written");
+
+  // SYLT only replaces the earlier frames rather than adding to them
+  let output = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--rewrite")
+    .arg("--id3-frames")
+    .arg("sylt")
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "Embedding should succeed");
+  let data = fs::read(&mp3_dest).unwrap();
+  assert!(!contains(&data, b"USLT"), "USLT should be removed");
+  assert_eq!(data.windows(4).filter(|window| window == b"SYLT").count(), 1, "There should be a single SYLT frame");
+
+  // The SYLT frame round-trips back to the original LRC
+  fs::remove_file(&lrc_dest).unwrap();
+  let output =
+    lyricsync_bin().arg("extract").arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "lyricsync extract should succeed");
+  assert_eq!(fs::read_to_string(&lrc_dest).unwrap().trim_end(), fs::read_to_string(lrc_source).unwrap().trim_end());
+}
+
+#[test]
+fn test_id3_version_option() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let mp3_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.mp3");
+  let lrc_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc");
+  let lrc_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.lrc");
+
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &mp3_dest);
+  copy_test_file(lrc_source, &lrc_dest);
+
+  let output = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--id3-version")
+    .arg("2.3")
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "Embedding should succeed");
+  assert_eq!(&fs::read(&mp3_dest).unwrap()[..4], b"ID3\x03", "The tag should be saved as ID3v2.3");
+
+  // Both frames are still read back from a v2.3 tag
+  fs::remove_file(&lrc_dest).unwrap();
+  let output =
+    lyricsync_bin().arg("extract").arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "lyricsync extract should succeed");
+  assert_eq!(fs::read_to_string(&lrc_dest).unwrap(), fs::read_to_string(lrc_source).unwrap());
+
+  let output = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--id3-version")
+    .arg("2.3")
+    .arg("--id3-frames")
+    .arg("sylt")
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "Embedding should succeed");
+  fs::remove_file(&lrc_dest).unwrap();
+  let output =
+    lyricsync_bin().arg("extract").arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "lyricsync extract should succeed");
+  assert_eq!(fs::read_to_string(&lrc_dest).unwrap().trim_end(), fs::read_to_string(lrc_source).unwrap().trim_end());
+
+  // Stripping the lyrics leaves the rest of the tag at the version it was
+  let output =
+    lyricsync_bin().arg("strip").arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "lyricsync strip should succeed");
+  assert_eq!(&fs::read(&mp3_dest).unwrap()[..4], b"ID3\x03", "The tag should still be ID3v2.3");
+}
+
+#[test]
+fn test_encoding_option() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let mp3_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.mp3");
+  let lrc_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc");
+  let lrc_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.lrc");
+
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &mp3_dest);
+  copy_test_file(lrc_source, &lrc_dest);
+
+  // The encoding byte follows the 10-byte frame header
+  let frame_encoding = |frame_id: &[u8]| {
+    let data = fs::read(&mp3_dest).unwrap();
+    let position = data.windows(4).position(|window| window == frame_id).expect("Frame should be written");
+    data[position + 10]
+  };
+  let embed = |encoding: &str| {
+    let output = lyricsync_bin()
+      .arg("--directory")
+      .arg(test_dir_path)
+      .arg("--rewrite")
+      .arg("--encoding")
+      .arg(encoding)
+      .output()
+      .expect("Failed to execute lyricsync");
+    assert!(output.status.success(), "Embedding should succeed");
+  };
+
+  embed("utf16");
+  assert_eq!(frame_encoding(b"USLT"), 1, "USLT should be UTF-16");
+  assert_eq!(frame_encoding(b"SYLT"), 1, "SYLT should be UTF-16");
+
+  embed("latin1");
+  assert_eq!(frame_encoding(b"USLT"), 0, "USLT should be Latin-1");
+  assert_eq!(frame_encoding(b"SYLT"), 0, "SYLT should be Latin-1");
+
+  // Lyrics that don't fit in Latin-1 fall back to UTF-16
+  fs::write(&lrc_dest, "[00:01.00]君と一緒\n").unwrap();
+  embed("latin1");
+  assert_eq!(frame_encoding(b"USLT"), 1, "USLT should fall back to UTF-16");
+
+  fs::remove_file(&lrc_dest).unwrap();
+  let output =
+    lyricsync_bin().arg("extract").arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "lyricsync extract should succeed");
+  assert_eq!(fs::read_to_string(&lrc_dest).unwrap(), "[00:01.00]君と一緒\n");
+}
+
+#[test]
+fn test_language_option() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let mp3_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.mp3");
+  let lrc_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.lrc");
+
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &mp3_dest);
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc"), &lrc_dest);
+
+  // The language code follows the 10-byte frame header and the encoding byte
+  let frame_languages = |frame_id: &[u8]| {
+    let data = fs::read(&mp3_dest).unwrap();
+    (0..data.len() - 14)
+      .filter(|&position| &data[position..position + 4] == frame_id)
+      .map(|position| String::from_utf8_lossy(&data[position + 11..position + 14]).into_owned())
+      .collect::<Vec<_>>()
+  };
+  let embed = |language: &str| {
+    lyricsync_bin()
+      .arg("--directory")
+      .arg(test_dir_path)
+      .arg("--language")
+      .arg(language)
+      .output()
+      .expect("Failed to execute lyricsync")
+  };
+
+  // Latin-script lyrics fall back to eng
+  assert!(embed("auto").status.success(), "Embedding should succeed");
+  assert_eq!(frame_languages(b"USLT"), ["eng"]);
+  assert_eq!(frame_languages(b"SYLT"), ["eng"]);
+
+  // Re-embedding under another language replaces the frames instead of adding to them
+  assert!(embed("FRA").status.success(), "Embedding should succeed");
+  assert_eq!(frame_languages(b"USLT"), ["fra"]);
+  assert_eq!(frame_languages(b"SYLT"), ["fra"]);
+
+  fs::write(&lrc_dest, "[ar:Someone]\n[00:01.00]君と一緒に\n[00:03.00]Stay with me\n").unwrap();
+  assert!(embed("auto").status.success(), "Embedding should succeed");
+  assert_eq!(frame_languages(b"USLT"), ["jpn"], "Japanese lyrics should be detected");
+
+  let output = embed("english");
+  assert!(!output.status.success(), "Invalid language codes should be rejected");
+}
+
+#[test]
+fn test_description_option() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let mp3_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.mp3");
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &mp3_dest);
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc"),
+    &test_dir_path.join("04 Avril Lavigne - I'm With You.lrc"),
+  );
+
+  let count =
+    |needle: &[u8]| fs::read(&mp3_dest).unwrap().windows(needle.len()).filter(|window| window == &needle).count();
+  let embed = |extra: &[&str]| {
+    let output =
+      lyricsync_bin().arg("--directory").arg(test_dir_path).args(extra).output().expect("Failed to execute lyricsync");
+    assert!(output.status.success(), "Embedding should succeed");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+  };
+
+  embed(&[]);
+  embed(&["--description", "Karaoke"]);
+  assert_eq!(count(b"USLT"), 2, "Frames with another description should be kept");
+  assert_eq!(count(b"SYLT"), 2, "Frames with another description should be kept");
+  assert_eq!(count(b"Karaoke"), 2, "The description should be written to both frames");
+
+  // Re-embedding replaces only the frames with the same description
+  embed(&["--description", "Karaoke"]);
+  assert_eq!(count(b"USLT"), 2, "Frames should be replaced, not duplicated");
+  assert_eq!(count(b"Karaoke"), 2, "Frames should be replaced, not duplicated");
+
+  // --skip only looks for the requested description
+  let stdout = embed(&["--skip", "--description", "Romaji"]);
+  assert!(stdout.contains("Embedded lyrics in 1 audio files"), "A missing description should not be skipped");
+  let stdout = embed(&["--skip"]);
+  assert!(stdout.contains("Embedded lyrics in 0 audio files"), "Any lyrics frame should be skipped by default");
+}
+
+#[test]
+fn test_multi_language_sidecars() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let mp3_dest = test_dir_path.join("Song.mp3");
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &mp3_dest);
+  fs::write(test_dir_path.join("Song.en.lrc"), "[00:01.00]With you\n").unwrap();
+  fs::write(test_dir_path.join("Song.ja.lrc"), "[00:01.00]君と\n").unwrap();
+  // Not a language code, so not a sidecar
+  fs::write(test_dir_path.join("Song.alt.lrc"), "[00:01.00]Alternate\n").unwrap();
+
+  let frame_languages = |frame_id: &[u8]| {
+    let data = fs::read(&mp3_dest).unwrap();
+    (0..data.len() - 14)
+      .filter(|&position| &data[position..position + 4] == frame_id)
+      .map(|position| String::from_utf8_lossy(&data[position + 11..position + 14]).into_owned())
+      .collect::<Vec<_>>()
+  };
+  let embed = || {
+    let output = lyricsync_bin()
+      .arg("--directory")
+      .arg(test_dir_path)
+      .arg("--skip")
+      .output()
+      .expect("Failed to execute lyricsync");
+    assert!(output.status.success(), "Embedding should succeed");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+  };
+
+  assert!(embed().contains("Embedded lyrics in 1 audio files"), "Language sidecars should be embedded");
+  assert_eq!(frame_languages(b"USLT"), ["eng", "jpn"], "There should be one USLT frame per language");
+  assert_eq!(frame_languages(b"SYLT"), ["eng", "jpn"], "There should be one SYLT frame per language");
+  let data = fs::read(&mp3_dest).unwrap();
+  assert!(!data.windows(9).any(|window| window == b"Alternate"), "Song.alt.lrc should be ignored");
+
+  // --skip only skips once every language is embedded
+  assert!(embed().contains("Embedded lyrics in 0 audio files"), "Embedded languages should be skipped");
+  fs::write(test_dir_path.join("Song.ko.lrc"), "[00:01.00]너와\n").unwrap();
+  assert!(embed().contains("Embedded lyrics in 1 audio files"), "A new language should be embedded");
+  assert_eq!(frame_languages(b"USLT"), ["eng", "jpn", "kor"]);
+}
+
+#[test]
+fn test_translation_frames() {
+  let test_dir = create_test_dir();
+  let mp3_path = test_dir.path().join("Song.mp3");
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &mp3_path);
+  fs::write(test_dir.path().join("Song.lrc"), "[00:01.00]With you\n").unwrap();
+  fs::write(test_dir.path().join("Song.zh.lrc"), "[00:01.00]和你\n").unwrap();
+  let embed = |args: &[&str]| {
+    let output = lyricsync_bin().args(["--rewrite", "-d"]).arg(test_dir.path()).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  };
+  // The language code follows the encoding byte, and the USLT frame's description comes right after
+  let count = |pattern: &[u8]| fs::read(&mp3_path).unwrap().windows(pattern.len()).filter(|w| *w == pattern).count();
+
+  embed(&[]);
+  assert_eq!(count(b"eng\0[00:01.00]With you"), 1, "The original should keep a frame without a description");
+  assert_eq!(count(b"chitranslation\0"), 1, "The translation should get a USLT frame of its own");
+  assert_eq!(count(b"translation"), 2, "The translation should get a SYLT frame of its own");
+  let lyrics = lyricsync::extract_lyrics(&mp3_path, &lyricsync::FormatResolver::default()).unwrap();
+  assert_eq!(lyrics.as_deref(), Some("[00:01.00]With you\n"), "The original should be read back");
+
+  // Another description leaves the earlier frames to be replaced, translations included
+  embed(&["--description", "Synced"]);
+  assert_eq!(count(b"translation"), 2);
+}
+
+#[test]
+fn test_merge_translations() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("Song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  fs::write(test_dir.path().join("Song.lrc"), "[00:01.00]Hello\n[00:03.00]World\n").unwrap();
+  fs::write(test_dir.path().join("Song.zh.lrc"), "[00:01.00]你好\n[00:03.00]世界\n").unwrap();
+  let embed = |args: &[&str]| {
+    let output = lyricsync_bin().args(["--rewrite", "-d"]).arg(test_dir.path()).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap().unwrap()
+  };
+
+  assert_eq!(embed(&[]), "[00:01.00]Hello\n[00:03.00]World\n", "Opus files only take the original by default");
+  assert_eq!(embed(&["--translations", "merge"]), "[00:01.00]Hello\n[00:01.00]你好\n[00:03.00]World\n[00:03.00]世界\n");
+  // Reducing deletes the translation along with the original
+  embed(&["--translations", "merge", "--reduce"]);
+  assert!(!test_dir.path().join("Song.zh.lrc").exists());
+}
+
+#[test]
+fn test_conflict_policies() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let lrc_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.lrc");
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"),
+    &test_dir_path.join("04 Avril Lavigne - I'm With You.mp3"),
+  );
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc"), &lrc_dest);
+
+  let embed = |flags: &[&str]| {
+    let output =
+      lyricsync_bin().arg("--directory").arg(test_dir_path).args(flags).output().expect("Failed to execute lyricsync");
+    assert!(output.status.success(), "Embedding should succeed");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+  };
+
+  assert!(embed(&["--force"]).contains("Embedded lyrics in 1 audio files"), "Lyrics should be embedded");
+
+  // The audio file was just written, so the sidecar is older
+  let stdout = embed(&["--if-newer", "--rewrite"]);
+  assert!(stdout.contains("Embedded lyrics in 0 audio files"), "An older sidecar should be skipped");
+
+  std::thread::sleep(std::time::Duration::from_millis(50));
+  fs::write(&lrc_dest, "[00:01.00]Updated\n").unwrap();
+  assert!(embed(&["--if-newer"]).contains("Embedded lyrics in 1 audio files"), "A newer sidecar should be embedded");
+  fs::write(&lrc_dest, "[00:01.00]Updated again\n").unwrap();
+  assert!(embed(&["--force"]).contains("Embedded lyrics in 1 audio files"), "--force should always overwrite");
+
+  let output = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--skip")
+    .arg("--force")
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(!output.status.success(), "--skip and --force should conflict");
+}
+
+#[test]
+fn test_replace_option() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let mp3_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.mp3");
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &mp3_dest);
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc"),
+    &test_dir_path.join("04 Avril Lavigne - I'm With You.lrc"),
+  );
+
+  let count =
+    |needle: &[u8]| fs::read(&mp3_dest).unwrap().windows(needle.len()).filter(|window| window == &needle).count();
+  let embed = |extra: &[&str]| {
+    let output = lyricsync_bin()
+      .arg("--directory")
+      .arg(test_dir_path)
+      .arg("--rewrite")
+      .args(extra)
+      .output()
+      .expect("Failed to execute lyricsync");
+    assert!(output.status.success(), "Embedding should succeed");
+  };
+
+  embed(&[]);
+  embed(&["--description", "Karaoke"]);
+  assert_eq!((count(b"USLT"), count(b"SYLT")), (2, 2));
+
+  // Only the USLT frames being written are replaced; the SYLT frame stays
+  embed(&["--id3-frames", "uslt", "--replace", "matching"]);
+  assert_eq!((count(b"USLT"), count(b"SYLT")), (2, 2), "Matching should leave SYLT frames alone");
+
+  // The default clears this tool's own SYLT frame, but not the one with another description
+  embed(&["--id3-frames", "uslt"]);
+  assert_eq!((count(b"USLT"), count(b"SYLT")), (2, 1), "Own should clear the stale SYLT frame");
+
+  embed(&["--id3-frames", "uslt", "--replace", "all"]);
+  assert_eq!((count(b"USLT"), count(b"SYLT")), (1, 0), "All should clear every other lyrics frame");
+}
+
+#[test]
+fn test_vorbis_key_options() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let opus_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &opus_dest);
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc"),
+    &test_dir_path.join("04 Avril Lavigne - I'm With You.lrc"),
+  );
+
+  let count =
+    |needle: &[u8]| fs::read(&opus_dest).unwrap().windows(needle.len()).filter(|window| window == &needle).count();
+
+  let output = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--vorbis-key")
+    .arg("both")
+    .arg("--synced-lyrics-key")
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "Embedding should succeed");
+  assert_eq!(count(b"UNSYNCEDLYRICS="), 1, "UNSYNCEDLYRICS should be written");
+  assert_eq!(count(b"SYNCEDLYRICS="), 2, "SYNCEDLYRICS should be written");
+  assert_eq!(count(b"LYRICS="), 3, "LYRICS should be written");
+
+  // Re-embedding with the defaults leaves only LYRICS
+  let output = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--rewrite")
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "Embedding should succeed");
+  assert_eq!(count(b"LYRICS="), 1, "Only LYRICS should remain");
+  assert_eq!(count(b"SYNCEDLYRICS="), 0, "Stale keys should be cleared");
+}
+
+#[test]
+fn test_mp4_lyrics_option() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let m4a_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.m4a");
+  let lrc_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc");
+  let lrc_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.lrc");
+
+  copy_test_file(Path::new("tests/fixtures/silence.m4a"), &m4a_dest);
+  copy_test_file(lrc_source, &lrc_dest);
+
+  let contains = |needle: &[u8]| fs::read(&m4a_dest).unwrap().windows(needle.len()).any(|window| window == needle);
+  let embed = |mode: &str| {
+    let output = lyricsync_bin()
+      .arg("--directory")
+      .arg(test_dir_path)
+      .arg("--rewrite")
+      .arg("--mp4-lyrics")
+      .arg(mode)
+      .output()
+      .expect("Failed to execute lyricsync");
+    assert!(output.status.success(), "Embedding should succeed");
+  };
+
+  embed("both");
+  assert!(contains(b"\xa9lyr"), "The ©lyr atom should be written");
+  assert!(contains(b"com.apple.iTunes") && contains(b"LYRICS"), "The freeform atom should be written");
+
+  embed("timed");
+  assert!(!contains(b"\xa9lyr"), "The ©lyr atom should be removed");
+  assert!(contains(b"com.apple.iTunes"), "The freeform atom should be kept");
+
+  // Timed lyrics are still found by extract
+  fs::remove_file(&lrc_dest).unwrap();
+  let output =
+    lyricsync_bin().arg("extract").arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "lyricsync extract should succeed");
+  assert_eq!(fs::read_to_string(&lrc_dest).unwrap(), fs::read_to_string(lrc_source).unwrap());
+}
+
+#[test]
+fn test_json_logging() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &test_dir_path.join("song.opus"));
+  fs::write(test_dir_path.join("song.lrc"), "[00:01.00]Logged lyrics\n").unwrap();
+  fs::write(test_dir_path.join("broken.flac"), "not a FLAC file").unwrap();
+  fs::write(test_dir_path.join("broken.lrc"), "[00:01.00]Logged lyrics\n").unwrap();
+
+  let output = lyricsync_bin()
+    .args(["embed", "--log-level", "info", "--log-format", "json", "--directory"])
+    .arg(test_dir_path)
+    .output()
+    .expect("Failed to execute lyricsync");
+
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  let records: Vec<serde_json::Value> =
+    stderr.lines().map(|line| serde_json::from_str(line).expect("Every log line should be JSON")).collect();
+
+  let embedded = records.iter().find(|record| record["fields"]["message"] == "embedded 1 sidecar(s)");
+  let embedded = embedded.expect("The embedded file should be logged");
+  assert_eq!(embedded["level"], "INFO");
+  assert!(embedded["spans"][0]["path"].as_str().unwrap().ends_with("song.opus"), "Records should carry the file");
+
+  let failed = records.iter().find(|record| record["level"] == "ERROR").expect("The failure should be logged");
+  assert!(failed["fields"]["message"].as_str().unwrap().contains("broken.flac: corrupt audio file"));
+}
+
+#[test]
+fn test_quiet_and_verbose() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &test_dir_path.join("song.opus"));
+  fs::write(test_dir_path.join("song.lrc"), "[00:01.00]Logged lyrics\n").unwrap();
+  copy_test_file(Path::new("tests/fixtures/silence.wav"), &test_dir_path.join("other.wav"));
+
+  let run = |args: &[&str]| {
+    let output = lyricsync_bin().args(["embed", "--dry-run"]).args(args).arg("-d").arg(test_dir_path).output().unwrap();
+    assert!(output.status.success(), "lyricsync {} should succeed", args.join(" "));
+    (String::from_utf8_lossy(&output.stdout).into_owned(), String::from_utf8_lossy(&output.stderr).into_owned())
+  };
+
+  let (stdout, stderr) = run(&["--quiet"]);
+  assert!(stdout.contains("Summary:"), "--quiet should keep the summary");
+  assert!(!stdout.contains("No files will be modified"), "--quiet should drop the banner");
+  assert!(stderr.is_empty(), "--quiet should print nothing else: {stderr}");
+
+  let (_, stderr) = run(&["-v"]);
+  assert!(stderr.contains("would embed 1 sidecar(s)"), "-v should log what's done with each file");
+  assert!(stderr.contains("skipped, no LRC sidecar"), "-v should log skipped files");
+  assert!(!stderr.contains("matched"), "-v shouldn't log how sidecars were matched");
+
+  let (_, stderr) = run(&["-vv"]);
+  assert!(stderr.contains("matched sidecar="), "-vv should log the sidecars matched");
+  assert!(stderr.contains("found LRC sidecars count=1"), "-vv should log how many sidecars were found");
+
+  let output = lyricsync_bin().args(["-q", "-v", "-d"]).arg(test_dir_path).output().unwrap();
+  assert_eq!(output.status.code(), Some(2), "--quiet and --verbose conflict");
+}
+
+#[test]
+fn test_plain_output_when_piped() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &test_dir_path.join("song.opus"));
+  fs::write(test_dir_path.join("song.lrc"), "[00:01.00]Piped lyrics\n").unwrap();
+
+  // The tests' stdout is never a terminal
+  let output = lyricsync_bin().args(["--dry-run", "-d"]).arg(test_dir_path).output().unwrap();
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(output.status.success());
+  assert!(!stdout.contains("No files will be modified"), "Piped output shouldn't have the banner");
+  assert!(stdout.contains("[DRY RUN] Would embed: "), "Piped output should have a line per file: {stdout}");
+  assert!(!stdout.contains('\r') && !stdout.contains('\x1b'), "Piped output shouldn't have control characters");
+
+  let run = |color: &str, no_color: Option<&str>| {
+    let mut command = lyricsync_bin();
+    command.args(["--dry-run", "-v", "--color", color, "-d"]).arg(test_dir_path);
+    match no_color {
+      Some(value) => command.env("NO_COLOR", value),
+      None => command.env_remove("NO_COLOR"),
+    };
+    let output = command.output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8_lossy(&output.stderr).contains('\x1b')
+  };
+  assert!(run("always", None), "--color always should color the log");
+  assert!(run("always", Some("1")), "--color always should win over NO_COLOR");
+  assert!(!run("never", None), "--color never shouldn't color the log");
+  assert!(!run("auto", Some("1")), "NO_COLOR should turn colors off");
+}
+
+#[test]
+fn test_config_file() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path().join("library");
+  let config_home = test_dir.path().join("config");
+  fs::create_dir(&library).unwrap();
+  fs::create_dir_all(config_home.join("lyricsync")).unwrap();
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &library.join("song.opus"));
+  fs::write(library.join("song.lrc"), "[00:01.00]First lyrics\n").unwrap();
+  assert!(lyricsync_bin().arg("-d").arg(&library).output().unwrap().status.success());
+  fs::write(library.join("song.lrc"), "[00:01.00]Second lyrics\n").unwrap();
+
+  let config = format!("directory = {:?}\n\n[embed]\nskip = true\n", library.to_str().unwrap());
+  fs::write(config_home.join("lyricsync").join("config.toml"), config).unwrap();
+  let run = |args: &[&str]| lyricsync_bin().env("XDG_CONFIG_HOME", &config_home).args(args).output().unwrap();
+
+  let output = run(&[]);
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(output.status.success(), "The directory should come from the config file");
+  assert!(stdout.contains("Skipped: "), "skip = true should keep the embedded lyrics: {stdout}");
+
+  let output = run(&["--force"]);
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Embedded: "), "--force should win over skip in the config file: {stdout}");
+
+  let other = test_dir.path().join("other.toml");
+  fs::write(&other, "bogus = 1\n").unwrap();
+  let output = run(&["check", "--config", other.to_str().unwrap()]);
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert_eq!(output.status.code(), Some(2), "An unknown option is a usage error");
+  assert!(stderr.contains("unknown option `bogus`"), "The error should name the option: {stderr}");
+}
+
+#[test]
+fn test_config_profiles() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path().join("library");
+  let config = test_dir.path().join("config.toml");
+  fs::create_dir(&library).unwrap();
+  let mp3 = library.join("song.mp3");
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &mp3);
+  fs::write(library.join("song.lrc"), "[00:01.00]First lyrics\n").unwrap();
+  assert!(lyricsync_bin().arg("-d").arg(&library).output().unwrap().status.success());
+  fs::write(library.join("song.lrc"), "[00:01.00]Second lyrics\n").unwrap();
+
+  let profiles = "[embed]\nskip = true\n\n[profile.car]\nid3-version = \"2.3\"\n\n[profile.car.embed]\nforce = true\n";
+  fs::write(&config, profiles).unwrap();
+  let run = |args: &[&str]| {
+    lyricsync_bin().arg("embed").arg("--config").arg(&config).args(args).arg("-d").arg(&library).output().unwrap()
+  };
+  // The fourth byte of an ID3v2 header is its major version
+  let version = || fs::read(&mp3).unwrap()[3];
+
+  let stdout = String::from_utf8_lossy(&run(&[]).stdout).into_owned();
+  assert!(stdout.contains("Skipped: "), "Without a profile the file's top level applies: {stdout}");
+  assert_eq!(version(), 4);
+
+  let stdout = String::from_utf8_lossy(&run(&["--profile", "car"]).stdout).into_owned();
+  assert!(stdout.contains("Embedded: "), "The profile's force should take over from skip: {stdout}");
+  assert_eq!(version(), 3, "The profile's ID3v2 version should be used");
+
+  let output = run(&["--profile", "boat"]);
+  assert_eq!(output.status.code(), Some(2), "An unknown profile is a usage error");
+  assert!(String::from_utf8_lossy(&output.stderr).contains("no profile `boat`"));
+}
+
+#[test]
+fn test_environment_variables() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path().join("library");
+  let config = test_dir.path().join("config.toml");
+  fs::create_dir(&library).unwrap();
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &library.join("song.opus"));
+  fs::write(library.join("song.lrc"), "[00:01.00]First lyrics\n").unwrap();
+  assert!(lyricsync_bin().arg("-d").arg(&library).output().unwrap().status.success());
+  fs::write(library.join("song.lrc"), "[00:01.00]Second lyrics\n").unwrap();
+  fs::write(&config, "[embed]\nskip = true\n").unwrap();
+
+  let output = lyricsync_bin().env("LYRICSYNC_DIRECTORY", &library).env("LYRICSYNC_DRY_RUN", "1").output().unwrap();
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(output.status.success(), "The directory should come from the environment");
+  assert!(stdout.contains("[DRY RUN] Would embed: "), "Flags should take 1 for true: {stdout}");
+
+  let output = lyricsync_bin()
+    .args(["embed", "--config"])
+    .arg(&config)
+    .env("LYRICSYNC_DIRECTORY", &library)
+    .env("LYRICSYNC_FORCE", "yes")
+    .output()
+    .unwrap();
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Embedded: "), "The environment should win over the config file: {stdout}");
+
+  let output = lyricsync_bin().env("LYRICSYNC_DIRECTORY", "/nonexistent").arg("-d").arg(&library).output().unwrap();
+  assert!(output.status.success(), "The command line should win over the environment");
+
+  let output = lyricsync_bin().env("LYRICSYNC_JOBS", "many").arg("-d").arg(&library).output().unwrap();
+  assert_eq!(output.status.code(), Some(2), "A bad value in the environment is a usage error");
+}
+
+#[test]
+fn test_directory_config_file() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+  let mp3 = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3");
+  for folder in ["Japanese/Album", "English"] {
+    fs::create_dir_all(test_dir_path.join(folder)).unwrap();
+    copy_test_file(mp3, &test_dir_path.join(folder).join("song.mp3"));
+    fs::write(test_dir_path.join(folder).join("song.lrc"), "[00:01.00]Folder lyrics\n").unwrap();
+  }
+  fs::write(test_dir_path.join("Japanese").join(".lyricsync.toml"), "id3-version = \"2.3\"\nlanguage = \"jpn\"\n")
+    .unwrap();
+
+  let output = lyricsync_bin().args(["-R", "-d"]).arg(test_dir_path).output().unwrap();
+  assert!(output.status.success(), "The run should succeed");
+  // The fourth byte of an ID3v2 header is its major version
+  let version = |folder: &str| fs::read(test_dir_path.join(folder).join("song.mp3")).unwrap()[3];
+  assert_eq!(version("Japanese/Album"), 3, "Files below the folder should get its options");
+  assert_eq!(version("English"), 4, "Other folders should keep the command line's");
+
+  fs::write(test_dir_path.join("English").join(".lyricsync.toml"), "jobs = 4\n").unwrap();
+  let output = lyricsync_bin().args(["-R", "-d"]).arg(test_dir_path).output().unwrap();
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(!output.status.success(), "A bad folder config file should stop the run");
+  assert!(stderr.contains("`jobs` can't be set for a directory"), "The error should name the option: {stderr}");
+}
+
+#[test]
+fn test_fuzzy_matching() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &test_dir_path.join("01 - Song.opus"));
+  fs::write(test_dir_path.join("song.lrc"), "[00:01.00]Fuzzy lyrics\n").unwrap();
+
+  let run = |args: &[&str]| lyricsync_bin().args(["--dry-run", "-d"]).arg(test_dir_path).args(args).output().unwrap();
+  let stdout = String::from_utf8_lossy(&run(&[]).stdout).into_owned();
+  assert!(!stdout.contains("Would embed: "), "Names should have to match without --fuzzy: {stdout}");
+  let stdout = String::from_utf8_lossy(&run(&["--fuzzy"]).stdout).into_owned();
+  assert!(stdout.contains("Would embed: "), "--fuzzy should ignore the track number and case: {stdout}");
+
+  assert_eq!(run(&["--fuzzy", "--min-confidence", "1.5"]).status.code(), Some(2), "Confidence goes from 0 to 1");
+}
+
+#[test]
+fn test_tag_matching() {
+  let test_dir = create_test_dir();
+  let music = test_dir.path().join("music");
+  let lyrics = test_dir.path().join("lyrics");
+  fs::create_dir_all(music.join("Let Go")).unwrap();
+  fs::create_dir_all(lyrics.join("Avril Lavigne")).unwrap();
+  let audio_path = music.join("Let Go").join("track04.mp3");
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &audio_path);
+  // Named after the tags, with the title alone as a weaker match
+  fs::write(lyrics.join("Avril Lavigne").join("Avril Lavigne - I'm With You.lrc"), "[00:01.00]By artist\n").unwrap();
+  fs::write(lyrics.join("I'm With You.lrc"), "[00:01.00]By title\n").unwrap();
+
+  let run = |args: &[&str]| lyricsync_bin().args(["--recursive", "-d"]).arg(&music).args(args).output().unwrap();
+  let stdout = String::from_utf8_lossy(&run(&["--dry-run", "--by-tags"]).stdout).into_owned();
+  assert!(!stdout.contains("Would embed: "), "The lyrics aren't under the music directory: {stdout}");
+
+  let output = run(&["--by-tags", "--lyrics-dir", lyrics.to_str().unwrap()]);
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  let embedded = lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap().unwrap();
+  assert!(embedded.contains("By artist"), "The artist and title should win over the title alone: {embedded}");
+
+  // A file that fails leaves the lyrics folder alone, as other files may take theirs from it
+  let shared = [lyrics.join("Avril Lavigne").join("Avril Lavigne - I'm With You.lrc"), lyrics.join("I'm With You.lrc")];
+  shared.iter().for_each(|path| fs::write(path, "No timestamps\n").unwrap());
+  let output = run(&["--by-tags", "--lyrics-dir", lyrics.to_str().unwrap(), "--reject-invalid"]);
+  assert_eq!(output.status.code(), Some(3), "The invalid lyrics should fail the file");
+  assert!(shared.iter().all(|path| path.exists()), "Lyrics matched by tags shouldn't be renamed");
+
+  assert_eq!(run(&["--lyrics-dir", "lyrics"]).status.code(), Some(2), "--lyrics-dir is for --by-tags");
+}
+
+#[test]
+fn test_mapping_file() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path().join("library");
+  fs::create_dir_all(library.join("lyrics")).unwrap();
+  for name in ["song.opus", "track02.opus"] {
+    copy_test_file(Path::new("tests/fixtures/silence.opus"), &library.join(name));
+  }
+  fs::write(library.join("song.lrc"), "[00:00.50]Same name\n").unwrap();
+  fs::write(library.join("lyrics").join("Song, live.lrc"), "[00:00.50]Mapped\n").unwrap();
+  fs::write(library.join("lyrics").join("Second Song.lrc"), "[00:00.50]Second\n").unwrap();
+  let lyrics = |name: &str| lyricsync::extract_lyrics(&library.join(name), &lyricsync::FormatResolver::default());
+
+  // Paths are relative to the mapping, and a mapping wins over a sidecar named like the file
+  let csv = library.join("pairs.csv");
+  fs::write(&csv, "audio_path,lrc_path\n# curated\nsong.opus,\"lyrics/Song, live.lrc\"\n").unwrap();
+  let json = test_dir.path().join("pairs.json");
+  fs::write(&json, r#"[{"audio_path": "library/track02.opus", "lrc_path": "library/lyrics/Second Song.lrc"}]"#)
+    .unwrap();
+  for mapping in [&json, &csv] {
+    let output = lyricsync_bin().arg("-d").arg(&library).arg("--map").arg(mapping).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  }
+  assert!(lyrics("song.opus").unwrap().unwrap().contains("Mapped"));
+  assert!(lyrics("track02.opus").unwrap().unwrap().contains("Second"));
+
+  let tsv = library.join("pairs.tsv");
+  fs::write(&tsv, "song.opus\n").unwrap();
+  let output = lyricsync_bin().arg("-d").arg(&library).arg("--map").arg(&tsv).output().unwrap();
+  assert!(!output.status.success(), "A row without an LRC path should be an error");
+  assert!(String::from_utf8_lossy(&output.stderr).contains("line 1: expected an audio path and an LRC path"));
+}
+
+#[test]
+fn test_review_and_interactive_need_terminal() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  fs::write(test_dir.path().join("song.lrc"), "[00:00.50]Reviewed\n").unwrap();
+
+  let run = |args: &[&str]| {
+    let mut command = lyricsync_bin();
+    command.arg("-d").arg(test_dir.path()).args(args).stdin(std::process::Stdio::null()).output().unwrap()
+  };
+  let output = run(&["--review"]);
+  assert_eq!(output.status.code(), Some(2), "Nobody is there to review the matches");
+  assert!(String::from_utf8_lossy(&output.stderr).contains("--review needs a terminal"));
+  assert_eq!(run(&["--review", "--progress", "jsonl"]).status.code(), Some(2), "Progress events use stdout");
+  let output = run(&["--interactive", "--fuzzy"]);
+  assert_eq!(output.status.code(), Some(2), "Nobody is there to answer the prompts");
+  assert!(String::from_utf8_lossy(&output.stderr).contains("--interactive needs a terminal"));
+  let embedded = lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default()).unwrap();
+  assert!(embedded.is_none(), "Nothing should be written without a review");
+}
+
+#[test]
+fn test_include_exclude_globs() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path();
+  for (fixture, path) in
+    [("silence.opus", "album/a.opus"), ("silence.wav", "album/b.wav"), ("silence.opus", "Podcasts/ep.opus")]
+  {
+    fs::create_dir_all(library.join(path).parent().unwrap()).unwrap();
+    copy_test_file(&Path::new("tests/fixtures").join(fixture), &library.join(path));
+    fs::write(library.join(path).with_extension("lrc"), "[00:00.50]Lyrics\n").unwrap();
+  }
+
+  let run = |args: &[&str]| {
+    let output = lyricsync_bin().args(["--dry-run", "--recursive", "-d"]).arg(library).args(args).output().unwrap();
+    String::from_utf8_lossy(&output.stdout).into_owned()
+  };
+  let stdout = run(&["--include", "*.opus", "--exclude", "**/Podcasts/**"]);
+  assert!(stdout.contains("Total audio files: 1"), "{stdout}");
+  assert!(stdout.contains("a.opus") && !stdout.contains("ep.opus"), "{stdout}");
+  let stdout = run(&["--exclude", "Podcasts"]);
+  assert!(stdout.contains("Total audio files: 2"), "An excluded folder shouldn't be walked: {stdout}");
+
+  let output =
+    lyricsync_bin().args(["check", "--recursive", "--include", "album/*", "-d"]).arg(library).output().unwrap();
+  assert!(String::from_utf8_lossy(&output.stdout).contains("Total audio files: 2"));
+  let output = lyricsync_bin().arg("-d").arg(library).args(["--include", "a[b"]).output().unwrap();
+  assert_eq!(output.status.code(), Some(2), "A pattern that doesn't parse should be rejected up front");
+}
+
+#[test]
+fn test_name_rules() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path().join("library");
+  fs::create_dir(&library).unwrap();
+  for name in ["Song [Explicit].opus", "Artist feat. Guest - Other.opus"] {
+    copy_test_file(Path::new("tests/fixtures/silence.opus"), &library.join(name));
+  }
+  fs::write(library.join("Song.lrc"), "[00:00.50]Clean name\n").unwrap();
+  fs::write(library.join("Artist ft. Guest - Other.lrc"), "[00:00.50]Featuring\n").unwrap();
+  let config = test_dir.path().join("config.toml");
+  fs::write(&config, r"name-rule = ['\s*\[Explicit\]$=>', '(?i) feat\. => ft. ']").unwrap();
+
+  let output = lyricsync_bin().arg("-d").arg(&library).arg("--config").arg(&config).output().unwrap();
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  for (name, expected) in [("Song [Explicit].opus", "Clean name"), ("Artist feat. Guest - Other.opus", "Featuring")] {
+    let lyrics = lyricsync::extract_lyrics(&library.join(name), &lyricsync::FormatResolver::default()).unwrap();
+    assert!(lyrics.is_some_and(|lyrics| lyrics.contains(expected)), "{name} should take its rewritten LRC file");
+  }
+
+  let output = lyricsync_bin().arg("-d").arg(&library).args(["--name-rule", "(unclosed=>x"]).output().unwrap();
+  assert_eq!(output.status.code(), Some(2), "A pattern that isn't a regex should be rejected");
+}
+
+#[test]
+fn test_watch() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path();
+  let watcher = lyricsync_bin()
+    .args(["watch", "--debounce", "200", "-d"])
+    .arg(library)
+    .stdout(std::process::Stdio::piped())
+    .stderr(std::process::Stdio::piped())
+    .spawn()
+    .unwrap();
+  std::thread::sleep(std::time::Duration::from_millis(500));
+
+  // The LRC file turns up after the audio file, as a downloader might leave them
+  let audio_path = library.join("new.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  fs::write(library.join("new.lrc"), "[00:00.50]Watched lyrics\n").unwrap();
+  let embedded = (0..100).any(|_| {
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    let lyrics = lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default());
+    lyrics.is_ok_and(|lyrics| lyrics.is_some_and(|lyrics| lyrics.contains("Watched lyrics")))
+  });
+
+  // SAFETY: the process is the child spawned above, which is still running
+  unsafe { libc::kill(watcher.id() as libc::pid_t, libc::SIGTERM) };
+  let output = watcher.wait_with_output().unwrap();
+  assert!(embedded, "The new file should be embedded: {}", String::from_utf8_lossy(&output.stderr));
+  assert!(output.status.success(), "Stopping the watch is how it ends: {:?}", output.status);
+  assert!(String::from_utf8_lossy(&output.stdout).contains("Embedded lyrics in 1 audio files"));
+}
+
+#[test]
+fn test_daemon_scan() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path();
+  let output = lyricsync_bin().args(["daemon", "--cron", "not a schedule", "-d"]).arg(library).output().unwrap();
+  assert!(!output.status.success(), "A bad cron expression should be rejected");
+
+  // Already there when the daemon starts, so only its first scan finds it
+  let audio_path = library.join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  fs::write(library.join("song.lrc"), "[00:00.50]Scanned lyrics\n").unwrap();
+  let daemon = lyricsync_bin()
+    .args(["daemon", "--cron", "@daily", "-d"])
+    .arg(library)
+    .stdout(std::process::Stdio::piped())
+    .stderr(std::process::Stdio::piped())
+    .spawn()
+    .unwrap();
+  let embedded = (0..100).any(|_| {
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    let lyrics = lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default());
+    lyrics.is_ok_and(|lyrics| lyrics.is_some_and(|lyrics| lyrics.contains("Scanned lyrics")))
+  });
+
+  // SAFETY: the process is the child spawned above, which is still running
+  unsafe { libc::kill(daemon.id() as libc::pid_t, libc::SIGTERM) };
+  let output = daemon.wait_with_output().unwrap();
+  assert!(embedded, "The scan should embed the file: {}", String::from_utf8_lossy(&output.stderr));
+  assert!(output.status.success(), "Stopping the daemon is how it ends: {:?}", output.status);
+  assert!(String::from_utf8_lossy(&output.stdout).contains("Embedded lyrics in 1 audio files"));
+}
+
+#[test]
+fn test_daemon_control_socket() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path().join("library");
+  let socket = test_dir.path().join("lyricsync.sock");
+  fs::create_dir(&library).unwrap();
+  let daemon = lyricsync_bin()
+    .args(["daemon", "--cron", "@yearly", "--debounce", "200", "-d"])
+    .arg(&library)
+    .arg("--socket")
+    .arg(&socket)
+    .stdout(std::process::Stdio::piped())
+    .stderr(std::process::Stdio::piped())
+    .spawn()
+    .unwrap();
+  let stream = (0..100).find_map(|_| {
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    std::os::unix::net::UnixStream::connect(&socket).ok()
+  });
+  let stream = stream.expect("The daemon should listen on its socket");
+  let mut reader = BufReader::new(stream.try_clone().unwrap());
+  let mut send = |command: &str| {
+    writeln!(&stream, "{command}").unwrap();
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    serde_json::from_str::<serde_json::Value>(&line).unwrap()
+  };
+
+  let status = send("status");
+  assert_eq!(status["paused"], false);
+  assert!(status["next_scan"].is_u64(), "The next scan should be scheduled: {status}");
+  assert!(send("restart")["error"].is_string(), "Unknown commands should be answered with an error");
+  assert_eq!(send("pause")["paused"], true);
+
+  let audio_path = library.join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  fs::write(library.join("song.lrc"), "[00:00.50]Controlled lyrics\n").unwrap();
+  let embedded = || {
+    let lyrics = lyricsync::extract_lyrics(&audio_path, &lyricsync::FormatResolver::default());
+    lyrics.is_ok_and(|lyrics| lyrics.is_some_and(|lyrics| lyrics.contains("Controlled lyrics")))
+  };
+  std::thread::sleep(std::time::Duration::from_millis(1000));
+  assert!(!embedded(), "Nothing should be embedded while paused");
+  assert_eq!(send("resume")["paused"], false);
+  let resumed = (0..100).any(|_| {
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    embedded()
+  });
+  assert!(resumed, "Resuming should embed what turned up while paused");
+  assert_eq!(send("status")["embedded"], 1);
+
+  // SAFETY: the process is the child spawned above, which is still running
+  unsafe { libc::kill(daemon.id() as libc::pid_t, libc::SIGTERM) };
+  let output = daemon.wait_with_output().unwrap();
+  assert!(output.status.success(), "Stopping the daemon is how it ends: {:?}", output.status);
+  assert!(!socket.exists(), "The socket should be removed when the daemon stops");
+}
+
+// Starts `lyricsync serve` over `library` on a free port and returns it with the URL it serves on
+fn spawn_lyrics_server(library: &Path, args: &[&str]) -> (std::process::Child, String) {
+  let mut server = lyricsync_bin()
+    .args(["serve", "--listen", "127.0.0.1:0", "-d"])
+    .arg(library)
+    .args(args)
+    .stdout(std::process::Stdio::piped())
+    .spawn()
+    .unwrap();
+  let mut line = String::new();
+  BufReader::new(server.stdout.take().unwrap()).read_line(&mut line).unwrap();
+  let url = line.split_whitespace().last().expect("The server should say where it listens").to_string();
+  (server, url)
+}
+
+// Sends a GET request and returns the status, the `X-Lyrics-Source` header and the body
+fn http_get(url: &str) -> (u16, Option<String>, String) {
+  let agent: ureq::Agent = ureq::Agent::config_builder().http_status_as_error(false).build().into();
+  let mut response = agent.get(url).call().unwrap();
+  let source = response.headers().get("X-Lyrics-Source").map(|value| value.to_str().unwrap().to_string());
+  (response.status().as_u16(), source, response.body_mut().read_to_string().unwrap())
+}
+
+#[test]
+fn test_serve_lyrics() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path();
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"),
+    &library.join("04 Avril Lavigne - I'm With You.mp3"),
+  );
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc"),
+    &library.join("04 Avril Lavigne - I'm With You.lrc"),
+  );
+  let audio_path = library.join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  let lyrics = lyricsync::Lyrics::new("Embedded lyrics");
+  lyricsync::embed_lyrics(&audio_path, &lyrics, &lyricsync::EmbedOptions::default()).unwrap();
+  let (mut server, url) = spawn_lyrics_server(library, &[]);
+
+  let (status, source, body) = http_get(&format!("{url}/lyrics?artist=avril+lavigne&title=I%27m%20With%20You"));
+  assert_eq!(status, 200, "Tracks should be found by their tags, whatever the case: {body}");
+  assert_eq!(source.as_deref(), Some("sidecar"));
+  assert!(body.starts_with('['), "Synced lyrics should be served as LRC: {body}");
+  let (status, _, plain) = http_get(&format!("{url}/lyrics?artist=Avril+Lavigne&title=I'm+With+You&format=plain"));
+  assert_eq!(status, 200);
+  assert!(!plain.contains("[0"), "Plain text should have no timestamps: {plain}");
+  let (status, _, _) = http_get(&format!("{url}/lyrics?artist=Avril+Lavigne&title=I'm+With+You&duration=1000"));
+  assert_eq!(status, 404, "A track of another length shouldn't be served");
+
+  let (status, source, body) = http_get(&format!("{url}/lyrics?path=song.opus"));
+  assert_eq!((status, source.as_deref(), body.as_str()), (200, Some("embedded"), "Embedded lyrics"));
+  let (status, _, _) = http_get(&format!("{url}/lyrics?path=..%2F{}", library.file_name().unwrap().to_string_lossy()));
+  assert_eq!(status, 404, "Paths out of the library should be turned down");
+  assert_eq!(http_get(&format!("{url}/lyrics?title=Alone")).0, 400, "A title alone isn't enough");
+  assert_eq!(http_get(&format!("{url}/other")).0, 404);
+  assert_eq!(http_get(&format!("{url}/api/get?artist_name=a&track_name=b")).0, 404, "LRCLIB's API is opt-in");
+
+  server.kill().unwrap();
+  server.wait().unwrap();
+}
+
+#[test]
+fn test_serve_lrclib_api() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path();
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"),
+    &library.join("04 Avril Lavigne - I'm With You.mp3"),
+  );
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc"),
+    &library.join("04 Avril Lavigne - I'm With You.lrc"),
+  );
+  let (mut server, url) = spawn_lyrics_server(library, &["--lrclib-api"]);
+
+  let query = "artist_name=Avril+Lavigne&track_name=I%27m+With+You&album_name=Let+Go";
+  let (status, _, body) = http_get(&format!("{url}/api/get?{query}"));
+  assert_eq!(status, 200, "{body}");
+  let track: serde_json::Value = serde_json::from_str(&body).unwrap();
+  assert_eq!(track["trackName"], "I'm With You");
+  assert_eq!(track["artistName"], "Avril Lavigne");
+  assert_eq!(track["albumName"], "Let Go");
+  assert_eq!(track["instrumental"], false);
+  assert!(track["syncedLyrics"].as_str().is_some_and(|lyrics| lyrics.starts_with('[')), "{track}");
+  assert!(track["plainLyrics"].as_str().is_some_and(|lyrics| !lyrics.contains("[0")), "{track}");
+  assert_eq!(http_get(&format!("{url}/api/get-cached?{query}")).0, 200);
+
+  let (status, _, body) = http_get(&format!("{url}/api/get?artist_name=Avril+Lavigne&track_name=Losing+Grip"));
+  assert_eq!(status, 404);
+  assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap()["name"], "TrackNotFound");
+  let other_album = "artist_name=Avril+Lavigne&track_name=I%27m+With+You&album_name=Under+My+Skin";
+  assert_eq!(http_get(&format!("{url}/api/get?{other_album}")).0, 404, "The album should match when it's given");
+  assert_eq!(http_get(&format!("{url}/api/get?track_name=Alone")).0, 400);
+
+  server.kill().unwrap();
+  server.wait().unwrap();
+}
+
+// Answers MPD's `status` and `currentsong` as a player `elapsed` seconds into `song.opus` would
+fn spawn_mpd_mock(elapsed: &'static str) -> String {
+  let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind mock server");
+  let port = listener.local_addr().unwrap().port().to_string();
+  std::thread::spawn(move || {
+    for stream in listener.incoming() {
+      let mut stream = stream.unwrap();
+      std::thread::spawn(move || {
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        writeln!(stream, "OK MPD 0.23.5").unwrap();
+        for command in reader.lines().map_while(|line| line.ok()) {
+          let answer = match command.as_str() {
+            "status" => format!("volume: 100\nstate: play\nelapsed: {elapsed}\nOK\n"),
+            "currentsong" => "file: song.opus\nArtist: Artist\nTitle: Song\nOK\n".to_string(),
+            _ => "ACK [5@0] {} unknown command\n".to_string(),
+          };
+          if stream.write_all(answer.as_bytes()).is_err() {
+            break;
+          }
+        }
+      });
+    }
+  });
+  port
+}
+
+#[test]
+fn test_now_playing() {
+  let test_dir = create_test_dir();
+  let music = test_dir.path();
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &music.join("song.opus"));
+  fs::write(music.join("song.lrc"), "[00:00.00]First line\n[00:00.40]Second line\n[00:10.00]Last line\n").unwrap();
+
+  let port = spawn_mpd_mock("10.500");
+  let output = lyricsync_bin()
+    .args(["now-playing", "--once", "--mpd-host", "127.0.0.1", "--mpd-port", &port, "-d"])
+    .arg(music)
+    .output()
+    .unwrap();
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  assert_eq!(String::from_utf8_lossy(&output.stdout), "Last line\n", "The line being sung should be printed");
+
+  let port = spawn_mpd_mock("0.000");
+  let mut now_playing = lyricsync_bin()
+    .args(["now-playing", "--mpd-host", "127.0.0.1", "--mpd-port", &port, "-d"])
+    .arg(music)
+    .stdout(std::process::Stdio::piped())
+    .spawn()
+    .unwrap();
+  let lines: Vec<String> =
+    BufReader::new(now_playing.stdout.take().unwrap()).lines().map_while(|line| line.ok()).take(4).collect();
+  now_playing.kill().unwrap();
+  now_playing.wait().unwrap();
+  assert_eq!(lines, ["", "♪ Artist - Song", "First line", "Second line"], "Lines should be printed as they're sung");
+}
+
+#[test]
+fn test_play_silent() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.opus");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+
+  let output = lyricsync_bin().args(["play", "--silent"]).arg(&audio_path).output().unwrap();
+  assert_eq!(output.status.code(), Some(4), "A file without lyrics should exit with 4");
+
+  fs::write(test_dir.path().join("song.lrc"), "[00:00.30]Second line\n[00:00.00]First line\n").unwrap();
+  let started = std::time::Instant::now();
+  let output = lyricsync_bin().args(["play", "--silent"]).arg(&audio_path).output().unwrap();
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert_eq!(
+    stdout.lines().skip(1).collect::<Vec<_>>(),
+    ["First line", "Second line"],
+    "Lines should be printed as they're sung when stdout isn't a terminal"
+  );
+  assert!(started.elapsed() >= std::time::Duration::from_millis(300), "The lines should be timed by the clock");
+}
+
+#[test]
+fn test_tap_refusals() {
+  let test_dir = create_test_dir();
+  let audio_path = test_dir.path().join("song.opus");
+  let lrc_path = test_dir.path().join("song.lrc");
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &audio_path);
+  fs::write(test_dir.path().join("song.txt"), "First line\nSecond line\n").unwrap();
+
+  let run = |args: &[&str]| {
+    let mut command = lyricsync_bin();
+    command.args(["tap", "--silent"]).arg(&audio_path).args(args).stdin(std::process::Stdio::null()).output().unwrap()
+  };
+  let output = run(&[]);
+  assert_eq!(output.status.code(), Some(2), "Nobody is there to tap");
+  assert!(String::from_utf8_lossy(&output.stderr).contains("tap needs a terminal"));
+
+  fs::write(&lrc_path, "[00:00.10]Timed before\n").unwrap();
+  let output = run(&[]);
+  assert!(!output.status.success(), "An LRC file that's there shouldn't be timed over");
+  assert!(String::from_utf8_lossy(&output.stderr).contains("--overwrite"));
+  assert_eq!(fs::read_to_string(&lrc_path).unwrap(), "[00:00.10]Timed before\n");
+  assert_eq!(run(&["--overwrite"]).status.code(), Some(2), "With --overwrite, it's the terminal that's missing");
+}
+
+#[test]
+fn test_log_file() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path().join("library");
+  let log_file = test_dir.path().join("lyricsync.log");
+  fs::create_dir(&library).unwrap();
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &library.join("song.opus"));
+  fs::write(library.join("song.lrc"), "[00:01.00]Logged lyrics\n").unwrap();
+  fs::write(library.join("broken.flac"), "not a FLAC file").unwrap();
+  fs::write(library.join("broken.lrc"), "[00:01.00]Logged lyrics\n").unwrap();
+
+  for args in [&["--quiet"][..], &["check"]] {
+    let output = lyricsync_bin().args(args).arg("-d").arg(&library).arg("--log-file").arg(&log_file).output().unwrap();
+    assert!(output.status.code().is_some(), "lyricsync should exit normally");
+  }
+
+  let log = fs::read_to_string(&log_file).expect("The log file should be written");
+  assert!(log.contains("embedded 1 sidecar(s)"), "Per-file outcomes should be logged whatever the console shows");
+  assert!(log.contains("broken.flac: corrupt audio file"), "Errors should be logged");
+  assert_eq!(log.matches(" INFO lyricsync ").count(), 2, "Runs should be appended to the same file");
+  assert!(log.lines().all(|line| line.starts_with("20")), "Every line should start with a timestamp: {log}");
+}
+
+#[test]
+fn test_summary_breakdown() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &test_dir_path.join("song.opus"));
+  fs::write(test_dir_path.join("song.lrc"), "[00:01.00]Counted\n").unwrap();
+  copy_test_file(Path::new("tests/fixtures/silence.wav"), &test_dir_path.join("other.wav"));
+  fs::write(test_dir_path.join("broken.flac"), "not a FLAC file").unwrap();
+  fs::write(test_dir_path.join("broken.lrc"), "[00:01.00]Counted\n").unwrap();
+
+  let output = lyricsync_bin().arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  let row = |name: &str| stdout.lines().find(|line| line.trim_start().starts_with(name)).map(str::split_whitespace);
+  let row = |name: &str| row(name).map(|columns| columns.collect::<Vec<_>>()).unwrap_or_default();
+  // Format, files, embedded, up to date, skipped and failed
+  assert_eq!(row("FLAC"), ["FLAC", "1", "0", "0", "0", "1"], "{stdout}");
+  assert_eq!(row("OPUS"), ["OPUS", "1", "1", "0", "0", "0"]);
+  assert_eq!(row("WAV"), ["WAV", "1", "0", "0", "1", "0"]);
+  assert!(stdout.contains("Failures by reason:"));
+  assert_eq!(row("corrupt file"), ["corrupt", "file", "1"]);
+}
+
+#[test]
+fn test_failed_list_and_retry() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path().join("library");
+  fs::create_dir(&library).unwrap();
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &library.join("good.opus"));
+  fs::write(library.join("good.lrc"), "[00:01.00]Good\n").unwrap();
+  fs::write(library.join("broken.opus"), "not an Opus file").unwrap();
+  fs::write(library.join("broken.lrc"), "[00:01.00]Retried\n").unwrap();
+
+  let text_list = test_dir.path().join("lyricsync-failed.txt");
+  let output = lyricsync_bin()
+    .arg("-d")
+    .arg(&library)
+    .arg("--failed-list")
+    .arg(&text_list)
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert_eq!(output.status.code(), Some(3));
+  let list = fs::read_to_string(&text_list).expect("The failures should be listed");
+  let broken = fs::canonicalize(library.join("broken.opus")).unwrap();
+  assert!(list.starts_with("# lyricsync embed: 1 failed file(s)\n"), "{list}");
+  assert!(list.contains(&format!("{}\tcorrupt audio file", broken.display())), "{list}");
+
+  // Once the file is fixed, retrying only goes through it, with the LRC file that was set aside
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &library.join("broken.opus"));
+  let json_list = test_dir.path().join("failed.json");
+  let output = lyricsync_bin()
+    .arg("-d")
+    .arg(&library)
+    .arg("--retry")
+    .arg(&text_list)
+    .arg("--failed-list")
+    .arg(&json_list)
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "The retry should succeed");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Total audio files: 1"), "Only the failed file should be retried: {stdout}");
+  assert!(!library.join("broken.lrc.failed").exists(), "The LRC file should be put back");
+  let lyrics = lyricsync::extract_lyrics(&broken, &lyricsync::FormatResolver::default()).unwrap();
+  assert_eq!(lyrics.as_deref(), Some("[00:01.00]Retried\n"));
+
+  let list: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json_list).unwrap()).unwrap();
+  assert_eq!(list["command"], "embed");
+  assert_eq!(list["failed"], serde_json::json!([]), "Nothing should be left to retry");
+}
+
+#[test]
+fn test_parallel_jobs() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let lrc_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc");
+  let fixtures = ["silence.opus", "silence.wav", "silence.m4a", "silence.aiff", "silence.ape", "silence.wma"];
+  for (index, fixture) in fixtures.iter().enumerate() {
+    let extension = Path::new(fixture).extension().unwrap().to_str().unwrap();
+    copy_test_file(&Path::new("tests/fixtures").join(fixture), &test_dir_path.join(format!("song{index}.{extension}")));
+    copy_test_file(lrc_source, &test_dir_path.join(format!("song{index}.lrc")));
+  }
+  fs::write(test_dir_path.join("broken.flac"), "not a FLAC file").unwrap();
+  copy_test_file(lrc_source, &test_dir_path.join("broken.lrc"));
+
+  let output = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .args(["--jobs", "4"])
+    .output()
+    .expect("Failed to execute lyricsync");
+
+  assert_eq!(output.status.code(), Some(3), "The broken file should fail the run");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Embedded lyrics in 6 audio files"), "Every worker's files should be counted");
+  assert!(stdout.contains("Total audio files: 7"));
+  assert!(stdout.contains("broken.flac: corrupt audio file"), "The failure should be listed");
+  assert!(test_dir_path.join("broken.lrc.failed").exists());
+
+  let output =
+    lyricsync_bin().arg("check").arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  assert!(String::from_utf8_lossy(&output.stdout).contains("Embedded lyrics: 6/7"));
+}
+
+#[test]
+fn test_unchanged_files_are_not_rewritten() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let mp3_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.mp3");
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &mp3_dest);
+  copy_test_file(
+    Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc"),
+    &test_dir_path.join("04 Avril Lavigne - I'm With You.lrc"),
+  );
+
+  let embed = |extra: &[&str]| {
+    let output =
+      lyricsync_bin().arg("--directory").arg(test_dir_path).args(extra).output().expect("Failed to execute lyricsync");
+    assert!(output.status.success(), "Embedding should succeed");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+  };
+  let modified = || fs::metadata(&mp3_dest).unwrap().modified().unwrap();
+
+  assert!(embed(&[]).contains("Embedded lyrics in 1 audio files"));
+  let written = modified();
+  std::thread::sleep(std::time::Duration::from_millis(50));
+
+  let stdout = embed(&[]);
+  assert!(stdout.contains("Already up to date: 1"), "The file should be reported as unchanged");
+  assert!(stdout.contains("Success rate: 100.00%"), "Unchanged files should count as done");
+  assert_eq!(modified(), written, "An unchanged file should not be saved again");
+
+  assert!(embed(&["--rewrite"]).contains("Embedded lyrics in 1 audio files"), "--rewrite should save it anyway");
+  assert_ne!(modified(), written);
+}
+
+#[test]
+fn test_preserve_mtime_option() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+
+  let mp3_dest = test_dir_path.join("04 Avril Lavigne - I'm With You.mp3");
+  let opus_dest = test_dir_path.join("05 Avril Lavigne - Complicated.opus");
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &mp3_dest);
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &opus_dest);
+  for lrc in ["04 Avril Lavigne - I'm With You.lrc", "05 Avril Lavigne - Complicated.lrc"] {
+    copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc"), &test_dir_path.join(lrc));
+  }
+
+  let then = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_500_000_000);
+  let times = fs::FileTimes::new().set_accessed(then).set_modified(then);
+  for path in [&mp3_dest, &opus_dest] {
+    fs::File::options().write(true).open(path).unwrap().set_times(times).unwrap();
+  }
+
+  let output = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--preserve-mtime")
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "Embedding should succeed");
+  assert!(String::from_utf8_lossy(&output.stdout).contains("Embedded lyrics in 2 audio files"));
+
+  for path in [&mp3_dest, &opus_dest] {
+    let metadata = fs::metadata(path).unwrap();
+    assert_eq!(metadata.modified().unwrap(), then, "{} should keep its modification time", path.display());
+    assert_eq!(metadata.accessed().unwrap(), then, "{} should keep its access time", path.display());
+  }
+}
+
+#[test]
+fn test_resume_option() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+  for name in ["01 First", "02 Second"] {
+    copy_test_file(Path::new("tests/fixtures/silence.opus"), &test_dir_path.join(format!("{name}.opus")));
+    fs::write(test_dir_path.join(format!("{name}.lrc")), "[00:01.00]Lyrics").unwrap();
+  }
+  // What an interrupted embed run leaves behind
+  let marker = test_dir_path.join(".lyricsync-resume.json");
+  fs::write(&marker, r#"{"command": "embed", "remaining": ["02 Second.opus"]}"#).unwrap();
+
+  // Another command's marker isn't picked up
+  let output = lyricsync_bin()
+    .args(["strip", "--resume", "--dry-run", "--directory"])
+    .arg(test_dir_path)
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(String::from_utf8_lossy(&output.stdout).contains("Total audio files: 2"));
+
+  let output = lyricsync_bin()
+    .args(["embed", "--resume", "--directory"])
+    .arg(test_dir_path)
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "Resuming should succeed");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Embedded lyrics in 1 audio files"), "Only the remaining file should be embedded: {stdout}");
+  assert!(stdout.contains("Total audio files: 1"));
+  assert!(!marker.exists(), "A finished run should remove the marker");
+}
+
+#[test]
+fn test_run_lock() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &test_dir_path.join("silence.opus"));
+  fs::write(test_dir_path.join("silence.lrc"), "[00:01.00]Lyrics").unwrap();
+
+  // Where the binary takes its locks, given the state directory `lyricsync_bin` sets
+  let lock_dir = std::env::temp_dir().join("lyricsync-test-state").join("lyricsync").join("locks");
+  let lock = lyricsync::lock::RunLock::acquire(&lock_dir, test_dir_path).unwrap();
+
+  let output = lyricsync_bin().arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  assert!(!output.status.success(), "A locked directory should be refused");
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(stderr.contains("Locked"), "{stderr}");
+
+  // Dry runs don't write, so they don't wait for the lock
+  let output = lyricsync_bin()
+    .arg("--directory")
+    .arg(test_dir_path)
+    .arg("--dry-run")
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "A dry run should ignore the lock");
+
+  drop(lock);
+  let output = lyricsync_bin().arg("--directory").arg(test_dir_path).output().expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "The directory should be free once the lock is released");
+  assert!(String::from_utf8_lossy(&output.stdout).contains("Embedded lyrics in 1 audio files"));
+}
+
+#[test]
+fn test_json_report() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &test_dir_path.join("01 Embedded.opus"));
+  fs::write(test_dir_path.join("01 Embedded.lrc"), "[00:01.00]Lyrics").unwrap();
+  copy_test_file(Path::new("tests/fixtures/silence.wav"), &test_dir_path.join("02 Without LRC.wav"));
+  fs::write(test_dir_path.join("03 Broken.flac"), "not a FLAC file").unwrap();
+  fs::write(test_dir_path.join("03 Broken.lrc"), "[00:01.00]Lyrics").unwrap();
+
+  // Without a path the report is all that's printed
+  let output = lyricsync_bin()
+    .args(["embed", "--report", "json", "--directory"])
+    .arg(test_dir_path)
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert_eq!(output.status.code(), Some(3), "The broken file should fail the run");
+  let report: serde_json::Value = serde_json::from_slice(&output.stdout).expect("stdout should be a JSON report");
+
+  assert_eq!(report["command"], "embed");
+  assert_eq!(report["summary"]["total"], 3);
+  assert_eq!(report["summary"]["embedded"], 1);
+  assert_eq!(report["summary"]["no_lrc"], 1);
+  assert_eq!(report["summary"]["failed"], 1);
+  let files = report["files"].as_array().unwrap();
+  let statuses: Vec<_> = files.iter().map(|file| file["status"].as_str().unwrap()).collect();
+  assert_eq!(statuses, ["embedded", "no-lrc", "failed"]);
+  assert_eq!(files[0]["format"], "opus");
+  assert_eq!(files[0]["tag_type"], "Vorbis comments");
+  assert!(files[0]["duration_ms"].is_u64());
+  assert!(files[1]["tag_type"].is_null());
+  assert!(files[2]["error"].as_str().unwrap().contains("03 Broken.flac"));
+  assert!(report["journal"].as_str().is_some(), "the journal of the run should be named");
+
+  // With a path the usual summary is printed as well
+  let report_path = test_dir_path.join("report.json");
+  let output = lyricsync_bin()
+    .args(["embed", "--rewrite", "--report"])
+    .arg(format!("json={}", report_path.display()))
+    .arg("--directory")
+    .arg(test_dir_path)
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(String::from_utf8_lossy(&output.stdout).contains("Total audio files: 3"));
+  let report: serde_json::Value = serde_json::from_slice(&fs::read(&report_path).unwrap()).unwrap();
+  assert_eq!(report["summary"]["embedded"], 1);
+
+  let output = lyricsync_bin().args(["embed", "--report", "xml", "--directory"]).arg(test_dir_path).output().unwrap();
+  assert!(!output.status.success(), "Unknown report formats should be rejected");
+}
+
+#[test]
+fn test_csv_report() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &test_dir_path.join("01 Embedded.opus"));
+  fs::write(test_dir_path.join("01 Embedded.lrc"), "[00:01.00]Lyrics").unwrap();
+  fs::write(test_dir_path.join("02 Broken, \"Live\".flac"), "not a FLAC file").unwrap();
+  fs::write(test_dir_path.join("02 Broken, \"Live\".lrc"), "[00:01.00]Lyrics").unwrap();
+
+  let output = lyricsync_bin()
+    .args(["embed", "--report", "csv", "--directory"])
+    .arg(test_dir_path)
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert_eq!(output.status.code(), Some(3), "The broken file should fail the run");
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  let rows: Vec<&str> = stdout.split_terminator("\r\n").collect();
+
+  assert_eq!(rows.len(), 3, "{stdout}");
+  assert_eq!(rows[0], "path,format,action,error");
+  assert_eq!(rows[1], format!("{},opus,embedded,", test_dir_path.join("01 Embedded.opus").display()));
+  // Fields with commas or quotes are quoted, with quotes doubled
+  let broken = test_dir_path.join("02 Broken, \"\"Live\"\".flac").display().to_string();
+  assert!(rows[2].starts_with(&format!("\"{broken}\",flac,failed,\"{broken}: ")), "{}", rows[2]);
+}
+
+#[test]
+fn test_jsonl_progress() {
+  let test_dir = create_test_dir();
+  let test_dir_path = test_dir.path();
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &test_dir_path.join("01 Embedded.opus"));
+  fs::write(test_dir_path.join("01 Embedded.lrc"), "[00:01.00]Lyrics").unwrap();
+  fs::write(test_dir_path.join("02 Broken.flac"), "not a FLAC file").unwrap();
+  fs::write(test_dir_path.join("02 Broken.lrc"), "[00:01.00]Lyrics").unwrap();
+
+  let output = lyricsync_bin()
+    .args(["embed", "--progress", "jsonl", "--directory"])
+    .arg(test_dir_path)
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert_eq!(output.status.code(), Some(3), "The broken file should fail the run");
+  let events: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stdout)
+    .lines()
+    .map(|line| serde_json::from_str(line).expect("Every line of stdout should be JSON"))
+    .collect();
+  let names: Vec<&str> = events.iter().map(|event| event["event"].as_str().unwrap()).collect();
+
+  // Files come in directory order, each started, matched, then embedded or failed
+  assert_eq!(names.len(), 9);
+  assert_eq!(names[..2], ["scanning", "scanned"]);
+  assert_eq!(names[8], "finished");
+  for file in names[2..8].chunks(3) {
+    assert_eq!(file[..2], ["started", "matched"]);
+  }
+  assert_eq!(events[1]["files"], 2);
+  let event = |name: &str| events.iter().find(|event| event["event"] == name).unwrap();
+  assert!(event("embedded")["path"].as_str().unwrap().ends_with("01 Embedded.opus"));
+  assert!(event("failed")["error"].as_str().unwrap().contains("02 Broken.flac"));
+  assert_eq!(events[8]["summary"]["embedded"], 1);
+  assert_eq!(events[8]["summary"]["failed"], 1);
+
+  // Both would want stdout
+  let output = lyricsync_bin()
+    .args(["embed", "--progress", "jsonl", "--report", "json", "--directory"])
+    .arg(test_dir_path)
+    .output()
+    .unwrap();
+  assert!(!output.status.success(), "A report on stdout should be refused alongside JSON progress");
+}
+
+#[test]
+fn test_backup_dir_option() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path().join("library");
+  let backup_dir = test_dir.path().join("backups");
+  let album = library.join("album");
+  fs::create_dir_all(&album).unwrap();
+
+  let mp3_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3");
+  let mp3_dest = album.join("04 Avril Lavigne - I'm With You.mp3");
+  let lrc_dest = album.join("04 Avril Lavigne - I'm With You.lrc");
+  let opus_dest = album.join("05 Avril Lavigne - Complicated.opus");
+  copy_test_file(mp3_source, &mp3_dest);
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc"), &lrc_dest);
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &opus_dest);
+  fs::write(album.join("05 Avril Lavigne - Complicated.lrc"), "[00:01.00]Complicated\n").unwrap();
+
+  // A backup left over from long ago, and one that's recent
+  let stale_backup = backup_dir.join("stale.flac");
+  let recent_backup = backup_dir.join("recent.flac");
+  fs::create_dir_all(&backup_dir).unwrap();
+  fs::write(&stale_backup, "old").unwrap();
+  fs::write(&recent_backup, "new").unwrap();
+  let then = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_500_000_000);
+  fs::File::options()
+    .write(true)
+    .open(&stale_backup)
+    .unwrap()
+    .set_times(fs::FileTimes::new().set_modified(then))
+    .unwrap();
+
+  let embed = |extra: &[&str]| {
+    let output = lyricsync_bin()
+      .arg("--directory")
+      .arg(&library)
+      .arg("--recursive")
+      .arg("--backup-dir")
+      .arg(&backup_dir)
+      .args(extra)
+      .output()
+      .expect("Failed to execute lyricsync");
+    assert!(output.status.success(), "Embedding should succeed");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+  };
+
+  assert!(embed(&["--backup-max-age", "30"]).contains("Embedded lyrics in 2 audio files"));
+  let mp3_backup = backup_dir.join("album").join("04 Avril Lavigne - I'm With You.mp3");
+  assert_eq!(fs::read(&mp3_backup).unwrap(), fs::read(mp3_source).unwrap(), "The backup should be the original file");
+  assert!(backup_dir.join("album").join("05 Avril Lavigne - Complicated.opus").exists());
+  assert!(!stale_backup.exists(), "Backups past --backup-max-age should be pruned");
+  assert!(recent_backup.exists(), "Recent backups should be kept");
+
+  // A file that already has a backup keeps the first one
+  fs::write(&lrc_dest, "[00:01.00]Updated\n").unwrap();
+  assert!(embed(&[]).contains("Embedded lyrics in 1 audio files"));
+  assert_eq!(fs::read(&mp3_backup).unwrap(), fs::read(mp3_source).unwrap(), "The first backup should be kept");
+
+  // Over the budget, the oldest backups go first, and a file that can't be backed up isn't touched
+  fs::remove_file(&mp3_backup).unwrap();
+  fs::remove_file(backup_dir.join("album").join("05 Avril Lavigne - Complicated.opus")).unwrap();
+  fs::write(&lrc_dest, "[00:01.00]Updated again\n").unwrap();
+  fs::write(album.join("05 Avril Lavigne - Complicated.lrc"), "[00:01.00]Still complicated\n").unwrap();
+  let before = fs::read(&mp3_dest).unwrap();
+  let budget = (fs::metadata(&opus_dest).unwrap().len() + 1).to_string();
+  // The file without a backup fails, which isn't what this checks
+  let stdout = embed(&["--backup-max-size", &budget, "--fail-on", "none"]);
+  assert!(stdout.contains("Embedded lyrics in 1 audio files"), "The file that fits should still be embedded");
+  assert!(stdout.contains("backup budget"), "The failure should name the budget");
+  assert_eq!(fs::read(&mp3_dest).unwrap(), before, "A file without a backup should not be modified");
+  assert!(!recent_backup.exists(), "Older backups should be pruned to make room");
+  assert!(backup_dir.join("album").join("05 Avril Lavigne - Complicated.opus").exists());
+
+  let output = lyricsync_bin()
+    .arg("--directory")
+    .arg(&library)
+    .args(["--backup-max-size", "1K"])
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(!output.status.success(), "--backup-max-size needs --backup-dir");
+}
+
+#[test]
+fn test_undo_command() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path().join("library");
+  let journal_dir = test_dir.path().join("journals");
+  fs::create_dir(&library).unwrap();
+
+  let mp3_dest = library.join("04 Avril Lavigne - I'm With You.mp3");
+  let lrc_source = Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.lrc");
+  let lrc_dest = library.join("04 Avril Lavigne - I'm With You.lrc");
+  let opus_dest = library.join("05 Avril Lavigne - Complicated.opus");
+  copy_test_file(Path::new("tests/fixtures/04 Avril Lavigne - I'm With You.mp3"), &mp3_dest);
+  copy_test_file(lrc_source, &lrc_dest);
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &opus_dest);
+  fs::write(library.join("05 Avril Lavigne - Complicated.lrc"), "[00:01.00]Complicated\n").unwrap();
+
+  let run = |args: &[&str]| {
+    let output =
+      lyricsync_bin().args(args).arg("--journal-dir").arg(&journal_dir).output().expect("Failed to execute lyricsync");
+    assert!(output.status.success(), "lyricsync {} should succeed", args.join(" "));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+  };
+  let lyrics = |path: &Path| lyricsync::extract_lyrics(path, &lyricsync::FormatResolver::default()).unwrap();
+  let library_arg = library.to_str().unwrap();
+
+  // The opus file already had lyrics, which undo should bring back
+  lyricsync::embed_lyrics(&opus_dest, &lyricsync::Lyrics::new("Old lyrics"), &lyricsync::EmbedOptions::default())
+    .unwrap();
+  let stdout = run(&["embed", "-d", library_arg, "--force", "--reduce"]);
+  assert!(stdout.contains("Undo with: lyricsync undo"), "The journal should be named in the summary");
+  assert!(!lrc_dest.exists(), "--reduce should delete the LRC file");
+  assert!(run(&["undo", "--list"]).contains("embed"), "The run should be listed");
+
+  let stdout = run(&["undo", "--dry-run"]);
+  assert!(stdout.contains("[DRY RUN] Would undo 4 changes"));
+  assert!(!lrc_dest.exists(), "A dry run should not re-create anything");
+
+  let stdout = run(&["undo"]);
+  assert!(stdout.contains("Undid 4 changes"));
+  assert_eq!(fs::read_to_string(&lrc_dest).unwrap(), fs::read_to_string(lrc_source).unwrap());
+  assert_eq!(lyrics(&mp3_dest), None, "Lyrics the file didn't have should be removed");
+  assert_eq!(lyrics(&opus_dest).as_deref(), Some("Old lyrics"), "The previous lyrics should be restored");
+
+  // Undone journals aren't undone twice, and strip can be undone too
+  assert!(run(&["undo", "--list"]).is_empty());
+  run(&["strip", "-d", library_arg]);
+  assert_eq!(lyrics(&opus_dest), None);
+  assert!(run(&["undo"]).contains("Undid 1 changes"), "Only the file that had lyrics was changed");
+  assert_eq!(lyrics(&opus_dest).as_deref(), Some("Old lyrics"));
+
+  let output =
+    lyricsync_bin().arg("undo").arg("--journal-dir").arg(&journal_dir).output().expect("Failed to execute lyricsync");
+  assert!(!output.status.success(), "There should be nothing left to undo");
+}
+
+#[test]
+fn test_undo_reduce_restores_sidecar_bytes() {
+  let test_dir = create_test_dir();
+  let library = test_dir.path().join("library");
+  let journal_dir = test_dir.path().join("journals");
+  fs::create_dir(&library).unwrap();
+
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &library.join("offset.opus"));
+  copy_test_file(Path::new("tests/fixtures/silence.opus"), &library.join("subtitles.opus"));
+  // The offset tag and the SubRip timing are what preparing the lyrics does away with
+  let lrc = b"[offset:+500]\n[00:01.00]Hello\n".to_vec();
+  let srt = b"1\r\n00:00:01,000 --> 00:00:02,000\r\nHello\r\n".to_vec();
+  fs::write(library.join("offset.lrc"), &lrc).unwrap();
+  fs::write(library.join("subtitles.srt"), &srt).unwrap();
+
+  let run = |args: &[&str]| {
+    let output = lyricsync_bin()
+      .args(args)
+      .arg("--directory")
+      .arg(&library)
+      .arg("--journal-dir")
+      .arg(&journal_dir)
+      .output()
+      .expect("Failed to execute lyricsync");
+    assert!(output.status.success(), "lyricsync {} should succeed", args.join(" "));
+  };
+  run(&["embed", "--reduce"]);
+  assert!(!library.join("offset.lrc").exists() && !library.join("subtitles.srt").exists());
+
+  let output =
+    lyricsync_bin().arg("undo").arg("--journal-dir").arg(&journal_dir).output().expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "lyricsync undo should succeed");
+  assert_eq!(fs::read(library.join("offset.lrc")).unwrap(), lrc, "The LRC file should come back as it was");
+  assert_eq!(fs::read(library.join("subtitles.srt")).unwrap(), srt, "The SubRip file should come back as it was");
 }
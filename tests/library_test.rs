@@ -0,0 +1,386 @@
+use lyricsync::lock::RunLock;
+use lyricsync::matching::{SidecarMatcher, name_confidence};
+use lyricsync::{
+  AudioFormat, ConflictPolicy, EmbedEvent, EmbedOptions, EmbedOutcome, EmbedSession, FailureKind, FormatResolver,
+  FrameFilter, Id3Frames, LrcError, Lyrics, SkipReason, VorbisKeys, backend, embed_lyrics, extract_lyrics,
+  has_embedded_lyrics, lrc, strip_lyrics, track,
+};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Helper function to copy a fixture into a temporary directory
+fn copy_fixture(fixture: &str, test_dir: &tempfile::TempDir) -> std::path::PathBuf {
+  let dest = test_dir.path().join(Path::new(fixture).file_name().unwrap());
+  fs::copy(fixture, &dest).expect("Failed to copy test file");
+  dest
+}
+
+#[test]
+fn test_embed_extract_round_trip() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let formats = FormatResolver::default();
+  let lyrics = fs::read_to_string("tests/fixtures/04 Avril Lavigne - I'm With You.lrc").unwrap();
+
+  for fixture in ["tests/fixtures/silence.opus", "tests/fixtures/silence.wav", "tests/fixtures/silence.m4a"] {
+    let audio_path = copy_fixture(fixture, &test_dir);
+    assert!(!has_embedded_lyrics(&audio_path, &formats).unwrap(), "{fixture} should start without lyrics");
+
+    embed_lyrics(&audio_path, &Lyrics::new(lyrics.clone()), &EmbedOptions::default()).unwrap();
+    assert!(has_embedded_lyrics(&audio_path, &formats).unwrap(), "{fixture} should have lyrics");
+    assert_eq!(extract_lyrics(&audio_path, &formats).unwrap().as_deref(), Some(lyrics.as_str()));
+
+    assert!(strip_lyrics(&audio_path, &formats).unwrap(), "{fixture} should have lyrics to strip");
+    assert_eq!(extract_lyrics(&audio_path, &formats).unwrap(), None);
+  }
+}
+
+#[test]
+fn test_wma_untimed_lyrics_replace_synchronised() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let wma_path = copy_fixture("tests/fixtures/silence.wma", &test_dir);
+  let attribute: Vec<u8> = "WM/Lyrics_Synchronised".encode_utf16().flat_map(u16::to_le_bytes).collect();
+  let has_synchronised = || fs::read(&wma_path).unwrap().windows(attribute.len()).any(|window| window == attribute);
+
+  embed_lyrics(&wma_path, &Lyrics::new("[00:01.00]Timed\n"), &EmbedOptions::default()).unwrap();
+  assert!(has_synchronised(), "Timed lyrics should be written synchronised too");
+  embed_lyrics(&wma_path, &Lyrics::new("Plain\n"), &EmbedOptions::default()).unwrap();
+  assert!(!has_synchronised(), "The old synchronised lyrics shouldn't be left behind");
+  assert_eq!(extract_lyrics(&wma_path, &FormatResolver::default()).unwrap().as_deref(), Some("Plain\n"));
+}
+
+#[test]
+fn test_atomic_saves() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let formats = FormatResolver::default();
+  let opus_path = copy_fixture("tests/fixtures/silence.opus", &test_dir);
+  let broken_path = test_dir.path().join("broken.flac");
+  fs::write(&broken_path, "not a FLAC file").unwrap();
+  let lyrics = Lyrics::new("[00:01.00]Lyrics");
+
+  embed_lyrics(&opus_path, &lyrics, &EmbedOptions::default()).unwrap();
+  assert!(embed_lyrics(&broken_path, &lyrics, &EmbedOptions::default()).is_err());
+  assert_eq!(fs::read(&broken_path).unwrap(), b"not a FLAC file", "A failed save should leave the file alone");
+  assert!(strip_lyrics(&opus_path, &formats).unwrap());
+  assert!(!strip_lyrics(&opus_path, &formats).unwrap());
+
+  let mut names: Vec<_> = fs::read_dir(test_dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+  names.sort();
+  assert_eq!(names, ["broken.flac", "silence.opus"], "Temporary copies should not be left behind");
+
+  // Saving through a symlink updates the file it points to
+  #[cfg(unix)]
+  {
+    let link_path = test_dir.path().join("link.opus");
+    std::os::unix::fs::symlink(&opus_path, &link_path).unwrap();
+    embed_lyrics(&link_path, &lyrics, &EmbedOptions::default()).unwrap();
+    assert!(fs::symlink_metadata(&link_path).unwrap().file_type().is_symlink());
+    assert_eq!(extract_lyrics(&opus_path, &formats).unwrap().as_deref(), Some("[00:01.00]Lyrics"));
+  }
+}
+
+#[test]
+fn test_verified_saves() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let formats = FormatResolver::default();
+  let lyrics = fs::read_to_string("tests/fixtures/04 Avril Lavigne - I'm With You.lrc").unwrap();
+  let variants =
+    [Lyrics::new(lyrics.clone()), Lyrics { language: Some(*b"jpn"), text: "[00:01.00]こんにちは".to_string() }];
+
+  for extension in ["aiff", "ape", "dff", "dsf", "m4a", "opus", "wav", "wma", "wv"] {
+    let audio_path = copy_fixture(&format!("tests/fixtures/silence.{extension}"), &test_dir);
+    for id3_frames in [Id3Frames::Both, Id3Frames::Sylt] {
+      let options = EmbedOptions::builder().verify(true).id3_frames(id3_frames).build();
+      lyricsync::embed_lyrics_variants(&audio_path, &variants, &options)
+        .unwrap_or_else(|error| panic!("{extension} should pass verification: {error}"));
+    }
+    assert!(has_embedded_lyrics(&audio_path, &formats).unwrap(), "{extension} should have lyrics");
+  }
+}
+
+#[test]
+fn test_embed_session() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let formats = FormatResolver::default();
+  let opus_path = copy_fixture("tests/fixtures/silence.opus", &test_dir);
+  let wav_path = copy_fixture("tests/fixtures/silence.wav", &test_dir);
+  let m4a_path = copy_fixture("tests/fixtures/silence.m4a", &test_dir);
+  fs::write(test_dir.path().join("silence.lrc"), "[00:01.00]New lyrics").unwrap();
+  embed_lyrics(&wav_path, &Lyrics::new("Old lyrics"), &EmbedOptions::default()).unwrap();
+
+  let options = EmbedOptions::builder().conflict(ConflictPolicy::Skip).vorbis_keys(VorbisKeys::Both).build();
+  assert_eq!(options.vorbis_keys, VorbisKeys::Both);
+
+  // All three files share the `silence.lrc` sidecar, and the wav file keeps its lyrics
+  let mut session = EmbedSession::new(options);
+  let results: Vec<_> = session.run([opus_path.clone(), wav_path.clone(), m4a_path.clone()]).collect();
+  let outcomes: Vec<_> = results.iter().map(|result| *result.outcome.as_ref().unwrap()).collect();
+  assert_eq!(results[1].path, wav_path);
+  assert_eq!(outcomes, [EmbedOutcome::Embedded, EmbedOutcome::Skipped(SkipReason::HasLyrics), EmbedOutcome::Embedded]);
+  assert_eq!(extract_lyrics(&opus_path, &formats).unwrap().as_deref(), Some("[00:01.00]New lyrics"));
+  assert_eq!(extract_lyrics(&wav_path, &formats).unwrap().as_deref(), Some("Old lyrics"));
+
+  // Asking defers to the handler, and a dry run leaves the file alone
+  let options = EmbedOptions::builder().conflict(ConflictPolicy::Ask).build();
+  let mut asked = Vec::new();
+  let mut session = EmbedSession::new(options).dry_run(true).on_conflict(|path| {
+    asked.push(path.to_path_buf());
+    true
+  });
+  assert_eq!(session.embed_file(&wav_path).unwrap(), EmbedOutcome::Embedded);
+  drop(session);
+  assert_eq!(asked.len(), 1);
+  assert_eq!(asked[0], wav_path);
+  assert_eq!(extract_lyrics(&wav_path, &formats).unwrap().as_deref(), Some("Old lyrics"));
+
+  fs::remove_file(test_dir.path().join("silence.lrc")).unwrap();
+  let mut session = EmbedSession::new(EmbedOptions::default());
+  assert_eq!(session.embed_file(&wav_path).unwrap(), EmbedOutcome::Skipped(SkipReason::NoSidecar));
+}
+
+#[test]
+fn test_embed_session_loose_matches() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let formats = FormatResolver::default();
+  let audio_path = test_dir.path().join("01 - Song.opus");
+  fs::copy("tests/fixtures/silence.opus", &audio_path).unwrap();
+  fs::write(test_dir.path().join("sung.lrc"), "[00:01.00]Loose lyrics").unwrap();
+
+  let matcher = SidecarMatcher::default().fuzzy(0.5);
+  let mut asked = Vec::new();
+  let mut session = EmbedSession::new(EmbedOptions::default()).matcher(matcher.clone()).on_loose_match(|_, sidecar| {
+    asked.push(sidecar.path.clone());
+    false
+  });
+  assert_eq!(session.embed_file(&audio_path).unwrap(), EmbedOutcome::Skipped(SkipReason::Declined));
+  drop(session);
+  assert_eq!(asked, [test_dir.path().join("sung.lrc")]);
+  assert!(!has_embedded_lyrics(&audio_path, &formats).unwrap(), "A declined match should leave the file alone");
+
+  let mut session = EmbedSession::new(EmbedOptions::default()).matcher(matcher).on_loose_match(|_, _| true);
+  assert_eq!(session.embed_file(&audio_path).unwrap(), EmbedOutcome::Embedded);
+
+  // A sidecar named like the file isn't asked about
+  let exact_path = copy_fixture("tests/fixtures/silence.opus", &test_dir);
+  fs::write(test_dir.path().join("silence.lrc"), "[00:01.00]Exact lyrics").unwrap();
+  let mut session = EmbedSession::new(EmbedOptions::default()).on_loose_match(|_, _| false);
+  assert_eq!(session.embed_file(&exact_path).unwrap(), EmbedOutcome::Embedded);
+}
+
+#[test]
+fn test_embed_session_unchanged() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let opus_path = copy_fixture("tests/fixtures/silence.opus", &test_dir);
+  fs::write(test_dir.path().join("silence.lrc"), "[00:01.00]Lyrics\r\n").unwrap();
+
+  let mut session = EmbedSession::new(EmbedOptions::default());
+  assert_eq!(session.embed_file(&opus_path).unwrap(), EmbedOutcome::Embedded);
+  // Line endings don't count as a change
+  assert_eq!(session.embed_file(&opus_path).unwrap(), EmbedOutcome::Skipped(SkipReason::Unchanged));
+
+  let mut session = EmbedSession::new(EmbedOptions::default()).rewrite_unchanged(true);
+  assert_eq!(session.embed_file(&opus_path).unwrap(), EmbedOutcome::Embedded);
+
+  fs::write(test_dir.path().join("silence.lrc"), "[00:01.00]New lyrics").unwrap();
+  let mut session = EmbedSession::new(EmbedOptions::default());
+  assert_eq!(session.embed_file(&opus_path).unwrap(), EmbedOutcome::Embedded);
+}
+
+#[test]
+fn test_embed_session_events() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let opus_path = copy_fixture("tests/fixtures/silence.opus", &test_dir);
+  let unmatched_path = test_dir.path().join("unmatched.mp3");
+  let missing_path = test_dir.path().join("missing.flac");
+  fs::write(test_dir.path().join("silence.lrc"), "[00:01.00]Lyrics").unwrap();
+  fs::write(test_dir.path().join("missing.lrc"), "[00:01.00]Lyrics").unwrap();
+
+  let mut events = Vec::new();
+  let mut session = EmbedSession::new(EmbedOptions::default()).on_event(|event| {
+    events.push(match event {
+      EmbedEvent::Started { path } => format!("started {}", path.display()),
+      EmbedEvent::Matched { sidecars, .. } => format!("matched {}", sidecars.len()),
+      EmbedEvent::Skipped { reason, .. } => format!("skipped {reason:?}"),
+      EmbedEvent::Embedded { .. } => "embedded".to_string(),
+      EmbedEvent::Failed { .. } => "failed".to_string(),
+    })
+  });
+  let failures = session
+    .run([opus_path.clone(), unmatched_path.clone(), missing_path.clone()])
+    .filter(|result| result.outcome.is_err())
+    .count();
+  drop(session);
+
+  assert_eq!(failures, 1);
+  assert_eq!(
+    events,
+    [
+      format!("started {}", opus_path.display()),
+      "matched 1".to_string(),
+      "embedded".to_string(),
+      format!("started {}", unmatched_path.display()),
+      "skipped NoSidecar".to_string(),
+      format!("started {}", missing_path.display()),
+      "matched 1".to_string(),
+      "failed".to_string(),
+    ]
+  );
+}
+
+#[test]
+fn test_embed_session_stop() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let opus_path = copy_fixture("tests/fixtures/silence.opus", &test_dir);
+  let wav_path = test_dir.path().join("other.wav");
+  fs::copy("tests/fixtures/silence.wav", &wav_path).unwrap();
+  for lrc in ["silence.lrc", "other.lrc"] {
+    fs::write(test_dir.path().join(lrc), "[00:01.00]Lyrics").unwrap();
+  }
+
+  // Stopping while the first file is under way finishes it, keeps its sidecar and starts nothing else
+  let stop = AtomicBool::new(false);
+  let mut session = EmbedSession::new(EmbedOptions::default()).reduce(true).stop_on(&stop).on_event(|event| {
+    if matches!(event, EmbedEvent::Matched { .. }) {
+      stop.store(true, Ordering::Relaxed);
+    }
+  });
+  let results: Vec<_> = session.run([opus_path.clone(), wav_path.clone()]).collect();
+  drop(session);
+
+  assert_eq!(results.len(), 1);
+  assert_eq!(results[0].outcome.as_ref().unwrap(), &EmbedOutcome::Embedded);
+  assert!(has_embedded_lyrics(&opus_path, &FormatResolver::default()).unwrap());
+  assert!(test_dir.path().join("silence.lrc").exists(), "the sidecar should be kept when stopping");
+  assert!(!has_embedded_lyrics(&wav_path, &FormatResolver::default()).unwrap());
+}
+
+#[test]
+fn test_format_backends() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let lyrics = [Lyrics::new("[00:01.00]Backend lyrics")];
+
+  for fixture in ["tests/fixtures/silence.aiff", "tests/fixtures/silence.ape", "tests/fixtures/silence.dff"] {
+    let audio_path = copy_fixture(fixture, &test_dir);
+    let format = FormatResolver::default().resolve(&audio_path).unwrap();
+    let backend = backend::backend(format);
+
+    assert!(!backend.detect(&audio_path, &FrameFilter::default()).unwrap(), "{fixture} should start without lyrics");
+    backend.write(&audio_path, &lyrics, &EmbedOptions::default()).unwrap();
+    assert_eq!(backend.read(&audio_path).unwrap().as_deref(), Some("[00:01.00]Backend lyrics"));
+    assert!(backend.remove(&audio_path).unwrap(), "{fixture} should have lyrics to remove");
+    assert!(!backend.remove(&audio_path).unwrap(), "{fixture} should have nothing left to remove");
+  }
+}
+
+#[test]
+fn test_unsupported_format() {
+  let result = embed_lyrics(Path::new("song.xyz"), &Lyrics::new("Lyrics"), &EmbedOptions::default());
+  assert!(matches!(&result, Err(lyricsync::LrcError::UnsupportedFormat(extension)) if extension == "xyz"));
+  assert_eq!(result.unwrap_err().kind(), FailureKind::Unsupported);
+}
+
+#[test]
+fn test_run_lock() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let lock_dir = test_dir.path().join("locks");
+  let library = test_dir.path().join("library");
+  fs::create_dir(&library).unwrap();
+
+  let lock = RunLock::acquire(&lock_dir, &library).unwrap();
+  // The same directory under another name is still the same directory
+  match RunLock::acquire(&lock_dir, &library.join("..").join("library")) {
+    Err(LrcError::Locked { path, pid }) => {
+      assert_eq!(path, fs::canonicalize(&library).unwrap());
+      assert_eq!(pid, Some(std::process::id()));
+    },
+    other => panic!("expected the directory to be locked, got {:?}", other.map(|_| ())),
+  }
+  assert!(RunLock::acquire(&lock_dir, test_dir.path()).is_ok(), "other directories should stay free");
+
+  drop(lock);
+  assert!(RunLock::acquire(&lock_dir, &library).is_ok(), "the lock should be released when dropped");
+}
+
+#[test]
+fn test_errors_name_the_file() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let formats = FormatResolver::default();
+
+  let corrupt = test_dir.path().join("corrupt.flac");
+  fs::write(&corrupt, b"not really a FLAC file").unwrap();
+  let error = extract_lyrics(&corrupt, &formats).unwrap_err();
+  assert!(matches!(error, LrcError::CorruptAudio { .. }), "{error:?}");
+  assert_eq!(error.path(), Some(corrupt.as_path()));
+  assert_eq!(error.kind(), FailureKind::Corrupt);
+
+  let missing = test_dir.path().join("missing.opus");
+  let error = has_embedded_lyrics(&missing, &formats).unwrap_err();
+  assert!(matches!(error, LrcError::FileIo { .. }), "{error:?}");
+  assert!(error.to_string().starts_with(&missing.display().to_string()));
+  assert_eq!(error.kind(), FailureKind::Io);
+
+  let wma_path = copy_fixture("tests/fixtures/silence.wma", &test_dir);
+  let error = embed_lyrics(&wma_path, &Lyrics::new("la ".repeat(20_000)), &EmbedOptions::default()).unwrap_err();
+  assert!(matches!(error, LrcError::LyricsTooLarge { size: 120_002, limit: 65_535, .. }), "{error:?}");
+
+  // Sizes in a crafted header fail the file rather than overflow or allocate what it says
+  let header = fs::read(&wma_path).unwrap();
+  for (offset, size) in [(16, u64::MAX), (46, u64::MAX), (46, header.len() as u64 * 2)] {
+    let mut crafted = header.clone();
+    crafted[offset..offset + 8].copy_from_slice(&size.to_le_bytes());
+    fs::write(&wma_path, crafted).unwrap();
+    let error = extract_lyrics(&wma_path, &formats).unwrap_err();
+    assert_eq!(error.kind(), FailureKind::Corrupt, "{error:?}");
+  }
+
+  // An unreadable sidecar is reported against the sidecar, not the audio file. Bytes that aren't
+  // UTF-8 are decoded from a legacy encoding, so it takes a directory to fail reading one
+  let opus_path = copy_fixture("tests/fixtures/silence.opus", &test_dir);
+  fs::create_dir(test_dir.path().join("silence.lrc")).unwrap();
+  let error = EmbedSession::new(EmbedOptions::default()).embed_file(&opus_path).unwrap_err();
+  assert_eq!(error.path(), Some(test_dir.path().join("silence.lrc").as_path()));
+}
+
+#[test]
+fn test_fuzzy_sidecar_matching() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let audio_path = test_dir.path().join("01 - Song Title.mp3");
+  let formats = FormatResolver::default();
+  for name in ["01 - Song Title.mp3", "Song title.lrc", "Another Song.lrc", "Other.mp3", "Other.lrc"] {
+    fs::write(test_dir.path().join(name), "").unwrap();
+  }
+
+  assert_eq!(name_confidence("01 - Song Title", "song_title"), 1.0, "Track numbers, case and punctuation don't count");
+  assert_eq!(name_confidence("1979", "1979"), 1.0, "A title that's a number isn't a track number");
+  assert!(name_confidence("01 - Song Title", "Another Song") < 0.8);
+
+  assert!(SidecarMatcher::default().find(&audio_path, &formats).is_empty(), "Names have to match exactly by default");
+  let sidecars = SidecarMatcher::default().fuzzy(0.8).find(&audio_path, &formats);
+  assert_eq!(sidecars.len(), 1);
+  assert_eq!(sidecars[0].path, test_dir.path().join("Song title.lrc"));
+  assert_eq!(sidecars[0].confidence, 1.0);
+
+  // Other.lrc is Other.mp3's, however close its name
+  let other = test_dir.path().join("01 Other.mp3");
+  assert!(SidecarMatcher::default().fuzzy(0.5).find(&other, &formats).is_empty());
+}
+
+#[test]
+fn test_duration_disambiguation() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let audio_path = test_dir.path().join("01 Song.mp3");
+  fs::copy("tests/fixtures/04 Avril Lavigne - I'm With You.mp3", &audio_path).unwrap();
+  let formats = FormatResolver::default();
+  let duration = track::read_duration(&audio_path, AudioFormat::Mp3).unwrap().expect("MP3 files have a length");
+  let timestamp = |ms: u128| lrc::format_timestamp(ms as u32);
+  let end = duration.as_millis();
+
+  // The closer name ends too early, the extended cut too late
+  fs::write(test_dir.path().join("Song.lrc"), format!("{}Short\n", timestamp(end / 2))).unwrap();
+  fs::write(test_dir.path().join("Song (album).lrc"), format!("{}Album\n", timestamp(end - 5000))).unwrap();
+  fs::write(test_dir.path().join("Song (long).lrc"), format!("{}Long\n", timestamp(end + 60_000))).unwrap();
+
+  let candidates = SidecarMatcher::default().fuzzy(0.3).candidates(&audio_path, &formats);
+  let names: Vec<_> = candidates.iter().map(|sidecar| sidecar.path.file_name().unwrap().to_str().unwrap()).collect();
+  assert_eq!(names, ["Song (album).lrc", "Song.lrc"], "Lyrics running past the end of the track should be rejected");
+}
@@ -0,0 +1,65 @@
+use lyricsync::python::lyricsync;
+use pyo3::ffi::c_str;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::fs;
+
+#[test]
+fn test_python_module() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  fs::copy("tests/fixtures/silence.opus", test_dir.path().join("silence.opus")).unwrap();
+  fs::copy("tests/fixtures/silence.wav", test_dir.path().join("other.wav")).unwrap();
+
+  pyo3::append_to_inittab!(lyricsync);
+  Python::attach(|py| {
+    let globals = PyDict::new(py);
+    globals.set_item("directory", test_dir.path()).unwrap();
+    py.run(
+      c_str!(
+        r#"
+import lyricsync, os
+
+song = os.path.join(directory, "silence.opus")
+assert not lyricsync.has_lyrics(song)
+lyricsync.embed(song, "[00:01.00]Python lyrics", language="eng")
+assert lyricsync.has_lyrics(song)
+assert lyricsync.extract(song) == "[00:01.00]Python lyrics"
+
+with open(os.path.join(directory, "silence.lrc"), "w") as lrc:
+    lrc.write("[00:01.00]Python lyrics\n")
+report = sorted(lyricsync.check(directory), key=lambda checked: checked.status)
+assert [(os.path.basename(checked.path), checked.status) for checked in report] == [
+    ("silence.opus", "match"),
+    ("other.wav", "missing"),
+], report
+lyricsync.embed(song)
+
+try:
+    lyricsync.extract(os.path.join(directory, "song.xyz"))
+except lyricsync.LyricsyncError as error:
+    assert "Unsupported file format" in str(error)
+else:
+    raise AssertionError("expected LyricsyncError")
+
+other = os.path.join(directory, "other.wav")
+try:
+    lyricsync.embed(other)
+except lyricsync.LyricsyncError as error:
+    assert "no matching LRC file" in str(error)
+else:
+    raise AssertionError("expected LyricsyncError")
+
+try:
+    lyricsync.embed(song, "lyrics", language="english")
+except ValueError:
+    pass
+else:
+    raise AssertionError("expected ValueError")
+"#
+      ),
+      Some(&globals),
+      None,
+    )
+    .unwrap();
+  });
+}
@@ -0,0 +1,49 @@
+use lyricsync::romanize::romanize;
+use lyricsync::{EmbedOptions, EmbedSession, FormatResolver, Romanization, extract_lyrics};
+use std::fs;
+use std::path::Path;
+
+// Helper function to copy a fixture into a temporary directory
+fn copy_fixture(fixture: &str, test_dir: &tempfile::TempDir) -> std::path::PathBuf {
+  let dest = test_dir.path().join(Path::new(fixture).file_name().unwrap());
+  fs::copy(fixture, &dest).expect("Failed to copy test file");
+  dest
+}
+
+#[test]
+fn test_romanize() {
+  assert_eq!(
+    romanize("[ti:さくら]\n[00:01.00]さくら、キャッチ がっこう\n").as_deref(),
+    Some("[ti:さくら]\n[00:01.00]sakura, kyatchi gakkou\n")
+  );
+  assert_eq!(romanize("[00:01.00]東京タワー\n").as_deref(), Some("[00:01.00]東京tawa\n"), "Kanji are left as they are");
+  assert_eq!(
+    romanize("[00:01.00]<00:01.00>你<00:01.50>好，世界\n").as_deref(),
+    Some("[00:01.00]<00:01.00>ni <00:01.50>hao, shi jie\n")
+  );
+  assert_eq!(romanize("[00:01.00]안녕하세요 세계\n").as_deref(), Some("[00:01.00]annyeonghaseyo segye\n"));
+  assert_eq!(romanize("[00:01.00]Hello world\n"), None, "Latin script needs no romanization");
+}
+
+#[test]
+fn test_embed_romanized() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let formats = FormatResolver::default();
+  let opus_path = copy_fixture("tests/fixtures/silence.opus", &test_dir);
+  let mp3_path = test_dir.path().join("song.mp3");
+  fs::copy("tests/fixtures/04 Avril Lavigne - I'm With You.mp3", &mp3_path).unwrap();
+  for audio_path in [&opus_path, &mp3_path] {
+    fs::write(audio_path.with_extension("lrc"), "[00:01.00]さくら\n").unwrap();
+  }
+
+  let merge = EmbedOptions::builder().romanization(Romanization::Merge).build();
+  EmbedSession::new(merge).embed_file(&opus_path).unwrap();
+  assert_eq!(extract_lyrics(&opus_path, &formats).unwrap().as_deref(), Some("[00:01.00]さくら\n[00:01.00]sakura\n"));
+
+  let frames = EmbedOptions::builder().romanization(Romanization::Frames).build();
+  EmbedSession::new(frames).embed_file(&mp3_path).unwrap();
+  let data = fs::read(&mp3_path).unwrap();
+  // The USLT frame's description follows its language code, and its text the description
+  assert!(data.windows(26).any(|window| window == b"jpnromanization\0[00:01.00]"), "There should be a USLT frame");
+  assert_eq!(extract_lyrics(&mp3_path, &formats).unwrap().as_deref(), Some("[00:01.00]さくら\n"));
+}
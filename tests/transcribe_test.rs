@@ -0,0 +1,76 @@
+#![cfg(unix)]
+
+use lyricsync::transcribe::{PROVENANCE, Transcriber};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// A stand-in for whisper-cli that writes `transcript` where it's told to, as whisper-cli does with
+// --output-lrc, after checking it was given the model
+fn fake_whisper(dir: &Path, transcript: &str) -> PathBuf {
+  let script = dir.join("whisper-cli");
+  let body = format!(
+    "#!/bin/sh\nwhile [ $# -gt 0 ]; do\n  case \"$1\" in\n    --model) model=\"$2\"; shift ;;\n    \
+     --output-file) out=\"$2\"; shift ;;\n  esac\n  shift\ndone\n\
+     [ -f \"$model\" ] || {{ echo \"error: failed to open '$model'\" >&2; exit 1; }}\n\
+     printf '%s' '{transcript}' > \"$out.lrc\"\n"
+  );
+  fs::write(&script, body).unwrap();
+  fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+  script
+}
+
+#[test]
+fn test_transcribe() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let wav_path = test_dir.path().join("live.wav");
+  fs::copy("tests/fixtures/silence.wav", &wav_path).unwrap();
+  let model = test_dir.path().join("ggml-base.bin");
+  fs::write(&model, "").unwrap();
+  let whisper =
+    fake_whisper(test_dir.path(), "[by:whisper.cpp]\n\n[00:00.00] [Music]\n[00:01.50] Hello from the back row\n");
+
+  let transcriber = Transcriber::new(&whisper, &model);
+  assert_eq!(
+    transcriber.transcribe(&wav_path).unwrap(),
+    Some(format!("{PROVENANCE}\n[00:01.50]Hello from the back row\n")),
+    "Noise tags and whisper's own headers should be left out"
+  );
+
+  let error = Transcriber::new(&whisper, test_dir.path().join("missing.bin")).transcribe(&wav_path).unwrap_err();
+  assert!(error.to_string().contains("failed to open"), "whisper's error should be passed on: {error}");
+
+  let instrumental = fake_whisper(test_dir.path(), "[00:00.00] (upbeat music)\n[00:09.00] ♪\n");
+  assert_eq!(Transcriber::new(&instrumental, &model).transcribe(&wav_path).unwrap(), None);
+}
+
+#[test]
+fn test_fetch_transcribe() {
+  let test_dir = tempfile::tempdir().expect("Failed to create temp directory");
+  let library = test_dir.path().join("library");
+  fs::create_dir(&library).unwrap();
+  // Files without tags can't be looked up, so there's nothing online for them
+  fs::copy("tests/fixtures/silence.wav", library.join("untagged.wav")).unwrap();
+  let model = test_dir.path().join("ggml-base.bin");
+  fs::write(&model, "").unwrap();
+  let whisper = fake_whisper(test_dir.path(), "[00:02.00] Sing along\n");
+
+  let output = Command::new(env!("CARGO_BIN_EXE_lyricsync"))
+    .env("XDG_STATE_HOME", test_dir.path().join("state"))
+    .args(["fetch", "--no-cache", "--directory"])
+    .arg(&library)
+    .arg("--transcribe")
+    .arg(&model)
+    .arg("--whisper-cli")
+    .arg(&whisper)
+    .output()
+    .expect("Failed to execute lyricsync");
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Transcribed: 1") && stdout.contains("Not found: 0"), "{stdout}");
+  assert_eq!(
+    fs::read_to_string(library.join("untagged.lrc")).unwrap(),
+    format!("{PROVENANCE}\n[00:02.00]Sing along\n")
+  );
+}